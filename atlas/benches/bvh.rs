@@ -0,0 +1,93 @@
+use atlas::proc_gen::terrain::TerrainParams;
+use atlas::rand::rngs::SmallRng;
+use atlas::rand::{Rng, SeedableRng};
+use atlas::spatial::{ray_triangle, Aabb, Bvh};
+use criterion::{criterion_group, criterion_main, Criterion};
+use pantheon::Vec3;
+
+fn boxes(count: usize) -> Vec<Aabb> {
+    let mut rng = SmallRng::seed_from_u64(42);
+    (0..count)
+        .map(|_| {
+            let center = Vec3::new(
+                rng.gen_range(-500.0..500.0),
+                rng.gen_range(-500.0..500.0),
+                rng.gen_range(-500.0..500.0),
+            );
+            Aabb::from_center(center, Vec3::new_from_one(rng.gen_range(0.5..5.0)))
+        })
+        .collect()
+}
+
+fn picking(c: &mut Criterion) {
+    let boxes = boxes(1000);
+    let mut bvh = Bvh::default();
+    for (i, aabb) in boxes.iter().enumerate() {
+        bvh.insert(*aabb, i);
+    }
+    let origin = Vec3::new(-600, 0, 0);
+    let direction = Vec3::new(1., 0.01, 0.02).unit_vector();
+
+    c.bench_function("bvh ray 1000 boxes", |b| {
+        b.iter(|| bvh.ray(origin, direction, |i| boxes[*i].ray(origin, direction)))
+    });
+    c.bench_function("linear ray 1000 boxes", |b| {
+        b.iter(|| {
+            boxes
+                .iter()
+                .enumerate()
+                .filter_map(|(i, aabb)| Some((i, aabb.ray(origin, direction)?)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+        })
+    });
+
+    c.bench_function("bvh insert 1000 boxes", |b| {
+        b.iter(|| {
+            let mut bvh = Bvh::default();
+            for (i, aabb) in boxes.iter().enumerate() {
+                bvh.insert(*aabb, i);
+            }
+            bvh
+        })
+    });
+}
+
+fn terrain(c: &mut Criterion) {
+    let terrain = TerrainParams::new(7, 128).generate();
+    let triangle = |index: usize| {
+        let [p0, p1, p2] =
+            [0, 1, 2].map(|i| terrain.verts[terrain.indices[index * 3 + i] as usize].position);
+        (p0, p1, p2)
+    };
+    let count = terrain.indices.len() / 3;
+    let mut bvh = Bvh::new(0.);
+    for index in 0..count {
+        let (p0, p1, p2) = triangle(index);
+        bvh.insert(Aabb::from_points([p0, p1, p2]).unwrap(), index);
+    }
+
+    // the mesh is in its own space, which starts at the grid's corner
+    let origin = Vec3::new(10, 200, 10);
+    let direction = Vec3::new(0.3, -1., 0.2).unit_vector();
+    c.bench_function("bvh ray terrain", |b| {
+        b.iter(|| {
+            bvh.ray(origin, direction, |index| {
+                let (p0, p1, p2) = triangle(*index);
+                ray_triangle(origin, direction, p0, p1, p2)
+            })
+        })
+    });
+    c.bench_function("linear ray terrain", |b| {
+        b.iter(|| {
+            (0..count)
+                .filter_map(|index| {
+                    let (p0, p1, p2) = triangle(index);
+                    ray_triangle(origin, direction, p0, p1, p2)
+                })
+                .min_by(|a, b| a.total_cmp(b))
+        })
+    });
+}
+
+criterion_group!(benches, picking, terrain);
+criterion_main!(benches);
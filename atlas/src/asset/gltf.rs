@@ -0,0 +1,171 @@
+use super::{check_indices, parse_error, MeshError, DEFAULT_MESH_COLOR};
+use crate::entity::mesh::MeshData;
+use ::gltf::buffer::Source;
+use ::gltf::mesh::Mode;
+use ::gltf::{Gltf, Node};
+use pantheon::math::{Mat4, Vec4};
+use pantheon::{Color, Vec3};
+use std::path::Path;
+
+pub(super) fn parse(bytes: &[u8], path: &Path) -> Result<MeshData, MeshError> {
+    let Gltf { document, mut blob } = Gltf::from_slice(bytes).map_err(|e| parse_error(path, e))?;
+    let buffers = document
+        .buffers()
+        .map(|buffer| {
+            let data = read_buffer(buffer.source(), &mut blob, path)?;
+            if data.len() < buffer.length() {
+                return Err(parse_error(
+                    path,
+                    format!("buffer {} is shorter than it says", buffer.index()),
+                ));
+            }
+            Ok(data)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut mesh = MeshData::default();
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => {
+            for node in scene.nodes() {
+                add_node(&mut mesh, &node, &Mat4::identity(), &buffers, path)?;
+            }
+        }
+        // nothing says where the meshes go, they're taken as they are
+        None => {
+            for gltf_mesh in document.meshes() {
+                add_mesh(&mut mesh, &gltf_mesh, &Mat4::identity(), &buffers, path)?;
+            }
+        }
+    }
+
+    Ok(mesh)
+}
+
+/// A buffer's bytes, from the .glb's own binary chunk, a base64 data URI or a file next to
+/// the glTF. Images are never loaded so only buffers need reading.
+fn read_buffer(
+    source: Source,
+    blob: &mut Option<Vec<u8>>,
+    path: &Path,
+) -> Result<Vec<u8>, MeshError> {
+    let uri = match source {
+        Source::Bin => {
+            return blob
+                .take()
+                .ok_or_else(|| parse_error(path, "missing its binary chunk"))
+        }
+        Source::Uri(uri) => uri,
+    };
+
+    if let Some(data) = uri.strip_prefix("data:") {
+        let encoded = match data.split_once(";base64,") {
+            Some((_, encoded)) => encoded,
+            None => return Err(parse_error(path, "only base64 data URIs are supported")),
+        };
+        return base64::decode(encoded).map_err(|e| parse_error(path, e));
+    }
+
+    let file = path.parent().unwrap_or_else(|| Path::new("")).join(uri);
+    std::fs::read(&file).map_err(|source| MeshError::Read { path: file, source })
+}
+
+/// adds the node's mesh and all of its children's, moved by every transform above them
+fn add_node(
+    mesh: &mut MeshData,
+    node: &Node,
+    parent: &Mat4,
+    buffers: &[Vec<u8>],
+    path: &Path,
+) -> Result<(), MeshError> {
+    let transform = *parent * matrix(node.transform().matrix());
+    if let Some(gltf_mesh) = node.mesh() {
+        add_mesh(mesh, &gltf_mesh, &transform, buffers, path)?;
+    }
+    for child in node.children() {
+        add_node(mesh, &child, &transform, buffers, path)?;
+    }
+
+    Ok(())
+}
+
+fn add_mesh(
+    mesh: &mut MeshData,
+    gltf_mesh: &::gltf::Mesh,
+    transform: &Mat4,
+    buffers: &[Vec<u8>],
+    path: &Path,
+) -> Result<(), MeshError> {
+    for primitive in gltf_mesh.primitives() {
+        if primitive.mode() != Mode::Triangles {
+            return Err(MeshError::Unsupported {
+                path: path.to_path_buf(),
+                what: format!("{:?} primitives", primitive.mode()),
+            });
+        }
+
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let positions: Vec<Vec3> = match reader.read_positions() {
+            Some(positions) => positions.map(|[x, y, z]| Vec3::new(x, y, z)).collect(),
+            None => continue,
+        };
+        let normals: Option<Vec<Vec3>> = reader
+            .read_normals()
+            .map(|normals| normals.map(|[x, y, z]| Vec3::new(x, y, z)).collect());
+        let colors: Option<Vec<Color>> = reader.read_colors(0).map(|colors| {
+            colors
+                .into_rgba_f32()
+                .map(|[r, g, b, a]| Color::floats_a(r, g, b, a))
+                .collect()
+        });
+        let indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+
+        let vertices = positions
+            .iter()
+            .enumerate()
+            .map(|(i, &position)| {
+                let normal = normals
+                    .as_ref()
+                    .and_then(|normals| normals.get(i).copied())
+                    .unwrap_or_else(|| Vec3::new_from_one(0));
+                let color = colors
+                    .as_ref()
+                    .and_then(|colors| colors.get(i).copied())
+                    .unwrap_or(DEFAULT_MESH_COLOR);
+                (position, color, normal).into()
+            })
+            .collect();
+
+        let mut part = MeshData { vertices, indices };
+        check_indices(&part, path)?;
+        if normals.is_none() {
+            part.smooth_normals();
+        }
+        part.transform(transform);
+        // a mirroring transform turns every triangle inside out
+        if mirrors(transform) {
+            for triangle in part.indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+        mesh.append(&part);
+    }
+
+    Ok(())
+}
+
+/// glTF matrices are stored column by column, the same as `Mat4`
+fn matrix(columns: [[f32; 4]; 4]) -> Mat4 {
+    let [x, y, z, w] = columns.map(|[x, y, z, w]| Vec4::new(x, y, z, w));
+    Mat4::new(x, y, z, w)
+}
+
+fn mirrors(transform: &Mat4) -> bool {
+    let (x, y, z) = (transform.x.vec3(), transform.y.vec3(), transform.z.vec3());
+    x.cross(&y).dot(&z) < 0.
+}
@@ -0,0 +1,190 @@
+use crate::entity::mesh::MeshData;
+use pantheon::Color;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+mod gltf;
+mod obj;
+
+/// what vertices are colored when the file doesn't say, the same gray as a plain `Cuboid`
+pub const DEFAULT_MESH_COLOR: Color = Color::floats(60. / 255., 60. / 255., 60. / 255.);
+
+#[derive(Error, Debug)]
+pub enum MeshError {
+    #[error("failed to read mesh {path:?}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse mesh {path:?}: {message}")]
+    Parse { path: PathBuf, message: String },
+    #[error("mesh {path:?} should end in .obj, .gltf or .glb")]
+    UnknownFormat { path: PathBuf },
+    #[error("mesh {path:?} has {what}, only triangles can be loaded")]
+    Unsupported { path: PathBuf, what: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshFormat {
+    /// Wavefront OBJ, materials are ignored
+    Obj,
+    /// glTF 2.0, either the JSON or the binary .glb flavor
+    Gltf,
+}
+
+impl MeshFormat {
+    pub fn from_path(path: &Path) -> Result<Self, MeshError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("obj") => Ok(Self::Obj),
+            Some("gltf") | Some("glb") => Ok(Self::Gltf),
+            _ => Err(MeshError::UnknownFormat {
+                path: path.to_path_buf(),
+            }),
+        }
+    }
+
+    /// Everything in `bytes` as a single mesh. `path` points the error at the right file and
+    /// is where a glTF's separate buffers are looked for.
+    pub fn parse(self, bytes: &[u8], path: &Path) -> Result<MeshData, MeshError> {
+        match self {
+            Self::Obj => obj::parse(bytes, path),
+            Self::Gltf => gltf::parse(bytes, path),
+        }
+    }
+}
+
+/// Loads every triangle in an OBJ or glTF file into a single mesh, the format is picked from
+/// the file's extension. Vertices missing colors get `DEFAULT_MESH_COLOR` and meshes missing
+/// normals get `MeshData::smooth_normals`.
+pub fn load_mesh(path: impl AsRef<Path>) -> Result<MeshData, MeshError> {
+    let path = path.as_ref();
+    let format = MeshFormat::from_path(path)?;
+    let bytes = std::fs::read(path).map_err(|source| MeshError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    format.parse(&bytes, path)
+}
+
+/// Every index has to point at one of `mesh`'s vertices, they're uploaded to the GPU as they are
+fn check_indices(mesh: &MeshData, path: &Path) -> Result<(), MeshError> {
+    let count = mesh.vertices.len();
+    match mesh.indices.iter().find(|index| **index as usize >= count) {
+        Some(index) => Err(parse_error(
+            path,
+            format!("index {} is past the last of {} vertices", index, count),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn parse_error(path: &Path, error: impl std::fmt::Display) -> MeshError {
+    MeshError::Parse {
+        path: path.to_path_buf(),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pantheon::Vec3;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(name)
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    fn rgb(color: Color) -> (f32, f32, f32) {
+        (color.r, color.g, color.b)
+    }
+
+    #[test]
+    fn loads_obj() {
+        let quad = load_mesh(fixture("quad.obj")).unwrap();
+        assert_eq!(quad.vertices.len(), 4);
+        assert_eq!(quad.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(quad.vertices[2].position, Vec3::new(1, 0, -1));
+        assert_eq!(rgb(quad.vertices[1].color), (0., 1., 0.));
+        assert!(quad
+            .vertices
+            .iter()
+            .all(|vertex| vertex.normal == Vec3::new(0, 1, 0)));
+
+        // without normals they're worked out from the faces, all pointing outwards
+        let tetrahedron = load_mesh(fixture("tetrahedron.obj")).unwrap();
+        assert_eq!(tetrahedron.triangle_count(), 4);
+        let center = 0.25 * Vec3::new_from_one(1);
+        for vertex in tetrahedron.vertices.iter() {
+            assert!(vertex.normal.dot(&(vertex.position - center)) > 0.);
+            assert!((vertex.normal.magnitude() - 1.).abs() < 1e-5);
+            assert_eq!(rgb(vertex.color), rgb(DEFAULT_MESH_COLOR));
+        }
+    }
+
+    #[test]
+    fn loads_gltf() {
+        let mesh = load_mesh(fixture("triangle.gltf")).unwrap();
+        // one triangle used by three nodes
+        assert_eq!(mesh.vertices.len(), 9);
+        assert_eq!(mesh.indices[..3], [0, 1, 2]);
+
+        // moved by its parent and scaled by itself
+        let positions: Vec<Vec3> = mesh.vertices.iter().map(|v| v.position).collect();
+        assert!(close(positions[0], Vec3::new(1, 0, 0)));
+        assert!(close(positions[1], Vec3::new(3, 0, 0)));
+        assert!(close(positions[2], Vec3::new(1, 2, 0)));
+        assert!(close(mesh.vertices[0].normal, Vec3::new(0, 0, 1)));
+        assert_eq!(rgb(mesh.vertices[0].color), (1., 0., 0.));
+        assert_eq!(rgb(mesh.vertices[2].color), (0., 0., 1.));
+
+        // a quarter turn around y
+        assert!(close(positions[4], Vec3::new(0, 0, -1)));
+        assert!(close(mesh.vertices[4].normal, Vec3::new(1, 0, 0)));
+
+        // mirrored, the winding is flipped so it still faces along its normal
+        assert!(close(positions[7], Vec3::new(-1, 0, 0)));
+        let [p0, p1, p2] = mesh.triangles().nth(2).unwrap();
+        let face = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+        assert!(close(face, mesh.vertices[6].normal));
+
+        // the binary flavor holds the very same thing
+        let glb = load_mesh(fixture("triangle.glb")).unwrap();
+        assert_eq!(glb.indices, mesh.indices);
+        for (a, b) in glb.vertices.iter().zip(mesh.vertices.iter()) {
+            assert!(close(a.position, b.position));
+            assert!(close(a.normal, b.normal));
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            load_mesh(fixture("quad.fbx")),
+            Err(MeshError::UnknownFormat { .. })
+        ));
+        assert!(matches!(
+            load_mesh(fixture("missing.obj")),
+            Err(MeshError::Read { .. })
+        ));
+        assert!(matches!(
+            MeshFormat::Gltf.parse(b"{ not json", &fixture("broken.gltf")),
+            Err(MeshError::Parse { .. })
+        ));
+
+        // the triangle's last index points at a vertex it doesn't have
+        let out_of_range = std::fs::read_to_string(fixture("triangle.gltf"))
+            .unwrap()
+            .replace("AAABAAIAAAA=", "AAABAAUAAAA=");
+        assert!(matches!(
+            MeshFormat::Gltf.parse(out_of_range.as_bytes(), &fixture("out_of_range.gltf")),
+            Err(MeshError::Parse { .. })
+        ));
+    }
+}
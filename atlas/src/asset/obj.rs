@@ -0,0 +1,60 @@
+use super::{check_indices, parse_error, MeshError, DEFAULT_MESH_COLOR};
+use crate::entity::mesh::MeshData;
+use pantheon::{Color, Vec3};
+use std::io::BufReader;
+use std::path::Path;
+
+pub(super) fn parse(bytes: &[u8], path: &Path) -> Result<MeshData, MeshError> {
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+    };
+    // only the geometry is wanted, any .mtl files are never opened
+    let (models, _) = tobj::load_obj_buf(&mut BufReader::new(bytes), &options, |_| {
+        Err(tobj::LoadError::OpenFileFailed)
+    })
+    .map_err(|e| parse_error(path, e))?;
+
+    let mut mesh = MeshData::default();
+    for model in models {
+        mesh.append(&model_mesh(&model.mesh, path)?);
+    }
+
+    Ok(mesh)
+}
+
+fn model_mesh(model: &tobj::Mesh, path: &Path) -> Result<MeshData, MeshError> {
+    let count = model.positions.len() / 3;
+    let triple = |values: &[f32], i: usize| (values[3 * i], values[3 * i + 1], values[3 * i + 2]);
+    let has_colors = model.vertex_color.len() >= 3 * count;
+    let has_normals = model.normals.len() >= 3 * count;
+
+    let vertices = (0..count)
+        .map(|i| {
+            let position: Vec3 = triple(&model.positions, i).into();
+            let color: Color = if has_colors {
+                triple(&model.vertex_color, i).into()
+            } else {
+                DEFAULT_MESH_COLOR
+            };
+            let normal: Vec3 = if has_normals {
+                triple(&model.normals, i).into()
+            } else {
+                Vec3::new_from_one(0)
+            };
+            (position, color, normal).into()
+        })
+        .collect();
+
+    let mut mesh = MeshData {
+        vertices,
+        indices: model.indices.clone(),
+    };
+    check_indices(&mesh, path)?;
+    if !has_normals {
+        mesh.smooth_normals();
+    }
+    Ok(mesh)
+}
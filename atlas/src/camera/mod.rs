@@ -11,6 +11,24 @@ const MOUSE_DEFAULT: f32 = 100.;
 const EPSILON: f32 = 9.53674316 * 0.00000001;
 const INFINITE_PERSPECTIVE: bool = false;
 
+/// how the camera gets moved around
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// flown around with WASD
+    Free,
+    /// kept at the eyes of a player, see `Camera::follow`
+    FirstPerson,
+}
+
+impl CameraMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            Self::Free => Self::FirstPerson,
+            Self::FirstPerson => Self::Free,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Camera {
     pub origin: Vec3,
@@ -46,6 +64,7 @@ pub struct Camera {
     pub dirty: bool,
 
     pub infinite_perspective: bool,
+    pub mode: CameraMode,
 }
 
 impl Camera {
@@ -69,7 +88,7 @@ impl Camera {
         let u_r = (w.cross(&world_up)).unit_vector();
         let v_r = u.cross(&w).unit_vector();
 
-        println!("u: {:#?}, v: {:#?}, w: {:#?}", u, v, w);
+        tracing::trace!(?u, ?v, ?w, "camera basis");
         let pitch = w.y.asin();
         let yaw = {
             if pitch.cos() != 0.0 {
@@ -81,7 +100,7 @@ impl Camera {
             }
         };
         let pitch = pitch.to_degrees();
-        println!("pitch: {:#?}, yaw: {:#?}", pitch, yaw);
+        tracing::trace!(pitch, yaw, "camera orientation");
         let projection = if INFINITE_PERSPECTIVE {
             Mat4::infinite_perspective(vfov, aspect, near_plane, EPSILON)
         } else {
@@ -140,6 +159,7 @@ impl Camera {
             dirty: false,
 
             infinite_perspective: INFINITE_PERSPECTIVE,
+            mode: CameraMode::Free,
         }
     }
 
@@ -225,7 +245,20 @@ impl Camera {
         self.v_r = self.u_r.cross(&self.w_r).unit_vector();
     }
 
+    /// moves a first person camera to `eye`, a free camera is left where it is
+    pub fn follow(&mut self, eye: Vec3) {
+        if self.mode == CameraMode::FirstPerson && self.origin != eye {
+            self.origin = eye;
+            self.dirty = true;
+        }
+    }
+
+    /// WASD flies a free camera around, a first person one only moves with `follow`
     pub fn process_keypress(&mut self, key: VirtualKeyCode, delta_time: f32) {
+        if self.mode == CameraMode::FirstPerson {
+            return;
+        }
+
         let speed = if self.fast_move {
             self.move_speed * 5.0 * delta_time
         } else {
@@ -269,15 +302,7 @@ impl Camera {
         self.yaw += x_offset;
         self.pitch += y_offset;
 
-        self.pitch = if self.pitch > 89.0 {
-            println!("Clamping Camera pitch to 89 deg");
-            89.0
-        } else if self.pitch < -89.0 {
-            println!("Clamping Camera pitch to -89 deg");
-            -89.0
-        } else {
-            self.pitch
-        };
+        self.pitch = self.pitch.clamp(-89.0, 89.0);
 
         self.update_orientation();
     }
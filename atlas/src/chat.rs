@@ -0,0 +1,260 @@
+use crate::entity::id::EntityId;
+use crate::message::GameMessage;
+use hermes::message::{Message, MessageError};
+use thiserror::Error;
+
+/// Longest chat message in bytes of UTF-8, anything longer is rejected rather than cut short
+pub const MAX_CHAT_BYTES: usize = 200;
+
+#[derive(Error, Debug)]
+pub enum ChatError {
+    #[error("chat messages can't be empty")]
+    Empty,
+    #[error("chat messages are limited to {max} bytes, this one has {len}")]
+    TooLong { len: usize, max: usize },
+    #[error("chat messages have to be valid UTF-8")]
+    InvalidUtf8,
+    #[error("chat messages can't contain control characters")]
+    ControlCharacter,
+    #[error("unknown chat channel {0}")]
+    UnknownChannel(u8),
+    #[error(transparent)]
+    Message(#[from] MessageError),
+}
+
+/// Who gets to read a chat message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatChannel {
+    /// everyone on the server
+    Global,
+    /// players on the sender's team
+    Team,
+    /// only the given player, the sender gets a copy back
+    Whisper(EntityId),
+    /// from the server itself, never accepted from a client
+    System,
+}
+
+/// A single line of chat. Clients only fill in `channel` and `text`, the server sets `sender`
+/// and `timestamp_ms` before passing it on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub channel: ChatChannel,
+    /// the sending player, `None` for `ChatChannel::System`
+    pub sender: Option<EntityId>,
+    /// milliseconds since the unix epoch, as seen by the server
+    pub timestamp_ms: u64,
+    pub text: String,
+}
+
+impl ChatMessage {
+    /// what a client sends, `text` isn't checked until it goes out with `chat_message`
+    pub fn new(channel: ChatChannel, text: impl Into<String>) -> Self {
+        Self {
+            channel,
+            sender: None,
+            timestamp_ms: 0,
+            text: text.into(),
+        }
+    }
+
+    pub fn system(text: impl Into<String>, timestamp_ms: u64) -> Self {
+        Self {
+            channel: ChatChannel::System,
+            sender: None,
+            timestamp_ms,
+            text: text.into(),
+        }
+    }
+}
+
+/// Checks `text` could be sent, leading and trailing whitespace doesn't count towards it being
+/// empty but does count towards its length
+pub fn validate_text(text: &str) -> Result<(), ChatError> {
+    if text.trim().is_empty() {
+        return Err(ChatError::Empty);
+    }
+    if text.len() > MAX_CHAT_BYTES {
+        return Err(ChatError::TooLong {
+            len: text.len(),
+            max: MAX_CHAT_BYTES,
+        });
+    }
+    if text.chars().any(char::is_control) {
+        return Err(ChatError::ControlCharacter);
+    }
+
+    Ok(())
+}
+
+/// How a `ChatMessage` goes over the wire, plain integers so nothing pulled off a message can be
+/// an invalid enum. The text's bytes are pushed before it.
+#[derive(Clone, Copy, Debug)]
+struct ChatHeader {
+    timestamp_ms: u64,
+    /// only meaningful for whispers
+    target: EntityId,
+    sender: EntityId,
+    len: u16,
+    channel: u8,
+    has_sender: u8,
+}
+
+const GLOBAL: u8 = 0;
+const TEAM: u8 = 1;
+const WHISPER: u8 = 2;
+const SYSTEM: u8 = 3;
+
+/// Packs `chat` into a `GameMessage::Chat`, fails if its text couldn't be sent
+pub fn chat_message(chat: &ChatMessage) -> Result<Message<GameMessage>, ChatError> {
+    validate_text(&chat.text)?;
+
+    let (channel, target) = match chat.channel {
+        ChatChannel::Global => (GLOBAL, EntityId::default()),
+        ChatChannel::Team => (TEAM, EntityId::default()),
+        ChatChannel::Whisper(target) => (WHISPER, target),
+        ChatChannel::System => (SYSTEM, EntityId::default()),
+    };
+
+    let mut msg = Message::new(GameMessage::Chat);
+    for byte in chat.text.bytes() {
+        msg.push(byte);
+    }
+    msg.push(ChatHeader {
+        channel,
+        target,
+        has_sender: chat.sender.is_some() as u8,
+        sender: chat.sender.unwrap_or_default(),
+        timestamp_ms: chat.timestamp_ms,
+        len: chat.text.len() as u16,
+    });
+
+    Ok(msg)
+}
+
+/// Inverse of `chat_message`, everything is validated again since it came off the network
+pub fn pull_chat(msg: &mut Message<GameMessage>) -> Result<ChatMessage, ChatError> {
+    let header: ChatHeader = msg.pull()?;
+
+    let len = header.len as usize;
+    if len > MAX_CHAT_BYTES {
+        return Err(ChatError::TooLong {
+            len,
+            max: MAX_CHAT_BYTES,
+        });
+    }
+    let mut bytes = Vec::with_capacity(len);
+    for _ in 0..len {
+        bytes.push(msg.pull::<u8>()?);
+    }
+    // pulling works from the back of the body
+    bytes.reverse();
+
+    let text = String::from_utf8(bytes).map_err(|_| ChatError::InvalidUtf8)?;
+    validate_text(&text)?;
+
+    let channel = match header.channel {
+        GLOBAL => ChatChannel::Global,
+        TEAM => ChatChannel::Team,
+        WHISPER => ChatChannel::Whisper(header.target),
+        SYSTEM => ChatChannel::System,
+        other => return Err(ChatError::UnknownChannel(other)),
+    };
+
+    Ok(ChatMessage {
+        channel,
+        sender: (header.has_sender != 0).then_some(header.sender),
+        timestamp_ms: header.timestamp_ms,
+        text,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() -> Result<(), ChatError> {
+        let chats = [
+            ChatMessage::new(ChatChannel::Global, "hello there"),
+            ChatMessage {
+                channel: ChatChannel::Whisper(EntityId::new(4, 2)),
+                sender: Some(EntityId::new(1, 0)),
+                timestamp_ms: 1_700_000_000_123,
+                text: "héllo ☃".to_string(),
+            },
+            ChatMessage::system("server restarting", 5),
+        ];
+
+        for chat in chats {
+            let mut msg = chat_message(&chat)?;
+            assert_eq!(pull_chat(&mut msg)?, chat);
+            assert!(msg.body.is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_bad_text() {
+        assert!(matches!(validate_text("   "), Err(ChatError::Empty)));
+        assert!(matches!(
+            validate_text("a\nb"),
+            Err(ChatError::ControlCharacter)
+        ));
+        assert!(validate_text(&"a".repeat(MAX_CHAT_BYTES)).is_ok());
+        // the limit is in bytes, not characters
+        assert!(matches!(
+            validate_text(&"☃".repeat(MAX_CHAT_BYTES / 3 + 1)),
+            Err(ChatError::TooLong { .. })
+        ));
+        assert!(chat_message(&ChatMessage::new(ChatChannel::Team, "")).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_messages() -> Result<(), ChatError> {
+        let good = chat_message(&ChatMessage::new(ChatChannel::Global, "hi"))?;
+
+        // a byte which can't start a UTF-8 sequence
+        let mut msg = good.clone();
+        msg.body[0] = 0xff;
+        assert!(matches!(pull_chat(&mut msg), Err(ChatError::InvalidUtf8)));
+
+        // claims more text than the limit allows
+        let mut msg = Message::new(GameMessage::Chat);
+        msg.push(ChatHeader {
+            channel: GLOBAL,
+            target: EntityId::default(),
+            has_sender: 0,
+            sender: EntityId::default(),
+            timestamp_ms: 0,
+            len: MAX_CHAT_BYTES as u16 + 1,
+        });
+        assert!(matches!(
+            pull_chat(&mut msg),
+            Err(ChatError::TooLong { .. })
+        ));
+
+        // claims more text than was sent
+        let mut msg = good.clone();
+        msg.body.drain(..1);
+        assert!(matches!(pull_chat(&mut msg), Err(ChatError::Message(_))));
+
+        let mut msg = Message::new(GameMessage::Chat);
+        msg.push(b'x');
+        msg.push(ChatHeader {
+            channel: 9,
+            target: EntityId::default(),
+            has_sender: 0,
+            sender: EntityId::default(),
+            timestamp_ms: 0,
+            len: 1,
+        });
+        assert!(matches!(
+            pull_chat(&mut msg),
+            Err(ChatError::UnknownChannel(9))
+        ));
+
+        Ok(())
+    }
+}
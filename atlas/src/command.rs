@@ -0,0 +1,1511 @@
+use crate::ecs::components::{Light, Look, Mesh, Transform};
+use crate::ecs::replicate::Replicated;
+use crate::ecs::{hierarchy, World};
+use crate::entity::primitive::PrimitiveShape;
+use crate::message::GameMessage;
+use crate::proc_gen::terrain::{TerrainParams, MAX_PALETTE_COLORS};
+use crate::snapshot::EntityId;
+use crate::vertex::VertexKind;
+use hermes::message::{Message, MessageError};
+use pantheon::math::Quaternion;
+use pantheon::{Color, PolygonMode, Topology, Vec3};
+use std::collections::VecDeque;
+use thiserror::Error;
+
+/// undo steps kept for each editor, the oldest are forgotten past this
+pub const DEFAULT_HISTORY_LEN: usize = 100;
+/// edits don't spawn anything into a world already holding this many entities
+pub const MAX_ENTITIES: usize = 10_000;
+/// edits can't put anything further than this from the origin along any axis
+pub const WORLD_BOUND: f32 = 10_000.;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError {
+    #[error("{0} doesn't exist")]
+    NoSuchEntity(EntityId),
+    /// players only move through their own inputs
+    #[error("{0} can't be edited")]
+    Locked(EntityId),
+    #[error("{0} has no {1}")]
+    Missing(EntityId, &'static str),
+    #[error("invalid {0}")]
+    Invalid(&'static str),
+    #[error("the world is full")]
+    Full,
+    /// Undoing a delete only brings back the one entity, and moves are made in world space,
+    /// so anything with a parent or children is left alone
+    #[error("{0} is attached to other entities")]
+    Attached(EntityId),
+}
+
+/// Everything a `Command` can change
+pub struct Scene<'s> {
+    pub world: &'s mut World,
+    /// the terrain is regenerated from these by whoever owns it once they change
+    pub terrain: &'s mut TerrainParams,
+}
+
+/// An edit to the world which can be undone. The `from` side of each one is filled in when
+/// it's executed, whatever it held before is ignored.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy)]
+pub enum Command {
+    /// `id` is filled in once it's been spawned
+    Spawn {
+        entity: Replicated,
+        id: Option<EntityId>,
+    },
+    /// `entity` is filled in with what it takes to bring it back
+    Delete {
+        id: EntityId,
+        entity: Option<Replicated>,
+    },
+    Move {
+        id: EntityId,
+        from: Vec3,
+        to: Vec3,
+    },
+    Rotate {
+        id: EntityId,
+        from: Quaternion,
+        to: Quaternion,
+    },
+    Recolor {
+        id: EntityId,
+        from: Color,
+        to: Color,
+    },
+    Terrain {
+        from: TerrainParams,
+        to: TerrainParams,
+    },
+}
+
+/// Sent by a client with `GameMessage::Edit`, the server keeps a `History` for every client so
+/// each one undoes its own edits
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy)]
+pub enum EditRequest {
+    Apply(Command),
+    Undo,
+    Redo,
+    /// everything applied until `EndGroup` is undone in one go, sent around a drag
+    BeginGroup,
+    EndGroup,
+}
+
+/// An entity which was despawned and spawned again can't have its old id back, everything
+/// still pointing at `old` has to be pointed at `new`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Respawned {
+    pub old: EntityId,
+    pub new: EntityId,
+}
+
+impl Command {
+    pub fn spawn(entity: Replicated) -> Self {
+        Self::Spawn { entity, id: None }
+    }
+
+    pub fn delete(id: EntityId) -> Self {
+        Self::Delete { id, entity: None }
+    }
+
+    pub fn move_to(id: EntityId, to: Vec3) -> Self {
+        Self::Move { id, from: to, to }
+    }
+
+    pub fn rotate_to(id: EntityId, to: Quaternion) -> Self {
+        Self::Rotate { id, from: to, to }
+    }
+
+    pub fn recolor(id: EntityId, to: Color) -> Self {
+        Self::Recolor { id, from: to, to }
+    }
+
+    pub fn terrain(to: TerrainParams) -> Self {
+        Self::Terrain { from: to, to }
+    }
+
+    /// whether the terrain has to be regenerated after this is executed or undone
+    pub fn changes_terrain(&self) -> bool {
+        matches!(self, Self::Terrain { .. })
+    }
+
+    /// Checks the command against `scene` and applies it, nothing is changed when it fails.
+    /// Comes back with the new id when a spawn is redone.
+    pub fn execute(&mut self, scene: &mut Scene) -> Result<Option<Respawned>, CommandError> {
+        let world = &mut *scene.world;
+        match self {
+            Self::Spawn { entity, id } => {
+                if entity.is_player() {
+                    return Err(CommandError::Invalid("entity"));
+                }
+                if let Some(primitive) = entity.mesh.primitive {
+                    if !primitive.is_valid() {
+                        return Err(CommandError::Invalid("shape"));
+                    }
+                }
+                // nothing can draw a textured cube yet
+                if entity.mesh.vertex_kind == VertexKind::Textured {
+                    return Err(CommandError::Invalid("vertex kind"));
+                }
+                if !entity.mesh.size.is_normal() || entity.mesh.size < 0. {
+                    return Err(CommandError::Invalid("size"));
+                }
+                let transform = &mut entity.transform;
+                if !finite(transform.position) {
+                    return Err(CommandError::Invalid("position"));
+                }
+                transform.position = bounded(transform.position);
+                transform.rotation =
+                    unit(transform.rotation).ok_or(CommandError::Invalid("rotation"))?;
+                let scale = transform.scale;
+                if ![scale.x, scale.y, scale.z]
+                    .iter()
+                    .all(|v| v.is_normal() && *v > 0.)
+                {
+                    return Err(CommandError::Invalid("scale"));
+                }
+                if !finite_color(entity.mesh.color) {
+                    return Err(CommandError::Invalid("color"));
+                }
+                if let Some(light) = entity.light {
+                    if !finite_color(light.color)
+                        || !light.radians.is_finite()
+                        || !finite(light.rotation_axis)
+                    {
+                        return Err(CommandError::Invalid("light"));
+                    }
+                }
+                if entity.velocity.is_some_and(|velocity| !finite(velocity.0)) {
+                    return Err(CommandError::Invalid("velocity"));
+                }
+                if world.len() >= MAX_ENTITIES {
+                    return Err(CommandError::Full);
+                }
+                let new = world.spawn();
+                entity.insert(world, new);
+
+                Ok(id.replace(new).map(|old| Respawned { old, new }))
+            }
+            Self::Delete { id, entity } => {
+                editable(world, *id)?;
+                detached(world, *id)?;
+                let components =
+                    Replicated::from_world(world, *id).ok_or(CommandError::Missing(*id, "mesh"))?;
+                *entity = Some(components);
+                hierarchy::despawn(world, *id);
+
+                Ok(None)
+            }
+            Self::Move { id, from, to } => {
+                if !finite(*to) {
+                    return Err(CommandError::Invalid("position"));
+                }
+                *to = bounded(*to);
+                let transform = transform(world, *id)?;
+                *from = transform.position;
+                hierarchy::set_transform(
+                    world,
+                    *id,
+                    Transform {
+                        position: *to,
+                        ..transform
+                    },
+                );
+
+                Ok(None)
+            }
+            Self::Rotate { id, from, to } => {
+                *to = unit(*to).ok_or(CommandError::Invalid("rotation"))?;
+                let transform = transform(world, *id)?;
+                *from = transform.rotation;
+                hierarchy::set_transform(
+                    world,
+                    *id,
+                    Transform {
+                        rotation: *to,
+                        ..transform
+                    },
+                );
+
+                Ok(None)
+            }
+            Self::Recolor { id, from, to } => {
+                if !finite_color(*to) {
+                    return Err(CommandError::Invalid("color"));
+                }
+                editable(world, *id)?;
+                let mesh = world
+                    .get_mut::<Mesh>(*id)
+                    .ok_or(CommandError::Missing(*id, "mesh"))?;
+                *from = mesh.color;
+                mesh.color = *to;
+
+                Ok(None)
+            }
+            Self::Terrain { from, to } => {
+                if !to.is_valid() {
+                    return Err(CommandError::Invalid("terrain"));
+                }
+                *from = *scene.terrain;
+                *scene.terrain = *to;
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// Puts back whatever `execute` changed. Comes back with the new id of a deleted entity
+    /// which was brought back.
+    pub fn undo(&mut self, scene: &mut Scene) -> Result<Option<Respawned>, CommandError> {
+        let world = &mut *scene.world;
+        match self {
+            Self::Spawn { id, .. } => {
+                let id = id.ok_or(CommandError::Invalid("spawn"))?;
+                if !hierarchy::despawn(world, id) {
+                    return Err(CommandError::NoSuchEntity(id));
+                }
+
+                Ok(None)
+            }
+            Self::Delete { id, entity } => {
+                let components = entity.ok_or(CommandError::Invalid("delete"))?;
+                if world.len() >= MAX_ENTITIES {
+                    return Err(CommandError::Full);
+                }
+                let old = *id;
+                let new = world.spawn();
+                components.insert(world, new);
+                *id = new;
+
+                Ok(Some(Respawned { old, new }))
+            }
+            Self::Move { id, from, .. } => {
+                let transform = transform(world, *id)?;
+                hierarchy::set_transform(
+                    world,
+                    *id,
+                    Transform {
+                        position: *from,
+                        ..transform
+                    },
+                );
+
+                Ok(None)
+            }
+            Self::Rotate { id, from, .. } => {
+                let transform = transform(world, *id)?;
+                hierarchy::set_transform(
+                    world,
+                    *id,
+                    Transform {
+                        rotation: *from,
+                        ..transform
+                    },
+                );
+
+                Ok(None)
+            }
+            Self::Recolor { id, from, .. } => {
+                editable(world, *id)?;
+                let mesh = world
+                    .get_mut::<Mesh>(*id)
+                    .ok_or(CommandError::Missing(*id, "mesh"))?;
+                mesh.color = *from;
+
+                Ok(None)
+            }
+            Self::Terrain { from, .. } => {
+                *scene.terrain = *from;
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// Folds `next` into this command when both change the same thing on the same entity, so
+    /// a drag only leaves its start and end behind. False if they can't be merged.
+    pub fn merge(&mut self, next: &Command) -> bool {
+        match (self, next) {
+            (
+                Self::Move { id, to, .. },
+                Self::Move {
+                    id: next,
+                    to: next_to,
+                    ..
+                },
+            ) if id == next => {
+                *to = *next_to;
+                true
+            }
+            (
+                Self::Rotate { id, to, .. },
+                Self::Rotate {
+                    id: next,
+                    to: next_to,
+                    ..
+                },
+            ) if id == next => {
+                *to = *next_to;
+                true
+            }
+            (
+                Self::Recolor { id, to, .. },
+                Self::Recolor {
+                    id: next,
+                    to: next_to,
+                    ..
+                },
+            ) if id == next => {
+                *to = *next_to;
+                true
+            }
+            (Self::Terrain { to, .. }, Self::Terrain { to: next_to, .. }) => {
+                *to = *next_to;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn respawns(&self) -> bool {
+        matches!(self, Self::Spawn { .. } | Self::Delete { .. })
+    }
+
+    fn remap(&mut self, respawned: Respawned) {
+        let id = match self {
+            Self::Spawn { id: Some(id), .. } => id,
+            Self::Spawn { id: None, .. } | Self::Terrain { .. } => return,
+            Self::Delete { id, .. }
+            | Self::Move { id, .. }
+            | Self::Rotate { id, .. }
+            | Self::Recolor { id, .. } => id,
+        };
+        if *id == respawned.old {
+            *id = respawned.new;
+        }
+    }
+}
+
+fn finite(v: Vec3) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+fn finite_color(color: Color) -> bool {
+    [color.r, color.g, color.b, color.a]
+        .iter()
+        .all(|v| v.is_finite())
+}
+
+/// pulls a finite `position` back inside `WORLD_BOUND`
+fn bounded(position: Vec3) -> Vec3 {
+    let clamp = |v: f32| v.clamp(-WORLD_BOUND, WORLD_BOUND);
+    Vec3::new(clamp(position.x), clamp(position.y), clamp(position.z))
+}
+
+/// `None` for a rotation there's no telling the meaning of
+fn unit(rotation: Quaternion) -> Option<Quaternion> {
+    let magnitude = rotation.magnitude();
+    if !magnitude.is_normal() || !finite(rotation.vector) || !rotation.scalar.is_finite() {
+        return None;
+    }
+
+    Some((1. / magnitude) * rotation)
+}
+
+fn editable(world: &World, id: EntityId) -> Result<(), CommandError> {
+    if !world.is_alive(id) {
+        return Err(CommandError::NoSuchEntity(id));
+    }
+    if world.has::<Look>(id) {
+        return Err(CommandError::Locked(id));
+    }
+
+    Ok(())
+}
+
+fn detached(world: &World, id: EntityId) -> Result<(), CommandError> {
+    if hierarchy::parent(world, id).is_some() || !hierarchy::children(world, id).is_empty() {
+        return Err(CommandError::Attached(id));
+    }
+
+    Ok(())
+}
+
+fn transform(world: &World, id: EntityId) -> Result<Transform, CommandError> {
+    editable(world, id)?;
+    detached(world, id)?;
+    world
+        .get::<Transform>(id)
+        .copied()
+        .ok_or(CommandError::Missing(id, "transform"))
+}
+
+/// What an undo or redo did, so whoever owns the world can catch up
+#[derive(Debug, Default)]
+pub struct Applied {
+    pub terrain_changed: bool,
+    pub respawned: Vec<Respawned>,
+}
+
+impl Applied {
+    fn add(&mut self, command: &Command, respawned: Option<Respawned>) {
+        self.terrain_changed |= command.changes_terrain();
+        self.respawned.extend(respawned);
+    }
+}
+
+/// Executed commands which can be undone and redone, a step at a time. Everything applied
+/// between `begin_group` and `end_group` is a single step, which is how a drag made of many
+/// small moves comes back with one undo.
+#[derive(Debug)]
+pub struct History {
+    undo: VecDeque<Vec<Command>>,
+    redo: Vec<Vec<Command>>,
+    capacity: usize,
+    group: Option<Vec<Command>>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LEN)
+    }
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            capacity: capacity.max(1),
+            group: None,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.group.as_ref().is_some_and(|group| !group.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// whether `undo` would change the terrain, so it can be held back before it does
+    pub fn undo_changes_terrain(&self) -> bool {
+        let step = match self.group.as_ref() {
+            Some(group) if !group.is_empty() => Some(group),
+            _ => self.undo.back(),
+        };
+        step.is_some_and(|step| step.iter().any(Command::changes_terrain))
+    }
+
+    /// like `undo_changes_terrain` for `redo`
+    pub fn redo_changes_terrain(&self) -> bool {
+        self.redo
+            .last()
+            .is_some_and(|step| step.iter().any(Command::changes_terrain))
+    }
+
+    /// Executes `command` and keeps it to be undone, anything which could have been redone is
+    /// gone after this
+    pub fn apply(
+        &mut self,
+        scene: &mut Scene,
+        mut command: Command,
+    ) -> Result<Applied, CommandError> {
+        let respawned = command.execute(scene)?;
+        let mut applied = Applied::default();
+        applied.add(&command, respawned);
+        self.redo.clear();
+
+        match self.group.as_mut() {
+            Some(group) => {
+                // dragging several entities at once interleaves their moves, each one still
+                // merges into its own as long as nothing was spawned or deleted since
+                let merged = group
+                    .iter_mut()
+                    .rev()
+                    .take_while(|earlier| !earlier.respawns())
+                    .any(|earlier| earlier.merge(&command));
+                if !merged {
+                    group.push(command);
+                }
+            }
+            None => self.push_step(vec![command]),
+        }
+
+        Ok(applied)
+    }
+
+    /// starts a step which everything applied until `end_group` is part of
+    pub fn begin_group(&mut self) {
+        self.end_group();
+        self.group = Some(vec![]);
+    }
+
+    pub fn end_group(&mut self) {
+        if let Some(group) = self.group.take() {
+            if !group.is_empty() {
+                self.push_step(group);
+            }
+        }
+    }
+
+    fn push_step(&mut self, step: Vec<Command>) {
+        self.undo.push_back(step);
+        while self.undo.len() > self.capacity {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Undoes the last step, `None` when there's nothing to undo. A step which fails halfway,
+    /// because another editor deleted what it changed for one, is dropped with whatever was
+    /// undone before the failure left undone. That part still comes back with the error, the
+    /// world has changed either way.
+    pub fn undo(&mut self, scene: &mut Scene) -> Option<(Applied, Option<CommandError>)> {
+        self.end_group();
+        let mut step = self.undo.pop_back()?;
+
+        let mut applied = Applied::default();
+        for i in (0..step.len()).rev() {
+            match step[i].undo(scene) {
+                Ok(respawned) => {
+                    applied.add(&step[i], respawned);
+                    if let Some(respawned) = respawned {
+                        self.remap(respawned);
+                        step.iter_mut().for_each(|command| command.remap(respawned));
+                    }
+                }
+                Err(e) => return Some((applied, Some(e))),
+            }
+        }
+        self.redo.push(step);
+
+        Some((applied, None))
+    }
+
+    /// redoes the last undone step, failures are handled the same as in `undo`
+    pub fn redo(&mut self, scene: &mut Scene) -> Option<(Applied, Option<CommandError>)> {
+        self.end_group();
+        let mut step = self.redo.pop()?;
+
+        let mut applied = Applied::default();
+        for i in 0..step.len() {
+            match step[i].execute(scene) {
+                Ok(respawned) => {
+                    applied.add(&step[i], respawned);
+                    if let Some(respawned) = respawned {
+                        self.remap(respawned);
+                        step.iter_mut().for_each(|command| command.remap(respawned));
+                    }
+                }
+                Err(e) => return Some((applied, Some(e))),
+            }
+        }
+        self.push_step(step);
+
+        Some((applied, None))
+    }
+
+    /// points every command at `respawned.new` instead of its old id, including commands in
+    /// other editors' histories when they share a world
+    pub fn remap(&mut self, respawned: Respawned) {
+        self.undo
+            .iter_mut()
+            .chain(self.redo.iter_mut())
+            .chain(self.group.iter_mut())
+            .flatten()
+            .for_each(|command| command.remap(respawned));
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum EditError {
+    #[error("unknown {what} tag {tag} in edit")]
+    UnknownTag { what: &'static str, tag: u8 },
+    #[error(transparent)]
+    Message(#[from] MessageError),
+}
+
+/// How an `EditRequest` goes over the wire, plain integers so nothing pulled off a message can
+/// be an invalid enum. Whatever `command` carries besides `id` is pushed before it, the `from`
+/// side of a command never goes out since it's filled in when it's executed.
+#[derive(Clone, Copy, Debug)]
+struct EditHeader {
+    id: EntityId,
+    request: u8,
+    command: u8,
+}
+
+/// what a client asks to spawn, a cube unless `shape` is one of the primitive tags
+#[derive(Clone, Copy, Debug)]
+struct SpawnBody {
+    transform: Transform,
+    color: Color,
+    size: f32,
+    /// the primitive's radius, height, tube radius or size, in the order its variant has them
+    shape_sizes: [f32; 2],
+    /// the primitive's segments, rings, subdivisions or sides, in the order its variant has them
+    shape_details: [u32; 2],
+    light_color: Color,
+    light_radians: f32,
+    light_axis: Vec3,
+    vertex_kind: u8,
+    topology: u8,
+    polygon_mode: u8,
+    shape: u8,
+    has_light: u8,
+    light_rotating: u8,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TerrainBody {
+    seed: i64,
+    size: u32,
+    roughness: f32,
+    octaves: i32,
+    amplitude: f32,
+    height: f32,
+    scale: f32,
+    color_spread: f32,
+    palette_len: u32,
+    palette: [Color; MAX_PALETTE_COLORS],
+    clamped: u8,
+}
+
+const APPLY: u8 = 0;
+const UNDO: u8 = 1;
+const REDO: u8 = 2;
+const BEGIN_GROUP: u8 = 3;
+const END_GROUP: u8 = 4;
+
+const SPAWN: u8 = 0;
+const DELETE: u8 = 1;
+const MOVE: u8 = 2;
+const ROTATE: u8 = 3;
+const RECOLOR: u8 = 4;
+const TERRAIN: u8 = 5;
+
+/// Packs `request` into a `GameMessage::Edit`
+pub fn edit_message(request: &EditRequest) -> Message<GameMessage> {
+    let mut msg = Message::new(GameMessage::Edit);
+    let (request, command, id) = match *request {
+        EditRequest::Apply(command) => {
+            let (tag, id) = push_command(&mut msg, &command);
+            (APPLY, tag, id)
+        }
+        EditRequest::Undo => (UNDO, 0, EntityId::default()),
+        EditRequest::Redo => (REDO, 0, EntityId::default()),
+        EditRequest::BeginGroup => (BEGIN_GROUP, 0, EntityId::default()),
+        EditRequest::EndGroup => (END_GROUP, 0, EntityId::default()),
+    };
+    msg.push(EditHeader {
+        id,
+        request,
+        command,
+    });
+
+    msg
+}
+
+/// Inverse of `edit_message`, every tag is checked since it came off the network. Whether the
+/// edit makes sense is left to `Command::execute`.
+pub fn pull_edit(msg: &mut Message<GameMessage>) -> Result<EditRequest, EditError> {
+    let header: EditHeader = msg.pull()?;
+
+    let request = match header.request {
+        APPLY => EditRequest::Apply(pull_command(msg, &header)?),
+        UNDO => EditRequest::Undo,
+        REDO => EditRequest::Redo,
+        BEGIN_GROUP => EditRequest::BeginGroup,
+        END_GROUP => EditRequest::EndGroup,
+        tag => {
+            return Err(EditError::UnknownTag {
+                what: "request",
+                tag,
+            })
+        }
+    };
+
+    Ok(request)
+}
+
+fn push_command(msg: &mut Message<GameMessage>, command: &Command) -> (u8, EntityId) {
+    match *command {
+        Command::Spawn { entity, .. } => {
+            msg.push(spawn_body(&entity));
+            (SPAWN, EntityId::default())
+        }
+        Command::Delete { id, .. } => (DELETE, id),
+        Command::Move { id, to, .. } => {
+            msg.push(to);
+            (MOVE, id)
+        }
+        Command::Rotate { id, to, .. } => {
+            msg.push(to);
+            (ROTATE, id)
+        }
+        Command::Recolor { id, to, .. } => {
+            msg.push(to);
+            (RECOLOR, id)
+        }
+        Command::Terrain { to, .. } => {
+            msg.push(TerrainBody {
+                seed: to.seed as i64,
+                size: to.size,
+                roughness: to.roughness,
+                octaves: to.octaves,
+                amplitude: to.amplitude,
+                height: to.height,
+                scale: to.scale,
+                color_spread: to.color_spread,
+                palette_len: to.palette_len,
+                palette: to.palette,
+                clamped: to.clamped as u8,
+            });
+            (TERRAIN, EntityId::default())
+        }
+    }
+}
+
+fn pull_command(msg: &mut Message<GameMessage>, header: &EditHeader) -> Result<Command, EditError> {
+    let id = header.id;
+    let command = match header.command {
+        SPAWN => Command::spawn(spawned(&msg.pull()?)?),
+        DELETE => Command::delete(id),
+        MOVE => Command::move_to(id, msg.pull()?),
+        ROTATE => Command::rotate_to(id, msg.pull()?),
+        RECOLOR => Command::recolor(id, msg.pull()?),
+        TERRAIN => {
+            let body: TerrainBody = msg.pull()?;
+            Command::terrain(TerrainParams {
+                seed: body.seed as isize,
+                size: body.size,
+                clamped: body.clamped != 0,
+                roughness: body.roughness,
+                octaves: body.octaves,
+                amplitude: body.amplitude,
+                height: body.height,
+                scale: body.scale,
+                color_spread: body.color_spread,
+                palette_len: body.palette_len,
+                palette: body.palette,
+            })
+        }
+        tag => {
+            return Err(EditError::UnknownTag {
+                what: "command",
+                tag,
+            })
+        }
+    };
+
+    Ok(command)
+}
+
+fn spawn_body(entity: &Replicated) -> SpawnBody {
+    let mesh = &entity.mesh;
+    let (shape, shape_sizes, shape_details) = match mesh.primitive {
+        None => (0, [0.; 2], [0; 2]),
+        Some(PrimitiveShape::UvSphere {
+            radius,
+            segments,
+            rings,
+        }) => (1, [radius, 0.], [segments, rings]),
+        Some(PrimitiveShape::Icosphere {
+            radius,
+            subdivisions,
+        }) => (2, [radius, 0.], [subdivisions, 0]),
+        Some(PrimitiveShape::Cylinder {
+            radius,
+            height,
+            segments,
+        }) => (3, [radius, height], [segments, 0]),
+        Some(PrimitiveShape::Cone {
+            radius,
+            height,
+            segments,
+        }) => (4, [radius, height], [segments, 0]),
+        Some(PrimitiveShape::Capsule {
+            radius,
+            height,
+            segments,
+            rings,
+        }) => (5, [radius, height], [segments, rings]),
+        Some(PrimitiveShape::Torus {
+            radius,
+            tube_radius,
+            segments,
+            sides,
+        }) => (6, [radius, tube_radius], [segments, sides]),
+        Some(PrimitiveShape::Plane { size, subdivisions }) => (7, [size, 0.], [subdivisions, 0]),
+    };
+    let (topology, mode) = match mesh.topology {
+        Topology::PointList(mode) => (0, mode),
+        Topology::LineList(mode) => (1, mode),
+        Topology::LineStrip(mode) => (2, mode),
+        Topology::TriangleList(mode) => (3, mode),
+        Topology::TriangleStrip(mode) => (4, mode),
+    };
+    let light = entity.light.unwrap_or(Light {
+        color: Color::floats(0., 0., 0.),
+        radians: 0.,
+        rotating: false,
+        rotation_axis: Vec3::new_from_one(0),
+    });
+
+    SpawnBody {
+        transform: entity.transform,
+        color: mesh.color,
+        size: mesh.size,
+        shape_sizes,
+        shape_details,
+        light_color: light.color,
+        light_radians: light.radians,
+        light_axis: light.rotation_axis,
+        vertex_kind: match mesh.vertex_kind {
+            VertexKind::Basic => 0,
+            VertexKind::Shaded => 1,
+            VertexKind::Textured => 2,
+        },
+        topology,
+        polygon_mode: match mode {
+            PolygonMode::Fill => 0,
+            PolygonMode::Line => 1,
+            PolygonMode::Point => 2,
+        },
+        shape,
+        has_light: entity.light.is_some() as u8,
+        light_rotating: light.rotating as u8,
+    }
+}
+
+/// Spawned entities never come with a `Look` or `Velocity`, players can't be spawned by
+/// clients
+fn spawned(body: &SpawnBody) -> Result<Replicated, EditError> {
+    let unknown = |what, tag| EditError::UnknownTag { what, tag };
+    let [a, b] = body.shape_sizes;
+    let [first, second] = body.shape_details;
+    let primitive = match body.shape {
+        0 => None,
+        1 => Some(PrimitiveShape::UvSphere {
+            radius: a,
+            segments: first,
+            rings: second,
+        }),
+        2 => Some(PrimitiveShape::Icosphere {
+            radius: a,
+            subdivisions: first,
+        }),
+        3 => Some(PrimitiveShape::Cylinder {
+            radius: a,
+            height: b,
+            segments: first,
+        }),
+        4 => Some(PrimitiveShape::Cone {
+            radius: a,
+            height: b,
+            segments: first,
+        }),
+        5 => Some(PrimitiveShape::Capsule {
+            radius: a,
+            height: b,
+            segments: first,
+            rings: second,
+        }),
+        6 => Some(PrimitiveShape::Torus {
+            radius: a,
+            tube_radius: b,
+            segments: first,
+            sides: second,
+        }),
+        7 => Some(PrimitiveShape::Plane {
+            size: a,
+            subdivisions: first,
+        }),
+        tag => return Err(unknown("shape", tag)),
+    };
+    let mode = match body.polygon_mode {
+        0 => PolygonMode::Fill,
+        1 => PolygonMode::Line,
+        2 => PolygonMode::Point,
+        tag => return Err(unknown("polygon mode", tag)),
+    };
+    let topology = match body.topology {
+        0 => Topology::PointList(mode),
+        1 => Topology::LineList(mode),
+        2 => Topology::LineStrip(mode),
+        3 => Topology::TriangleList(mode),
+        4 => Topology::TriangleStrip(mode),
+        tag => return Err(unknown("topology", tag)),
+    };
+    let vertex_kind = match body.vertex_kind {
+        0 => VertexKind::Basic,
+        1 => VertexKind::Shaded,
+        2 => VertexKind::Textured,
+        tag => return Err(unknown("vertex kind", tag)),
+    };
+
+    Ok(Replicated {
+        light: (body.has_light != 0).then_some(Light {
+            color: body.light_color,
+            radians: body.light_radians,
+            rotating: body.light_rotating != 0,
+            rotation_axis: body.light_axis,
+        }),
+        ..Replicated::new(
+            body.transform,
+            Mesh {
+                size: body.size,
+                color: body.color,
+                vertex_kind,
+                topology,
+                primitive,
+            },
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::MAX_TERRAIN_SIZE;
+    use crate::ecs::components::{Collider, Networked, Velocity};
+
+    fn cube(position: Vec3) -> Replicated {
+        Replicated::new(
+            Transform::new(position),
+            Mesh::cube(1., Color::new(60, 60, 60)),
+        )
+    }
+
+    fn position(world: &World, id: EntityId) -> Vec3 {
+        world.get::<Transform>(id).unwrap().position
+    }
+
+    /// an undo or redo which went through without failing
+    fn step(result: Option<(Applied, Option<CommandError>)>) -> Applied {
+        let (applied, error) = result.unwrap();
+        assert_eq!(error, None);
+        applied
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut world = World::new();
+        let mut terrain = TerrainParams::new(0, 10);
+        let mut history = History::default();
+        let mut scene = Scene {
+            world: &mut world,
+            terrain: &mut terrain,
+        };
+
+        history
+            .apply(&mut scene, Command::spawn(cube(Vec3::new(1, 2, 3))))
+            .unwrap();
+        let id = scene.world.query::<Networked>().next().unwrap().0;
+        history
+            .apply(&mut scene, Command::move_to(id, Vec3::new(5, 5, 5)))
+            .unwrap();
+        history
+            .apply(&mut scene, Command::recolor(id, Color::new(255, 0, 0)))
+            .unwrap();
+        assert_eq!(position(scene.world, id), Vec3::new(5, 5, 5));
+
+        step(history.undo(&mut scene));
+        assert_eq!(
+            scene.world.get::<Mesh>(id).unwrap().color.g,
+            Color::new(60, 60, 60).g
+        );
+        step(history.undo(&mut scene));
+        assert_eq!(position(scene.world, id), Vec3::new(1, 2, 3));
+        step(history.undo(&mut scene));
+        assert!(!scene.world.is_alive(id));
+        assert!(history.undo(&mut scene).is_none());
+
+        // the spawn comes back under a new id, which the moves after it follow
+        let applied = step(history.redo(&mut scene));
+        let respawned = applied.respawned[0];
+        assert_eq!(respawned.old, id);
+        step(history.redo(&mut scene));
+        assert_eq!(position(scene.world, respawned.new), Vec3::new(5, 5, 5));
+
+        // a new edit throws away what could have been redone
+        history
+            .apply(
+                &mut scene,
+                Command::move_to(respawned.new, Vec3::new(0, 0, 0)),
+            )
+            .unwrap();
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn drags_are_one_step() {
+        let mut world = World::new();
+        let mut terrain = TerrainParams::new(0, 10);
+        let mut history = History::new(2);
+        let mut scene = Scene {
+            world: &mut world,
+            terrain: &mut terrain,
+        };
+        history
+            .apply(&mut scene, Command::spawn(cube(Vec3::new(0, 0, 0))))
+            .unwrap();
+        let id = scene.world.query::<Networked>().next().unwrap().0;
+
+        history.begin_group();
+        for x in 1..=10 {
+            history
+                .apply(&mut scene, Command::move_to(id, Vec3::new(x, 0, 0)))
+                .unwrap();
+        }
+        history.end_group();
+        step(history.undo(&mut scene));
+        assert_eq!(position(scene.world, id), Vec3::new(0, 0, 0));
+
+        // only 2 steps are kept, the spawn is forgotten
+        step(history.redo(&mut scene));
+        history
+            .apply(
+                &mut scene,
+                Command::rotate_to(id, Quaternion::rotation(1., (0, 1, 0).into())),
+            )
+            .unwrap();
+        step(history.undo(&mut scene));
+        step(history.undo(&mut scene));
+        assert!(history.undo(&mut scene).is_none());
+        assert!(scene.world.is_alive(id));
+
+        // two entities dragged together
+        let other = scene.world.spawn();
+        scene
+            .world
+            .insert(other, Transform::new(Vec3::new(0, 5, 0)));
+        history.begin_group();
+        for x in 1..=10 {
+            for (id, y) in [(id, 0), (other, 5)] {
+                history
+                    .apply(&mut scene, Command::move_to(id, Vec3::new(x, y, 0)))
+                    .unwrap();
+            }
+        }
+        history.end_group();
+        assert_eq!(history.undo.back().unwrap().len(), 2);
+        step(history.undo(&mut scene));
+        assert_eq!(position(scene.world, other), Vec3::new(0, 5, 0));
+    }
+
+    #[test]
+    fn deleted_entities_come_back() {
+        let mut world = World::new();
+        let mut terrain = TerrainParams::new(0, 10);
+        let mut history = History::default();
+        let sun = world
+            .build()
+            .with(Transform::new(Vec3::new(0, 10, 0)))
+            .with(Mesh::cube(5., Color::new(255, 250, 209)))
+            .with(Light {
+                color: Color::new(255, 250, 209),
+                radians: 0.,
+                rotating: false,
+                rotation_axis: (0, 1, 0).into(),
+            })
+            .with(Networked)
+            .id();
+        let player = world
+            .build()
+            .with(Transform::default())
+            .with(Look::default())
+            .id();
+        let mut scene = Scene {
+            world: &mut world,
+            terrain: &mut terrain,
+        };
+
+        assert_eq!(
+            history
+                .apply(&mut scene, Command::delete(player))
+                .unwrap_err(),
+            CommandError::Locked(player)
+        );
+        history.apply(&mut scene, Command::delete(sun)).unwrap();
+        assert!(!scene.world.is_alive(sun));
+
+        let applied = step(history.undo(&mut scene));
+        let sun = applied.respawned[0].new;
+        assert!(scene.world.has::<Light>(sun));
+        assert_eq!(position(scene.world, sun), Vec3::new(0, 10, 0));
+    }
+
+    #[test]
+    fn spawns_primitives() {
+        let mut world = World::new();
+        let mut terrain = TerrainParams::new(0, 10);
+        let mut history = History::default();
+        let mut scene = Scene {
+            world: &mut world,
+            terrain: &mut terrain,
+        };
+        let primitive = |shape| {
+            Replicated::new(
+                Transform::new(Vec3::new(0, 1, 0)),
+                Mesh::primitive(shape, Color::new(60, 60, 60)),
+            )
+        };
+
+        let too_fine = PrimitiveShape::UvSphere {
+            radius: 1.,
+            segments: 100_000,
+            rings: 8,
+        };
+        assert_eq!(
+            history
+                .apply(&mut scene, Command::spawn(primitive(too_fine)))
+                .unwrap_err(),
+            CommandError::Invalid("shape")
+        );
+
+        let cone = PrimitiveShape::from_name("cone", 2.).unwrap();
+        history
+            .apply(&mut scene, Command::spawn(primitive(cone)))
+            .unwrap();
+        let id = scene.world.query::<Networked>().next().unwrap().0;
+        assert_eq!(scene.world.get::<Collider>(id), Some(&cone.collider()));
+        history.apply(&mut scene, Command::delete(id)).unwrap();
+
+        let applied = step(history.undo(&mut scene));
+        let id = applied.respawned[0].new;
+        assert_eq!(scene.world.get::<Mesh>(id).unwrap().primitive, Some(cone));
+    }
+
+    #[test]
+    fn rejects_broken_fields() {
+        let mut world = World::new();
+        let mut terrain = TerrainParams::new(0, 10);
+        let mut history = History::default();
+        let mut scene = Scene {
+            world: &mut world,
+            terrain: &mut terrain,
+        };
+        type Spoil = fn(&mut Replicated);
+        let spoiled: [(Spoil, &str); 5] = [
+            (
+                |entity| entity.transform.rotation.scalar = f32::NAN,
+                "rotation",
+            ),
+            (|entity| entity.transform.scale.y = f32::INFINITY, "scale"),
+            (|entity| entity.transform.scale.x = -1., "scale"),
+            (|entity| entity.mesh.color.r = f32::NAN, "color"),
+            (
+                |entity| entity.velocity = Some(Velocity(Vec3::new(f32::NAN, 0, 0))),
+                "velocity",
+            ),
+        ];
+        for (spoil, field) in spoiled {
+            let mut entity = cube(Vec3::new(0, 0, 0));
+            spoil(&mut entity);
+            assert_eq!(
+                history
+                    .apply(&mut scene, Command::spawn(entity))
+                    .unwrap_err(),
+                CommandError::Invalid(field)
+            );
+        }
+        assert!(scene.world.is_empty());
+
+        // far away is pulled back in and rotations come out unit length
+        let mut entity = cube(Vec3::new(1e30, 0, -1e30));
+        entity.transform.rotation = 3. * Quaternion::rotation(0.5, Vec3::new(0, 1, 0));
+        history.apply(&mut scene, Command::spawn(entity)).unwrap();
+        let id = scene.world.query::<Networked>().next().unwrap().0;
+        let transform = *scene.world.get::<Transform>(id).unwrap();
+        assert_eq!(transform.position, Vec3::new(WORLD_BOUND, 0, -WORLD_BOUND));
+        assert!((transform.rotation.magnitude() - 1.).abs() < 1e-5);
+
+        history
+            .apply(&mut scene, Command::move_to(id, Vec3::new(0, 5e20, 0)))
+            .unwrap();
+        assert_eq!(
+            scene.world.get::<Transform>(id).unwrap().position,
+            Vec3::new(0, WORLD_BOUND, 0)
+        );
+        assert_eq!(
+            history
+                .apply(
+                    &mut scene,
+                    Command::recolor(id, Color::floats(f32::NAN, 0., 0.))
+                )
+                .unwrap_err(),
+            CommandError::Invalid("color")
+        );
+    }
+
+    #[test]
+    fn terrain_edits() {
+        let mut world = World::new();
+        let mut terrain = TerrainParams::new(0, 10);
+        let mut history = History::default();
+        let mut scene = Scene {
+            world: &mut world,
+            terrain: &mut terrain,
+        };
+
+        let bad = TerrainParams::new(1, MAX_TERRAIN_SIZE + 1);
+        assert!(history.apply(&mut scene, Command::terrain(bad)).is_err());
+        let flat = TerrainParams {
+            scale: 0.,
+            ..TerrainParams::new(1, 10)
+        };
+        assert!(history.apply(&mut scene, Command::terrain(flat)).is_err());
+
+        // one color has nothing to blend with, it has to be turned away before it's generated
+        let mut single = TerrainParams::new(1, 10);
+        single.set_palette(&[Color::new(255, 0, 0)]);
+        let edit = pull_edit(&mut edit_message(&EditRequest::Apply(Command::terrain(
+            single,
+        ))));
+        let EditRequest::Apply(command) = edit.unwrap() else {
+            panic!("not an apply");
+        };
+        assert!(matches!(
+            history.apply(&mut scene, command),
+            Err(CommandError::Invalid("terrain"))
+        ));
+        assert_eq!(scene.terrain.seed, 0);
+        let applied = history
+            .apply(&mut scene, Command::terrain(TerrainParams::new(7, 10)))
+            .unwrap();
+        assert!(applied.terrain_changed);
+        assert_eq!(scene.terrain.seed, 7);
+        assert!(history.undo_changes_terrain() && !history.redo_changes_terrain());
+        assert!(step(history.undo(&mut scene)).terrain_changed);
+        assert_eq!(scene.terrain.seed, 0);
+        assert!(!history.undo_changes_terrain() && history.redo_changes_terrain());
+    }
+
+    #[test]
+    fn full_worlds_take_no_more() {
+        let mut world = World::new();
+        let mut terrain = TerrainParams::new(0, 10);
+        let mut history = History::default();
+        let mut scene = Scene {
+            world: &mut world,
+            terrain: &mut terrain,
+        };
+        history
+            .apply(&mut scene, Command::spawn(cube(Vec3::new(0, 0, 0))))
+            .unwrap();
+        let id = scene.world.query::<Networked>().next().unwrap().0;
+        history.apply(&mut scene, Command::delete(id)).unwrap();
+        while scene.world.len() < MAX_ENTITIES {
+            scene.world.spawn();
+        }
+
+        assert!(matches!(
+            history.apply(&mut scene, Command::spawn(cube(Vec3::new(0, 0, 0)))),
+            Err(CommandError::Full)
+        ));
+        // bringing back what was deleted spawns too
+        assert!(matches!(
+            history.undo(&mut scene),
+            Some((_, Some(CommandError::Full)))
+        ));
+    }
+
+    #[test]
+    fn hierarchies_are_left_alone() {
+        let mut world = World::new();
+        let mut terrain = TerrainParams::new(0, 10);
+        let mut history = History::default();
+        let mut scene = Scene {
+            world: &mut world,
+            terrain: &mut terrain,
+        };
+        history
+            .apply(&mut scene, Command::spawn(cube(Vec3::new(0, 0, 0))))
+            .unwrap();
+        history
+            .apply(&mut scene, Command::spawn(cube(Vec3::new(5, 0, 0))))
+            .unwrap();
+        let ids = scene
+            .world
+            .query::<Networked>()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        let (parent, child) = (ids[0], ids[1]);
+        assert!(hierarchy::set_parent(scene.world, child, Some(parent)));
+
+        for id in [parent, child] {
+            for command in [
+                Command::delete(id),
+                Command::move_to(id, Vec3::new(1, 0, 0)),
+                Command::rotate_to(id, Quaternion::rotation(0.5, Vec3::new(0, 1, 0))),
+            ] {
+                assert_eq!(
+                    history.apply(&mut scene, command).unwrap_err(),
+                    CommandError::Attached(id)
+                );
+            }
+        }
+        assert!(scene.world.is_alive(parent) && scene.world.is_alive(child));
+        history
+            .apply(&mut scene, Command::recolor(child, Color::new(1, 2, 3)))
+            .unwrap();
+    }
+
+    #[test]
+    fn failed_steps_keep_what_they_did() {
+        let mut world = World::new();
+        let mut terrain = TerrainParams::new(0, 10);
+        let mut history = History::default();
+        let mut scene = Scene {
+            world: &mut world,
+            terrain: &mut terrain,
+        };
+        history
+            .apply(&mut scene, Command::spawn(cube(Vec3::new(0, 0, 0))))
+            .unwrap();
+        history
+            .apply(&mut scene, Command::spawn(cube(Vec3::new(5, 0, 0))))
+            .unwrap();
+        let ids = scene
+            .world
+            .query::<Networked>()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        let (moved, deleted) = (ids[0], ids[1]);
+
+        history.begin_group();
+        history
+            .apply(&mut scene, Command::move_to(moved, Vec3::new(1, 0, 0)))
+            .unwrap();
+        history.apply(&mut scene, Command::delete(deleted)).unwrap();
+        history
+            .apply(&mut scene, Command::terrain(TerrainParams::new(7, 10)))
+            .unwrap();
+        history.end_group();
+
+        // someone else deletes the moved cube, the rest of the step is still undone first
+        hierarchy::despawn(scene.world, moved);
+        let (applied, error) = history.undo(&mut scene).unwrap();
+        assert_eq!(error, Some(CommandError::NoSuchEntity(moved)));
+        assert!(applied.terrain_changed);
+        assert_eq!(scene.terrain.seed, 0);
+        assert_eq!(applied.respawned.len(), 1);
+        assert_eq!(applied.respawned[0].old, deleted);
+        assert!(scene.world.is_alive(applied.respawned[0].new));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn edits_round_trip() -> Result<(), EditError> {
+        let id = EntityId::new(3, 1);
+        let torus = PrimitiveShape::from_name("torus", 2.).unwrap();
+        let sun = Replicated {
+            light: Some(Light {
+                color: Color::new(255, 250, 209),
+                radians: 0.5,
+                rotating: true,
+                rotation_axis: (0, 1, 0).into(),
+            }),
+            ..cube(Vec3::new(0, 10, 0))
+        };
+        let mut terrain = TerrainParams::new(-42, 64);
+        terrain.clamped = true;
+        let requests = [
+            EditRequest::Apply(Command::spawn(cube(Vec3::new(1, 2, 3)))),
+            EditRequest::Apply(Command::spawn(Replicated::new(
+                Transform::new(Vec3::new(0, 1, 0)),
+                Mesh::primitive(torus, Color::new(0, 0, 255)),
+            ))),
+            EditRequest::Apply(Command::spawn(sun)),
+            EditRequest::Apply(Command::delete(id)),
+            EditRequest::Apply(Command::move_to(id, Vec3::new(5, 0, 5))),
+            EditRequest::Apply(Command::rotate_to(
+                id,
+                Quaternion::rotation(1., (0, 1, 0).into()),
+            )),
+            EditRequest::Apply(Command::recolor(id, Color::new(1, 2, 3))),
+            EditRequest::Apply(Command::terrain(terrain)),
+            EditRequest::Undo,
+            EditRequest::Redo,
+            EditRequest::BeginGroup,
+            EditRequest::EndGroup,
+        ];
+
+        for request in requests {
+            let mut msg = edit_message(&request);
+            let pulled = pull_edit(&mut msg)?;
+            assert!(msg.body.is_empty());
+            // `Debug` covers every field, floats included
+            assert_eq!(format!("{:?}", pulled), format!("{:?}", request));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_edit_tags() {
+        let mut msg = Message::new(GameMessage::Edit);
+        msg.push(EditHeader {
+            id: EntityId::default(),
+            request: 9,
+            command: 0,
+        });
+        assert!(matches!(
+            pull_edit(&mut msg),
+            Err(EditError::UnknownTag {
+                what: "request",
+                tag: 9
+            })
+        ));
+
+        let mut msg = Message::new(GameMessage::Edit);
+        msg.push(EditHeader {
+            id: EntityId::default(),
+            request: APPLY,
+            command: 42,
+        });
+        assert!(matches!(
+            pull_edit(&mut msg),
+            Err(EditError::UnknownTag {
+                what: "command",
+                ..
+            })
+        ));
+
+        let mut body = spawn_body(&cube(Vec3::new(0, 0, 0)));
+        body.shape = 200;
+        let mut msg = Message::new(GameMessage::Edit);
+        msg.push(body);
+        msg.push(EditHeader {
+            id: EntityId::default(),
+            request: APPLY,
+            command: SPAWN,
+        });
+        assert!(matches!(
+            pull_edit(&mut msg),
+            Err(EditError::UnknownTag {
+                what: "shape",
+                tag: 200
+            })
+        ));
+
+        // a well formed spawn can still be one which can't be drawn
+        let mut world = World::new();
+        let mut terrain = TerrainParams::new(0, 10);
+        let mut scene = Scene {
+            world: &mut world,
+            terrain: &mut terrain,
+        };
+        let mut textured = cube(Vec3::new(0, 0, 0));
+        textured.mesh.vertex_kind = VertexKind::Textured;
+        assert_eq!(
+            Command::spawn(textured).execute(&mut scene).unwrap_err(),
+            CommandError::Invalid("vertex kind")
+        );
+    }
+}
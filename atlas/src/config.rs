@@ -0,0 +1,344 @@
+use crate::proc_gen::terrain::{TerrainParams, MAX_PALETTE_COLORS, MIN_PALETTE_COLORS};
+use pantheon::Color;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read config {path:?}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse config {path:?}: {message}")]
+    Parse { path: PathBuf, message: String },
+    #[error("config {path:?} should end in .toml or .ron")]
+    UnknownFormat { path: PathBuf },
+    #[error("{flag} expects a value")]
+    MissingValue { flag: String },
+    #[error("invalid value {value:?} for {flag}: {reason}")]
+    InvalidArgument {
+        flag: String,
+        value: String,
+        reason: String,
+    },
+    #[error("unknown argument {arg:?}, see --help")]
+    UnknownArgument { arg: String },
+    #[error("invalid {field}: {reason}")]
+    Invalid { field: &'static str, reason: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Ron,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Result<Self, ConfigError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Ok(Self::Toml),
+            Some("ron") => Ok(Self::Ron),
+            _ => Err(ConfigError::UnknownFormat {
+                path: path.to_path_buf(),
+            }),
+        }
+    }
+
+    /// `path` is only used to point the error at the right file
+    pub fn parse<T: DeserializeOwned>(self, text: &str, path: &Path) -> Result<T, ConfigError> {
+        let result = match self {
+            Self::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+            Self::Ron => ron::from_str(text).map_err(|e| e.to_string()),
+        };
+
+        result.map_err(|message| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+}
+
+/// the format is picked from the file's extension
+pub fn load_file<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, ConfigError> {
+    let path = path.as_ref();
+    let format = ConfigFormat::from_path(path)?;
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    format.parse(&text, path)
+}
+
+/// Splits command line arguments into `--flag value` pairs, every flag takes exactly one value.
+/// Checking the flags themselves is left to each binary.
+pub fn flag_pairs(
+    args: impl Iterator<Item = String>,
+) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut pairs = vec![];
+    let mut args = args.peekable();
+    while let Some(flag) = args.next() {
+        if !flag.starts_with("--") {
+            return Err(ConfigError::UnknownArgument { arg: flag });
+        }
+
+        match args.next_if(|value| !value.starts_with("--")) {
+            Some(value) => pairs.push((flag, value)),
+            None => return Err(ConfigError::MissingValue { flag }),
+        }
+    }
+
+    Ok(pairs)
+}
+
+pub fn parse_flag<T>(flag: &str, value: &str) -> Result<T, ConfigError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e: T::Err| ConfigError::InvalidArgument {
+            flag: flag.to_string(),
+            value: value.to_string(),
+            reason: e.to_string(),
+        })
+}
+
+pub fn wants_help(args: impl Iterator<Item = String>) -> bool {
+    args.into_iter().any(|arg| arg == "--help" || arg == "-h")
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// whether a message at `level` should be shown when running at `self`
+    pub fn allows(self, level: LogLevel) -> bool {
+        level <= self
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" => Ok(Self::Debug),
+            "trace" => Ok(Self::Trace),
+            _ => Err("expected one of error, warn, info, debug or trace".to_string()),
+        }
+    }
+}
+
+/// `#rrggbb`, the leading `#` is optional
+pub fn parse_hex_color(hex: &str) -> Result<Color, String> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{:?} is not a #rrggbb color", hex));
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+    Ok(Color::new(channel(0), channel(2), channel(4)))
+}
+
+/// The parts of the world that are picked at startup, the rest of `TerrainParams` stays at its
+/// defaults
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldConfig {
+    pub seed: isize,
+    pub terrain_size: u32,
+    /// how much the terrain, and so everything standing on it, is scaled up by
+    pub scale: f32,
+    /// `#rrggbb` colors, the built in palette is used when this is empty
+    pub palette: Vec<String>,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        let params = TerrainParams::default();
+        Self {
+            seed: params.seed,
+            terrain_size: params.size,
+            scale: params.scale,
+            palette: vec![],
+        }
+    }
+}
+
+/// big enough for anything the renderer can keep up with
+pub const MAX_TERRAIN_SIZE: u32 = 2048;
+
+impl WorldConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.terrain_size < 2 || self.terrain_size > MAX_TERRAIN_SIZE {
+            return Err(ConfigError::Invalid {
+                field: "world.terrain_size",
+                reason: format!(
+                    "{} is outside of 2..={}",
+                    self.terrain_size, MAX_TERRAIN_SIZE
+                ),
+            });
+        }
+
+        if !self.scale.is_normal() || self.scale < 0. {
+            return Err(ConfigError::Invalid {
+                field: "world.scale",
+                reason: format!("{} has to be a positive number", self.scale),
+            });
+        }
+
+        if self.palette.len() > MAX_PALETTE_COLORS {
+            return Err(ConfigError::Invalid {
+                field: "world.palette",
+                reason: format!(
+                    "{} colors were given but at most {} are supported",
+                    self.palette.len(),
+                    MAX_PALETTE_COLORS
+                ),
+            });
+        }
+
+        // an empty palette means the built in one
+        if !self.palette.is_empty() && self.palette.len() < MIN_PALETTE_COLORS {
+            return Err(ConfigError::Invalid {
+                field: "world.palette",
+                reason: format!(
+                    "{} colors were given but at least {} are needed to blend between",
+                    self.palette.len(),
+                    MIN_PALETTE_COLORS
+                ),
+            });
+        }
+
+        self.palette_colors().map(|_| ())
+    }
+
+    pub fn palette_colors(&self) -> Result<Vec<Color>, ConfigError> {
+        self.palette
+            .iter()
+            .map(|hex| parse_hex_color(hex))
+            .collect::<Result<_, _>>()
+            .map_err(|reason| ConfigError::Invalid {
+                field: "world.palette",
+                reason,
+            })
+    }
+
+    pub fn terrain_params(&self) -> Result<TerrainParams, ConfigError> {
+        self.validate()?;
+
+        let mut params = TerrainParams::new(self.seed, self.terrain_size);
+        params.scale = self.scale;
+        if !self.palette.is_empty() {
+            params.set_palette(&self.palette_colors()?);
+        }
+
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn flags() {
+        let pairs = flag_pairs(args(&["--seed", "4", "--log-level", "debug"])).unwrap();
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[1], ("--log-level".to_string(), "debug".to_string()));
+
+        assert!(matches!(
+            flag_pairs(args(&["--seed"])),
+            Err(ConfigError::MissingValue { .. })
+        ));
+        assert!(matches!(
+            flag_pairs(args(&["--seed", "--bind", "x"])),
+            Err(ConfigError::MissingValue { .. })
+        ));
+        assert!(matches!(
+            flag_pairs(args(&["seed"])),
+            Err(ConfigError::UnknownArgument { .. })
+        ));
+        assert!(matches!(
+            parse_flag::<u32>("--seed", "abc"),
+            Err(ConfigError::InvalidArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn world_config_formats() {
+        let path = Path::new("world");
+        let toml: WorldConfig = ConfigFormat::Toml
+            .parse(
+                "seed = 7\nscale = 1.5\npalette = [\"#ff0000\", \"00ff00\"]",
+                path,
+            )
+            .unwrap();
+        let ron: WorldConfig = ConfigFormat::Ron
+            .parse(
+                "(seed: 7, scale: 1.5, palette: [\"#ff0000\", \"00ff00\"])",
+                path,
+            )
+            .unwrap();
+
+        for world in [toml, ron] {
+            let params = world.terrain_params().unwrap();
+            assert_eq!(params.seed, 7);
+            assert_eq!(params.size, TerrainParams::default().size);
+            assert_eq!(params.scale, 1.5);
+            assert_eq!(params.palette_len, 2);
+            assert_eq!(params.palette[1].g, 1.);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_world() {
+        let path = Path::new("world.toml");
+        assert!(matches!(
+            ConfigFormat::Toml.parse::<WorldConfig>("sed = 7", path),
+            Err(ConfigError::Parse { .. })
+        ));
+
+        let world = WorldConfig {
+            terrain_size: 1,
+            ..WorldConfig::default()
+        };
+        assert!(world.validate().is_err());
+
+        let world = WorldConfig {
+            scale: 0.,
+            ..WorldConfig::default()
+        };
+        assert!(world.validate().is_err());
+
+        let world = WorldConfig {
+            palette: vec!["#12345".to_string()],
+            ..WorldConfig::default()
+        };
+        assert!(world.validate().is_err());
+    }
+}
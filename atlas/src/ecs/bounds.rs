@@ -0,0 +1,154 @@
+use super::components::Collider;
+use super::{hierarchy, World};
+use crate::snapshot::EntityId;
+use crate::spatial::{Aabb, BoundingSphere, Bvh, Proxy};
+use pantheon::math::{Dim, Vec4};
+use pantheon::Vec3;
+use std::collections::HashMap;
+
+/// Every entity with a `Collider` in a `Bvh`, along with `Aabb` and `BoundingSphere`
+/// components for where they are in the world. `update` once things have moved and before
+/// asking where anything is.
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    bvh: Bvh<EntityId>,
+    proxies: HashMap<EntityId, Proxy>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bvh.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bvh.is_empty()
+    }
+
+    /// brings the bounds of every `Collider` up to date, dropping anything despawned
+    pub fn update(&mut self, world: &mut World) {
+        let gone: Vec<_> = self
+            .proxies
+            .keys()
+            .filter(|id| !world.has::<Collider>(**id))
+            .copied()
+            .collect();
+        for id in gone {
+            if let Some(proxy) = self.proxies.remove(&id) {
+                self.bvh.remove(proxy);
+            }
+        }
+
+        let colliders: Vec<_> = world
+            .query::<Collider>()
+            .map(|(id, collider)| (id, *collider))
+            .collect();
+        for (id, collider) in colliders {
+            let matrix = match hierarchy::update(world, id) {
+                Some(matrix) => matrix,
+                None => continue,
+            };
+            let aabb = collider.local_aabb().transformed(&matrix);
+            world.insert(id, aabb);
+            world.insert(id, BoundingSphere::from_aabb(&aabb));
+
+            match self.proxies.get(&id) {
+                Some(proxy) => {
+                    self.bvh.update(*proxy, aabb);
+                }
+                None => {
+                    self.proxies.insert(id, self.bvh.insert(aabb, id));
+                }
+            }
+        }
+    }
+
+    /// the closest entity the ray hits, tested against its `Collider` however it's turned, and
+    /// how far along the ray it is
+    pub fn ray(&self, world: &World, origin: Vec3, direction: Vec3) -> Option<(EntityId, f32)> {
+        self.bvh
+            .ray(origin, direction, |id| {
+                let collider = world.get::<Collider>(*id)?;
+                // into the collider's own space, distances along the ray stay the same
+                let inverse = hierarchy::world_matrix(world, *id)?.invert()?;
+                let origin = (inverse * Vec4::from_vec3(origin)).truncate(Dim::W);
+                let direction = (inverse * Vec4::new(direction.x, direction.y, direction.z, 0.))
+                    .truncate(Dim::W);
+
+                collider.ray(origin, direction)
+            })
+            .map(|(id, distance)| (*id, distance))
+    }
+
+    /// everything whose `Aabb` overlaps `aabb`
+    pub fn query_box(&self, aabb: &Aabb) -> Vec<EntityId> {
+        self.bvh.query_box(aabb).into_iter().copied().collect()
+    }
+
+    /// everything whose `Aabb` overlaps `sphere`
+    pub fn query_sphere(&self, sphere: &BoundingSphere) -> Vec<EntityId> {
+        self.bvh.query_sphere(sphere).into_iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecs::components::Transform;
+    use pantheon::math::Quaternion;
+
+    #[test]
+    fn finds_colliders() {
+        let mut world = World::new();
+        let mut index = SpatialIndex::new();
+        let near = world
+            .build()
+            .with(Transform::new(Vec3::new(5, 0, 0)))
+            .with(Collider::cube(2.))
+            .id();
+        // turned so its corner points back along the ray, which hits it sooner than a face
+        let far = world
+            .build()
+            .with(Transform {
+                rotation: Quaternion::rotation_from_degrees(45., (0, 1, 0).into()),
+                ..Transform::new(Vec3::new(20, 0, 0))
+            })
+            .with(Collider::cube(2.))
+            .id();
+        world.build().with(Transform::new(Vec3::new(5, 0, 0)));
+        index.update(&mut world);
+        assert_eq!(index.len(), 2);
+        assert!(world.has::<Aabb>(near) && world.has::<BoundingSphere>(far));
+
+        let x = Vec3::new(1, 0, 0);
+        assert_eq!(
+            index.ray(&world, Vec3::new_from_one(0), x),
+            Some((near, 4.))
+        );
+        let (id, distance) = index.ray(&world, Vec3::new(10, 0, 0), x).unwrap();
+        assert_eq!(id, far);
+        assert!((distance - (10. - 2f32.sqrt())).abs() < 1e-4);
+
+        let sphere = BoundingSphere::new(Vec3::new(15, 0, 0), 4.);
+        assert_eq!(index.query_sphere(&sphere), vec![far]);
+
+        // moved out of the way, then gone altogether
+        hierarchy::set_transform(&mut world, near, Transform::new(Vec3::new(5, 10, 0)));
+        index.update(&mut world);
+        assert_eq!(index.ray(&world, Vec3::new_from_one(0), x).unwrap().0, far);
+        world.despawn(far);
+        index.update(&mut world);
+        assert_eq!(index.len(), 1);
+        assert!(index.ray(&world, Vec3::new_from_one(0), x).is_none());
+        assert_eq!(
+            index.query_box(&Aabb::from_center(
+                Vec3::new(5, 10, 0),
+                Vec3::new_from_one(1)
+            )),
+            vec![near]
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use crate::entity::cube;
+use crate::entity::mesh::MeshData;
+use crate::entity::primitive::PrimitiveShape;
+use crate::snapshot::EntityId;
+use crate::spatial::{Aabb, BoundingSphere};
+use crate::vertex::VertexKind;
+use pantheon::math::Quaternion;
+use pantheon::{Color, Mat4, PolygonMode, Topology, Vec3, Vec4};
+
+/// Where an entity is, which way it's facing and how big it is, relative to its `Parent` if it
+/// has one. Changing one in place has to be followed by `hierarchy::mark_dirty` so the
+/// entity's `GlobalTransform` and those of its children are worked out again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub position: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new(Vec3::new_from_one(0))
+    }
+}
+
+impl Transform {
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            rotation: Quaternion::identity(),
+            scale: Vec3::new_from_one(1),
+        }
+    }
+
+    /// Splits a matrix made of a translation, rotation and scale back into them. Shear, which
+    /// nonuniform scales under a rotation can make, is lost.
+    pub fn from_matrix(matrix: &Mat4) -> Self {
+        let (x, y, z) = (matrix.x.vec3(), matrix.y.vec3(), matrix.z.vec3());
+        let mut scale = Vec3::new(x.magnitude(), y.magnitude(), z.magnitude());
+        // a mirrored matrix, flipping one axis is enough to make what's left a rotation
+        if x.cross(&y).dot(&z) < 0. {
+            scale.x = -scale.x;
+        }
+
+        let axis = |v: Vec3, s: f32| {
+            let v = if s == 0. { v } else { v * (1. / s) };
+            Vec4::new(v.x, v.y, v.z, 0)
+        };
+        let rotation = Mat4::new(
+            axis(x, scale.x),
+            axis(y, scale.y),
+            axis(z, scale.z),
+            Vec4::new(0, 0, 0, 1),
+        );
+
+        Self {
+            position: matrix.w.vec3(),
+            rotation: Quaternion::from_rotation_matrix(&rotation),
+            scale,
+        }
+    }
+
+    /// translation * rotation * scale
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::translation::<f32>(self.position.into())
+            * self.rotation.rotation_matrix()
+            * Mat4::scalar(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// The entity an entity is attached to, its `Transform` is relative to the parent's. Only ever
+/// changed through `hierarchy` so the parent's `Children` stay in step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parent(pub EntityId);
+
+/// everything attached to an entity, the other side of `Parent`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Children(pub Vec<EntityId>);
+
+/// An entity's `Transform` combined with all of its parents', worked out lazily by `hierarchy`
+/// and only trusted while it isn't `dirty`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlobalTransform {
+    pub matrix: Mat4,
+    pub dirty: bool,
+}
+
+/// What an entity is drawn as, a cube `size` across unless it has a `primitive` shape
+#[derive(Debug, Clone, Copy)]
+pub struct Mesh {
+    pub size: f32,
+    pub color: Color,
+    pub vertex_kind: VertexKind,
+    pub topology: Topology,
+    pub primitive: Option<PrimitiveShape>,
+}
+
+impl Mesh {
+    pub fn cube(size: f32, color: Color) -> Self {
+        Self {
+            size,
+            color,
+            vertex_kind: VertexKind::Shaded,
+            topology: Topology::TriangleList(PolygonMode::Fill),
+            primitive: None,
+        }
+    }
+
+    /// `size` is as far across as the shape's bounding sphere
+    pub fn primitive(primitive: PrimitiveShape, color: Color) -> Self {
+        Self {
+            primitive: Some(primitive),
+            ..Self::cube(2. * primitive.bounding_radius(), color)
+        }
+    }
+
+    /// the solid shape which goes with what's drawn
+    pub fn collider(&self) -> Collider {
+        match self.primitive {
+            Some(primitive) => primitive.collider(),
+            None => Collider::cube(self.size),
+        }
+    }
+
+    /// the triangles which are drawn, in the entity's own space
+    pub fn data(&self) -> MeshData {
+        if let Some(primitive) = self.primitive {
+            return primitive.mesh(self.color);
+        }
+
+        let normals = cube::cube_normals();
+        MeshData {
+            vertices: cube::get_cube_verts(self.size)
+                .iter()
+                .zip(normals)
+                .map(|(position, normal)| (*position, self.color, normal).into())
+                .collect(),
+            indices: cube::cube_indices().to_vec(),
+        }
+    }
+}
+
+/// The solid shape around the entity's position, rotating with it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Collider {
+    Box { half_extents: Vec3 },
+    Sphere { radius: f32 },
+}
+
+impl Collider {
+    pub fn cube(size: f32) -> Self {
+        Self::Box {
+            half_extents: Vec3::new_from_one(size / 2.),
+        }
+    }
+
+    pub fn sphere(radius: f32) -> Self {
+        Self::Sphere { radius }
+    }
+
+    /// the shape's bounds before it's moved to where the entity is
+    pub fn local_aabb(&self) -> Aabb {
+        match *self {
+            Self::Box { half_extents } => Aabb::from_center(Vec3::new_from_one(0), half_extents),
+            Self::Sphere { radius } => BoundingSphere::new(Vec3::new_from_one(0), radius).aabb(),
+        }
+    }
+
+    /// like `Aabb::ray`, with the ray already in the collider's own space
+    pub fn ray(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        match *self {
+            Self::Box { .. } => self.local_aabb().ray(origin, direction),
+            Self::Sphere { radius } => {
+                BoundingSphere::new(Vec3::new_from_one(0), radius).ray(origin, direction)
+            }
+        }
+    }
+}
+
+/// Lights up the world from the entity's position, only the sun has one
+#[derive(Debug, Clone, Copy)]
+pub struct Light {
+    pub color: Color,
+    pub radians: f32,
+    pub rotating: bool,
+    pub rotation_axis: Vec3,
+}
+
+/// units per second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Velocity(pub Vec3);
+
+/// Which way a player is looking, in radians. Only the yaw turns their body.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Look {
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// Sent to clients in snapshots, see `replicate`. Anything without it only exists wherever it
+/// was spawned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Networked;
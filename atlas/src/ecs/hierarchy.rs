@@ -0,0 +1,300 @@
+use super::components::{Children, GlobalTransform, Parent, Transform};
+use super::World;
+use crate::snapshot::EntityId;
+use pantheon::Mat4;
+
+/// The entity's world matrix, using cached `GlobalTransform`s where they aren't dirty. Nothing
+/// is cached, see `update` for that. `None` without a `Transform`.
+pub fn world_matrix(world: &World, id: EntityId) -> Option<Mat4> {
+    if let Some(global) = world.get::<GlobalTransform>(id) {
+        if !global.dirty {
+            return Some(global.matrix);
+        }
+    }
+
+    let local = world.get::<Transform>(id)?.matrix();
+    let parent = world
+        .get::<Parent>(id)
+        .and_then(|parent| world_matrix(world, parent.0));
+
+    Some(parent.map_or(local, |parent| parent * local))
+}
+
+/// Like `world_matrix`, but whatever had to be worked out is kept in `GlobalTransform`s for
+/// next time
+pub fn update(world: &mut World, id: EntityId) -> Option<Mat4> {
+    if let Some(global) = world.get::<GlobalTransform>(id) {
+        if !global.dirty {
+            return Some(global.matrix);
+        }
+    }
+
+    let local = world.get::<Transform>(id)?.matrix();
+    let matrix = match world.get::<Parent>(id).copied() {
+        Some(Parent(parent)) => update(world, parent).map_or(local, |parent| parent * local),
+        None => local,
+    };
+    world.insert(
+        id,
+        GlobalTransform {
+            matrix,
+            dirty: false,
+        },
+    );
+
+    Some(matrix)
+}
+
+/// brings every dirty `GlobalTransform` up to date, so reading them afterwards is free
+pub fn propagate(world: &mut World) {
+    let ids: Vec<_> = world.query::<Transform>().map(|(id, _)| id).collect();
+    for id in ids {
+        update(world, id);
+    }
+}
+
+/// The entity's transform in world space, its own `Transform` unless it has a parent
+pub fn world_transform(world: &World, id: EntityId) -> Option<Transform> {
+    if !world.has::<Parent>(id) {
+        return world.get::<Transform>(id).copied();
+    }
+
+    world_matrix(world, id).map(|matrix| Transform::from_matrix(&matrix))
+}
+
+/// Has the entity's world matrix and those of everything under it worked out again the next
+/// time they're asked for. Needed after changing a `Transform` in place.
+pub fn mark_dirty(world: &mut World, id: EntityId) {
+    if let Some(global) = world.get_mut::<GlobalTransform>(id) {
+        // everything under a dirty entity is already dirty, nothing below it can have been
+        // cached since
+        if global.dirty {
+            return;
+        }
+        global.dirty = true;
+    }
+
+    let children = match world.get::<Children>(id) {
+        Some(children) => children.0.clone(),
+        None => return,
+    };
+    for child in children {
+        mark_dirty(world, child);
+    }
+}
+
+/// replaces the entity's `Transform`, returns false for stale ids
+pub fn set_transform(world: &mut World, id: EntityId, transform: Transform) -> bool {
+    if !world.insert(id, transform) {
+        return false;
+    }
+    mark_dirty(world, id);
+
+    true
+}
+
+pub fn parent(world: &World, id: EntityId) -> Option<EntityId> {
+    world.get::<Parent>(id).map(|parent| parent.0)
+}
+
+pub fn children(world: &World, id: EntityId) -> &[EntityId] {
+    world
+        .get::<Children>(id)
+        .map_or(&[], |children| &children.0)
+}
+
+/// whether `ancestor` is somewhere above `id`
+pub fn is_ancestor(world: &World, ancestor: EntityId, id: EntityId) -> bool {
+    let mut next = parent(world, id);
+    while let Some(parent) = next {
+        if parent == ancestor {
+            return true;
+        }
+        next = self::parent(world, parent);
+    }
+
+    false
+}
+
+/// Attaches `child` to `parent`, or detaches it for `None`, keeping its `Transform` as it is so
+/// it moves to the same spot relative to its new parent. Returns false without changing
+/// anything for stale ids, or when `child` would end up its own ancestor.
+pub fn set_parent(world: &mut World, child: EntityId, parent: Option<EntityId>) -> bool {
+    if !world.is_alive(child) {
+        return false;
+    }
+    if let Some(parent) = parent {
+        if !world.is_alive(parent) || parent == child || is_ancestor(world, child, parent) {
+            return false;
+        }
+    }
+
+    if let Some(Parent(old)) = world.remove::<Parent>(child) {
+        unlink(world, old, child);
+    }
+    if let Some(parent) = parent {
+        world.insert(child, Parent(parent));
+        match world.get_mut::<Children>(parent) {
+            Some(children) => children.0.push(child),
+            None => {
+                world.insert(parent, Children(vec![child]));
+            }
+        }
+    }
+    mark_dirty(world, child);
+
+    true
+}
+
+/// Like `set_parent`, but the child's `Transform` is changed so it stays where it is in the
+/// world. Also false when the new parent's world matrix can't be inverted, a zero scale
+/// somewhere above it for one.
+pub fn reparent(world: &mut World, child: EntityId, parent: Option<EntityId>) -> bool {
+    let child_matrix = match update(world, child) {
+        Some(matrix) => matrix,
+        // nothing to keep in place
+        None => return set_parent(world, child, parent),
+    };
+    let local = match parent.and_then(|parent| update(world, parent)) {
+        Some(parent_matrix) => match parent_matrix.invert() {
+            Some(inverse) => inverse * child_matrix,
+            None => return false,
+        },
+        None => child_matrix,
+    };
+
+    if !set_parent(world, child, parent) {
+        return false;
+    }
+    world.insert(child, Transform::from_matrix(&local));
+
+    true
+}
+
+/// Despawns the entity along with everything attached to it, returns false for stale ids
+pub fn despawn(world: &mut World, id: EntityId) -> bool {
+    if !world.is_alive(id) {
+        return false;
+    }
+    if let Some(Parent(parent)) = world.get::<Parent>(id).copied() {
+        unlink(world, parent, id);
+    }
+
+    let mut stack = vec![id];
+    while let Some(next) = stack.pop() {
+        if let Some(Children(children)) = world.remove::<Children>(next) {
+            stack.extend(children);
+        }
+        world.despawn(next);
+    }
+
+    true
+}
+
+fn unlink(world: &mut World, parent: EntityId, child: EntityId) {
+    if let Some(children) = world.get_mut::<Children>(parent) {
+        children.0.retain(|id| *id != child);
+        if children.0.is_empty() {
+            world.remove::<Children>(parent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pantheon::math::Quaternion;
+    use pantheon::Vec3;
+
+    fn position(world: &World, id: EntityId) -> Vec3 {
+        world_transform(world, id).unwrap().position
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).magnitude() < 1e-4
+    }
+
+    #[test]
+    fn children_follow_their_parents() {
+        let mut world = World::new();
+        let platform = world.build().with(Transform::new(Vec3::new(10, 0, 0))).id();
+        let lamp = world.build().with(Transform::new(Vec3::new(0, 2, 0))).id();
+        assert!(set_parent(&mut world, lamp, Some(platform)));
+        assert_eq!(children(&world, platform), &[lamp]);
+        assert!(close(position(&world, lamp), Vec3::new(10, 2, 0)));
+
+        // cached now, moving the platform only shows once it's marked dirty
+        propagate(&mut world);
+        world.get_mut::<Transform>(platform).unwrap().position = Vec3::new(0, 0, 5);
+        assert!(close(position(&world, lamp), Vec3::new(10, 2, 0)));
+        mark_dirty(&mut world, platform);
+        assert!(close(position(&world, lamp), Vec3::new(0, 2, 5)));
+
+        // a quarter turn swings the lamp around with it
+        let turned = Transform {
+            rotation: Quaternion::rotation_from_degrees(90., (0, 0, 1).into()),
+            ..Transform::new(Vec3::new(0, 0, 5))
+        };
+        assert!(set_transform(&mut world, platform, turned));
+        assert!(close(
+            update(&mut world, lamp).unwrap().w.vec3(),
+            Vec3::new(-2, 0, 5)
+        ));
+        assert!(!world.get::<GlobalTransform>(lamp).unwrap().dirty);
+    }
+
+    #[test]
+    fn reparenting_keeps_world_position() {
+        let mut world = World::new();
+        let a = world
+            .build()
+            .with(Transform {
+                rotation: Quaternion::rotation_from_degrees(90., (0, 1, 0).into()),
+                scale: Vec3::new_from_one(2),
+                ..Transform::new(Vec3::new(5, 0, 0))
+            })
+            .id();
+        let b = world.build().with(Transform::new(Vec3::new(0, 3, 0))).id();
+        let cube = world.build().with(Transform::new(Vec3::new(1, 1, 1))).id();
+
+        assert!(reparent(&mut world, cube, Some(a)));
+        assert!(close(position(&world, cube), Vec3::new(1, 1, 1)));
+        let local = world.get::<Transform>(cube).unwrap();
+        assert!(close(local.scale, Vec3::new_from_one(0.5)));
+
+        assert!(reparent(&mut world, cube, Some(b)));
+        assert!(close(position(&world, cube), Vec3::new(1, 1, 1)));
+        assert!(children(&world, a).is_empty());
+        assert!(!world.has::<Children>(a));
+
+        assert!(reparent(&mut world, cube, None));
+        assert!(parent(&world, cube).is_none());
+        assert!(close(
+            world.get::<Transform>(cube).unwrap().position,
+            Vec3::new(1, 1, 1)
+        ));
+    }
+
+    #[test]
+    fn no_cycles() {
+        let mut world = World::new();
+        let a = world.build().with(Transform::default()).id();
+        let b = world.build().with(Transform::default()).id();
+        let c = world.build().with(Transform::default()).id();
+        assert!(set_parent(&mut world, b, Some(a)));
+        assert!(set_parent(&mut world, c, Some(b)));
+
+        assert!(!set_parent(&mut world, a, Some(c)));
+        assert!(!set_parent(&mut world, a, Some(a)));
+        assert!(is_ancestor(&world, a, c));
+        assert_eq!(parent(&world, a), None);
+
+        // the whole branch goes, and nothing is left pointing at it
+        let d = world.build().with(Transform::default()).id();
+        assert!(set_parent(&mut world, d, Some(a)));
+        assert!(despawn(&mut world, b));
+        assert!(!world.is_alive(b) && !world.is_alive(c));
+        assert_eq!(children(&world, a), &[d]);
+        assert!(!despawn(&mut world, c));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::entity::id::IdAllocator;
+use crate::snapshot::EntityId;
+use std::any::TypeId;
+use std::collections::HashMap;
+
+pub mod bounds;
+pub mod components;
+pub mod hierarchy;
+pub mod replicate;
+mod storage;
+pub mod systems;
+
+use storage::AnyStorage;
+pub use storage::SparseSet;
+
+/// Anything can be a component as long as it doesn't borrow
+pub trait Component: 'static {}
+
+impl<T: 'static> Component for T {}
+
+/// Entities as ids with any number of components attached, each type of component kept in its
+/// own `SparseSet`. New kinds of entity are new combinations of components, so the server,
+/// client and tools can add their own without touching `EntityKind`.
+#[derive(Default)]
+pub struct World {
+    ids: IdAllocator,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+}
+
+impl std::fmt::Debug for World {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("World")
+            .field("entities", &self.len())
+            .field("storages", &self.storages.len())
+            .finish()
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// an empty world which carries on handing out ids where `ids` left off, every live id in it
+    /// can be given components straight away
+    pub fn from_ids(ids: IdAllocator) -> Self {
+        Self {
+            ids,
+            storages: HashMap::new(),
+        }
+    }
+
+    pub fn ids(&self) -> &IdAllocator {
+        &self.ids
+    }
+
+    pub fn spawn(&mut self) -> EntityId {
+        self.ids.allocate()
+    }
+
+    /// spawns an entity and adds components to it in one go
+    pub fn build(&mut self) -> EntityBuilder<'_> {
+        let id = self.spawn();
+        EntityBuilder { world: self, id }
+    }
+
+    /// drops every component `id` had, returns false for ids which are already stale
+    pub fn despawn(&mut self, id: EntityId) -> bool {
+        if !self.ids.free(id) {
+            return false;
+        }
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(id);
+        }
+
+        true
+    }
+
+    pub fn is_alive(&self, id: EntityId) -> bool {
+        self.ids.is_alive(id)
+    }
+
+    /// live entities, whether or not they have any components
+    pub fn len(&self) -> usize {
+        self.ids.slots().iter().filter(|slot| slot.alive).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn storage<T: Component>(&self) -> Option<&SparseSet<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    /// created empty the first time a component of this type is asked for
+    pub fn storage_mut<T: Component>(&mut self) -> &mut SparseSet<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(SparseSet::<T>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+
+    /// replaces whatever `T` the entity already had, returns false for stale ids which are
+    /// ignored
+    pub fn insert<T: Component>(&mut self, id: EntityId, component: T) -> bool {
+        if !self.is_alive(id) {
+            return false;
+        }
+
+        self.storage_mut().insert(id, component);
+        true
+    }
+
+    pub fn remove<T: Component>(&mut self, id: EntityId) -> Option<T> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut::<SparseSet<T>>()?
+            .remove(id)
+    }
+
+    pub fn get<T: Component>(&self, id: EntityId) -> Option<&T> {
+        self.storage::<T>()?.get(id)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, id: EntityId) -> Option<&mut T> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut::<SparseSet<T>>()?
+            .get_mut(id)
+    }
+
+    pub fn has<T: Component>(&self, id: EntityId) -> bool {
+        self.storage::<T>()
+            .is_some_and(|storage| storage.contains(id))
+    }
+
+    /// every entity with a `T`
+    pub fn query<T: Component>(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.storage::<T>().into_iter().flat_map(SparseSet::iter)
+    }
+
+    pub fn query_mut<T: Component>(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut::<SparseSet<T>>())
+            .into_iter()
+            .flat_map(SparseSet::iter_mut)
+    }
+
+    /// every entity with both an `A` and a `B`
+    pub fn query2<A: Component, B: Component>(&self) -> impl Iterator<Item = (EntityId, &A, &B)> {
+        let a = self.storage::<A>();
+        let b = self.storage::<B>();
+
+        a.zip(b).into_iter().flat_map(|(a, b)| {
+            a.iter()
+                .filter_map(move |(id, a)| b.get(id).map(|b| (id, a, b)))
+        })
+    }
+
+    /// Like `query2` with the `A`s mutable, which is usually all a system needs to change.
+    /// `A` and `B` have to be different types.
+    pub fn query2_mut<A: Component, B: Component>(
+        &mut self,
+    ) -> impl Iterator<Item = (EntityId, &mut A, &B)> {
+        assert_ne!(
+            TypeId::of::<A>(),
+            TypeId::of::<B>(),
+            "query2_mut can't borrow the same storage twice"
+        );
+
+        let [a, b] = self
+            .storages
+            .get_disjoint_mut([&TypeId::of::<A>(), &TypeId::of::<B>()]);
+        let a = a.and_then(|a| a.as_any_mut().downcast_mut::<SparseSet<A>>());
+        let b = b.and_then(|b| b.as_any().downcast_ref::<SparseSet<B>>());
+
+        a.zip(b).into_iter().flat_map(|(a, b)| {
+            a.iter_mut()
+                .filter_map(move |(id, a)| b.get(id).map(|b| (id, a, b)))
+        })
+    }
+}
+
+/// Adds components to a freshly spawned entity, see `World::build`
+pub struct EntityBuilder<'w> {
+    world: &'w mut World,
+    id: EntityId,
+}
+
+impl<'w> EntityBuilder<'w> {
+    pub fn with<T: Component>(self, component: T) -> Self {
+        self.world.storage_mut().insert(self.id, component);
+        self
+    }
+
+    pub fn id(self) -> EntityId {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+    #[derive(Debug, PartialEq)]
+    struct Speed(i32);
+    struct Frozen;
+
+    #[test]
+    fn components_come_and_go() {
+        let mut world = World::new();
+        let a = world.build().with(Position(0)).with(Speed(2)).id();
+        let b = world.build().with(Position(10)).id();
+        assert_eq!(world.len(), 2);
+
+        assert_eq!(world.get::<Position>(b), Some(&Position(10)));
+        assert!(world.get::<Speed>(b).is_none());
+        assert!(world.insert(b, Speed(1)));
+        assert_eq!(world.remove::<Speed>(a), Some(Speed(2)));
+        assert!(!world.has::<Speed>(a));
+        assert!(world.get::<Frozen>(a).is_none());
+
+        assert!(world.despawn(a));
+        assert!(!world.despawn(a));
+        assert!(world.get::<Position>(a).is_none());
+        assert!(!world.insert(a, Frozen));
+
+        // the index is reused, none of the old entity's components come with it
+        let c = world.spawn();
+        assert_eq!(c.index, a.index);
+        assert!(world.get::<Position>(c).is_none());
+        assert_eq!(world.len(), 2);
+    }
+
+    #[test]
+    fn queries() {
+        let mut world = World::new();
+        assert_eq!(world.query::<Position>().count(), 0);
+        assert_eq!(world.query2_mut::<Position, Speed>().count(), 0);
+
+        let moving = world.build().with(Position(0)).with(Speed(3)).id();
+        let still = world.build().with(Position(5)).id();
+        world.build().with(Speed(7));
+
+        // a movement system
+        for (_, position, speed) in world.query2_mut::<Position, Speed>() {
+            position.0 += speed.0;
+        }
+        assert_eq!(world.get::<Position>(moving), Some(&Position(3)));
+        assert_eq!(world.get::<Position>(still), Some(&Position(5)));
+
+        let both: Vec<_> = world
+            .query2::<Position, Speed>()
+            .map(|(id, _, _)| id)
+            .collect();
+        assert_eq!(both, vec![moving]);
+
+        for (_, position) in world.query_mut::<Position>() {
+            position.0 *= 2;
+        }
+        let mut positions: Vec<_> = world.query::<Position>().map(|(_, p)| p.0).collect();
+        positions.sort();
+        assert_eq!(positions, vec![6, 10]);
+    }
+}
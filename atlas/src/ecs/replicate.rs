@@ -0,0 +1,304 @@
+use super::components::*;
+use super::{hierarchy, World};
+use crate::entity::cube::Cuboid;
+use crate::entity::player::Player;
+use crate::entity::primitive::Primitive;
+use crate::entity::sun::Sun;
+use crate::entity::EntityKind;
+use crate::snapshot::{fields, EntityId, EntityState, Snapshot, Tick};
+use crate::spatial::BoundingSphere;
+use pantheon::math::Quaternion;
+use pantheon::Vec3;
+
+/// The components of a `Networked` entity which clients are sent in snapshots. The
+/// `Transform` is the one in world space, since clients don't know about `Parent`s. Clients
+/// build whatever they draw from these, see `drawable`.
+#[derive(Debug, Clone, Copy)]
+pub struct Replicated {
+    pub transform: Transform,
+    pub mesh: Mesh,
+    pub light: Option<Light>,
+    pub look: Option<Look>,
+    pub velocity: Option<Velocity>,
+}
+
+impl Replicated {
+    /// a plain mesh, which isn't lit up, looking around or moving
+    pub fn new(transform: Transform, mesh: Mesh) -> Self {
+        Self {
+            transform,
+            mesh,
+            light: None,
+            look: None,
+            velocity: None,
+        }
+    }
+
+    /// `None` for entities which aren't `Networked` or are missing a `Transform` or a `Mesh`
+    pub fn from_world(world: &World, id: EntityId) -> Option<Self> {
+        if !world.has::<Networked>(id) {
+            return None;
+        }
+
+        Some(Self {
+            transform: hierarchy::world_transform(world, id)?,
+            mesh: *world.get::<Mesh>(id)?,
+            light: world.get::<Light>(id).copied(),
+            look: world.get::<Look>(id).copied(),
+            velocity: world.get::<Velocity>(id).copied(),
+        })
+    }
+
+    /// Gives `id` these components along with the `Collider` which goes with the mesh, the
+    /// other way around from `from_world`. Returns false for stale ids.
+    pub fn insert(&self, world: &mut World, id: EntityId) -> bool {
+        if !world.insert(id, self.transform) {
+            return false;
+        }
+        world.insert(id, self.mesh);
+        world.insert(id, self.mesh.collider());
+        world.insert(id, Networked);
+        if let Some(light) = self.light {
+            world.insert(id, light);
+        }
+        if let Some(look) = self.look {
+            world.insert(id, look);
+        }
+        if let Some(velocity) = self.velocity {
+            world.insert(id, velocity);
+        }
+
+        true
+    }
+
+    /// players only move through their own inputs
+    pub fn is_player(&self) -> bool {
+        self.look.is_some()
+    }
+
+    pub fn state(&self) -> EntityState {
+        EntityState {
+            position: self.transform.position,
+            rotation: self.transform.rotation.rotation_matrix(),
+            color: self.mesh.color,
+            velocity: self.velocity.map_or(Vec3::new_from_one(0), |v| v.0),
+        }
+    }
+
+    /// only the `fields` set in `changed` are copied over
+    pub fn apply_state(&mut self, changed: u8, state: &EntityState) {
+        if changed & fields::POSITION != 0 {
+            self.transform.position = state.position;
+        }
+        if changed & fields::ROTATION != 0 {
+            self.transform.rotation = Quaternion::from_rotation_matrix(&state.rotation);
+        }
+        if changed & fields::COLOR != 0 {
+            self.mesh.color = state.color;
+        }
+        if changed & fields::VELOCITY != 0 {
+            self.velocity = Some(Velocity(state.velocity));
+        }
+    }
+
+    /// Where the ray hits the mesh and how far along it that is, the same triangles a client
+    /// picks from its `drawable`. Those are drawn without the transform's scale.
+    pub fn ray_pick(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, f32)> {
+        // generating the triangles isn't free, most entities are nowhere near the ray
+        let reach = self.mesh.size * 3f32.sqrt() / 2.;
+        BoundingSphere::new(self.transform.position, reach).ray(origin, direction)?;
+
+        let model = Transform {
+            scale: Vec3::new_from_one(1),
+            ..self.transform
+        }
+        .matrix();
+        self.mesh.data().ray_pick(&model, origin, direction)
+    }
+
+    /// What a client draws for these components. Anything with a `Light` is a `Sun`, a `Look` a
+    /// `Player`, a `Mesh` with a primitive shape a `Primitive` and everything else a `Cuboid`.
+    pub fn drawable(&self) -> EntityKind<'static> {
+        let (position, rotation) = (
+            self.transform.position,
+            self.transform.rotation.rotation_matrix(),
+        );
+        let mesh = &self.mesh;
+
+        if let Some(light) = self.light {
+            let mut sun = Sun::new(position, mesh.size, mesh.color, light.color);
+            sun.cube.rotation = rotation;
+            sun.radians = light.radians;
+            sun.rotating = light.rotating;
+            sun.rotation_axis = light.rotation_axis;
+            return EntityKind::from(sun);
+        }
+
+        if let Some(shape) = mesh.primitive {
+            let mut primitive = Primitive::new(
+                shape,
+                position,
+                Some(mesh.color),
+                mesh.vertex_kind,
+                Some(mesh.topology),
+            );
+            primitive.rotation = rotation;
+            return EntityKind::from(primitive);
+        }
+
+        let mut cube = Cuboid::cube(
+            mesh.size,
+            position,
+            Some(mesh.color),
+            mesh.vertex_kind,
+            Some(mesh.topology),
+        );
+        cube.rotation = rotation;
+
+        if let Some(look) = self.look {
+            return EntityKind::from(Player {
+                cube,
+                velocity: self.state().velocity,
+                yaw: look.yaw,
+                pitch: look.pitch,
+                // found out again by its next input
+                grounded: false,
+            });
+        }
+
+        EntityKind::from(cube)
+    }
+}
+
+/// every `Networked` entity as of `tick`
+pub fn snapshot(world: &World, tick: Tick) -> Snapshot {
+    let entities = world
+        .query::<Networked>()
+        .filter_map(|(id, _)| Some((id, Replicated::from_world(world, id)?)))
+        .collect();
+
+    Snapshot::new(tick, entities)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entity::primitive::PrimitiveShape;
+    use crate::vertex::VertexKind;
+    use pantheon::Color;
+
+    #[test]
+    fn round_trip() {
+        let mut world = World::new();
+        let rotation = Quaternion::rotation(1., (0, 1, 0).into());
+        let player = Replicated {
+            transform: Transform {
+                rotation,
+                ..Transform::new((1, 2, 3).into())
+            },
+            mesh: Mesh::cube(1., Color::new(255, 0, 0)),
+            light: None,
+            look: Some(Look { yaw: 1., pitch: 0. }),
+            velocity: Some(Velocity(Vec3::new(4, 0, 0))),
+        };
+        let sun = Replicated {
+            transform: Transform::new((0, 10, 0).into()),
+            mesh: Mesh::cube(5., Color::new(1, 2, 3)),
+            light: Some(Light {
+                color: Color::new(4, 5, 6),
+                radians: 0.,
+                rotating: false,
+                rotation_axis: (0, 1, 0).into(),
+            }),
+            look: None,
+            velocity: None,
+        };
+        let cube = Replicated::new(
+            Transform::new((5, 0, 5).into()),
+            Mesh::cube(2., Color::new(60, 60, 60)),
+        );
+        let torus_shape = PrimitiveShape::from_name("torus", 3.).unwrap();
+        let torus = Replicated {
+            transform: Transform {
+                rotation: Quaternion::rotation(0.5, (1, 0, 0).into()),
+                ..Transform::new((-5, 1, 0).into())
+            },
+            mesh: Mesh {
+                vertex_kind: VertexKind::Basic,
+                ..Mesh::primitive(torus_shape, Color::new(0, 0, 255))
+            },
+            ..cube
+        };
+
+        let mut ids = vec![];
+        for entity in [player, sun, cube, torus] {
+            let id = world.spawn();
+            assert!(entity.insert(&mut world, id));
+            ids.push((id, entity));
+        }
+        assert_eq!(
+            world.get::<Collider>(ids[3].0),
+            Some(&torus_shape.collider())
+        );
+        // local only, it never goes out to clients
+        world
+            .build()
+            .with(Transform::new(Vec3::new_from_one(0)))
+            .with(Mesh::cube(1., Color::new(0, 0, 0)));
+
+        let snapshot = snapshot(&world, 7);
+        assert_eq!(snapshot.tick, 7);
+        assert_eq!(snapshot.entities.len(), 4);
+        for (id, entity) in ids.iter() {
+            let copy = snapshot.entities[id];
+            assert_eq!(copy.state().changed_fields(&entity.state()), 0);
+            assert_eq!(copy.transform, entity.transform);
+            assert_eq!(copy.look, entity.look);
+        }
+
+        let drawables = ids.iter().map(|(id, _)| snapshot.entities[id].drawable());
+        match drawables.collect::<Vec<_>>()[..] {
+            [EntityKind::Player(player), EntityKind::Sun(sun), EntityKind::Cuboid(cube), EntityKind::Primitive(torus)] =>
+            {
+                assert_eq!(player.yaw, 1.);
+                assert_eq!(player.velocity, Vec3::new(4, 0, 0));
+                assert_eq!(sun.light_color.g, Color::new(4, 5, 6).g);
+                assert_eq!(cube.size(), 2.);
+                assert_eq!(torus.primitive, torus_shape);
+                assert_eq!(torus.vertex_kind(), VertexKind::Basic);
+            }
+            _ => panic!("drawn as the wrong kinds of entity"),
+        }
+    }
+
+    #[test]
+    fn picks_the_drawn_triangles() {
+        let cube = Replicated::new(
+            Transform::new((0, 0, -10).into()),
+            Mesh::cube(2., Color::new(0, 0, 0)),
+        );
+        let origin = Vec3::new(0.5, 0, 0);
+        let forward = Vec3::new(0, 0, -1);
+        let (point, distance) = cube.ray_pick(origin, forward).unwrap();
+        assert!((point - Vec3::new(0.5, 0, -9)).magnitude() < 1e-5);
+        assert_eq!(
+            cube.drawable().ray_pick(origin, forward).map(|(_, t)| t),
+            Some(distance)
+        );
+
+        // through the torus' hole
+        let torus = Replicated {
+            mesh: Mesh::primitive(
+                PrimitiveShape::from_name("torus", 3.).unwrap(),
+                Color::new(0, 0, 0),
+            ),
+            transform: Transform {
+                rotation: Quaternion::rotation(std::f32::consts::FRAC_PI_2, (1, 0, 0).into()),
+                ..cube.transform
+            },
+            ..cube
+        };
+        assert!(torus.ray_pick(Vec3::new(0, 0, 0), forward).is_none());
+        assert!(torus.ray_pick(Vec3::new(1.05, 0, 0), forward).is_some());
+    }
+}
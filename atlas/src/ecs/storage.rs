@@ -0,0 +1,173 @@
+use crate::entity::id::EntityId;
+use std::any::Any;
+
+/// marks an index in `SparseSet::sparse` with nothing stored for it
+const EMPTY: u32 = u32::MAX;
+
+/// Every component of one type, packed together so iterating them is a walk over a `Vec`.
+/// `sparse` maps an entity's index to where its component sits in `dense` and `data`, removing
+/// one moves the last component into the gap.
+#[derive(Debug, Clone)]
+pub struct SparseSet<T> {
+    sparse: Vec<u32>,
+    dense: Vec<EntityId>,
+    data: Vec<T>,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self {
+            sparse: vec![],
+            dense: vec![],
+            data: vec![],
+        }
+    }
+}
+
+impl<T> SparseSet<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn slot(&self, id: EntityId) -> Option<usize> {
+        let slot = *self.sparse.get(id.index as usize)?;
+        // a stale id with the same index finds whatever replaced it, the generation tells them
+        // apart
+        (slot != EMPTY && self.dense[slot as usize] == id).then_some(slot as usize)
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.slot(id).is_some()
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&T> {
+        self.slot(id).map(|slot| &self.data[slot])
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        self.slot(id).map(move |slot| &mut self.data[slot])
+    }
+
+    /// Returns the component `id` already had. Anything left behind by an older generation of
+    /// the same index is dropped.
+    pub fn insert(&mut self, id: EntityId, value: T) -> Option<T> {
+        let index = id.index as usize;
+        if index >= self.sparse.len() {
+            self.sparse.resize(index + 1, EMPTY);
+        }
+
+        match self.sparse[index] {
+            EMPTY => {
+                self.sparse[index] = self.data.len() as u32;
+                self.dense.push(id);
+                self.data.push(value);
+                None
+            }
+            slot => {
+                let slot = slot as usize;
+                let old = std::mem::replace(&mut self.data[slot], value);
+                let same = std::mem::replace(&mut self.dense[slot], id) == id;
+                same.then_some(old)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<T> {
+        let slot = self.slot(id)?;
+        self.sparse[id.index as usize] = EMPTY;
+
+        let last = self.dense.len() - 1;
+        if slot != last {
+            let moved = self.dense[last];
+            self.sparse[moved.index as usize] = slot as u32;
+        }
+        self.dense.swap_remove(slot);
+        Some(self.data.swap_remove(slot))
+    }
+
+    /// ids with a component, in no particular order
+    pub fn ids(&self) -> &[EntityId] {
+        &self.dense
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.dense.iter().copied().zip(self.data.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.dense.iter().copied().zip(self.data.iter_mut())
+    }
+}
+
+/// What `World` needs from a `SparseSet` without knowing its component type
+pub(crate) trait AnyStorage: Any {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn remove_entity(&mut self, id: EntityId);
+}
+
+impl<T: 'static> AnyStorage for SparseSet<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn remove_entity(&mut self, id: EntityId) {
+        self.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn id(index: u32, generation: u32) -> EntityId {
+        EntityId::new(index, generation)
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut set = SparseSet::new();
+        assert_eq!(set.insert(id(3, 0), 'a'), None);
+        assert_eq!(set.insert(id(0, 0), 'b'), None);
+        assert_eq!(set.insert(id(7, 0), 'c'), None);
+        assert_eq!(set.insert(id(0, 0), 'd'), Some('b'));
+        assert_eq!(set.len(), 3);
+
+        // the last component moves into the gap and can still be found
+        assert_eq!(set.remove(id(3, 0)), Some('a'));
+        assert_eq!(set.remove(id(3, 0)), None);
+        assert_eq!(set.get(id(7, 0)), Some(&'c'));
+        assert_eq!(set.get(id(0, 0)), Some(&'d'));
+
+        *set.get_mut(id(7, 0)).unwrap() = 'e';
+        let mut all: Vec<_> = set.iter().collect();
+        all.sort();
+        assert_eq!(all, vec![(id(0, 0), &'d'), (id(7, 0), &'e')]);
+    }
+
+    #[test]
+    fn stale_generations() {
+        let mut set = SparseSet::new();
+        set.insert(id(1, 0), 1);
+        assert!(set.get(id(1, 1)).is_none());
+        assert!(set.remove(id(1, 1)).is_none());
+
+        // the newer generation takes the slot over rather than handing back the old value
+        assert_eq!(set.insert(id(1, 1), 2), None);
+        assert!(!set.contains(id(1, 0)));
+        assert_eq!(set.get(id(1, 1)), Some(&2));
+        assert_eq!(set.len(), 1);
+    }
+}
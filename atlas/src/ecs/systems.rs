@@ -0,0 +1,134 @@
+use super::components::*;
+use super::{hierarchy, World};
+use crate::entity::player::{self, OBSTACLE_REACH};
+use crate::message::PlayerInput;
+use crate::physics::{self, Shape};
+use crate::snapshot::EntityId;
+use pantheon::math::Quaternion;
+use pantheon::Vec3;
+
+/// Moves the player `id` by a single input the same way `Player::apply_input` does, see
+/// `player::step`, bumping into every other entity's `Collider`. Returns the `dt` which was
+/// actually used, or `None` when `id` has no `Transform` and `Look` to move.
+pub fn move_player(
+    world: &mut World,
+    id: EntityId,
+    input: &PlayerInput,
+    ground_height: impl Fn(f32, f32) -> Option<f32>,
+) -> Option<f32> {
+    let look = *world.get::<Look>(id)?;
+    let velocity = world
+        .get::<Velocity>(id)
+        .map_or(Vec3::new_from_one(0), |velocity| velocity.0);
+    let position = world.get::<Transform>(id)?.position;
+    let obstacles = obstacles(world, id, position);
+
+    let step = player::step(
+        input,
+        position,
+        velocity,
+        look.yaw,
+        look.pitch,
+        ground_height,
+        &obstacles,
+    );
+
+    let transform = world.get_mut::<Transform>(id)?;
+
+    transform.position = step.position;
+    transform.rotation = Quaternion::rotation(step.yaw, (0, 1, 0).into());
+    hierarchy::mark_dirty(world, id);
+    world.insert(
+        id,
+        Look {
+            yaw: step.yaw,
+            pitch: step.pitch,
+        },
+    );
+    world.insert(id, Velocity(step.velocity));
+
+    Some(step.dt)
+}
+
+/// every collider other than `id`'s own which a single input could take a player at
+/// `position` into
+fn obstacles(world: &World, id: EntityId, position: Vec3) -> Vec<Shape> {
+    world
+        .query::<Collider>()
+        .filter(|&(other, _)| other != id)
+        .filter_map(|(other, _)| physics::shape(world, other))
+        .filter(|shape| (shape.closest_point(position) - position).magnitude() <= OBSTACLE_REACH)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entity::player::{Player, MAX_INPUT_DT, PLAYER_MAX_SPEED, PLAYER_SIZE};
+    use pantheon::{Color, Vec3};
+
+    #[test]
+    fn moves_like_a_player() {
+        let start = Vec3::new(0, 10, 0);
+        let mut player = Player::new(start, Color::new(255, 0, 0));
+        let mut world = World::new();
+        let id = world
+            .build()
+            .with(Transform::new(start))
+            .with(Look::default())
+            .id();
+        let input = PlayerInput {
+            sequence: 0,
+            velocity: Vec3::new(4, 0, 0),
+            yaw: 1.,
+            pitch: 0.5,
+            dt: 0.1,
+            jump: 0,
+        };
+        let ground = |_: f32, _: f32| Some(12.);
+
+        let dt = move_player(&mut world, id, &input, ground);
+        assert_eq!(dt, Some(player.apply_input(&input, ground, &[])));
+        let transform = world.get::<Transform>(id).unwrap();
+        assert_eq!(transform.position, player.position());
+        assert_eq!(transform.position.y, 12. + PLAYER_SIZE / 2.);
+        assert_eq!(world.get::<Velocity>(id), Some(&Velocity(player.velocity)));
+        assert_eq!(world.get::<Look>(id).unwrap().yaw, 1.);
+
+        // a plain cube has nowhere to look
+        let cube = world.build().with(Transform::new(start)).id();
+        assert!(move_player(&mut world, cube, &input, ground).is_none());
+    }
+
+    #[test]
+    fn bumps_into_colliders() {
+        let start = Vec3::new(0, PLAYER_SIZE / 2., 0);
+        let mut world = World::new();
+        let id = world
+            .build()
+            .with(Transform::new(start))
+            .with(Look::default())
+            .with(Collider::cube(PLAYER_SIZE))
+            .id();
+        world
+            .build()
+            .with(Transform::new(Vec3::new(3, 1, 0)))
+            .with(Collider::cube(2.))
+            .id();
+        let input = PlayerInput {
+            sequence: 0,
+            velocity: Vec3::new(PLAYER_MAX_SPEED, 0, 0),
+            yaw: 0.,
+            pitch: 0.,
+            dt: MAX_INPUT_DT,
+            jump: 0,
+        };
+
+        // its own collider doesn't get in the way, the wall does
+        for _ in 0..4 {
+            move_player(&mut world, id, &input, |_, _| Some(0.));
+        }
+        let x = world.get::<Transform>(id).unwrap().position.x;
+        assert!(x > 1. && x < 2., "walked to {}", x);
+    }
+}
@@ -12,6 +12,9 @@ pub trait DrawComponent<'a> {
     // @FIXME remove the default impl once existing entitys are updated
     fn register(&mut self, _ctx: &mut Context<'a>) {}
 
+    /// stops drawing the entity, the buffer space it was registered with is not reclaimed
+    fn unregister(&mut self, _ctx: &mut Context<'a>) {}
+
     fn draw(&mut self, ctx: &mut Context<'a>);
 
     /// this offers an additional draw call to draw stuff like surface norms and what not
@@ -1,16 +1,17 @@
 use super::component::*;
-use super::plane::Plane;
 use super::triangle::Triangle;
 use super::Camera;
 use super::Entity;
+use crate::ecs::components::{Collider, Transform};
+use crate::physics::Shape;
 use crate::rendering;
+use crate::snapshot::{fields, EntityState};
+use crate::spatial::{ray_triangle, Aabb, BoundingSphere};
 use crate::vertex::*;
 use pantheon::context::Context;
 use pantheon::graphics::prelude::*;
 use pantheon::graphics::Drawable;
-use pantheon::input::mouse;
 use pantheon::math::*;
-use pantheon::winit::event::MouseButton;
 
 pub fn _get_unit_cube_verts() -> [Vec3; 8] {
     [
@@ -42,6 +43,35 @@ pub fn get_cube_verts(size: f32) -> [Vec3; 8] {
     ]
 }
 
+/// The edges of a box reaching `half_extents` out from the origin as a line list, in the color
+/// selected entities are outlined with
+pub fn box_outline(half_extents: Vec3) -> [BasicVertex; 24] {
+    #[rustfmt::skip]
+    const EDGES: [(usize, usize); 12] = [
+        // front
+        (0, 1), (1, 2), (2, 3), (3, 0),
+        // back
+        (4, 5), (5, 6), (6, 7), (7, 4),
+        // sides
+        (0, 4), (1, 5), (2, 6), (3, 7),
+    ];
+    let color = Color::new(255, 200, 0);
+    let pos = get_cube_verts(2.).map(|corner| {
+        Vec3::new(
+            corner.x * half_extents.x,
+            corner.y * half_extents.y,
+            corner.z * half_extents.z,
+        )
+    });
+
+    let mut vertices = [(Vec3::new_from_one(0), color).into(); 24];
+    for (i, (a, b)) in EDGES.iter().enumerate() {
+        vertices[2 * i] = (pos[*a], color).into();
+        vertices[2 * i + 1] = (pos[*b], color).into();
+    }
+    vertices
+}
+
 pub fn cube_normals() -> [Vec3; 8] {
     [
         (0, 0, 1).into(),  // front
@@ -56,7 +86,7 @@ pub fn cube_normals() -> [Vec3; 8] {
 }
 
 #[rustfmt::skip]
-fn cube_indices() -> [u32; 36] {
+pub fn cube_indices() -> [u32; 36] {
     [
         // front
         0, 1, 2,
@@ -159,10 +189,14 @@ pub struct Cuboid<'a> {
     faces: [(Triangle, Triangle); 6],
     indices: [u32; 36],
     draw_call_handle: Option<DrawCallHandle<'a>>,
+    /// registered hidden along with the cube, only drawn while it's `selected`
+    outline: Option<DrawCallHandle<'a>>,
     pub topology: Topology,
     pub position: Vec3,
     pub rotation: Mat4,
     pub moused_over: bool,
+    /// set by `click_start` and cleared by `click_end`, selected cubes are outlined
+    pub selected: bool,
 }
 
 impl<'a> Cuboid<'a> {
@@ -237,10 +271,34 @@ impl<'a> Cuboid<'a> {
             faces,
             indices: cube_indices(),
             draw_call_handle: None,
+            outline: None,
             topology: topology.unwrap_or(Topology::TriangleList(PolygonMode::Fill)),
             position,
             rotation: Mat4::identity(),
             moused_over: false,
+            selected: false,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self.vertices {
+            CubioidVertMode::Basic(ref verts) => verts[0].color,
+            CubioidVertMode::Shaded(ref verts) => verts[0].color,
+        }
+    }
+
+    /// edge length, recovered from the first edge of the front face
+    pub fn size(&self) -> f32 {
+        match self.vertices {
+            CubioidVertMode::Basic(ref verts) => verts[1].position.x - verts[0].position.x,
+            CubioidVertMode::Shaded(ref verts) => verts[1].position.x - verts[0].position.x,
+        }
+    }
+
+    pub fn vertex_kind(&self) -> VertexKind {
+        match self.vertices {
+            CubioidVertMode::Basic(_) => VertexKind::Basic,
+            CubioidVertMode::Shaded(_) => VertexKind::Shaded,
         }
     }
 
@@ -259,6 +317,75 @@ impl<'a> Cuboid<'a> {
         }
     }
 
+    pub fn state(&self) -> EntityState {
+        EntityState {
+            position: self.position,
+            rotation: self.rotation,
+            color: self.color(),
+            velocity: Vec3::new_from_one(0),
+        }
+    }
+
+    /// only the `fields` set in `changed` are copied over
+    pub fn apply_state(&mut self, changed: u8, state: &EntityState) {
+        if changed & fields::POSITION != 0 {
+            self.position = state.position;
+        }
+        if changed & fields::ROTATION != 0 {
+            self.rotation = state.rotation;
+        }
+        if changed & fields::COLOR != 0 {
+            self.set_color(state.color);
+        }
+    }
+
+    /// Closest point the ray from `origin` along `direction` hits one of the faces, along with
+    /// how far along the ray it is. Doesn't need a `Context` so the server can pick as well.
+    pub fn ray_pick(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, f32)> {
+        let model = self.model_matrix();
+        let world = |point: Vec3| (model * Vec4::from_vec3(point)).truncate(Dim::W);
+
+        self.faces
+            .iter()
+            .flat_map(|(tri_one, tri_two)| [tri_one, tri_two])
+            .filter_map(|tri| {
+                ray_triangle(
+                    origin,
+                    direction,
+                    world(tri.p0),
+                    world(tri.p1),
+                    world(tri.p2),
+                )
+            })
+            .min_by(|a, b| a.total_cmp(b))
+            .map(|t| ((t * direction) + origin, t))
+    }
+
+    /// world space box around the cube as it's turned right now
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_center(Vec3::new_from_one(0), Vec3::new_from_one(self.size() / 2.))
+            .transformed(&self.model_matrix())
+    }
+
+    /// the cube as something to bump into, the same as a `Collider::cube` of its size
+    pub fn shape(&self) -> Shape {
+        let transform = Transform {
+            rotation: Quaternion::from_rotation_matrix(&self.rotation),
+            ..Transform::new(self.position)
+        };
+        Shape::new(&Collider::cube(self.size()), &transform)
+    }
+
+    /// Around the cube however it's turned, so it only changes when the cube moves
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere::new(self.position, self.size() * 3f32.sqrt() / 2.)
+    }
+
+    /// the cube's edges as a line list, pushed out a little so they aren't hidden by its faces
+    fn outline_vertices(&self) -> [BasicVertex; 24] {
+        box_outline(Vec3::new_from_one(self.size() * 1.04 / 2.))
+    }
+
     pub fn invert_surface_norms(&mut self) {
         if let Some(verts) = self.vertices.try_as_shaded_mut() {
             for vert in verts.iter_mut() {
@@ -304,12 +431,42 @@ impl<'a> DrawComponent<'a> for Cuboid<'a> {
                 None,
             ),
         });
+
+        // buffer space can't be given back, so the outline is there from the start and only
+        // shown while selected
+        self.outline = Some(rendering::register(
+            ctx,
+            &["shaded"],
+            "basic",
+            Topology::LineList(PolygonMode::Fill),
+            &self.outline_vertices(),
+            0..0,
+            Some(PushConstant::vertex_data(0, &[self.model_matrix()])),
+            None,
+        ));
+    }
+
+    fn unregister(&mut self, ctx: &mut Context<'a>) {
+        if let Some(draw_call_handle) = self.draw_call_handle.take() {
+            rendering::hide(ctx, &draw_call_handle);
+        }
+        if let Some(outline) = self.outline.take() {
+            rendering::hide(ctx, &outline);
+        }
     }
 
     fn draw(&mut self, ctx: &mut Context<'a>) {
         if let Some(draw_call_handle) = self.draw_call_handle {
             draw_call_handle.set_push_constant_data(ctx, &[self.model_matrix()]);
         }
+        if let Some(outline) = self.outline {
+            if self.selected {
+                outline.set_push_constant_data(ctx, &[self.model_matrix()]);
+                rendering::show(ctx, &outline);
+            } else {
+                rendering::hide(ctx, &outline);
+            }
+        }
         /*
         let mut color: Color = (0, 0, 0).into();
         if self.moused_over {
@@ -368,27 +525,18 @@ impl<'a> DrawComponent<'a> for Cuboid<'a> {
 // impl AsComponent for Cuboid {}
 
 impl<'a> MouseComponent for Cuboid<'a> {
-    fn click_start(&mut self, _ctx: &mut Context) {}
-    fn click_end(&mut self, _ctx: &mut Context) {}
-
-    fn mouse_over(&mut self, ctx: &mut Context, pos: Vec3, camera: &Camera) {
-        self.moused_over = true;
-
-        if mouse::button_pressed(ctx, MouseButton::Left) {
-            //@TODO this is impossible in 3D space dont @ me
-            // fix to be in relation to a ground plane
-            let delta = mouse::delta(ctx);
-            //println!("delta: {:#?}", delta);
-
-            let ndc_x = delta.x / ctx.gfx_context.window_dims.width;
-            let ndc_y = -delta.y / ctx.gfx_context.window_dims.height;
+    fn click_start(&mut self, _ctx: &mut Context) {
+        self.selected = true;
+    }
 
-            let delta_x = 2.0 * (pos - camera.origin).magnitude() * ndc_x * camera.u;
-            let delta_y = 2.0 * (pos - camera.origin).magnitude() * ndc_y * camera.v;
-            let trans = delta_x + delta_y;
+    fn click_end(&mut self, _ctx: &mut Context) {
+        self.selected = false;
+    }
 
-            self.translate(trans.into());
-        }
+    /// dragging is left to whoever owns the selection, the server has the final say on where
+    /// things are
+    fn mouse_over(&mut self, _ctx: &mut Context, _pos: Vec3, _camera: &Camera) {
+        self.moused_over = true;
     }
 
     fn check_collision(
@@ -397,88 +545,8 @@ impl<'a> MouseComponent for Cuboid<'a> {
         camera_origin: Vec3,
         mouse_direction: Vec3,
     ) -> Option<MousePick> {
-        let mut to_return: Option<Vec3> = None;
-        let mut final_t = 0.0;
-        let model = self.model_matrix();
-        for (tri_one, tri_two) in self.faces.iter_mut() {
-            let p0 = model * Vec4::from_vec3(tri_one.p0);
-            let p1 = model * Vec4::from_vec3(tri_one.p1);
-            let p2 = model * Vec4::from_vec3(tri_one.p2);
-            let p0 = p0.truncate(Dim::W);
-            let p1 = p1.truncate(Dim::W);
-            let p2 = p2.truncate(Dim::W);
-            let plane = Plane::new(p0, p1, p2).unwrap();
-            // http://antongerdelan.net/opengl/raycasting.html
-            // solve for t = -(O dot norm) + (d) / (norm dot direction)
-            // plane:  point dot norm + d = 0 -> point dot norm = -d
-            let t = -((camera_origin.dot(&plane.norm)) - (plane.norm.dot(&plane.point)))
-                / (mouse_direction.dot(&plane.norm));
-
-            if t > 0.0 {
-                // On plane, check for triangle bounds
-                let point = (t * mouse_direction) + camera_origin;
-
-                // BARYCENTRIC TEST
-                // ref http://blackpawn.com/texts/pointinpoly/default.html
-                // and https://en.wikipedia.org/wiki/Barycentric_coordinate_system
-                let v0 = p2 - p0;
-                let v1 = p1 - p0;
-                let v2 = point - p0;
-
-                let dot00 = v0.dot(&v0);
-                let dot01 = v0.dot(&v1);
-                let dot02 = v0.dot(&v2);
-                let dot11 = v1.dot(&v1);
-                let dot12 = v1.dot(&v2);
-
-                let inv_denom = 1.0 / ((dot00 * dot11) - (dot01.powi(2)));
-
-                let u = ((dot11 * dot02) - (dot01 * dot12)) * inv_denom;
-                let v = ((dot00 * dot12) - (dot01 * dot02)) * inv_denom;
-
-                if u < 0.0 || v < 0.0 || (u + v > 1.0) {
-                    // not in first
-                    let p0 = model * Vec4::from_vec3(tri_two.p0);
-                    let p1 = model * Vec4::from_vec3(tri_two.p1);
-                    let p2 = model * Vec4::from_vec3(tri_two.p2);
-                    let p0 = p0.truncate(Dim::W);
-                    let p1 = p1.truncate(Dim::W);
-                    let p2 = p2.truncate(Dim::W);
-
-                    let v0 = p2 - p0;
-                    let v1 = p1 - p0;
-                    let v2 = point - p0;
-
-                    let dot00 = v0.dot(&v0);
-                    let dot01 = v0.dot(&v1);
-                    let dot02 = v0.dot(&v2);
-                    let dot11 = v1.dot(&v1);
-                    let dot12 = v1.dot(&v2);
-
-                    let inv_denom = 1.0 / ((dot00 * dot11) - (dot01.powi(2)));
-
-                    let u = ((dot11 * dot02) - (dot01 * dot12)) * inv_denom;
-                    let v = ((dot00 * dot12) - (dot01 * dot02)) * inv_denom;
-
-                    if u < 0.0 || v < 0.0 || (u + v > 1.0) {
-                        // both failed, continu
-                        continue;
-                    }
-                }
-
-                if let Some(other) = to_return {
-                    if (point - camera_origin).magnitude() < (other - camera_origin).magnitude() {
-                        to_return = Some(point);
-                        final_t = t;
-                    }
-                } else {
-                    to_return = Some(point);
-                    final_t = t;
-                }
-            }
-        }
-
-        to_return.map(move |point| MousePick::new(self, point, final_t))
+        self.ray_pick(camera_origin, mouse_direction)
+            .map(move |(point, t)| MousePick::new(self, point, t))
     }
 }
 
@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+
+/// Names a single networked entity. Indices get reused once an entity is gone, the generation
+/// is bumped every time that happens so an id held onto for too long stops matching instead of
+/// pointing at whatever took its place.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityId {
+    pub index: u32,
+    pub generation: u32,
+}
+
+impl EntityId {
+    pub fn new(index: u32, generation: u32) -> Self {
+        Self { index, generation }
+    }
+}
+
+impl std::fmt::Display for EntityId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub generation: u32,
+    pub alive: bool,
+}
+
+/// Hands out `EntityId`s on the server, the only place ids come from. Freed indices are reused
+/// oldest first, so the same sequence of spawns and despawns always gives the same ids.
+#[derive(Debug, Default, Clone)]
+pub struct IdAllocator {
+    slots: Vec<Slot>,
+    free: VecDeque<u32>,
+}
+
+impl IdAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Picks up from a save. `slots` holds every index ever handed out, dead ones are queued up
+    /// for reuse in `free`'s order, any missing from `free` are added after it.
+    pub fn from_slots(slots: Vec<Slot>, free: impl IntoIterator<Item = u32>) -> Self {
+        let mut queued = vec![false; slots.len()];
+        let mut out = VecDeque::new();
+        for index in free {
+            let i = index as usize;
+            if i < slots.len() && !slots[i].alive && !queued[i] {
+                queued[i] = true;
+                out.push_back(index);
+            }
+        }
+        for (i, slot) in slots.iter().enumerate() {
+            if !slot.alive && !queued[i] {
+                out.push_back(i as u32);
+            }
+        }
+
+        Self { slots, free: out }
+    }
+
+    pub fn allocate(&mut self) -> EntityId {
+        if let Some(index) = self.free.pop_front() {
+            let slot = &mut self.slots[index as usize];
+            slot.alive = true;
+            return EntityId::new(index, slot.generation);
+        }
+
+        let index = self.slots.len() as u32;
+        self.slots.push(Slot {
+            generation: 0,
+            alive: true,
+        });
+        EntityId::new(index, 0)
+    }
+
+    /// returns false for ids which are already stale, nothing changes then
+    pub fn free(&mut self, id: EntityId) -> bool {
+        if !self.is_alive(id) {
+            return false;
+        }
+
+        let slot = &mut self.slots[id.index as usize];
+        slot.alive = false;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push_back(id.index);
+
+        true
+    }
+
+    pub fn is_alive(&self, id: EntityId) -> bool {
+        self.slots
+            .get(id.index as usize)
+            .is_some_and(|slot| slot.alive && slot.generation == id.generation)
+    }
+
+    pub fn slots(&self) -> &[Slot] {
+        &self.slots
+    }
+
+    /// in the order they'll be reused
+    pub fn free_indices(&self) -> impl Iterator<Item = u32> + '_ {
+        self.free.iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stale_ids_stop_matching() {
+        let mut ids = IdAllocator::new();
+        let a = ids.allocate();
+        let b = ids.allocate();
+        assert_eq!((a, b), (EntityId::new(0, 0), EntityId::new(1, 0)));
+
+        assert!(ids.free(a));
+        assert!(!ids.free(a));
+        assert!(!ids.is_alive(a));
+
+        // the index comes back with a new generation
+        let c = ids.allocate();
+        assert_eq!(c, EntityId::new(0, 1));
+        assert_ne!(a, c);
+        assert!(ids.is_alive(c) && ids.is_alive(b));
+        assert!(!ids.is_alive(EntityId::new(7, 0)));
+    }
+
+    #[test]
+    fn allocation_is_deterministic() {
+        let run = || {
+            let mut ids = IdAllocator::new();
+            let spawned: Vec<_> = (0..4).map(|_| ids.allocate()).collect();
+            ids.free(spawned[2]);
+            ids.free(spawned[0]);
+            (0..3).map(|_| ids.allocate()).collect::<Vec<_>>()
+        };
+
+        let first = run();
+        assert_eq!(first, run());
+        // freed indices are reused oldest first before new ones are handed out
+        assert_eq!(
+            first,
+            vec![
+                EntityId::new(2, 1),
+                EntityId::new(0, 1),
+                EntityId::new(4, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn restores_from_slots() {
+        let mut ids = IdAllocator::new();
+        let spawned: Vec<_> = (0..3).map(|_| ids.allocate()).collect();
+        ids.free(spawned[1]);
+
+        let mut restored = IdAllocator::from_slots(ids.slots().to_vec(), ids.free_indices());
+        assert!(restored.is_alive(spawned[0]) && !restored.is_alive(spawned[1]));
+        assert_eq!(restored.allocate(), ids.allocate());
+        assert_eq!(restored.allocate(), ids.allocate());
+    }
+}
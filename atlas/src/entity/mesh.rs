@@ -0,0 +1,314 @@
+use super::component::*;
+use super::Camera;
+use super::Entity;
+use crate::rendering;
+use crate::spatial::{ray_triangle, Aabb, BoundingSphere};
+use crate::vertex::*;
+use pantheon::context::Context;
+use pantheon::graphics::prelude::*;
+use pantheon::graphics::Drawable;
+use pantheon::math::*;
+
+/// An indexed triangle list, everything a `MeshEntity` needs to be drawn and picked
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<ShadedVertex>,
+    /// three to a triangle, counter-clockwise seen from the front
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// each triangle's corners, skipping any whose indices are out of range
+    pub fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        self.indices.chunks_exact(3).filter_map(move |triangle| {
+            let corner = |i: u32| self.vertices.get(i as usize).map(|v| v.position);
+            Some([
+                corner(triangle[0])?,
+                corner(triangle[1])?,
+                corner(triangle[2])?,
+            ])
+        })
+    }
+
+    /// `None` for a mesh without any vertices
+    pub fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(self.vertices.iter().map(|v| v.position))
+    }
+
+    /// the same vertices without their normals, for drawing unlit with the "basic" pipeline
+    pub fn basic_vertices(&self) -> Vec<BasicVertex> {
+        self.vertices
+            .iter()
+            .map(|vertex| (vertex.position, vertex.color).into())
+            .collect()
+    }
+
+    /// adds `other`'s triangles after this mesh's own
+    pub fn append(&mut self, other: &MeshData) {
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices
+            .extend(other.indices.iter().map(|index| index + offset));
+    }
+
+    /// Moves every vertex by `matrix`. Normals are moved by its inverse transpose so they stay
+    /// at right angles to the surface when it's scaled unevenly.
+    pub fn transform(&mut self, matrix: &Mat4) {
+        let normal_matrix = matrix.invert().unwrap_or(*matrix).transpose();
+        for vertex in self.vertices.iter_mut() {
+            vertex.position = (*matrix * vertex.position.vec4()).vec3();
+            let normal = (normal_matrix
+                * Vec4::new(vertex.normal.x, vertex.normal.y, vertex.normal.z, 0.))
+            .vec3();
+            if normal.magnitude() > 0. {
+                vertex.normal = normal.unit_vector();
+            }
+        }
+    }
+
+    /// Replaces every normal with the average of the faces around its vertex, weighted by
+    /// their area. Vertices which aren't shared between faces end up with flat shading.
+    pub fn smooth_normals(&mut self) {
+        let mut normals = vec![Vec3::new_from_one(0); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+            if a.max(b).max(c) >= self.vertices.len() {
+                continue;
+            }
+            let (p0, p1, p2) = (
+                self.vertices[a].position,
+                self.vertices[b].position,
+                self.vertices[c].position,
+            );
+            // twice the triangle's area long
+            let face = (p1 - p0).cross(&(p2 - p0));
+            for i in [a, b, c] {
+                normals[i] += face;
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = if normal.magnitude() > 0. {
+                normal.unit_vector()
+            } else {
+                Vec3::new(0, 1, 0)
+            };
+        }
+    }
+
+    /// like `Cuboid::ray_pick` with the mesh moved by `model`
+    pub fn ray_pick(&self, model: &Mat4, origin: Vec3, direction: Vec3) -> Option<(Vec3, f32)> {
+        let world = |point: Vec3| (*model * point.vec4()).vec3();
+
+        self.triangles()
+            .filter_map(|[p0, p1, p2]| {
+                ray_triangle(origin, direction, world(p0), world(p1), world(p2))
+            })
+            .min_by(|a, b| a.total_cmp(b))
+            .map(|t| ((t * direction) + origin, t))
+    }
+}
+
+/// Draws any `MeshData`, such as one loaded by `asset::load_mesh`
+#[derive(Debug, Clone)]
+pub struct MeshEntity<'a> {
+    mesh: MeshData,
+    /// around the mesh in its own space, `None` when it's empty
+    local_aabb: Option<Aabb>,
+    draw_call_handle: Option<DrawCallHandle<'a>>,
+    pub topology: Topology,
+    pub position: Vec3,
+    pub rotation: Mat4,
+    pub moused_over: bool,
+    pub selected: bool,
+}
+
+impl<'a> MeshEntity<'a> {
+    pub fn new(mesh: MeshData, position: Vec3) -> Self {
+        Self {
+            local_aabb: mesh.aabb(),
+            mesh,
+            draw_call_handle: None,
+            topology: Topology::TriangleList(PolygonMode::Fill),
+            position,
+            rotation: Mat4::identity(),
+            moused_over: false,
+            selected: false,
+        }
+    }
+
+    pub fn mesh(&self) -> &MeshData {
+        &self.mesh
+    }
+
+    /// see `MeshData::ray_pick`
+    pub fn ray_pick(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, f32)> {
+        self.mesh.ray_pick(&self.model_matrix(), origin, direction)
+    }
+
+    /// world space box around the mesh as it's turned right now
+    pub fn aabb(&self) -> Option<Aabb> {
+        self.local_aabb
+            .map(|aabb| aabb.transformed(&self.model_matrix()))
+    }
+
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        self.aabb().map(|aabb| BoundingSphere::from_aabb(&aabb))
+    }
+}
+
+impl<'a> Entity for MeshEntity<'a> {
+    fn update(&mut self, _ctx: &mut Context) {
+        self.moused_over = false;
+    }
+}
+
+impl<'a> DrawComponent<'a> for MeshEntity<'a> {
+    fn register(&mut self, ctx: &mut Context<'a>) {
+        // wgpu won't take an empty buffer write
+        if self.mesh.is_empty() {
+            return;
+        }
+
+        self.draw_call_handle = Some(rendering::register_indexed(
+            ctx,
+            &["reflection", "refraction", "shaded"],
+            "shaded",
+            self.topology,
+            &self.mesh.vertices,
+            &self.mesh.indices,
+            0..1,
+            Some(PushConstant::vertex_data(0, &[self.model_matrix()])),
+            None,
+        ));
+    }
+
+    fn unregister(&mut self, ctx: &mut Context<'a>) {
+        if let Some(draw_call_handle) = self.draw_call_handle.take() {
+            rendering::hide(ctx, &draw_call_handle);
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context<'a>) {
+        if let Some(draw_call_handle) = self.draw_call_handle {
+            draw_call_handle.set_push_constant_data(ctx, &[self.model_matrix()]);
+        }
+    }
+
+    fn debug_draw(&mut self, _ctx: &mut Context) {}
+}
+
+impl<'a> MouseComponent for MeshEntity<'a> {
+    fn click_start(&mut self, _ctx: &mut Context) {
+        self.selected = true;
+    }
+
+    fn click_end(&mut self, _ctx: &mut Context) {
+        self.selected = false;
+    }
+
+    fn mouse_over(&mut self, _ctx: &mut Context, _pos: Vec3, _camera: &Camera) {
+        self.moused_over = true;
+    }
+
+    fn check_collision(
+        &mut self,
+        _ctx: &mut Context,
+        camera_origin: Vec3,
+        mouse_direction: Vec3,
+    ) -> Option<MousePick<'_>> {
+        self.ray_pick(camera_origin, mouse_direction)
+            .map(move |(point, t)| MousePick::new(self, point, t))
+    }
+}
+
+impl<'a> Drawable for MeshEntity<'a> {
+    fn model_matrix(&self) -> Mat4 {
+        Mat4::translation::<f32>(self.position.into()) * self.rotation
+    }
+
+    fn rotate(&mut self, theta: f32, axis: Vec3) {
+        let rot = Mat4::rotation(theta, axis);
+        self.rotation = rot * self.rotation;
+    }
+
+    fn translate(&mut self, tuple: (f32, f32, f32)) {
+        self.position += tuple.into();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn quad() -> MeshData {
+        let color = Color::new(255, 255, 255);
+        let up = Vec3::new(0, 1, 0);
+        MeshData {
+            vertices: [(0, 0, 0), (1, 0, 0), (1, 0, -1), (0, 0, -1)]
+                .map(|corner| (corner.into(), color, up).into())
+                .to_vec(),
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+
+    #[test]
+    fn transforms_normals() {
+        // a slope, squashing it along y makes it less steep and its normal steeper
+        let mut slope = MeshData {
+            vertices: vec![
+                (Vec3::new(0, 0, 0), Color::new(0, 0, 0), Vec3::new(0, 0, 0)).into(),
+                (Vec3::new(1, 1, 0), Color::new(0, 0, 0), Vec3::new(0, 0, 0)).into(),
+                (Vec3::new(0, 0, -1), Color::new(0, 0, 0), Vec3::new(0, 0, 0)).into(),
+            ],
+            indices: vec![0, 1, 2],
+        };
+        slope.smooth_normals();
+        let normal = slope.vertices[0].normal;
+        assert!((normal - Vec3::new(-1, 1, 0).unit_vector()).magnitude() < 1e-5);
+
+        slope.transform(&Mat4::scalar(1, 0.5, 1));
+        let [p0, p1, p2] = slope.triangles().next().unwrap();
+        let face = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+        assert!((slope.vertices[0].normal - face).magnitude() < 1e-5);
+
+        let mut moved = quad();
+        moved.transform(&Mat4::translation((0., 5., 0.)));
+        assert_eq!(moved.vertices[2].position, Vec3::new(1, 5, -1));
+        assert_eq!(moved.vertices[2].normal, Vec3::new(0, 1, 0));
+    }
+
+    #[test]
+    fn appends_and_picks() {
+        let mut mesh = quad();
+        let mut raised = quad();
+        raised.transform(&Mat4::translation((0., 1., 0.)));
+        mesh.append(&raised);
+        assert_eq!(mesh.vertices.len(), 8);
+        assert_eq!(&mesh.indices[6..], &[4, 5, 6, 4, 6, 7]);
+        assert_eq!(mesh.triangle_count(), 4);
+
+        // straight down onto the raised quad first
+        let entity = MeshEntity::new(mesh, Vec3::new(10, 0, 0));
+        let down = Vec3::new(0, -1, 0);
+        let (point, t) = entity.ray_pick(Vec3::new(10.5, 5, -0.5), down).unwrap();
+        assert!((point - Vec3::new(10.5, 1, -0.5)).magnitude() < 1e-5);
+        assert!((t - 4.).abs() < 1e-5);
+        assert!(entity.ray_pick(Vec3::new(0.5, 5, -0.5), down).is_none());
+
+        let aabb = entity.aabb().unwrap();
+        assert_eq!(aabb.min, Vec3::new(10, 0, -1));
+        assert_eq!(aabb.max, Vec3::new(11, 1, 0));
+        assert!(MeshEntity::new(MeshData::default(), Vec3::new_from_one(0))
+            .bounding_sphere()
+            .is_none());
+    }
+}
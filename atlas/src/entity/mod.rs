@@ -4,13 +4,22 @@ use pantheon::math::Vec3;
 pub mod component;
 // use component::AsComponent;
 use super::camera::Camera;
+use crate::physics::Shape;
+use crate::snapshot::EntityState;
+use crate::spatial::BoundingSphere;
 use component::*;
 use cube::Cuboid;
 use enum_dispatch::enum_dispatch;
+use player::Player;
+use primitive::Primitive;
 use sun::Sun;
 
 pub mod cube;
+pub mod id;
+pub mod mesh;
 pub mod plane;
+pub mod player;
+pub mod primitive;
 pub mod sun;
 pub mod terrain;
 pub mod triangle;
@@ -41,13 +50,68 @@ pub trait Entity {
 pub enum EntityKind<'a> {
     Cuboid(Cuboid<'a>),
     Sun(Sun<'a>),
+    Player(Player<'a>),
+    Primitive(Primitive<'a>),
     //Plane,
     //Triangle,
 }
 
-// scaffolding to allow for undoable/redoable actions
-//#[enum_dispatch(CommandKind)]
-pub trait Command {
-    fn execute(&mut self);
-    fn undo(&mut self);
+impl<'a> EntityKind<'a> {
+    pub fn state(&self) -> EntityState {
+        match self {
+            EntityKind::Cuboid(cube) => cube.state(),
+            EntityKind::Sun(sun) => sun.state(),
+            EntityKind::Player(player) => player.state(),
+            EntityKind::Primitive(primitive) => primitive.state(),
+        }
+    }
+
+    pub fn apply_state(&mut self, changed: u8, state: &EntityState) {
+        match self {
+            EntityKind::Cuboid(cube) => cube.apply_state(changed, state),
+            EntityKind::Sun(sun) => sun.apply_state(changed, state),
+            EntityKind::Player(player) => player.apply_state(changed, state),
+            EntityKind::Primitive(primitive) => primitive.apply_state(changed, state),
+        }
+    }
+
+    /// see `Cuboid::selected`
+    pub fn selected(&self) -> bool {
+        match self {
+            EntityKind::Cuboid(cube) => cube.selected,
+            EntityKind::Sun(sun) => sun.cube.selected,
+            EntityKind::Player(player) => player.cube.selected,
+            EntityKind::Primitive(primitive) => primitive.selected,
+        }
+    }
+
+    /// see `Cuboid::bounding_sphere`
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        match self {
+            EntityKind::Cuboid(cube) => cube.bounding_sphere(),
+            EntityKind::Sun(sun) => sun.cube.bounding_sphere(),
+            EntityKind::Player(player) => player.cube.bounding_sphere(),
+            EntityKind::Primitive(primitive) => primitive.bounding_sphere(),
+        }
+    }
+
+    /// see `Cuboid::shape`, everything but primitives collides as its cube
+    pub fn shape(&self) -> Shape {
+        match self {
+            EntityKind::Cuboid(cube) => cube.shape(),
+            EntityKind::Sun(sun) => sun.cube.shape(),
+            EntityKind::Player(player) => player.cube.shape(),
+            EntityKind::Primitive(primitive) => primitive.shape(),
+        }
+    }
+
+    /// see `Cuboid::ray_pick`, everything but primitives is picked by its cube
+    pub fn ray_pick(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, f32)> {
+        match self {
+            EntityKind::Cuboid(cube) => cube.ray_pick(origin, direction),
+            EntityKind::Sun(sun) => sun.cube.ray_pick(origin, direction),
+            EntityKind::Player(player) => player.cube.ray_pick(origin, direction),
+            EntityKind::Primitive(primitive) => primitive.ray_pick(origin, direction),
+        }
+    }
 }
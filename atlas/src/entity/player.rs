@@ -0,0 +1,307 @@
+use super::component::*;
+use super::cube::Cuboid;
+use super::Camera;
+use super::Entity;
+use crate::message::PlayerInput;
+use crate::physics::{CharacterController, CharacterState, Shape};
+use crate::snapshot::{fields, EntityState};
+use crate::vertex::VertexKind;
+use pantheon::context::Context;
+use pantheon::{Color, Mat4, Vec3};
+
+/// edge length of the cube players are drawn as
+pub const PLAYER_SIZE: f32 = 1.;
+/// units per second
+pub const PLAYER_MAX_SPEED: f32 = 10.;
+/// longest stretch of time a single input is allowed to cover
+pub const MAX_INPUT_DT: f32 = 0.25;
+/// how far away a collider can be and still be in the way of a single input
+pub const OBSTACLE_REACH: f32 = PLAYER_MAX_SPEED * MAX_INPUT_DT + PLAYER_SIZE;
+/// from the player's center up to where a first person camera sits
+pub const PLAYER_EYE_HEIGHT: f32 = 0.4;
+/// how players move, a capsule just inside the cube they're drawn as
+pub const PLAYER_CONTROLLER: CharacterController = CharacterController {
+    radius: 0.45,
+    height: PLAYER_SIZE,
+    step_height: 0.35,
+    max_slope: 0.8,
+    jump_speed: 6.,
+    gravity: 20.,
+};
+
+/// Where a single `PlayerInput` takes a player, see `step`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Step {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// the part of the input's `dt` which was actually used
+    pub dt: f32,
+    /// whether the player ended up standing on something, only then can it jump
+    pub grounded: bool,
+}
+
+impl Step {
+    /// the body only turns with the yaw
+    pub fn rotation(&self) -> Mat4 {
+        Mat4::rotation(self.yaw, (0, 1, 0).into())
+    }
+}
+
+/// Moves a player at `position` going at `velocity` and looking along `yaw` and `pitch` by a
+/// single input with `PLAYER_CONTROLLER`. The input is clamped to `PLAYER_MAX_SPEED` and
+/// `MAX_INPUT_DT` and only walks along the ground, anything vertical in it is dropped.
+/// `ground_height` returns the terrain height at an x/z position, or `None` when it's off the
+/// terrain which players can't walk onto. `obstacles` are the colliders around the player,
+/// not including its own.
+pub fn step(
+    input: &PlayerInput,
+    position: Vec3,
+    velocity: Vec3,
+    yaw: f32,
+    pitch: f32,
+    ground_height: impl Fn(f32, f32) -> Option<f32>,
+    obstacles: &[Shape],
+) -> Step {
+    let dt = if input.dt.is_finite() {
+        input.dt.clamp(0., MAX_INPUT_DT)
+    } else {
+        0.
+    };
+
+    let mut walk = if input.velocity.magnitude().is_finite() {
+        Vec3::new(input.velocity.x, 0., input.velocity.z)
+    } else {
+        Vec3::new_from_one(0)
+    };
+    let speed = walk.magnitude();
+    if speed > PLAYER_MAX_SPEED {
+        walk *= PLAYER_MAX_SPEED / speed;
+    }
+
+    let (yaw, pitch) = if input.yaw.is_finite() && input.pitch.is_finite() {
+        (
+            input.yaw % std::f32::consts::TAU,
+            input
+                .pitch
+                .clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2),
+        )
+    } else {
+        (yaw, pitch)
+    };
+
+    let state = PLAYER_CONTROLLER.step(
+        CharacterState::new(position, velocity),
+        walk,
+        input.jumps(),
+        dt,
+        ground_height,
+        obstacles,
+    );
+
+    Step {
+        position: state.position,
+        velocity: state.velocity,
+        yaw,
+        pitch,
+        dt,
+        grounded: state.grounded,
+    }
+}
+
+/// A client's avatar, only ever moved by the server applying that client's `PlayerInput`s so
+/// everyone else sees the validated position
+#[derive(Debug, Copy, Clone)]
+pub struct Player<'a> {
+    pub cube: Cuboid<'a>,
+    pub velocity: Vec3,
+    /// radians around the y axis
+    pub yaw: f32,
+    /// radians, only used for where the player is looking, the body stays upright
+    pub pitch: f32,
+    /// whether it was standing on something after the last input
+    pub grounded: bool,
+}
+
+impl<'a> Player<'a> {
+    pub fn new(position: Vec3, color: Color) -> Self {
+        Self {
+            cube: Cuboid::cube(PLAYER_SIZE, position, Some(color), VertexKind::Shaded, None),
+            velocity: Vec3::new_from_one(0),
+            yaw: 0.,
+            pitch: 0.,
+            grounded: false,
+        }
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.cube.position
+    }
+
+    /// Moves the player by a single input, see `step`. Returns the `dt` which was actually used.
+    pub fn apply_input(
+        &mut self,
+        input: &PlayerInput,
+        ground_height: impl Fn(f32, f32) -> Option<f32>,
+        obstacles: &[Shape],
+    ) -> f32 {
+        let step = step(
+            input,
+            self.cube.position,
+            self.velocity,
+            self.yaw,
+            self.pitch,
+            ground_height,
+            obstacles,
+        );
+
+        self.velocity = step.velocity;
+        self.yaw = step.yaw;
+        self.pitch = step.pitch;
+        self.grounded = step.grounded;
+        self.cube.position = step.position;
+        self.cube.rotation = step.rotation();
+
+        step.dt
+    }
+
+    pub fn state(&self) -> EntityState {
+        EntityState {
+            velocity: self.velocity,
+            ..self.cube.state()
+        }
+    }
+
+    pub fn apply_state(&mut self, changed: u8, state: &EntityState) {
+        self.cube.apply_state(changed, state);
+        if changed & fields::VELOCITY != 0 {
+            self.velocity = state.velocity;
+        }
+    }
+}
+
+impl<'a> Entity for Player<'a> {
+    // players only move when the server says so, unlike a plain `Cuboid` they don't spin
+    fn update(&mut self, _ctx: &mut Context) {}
+}
+
+impl<'a> DrawComponent<'a> for Player<'a> {
+    fn register(&mut self, ctx: &mut Context<'a>) {
+        self.cube.register(ctx);
+    }
+
+    fn unregister(&mut self, ctx: &mut Context<'a>) {
+        self.cube.unregister(ctx);
+    }
+
+    fn draw(&mut self, ctx: &mut Context<'a>) {
+        self.cube.draw(ctx);
+    }
+
+    fn debug_draw(&mut self, ctx: &mut Context<'a>) {
+        self.cube.debug_draw(ctx);
+    }
+}
+
+impl<'a> MouseComponent for Player<'a> {
+    fn click_start(&mut self, ctx: &mut Context) {
+        self.cube.click_start(ctx);
+    }
+
+    fn click_end(&mut self, ctx: &mut Context) {
+        self.cube.click_end(ctx);
+    }
+
+    fn mouse_over(&mut self, ctx: &mut Context, pos: Vec3, cam: &Camera) {
+        self.cube.mouse_over(ctx, pos, cam);
+    }
+
+    fn check_collision(
+        &mut self,
+        ctx: &mut Context,
+        camera_origin: Vec3,
+        mouse_direction: Vec3,
+    ) -> Option<MousePick> {
+        self.cube
+            .check_collision(ctx, camera_origin, mouse_direction)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn input(velocity: Vec3, dt: f32) -> PlayerInput {
+        PlayerInput {
+            sequence: 0,
+            velocity,
+            yaw: 0.,
+            pitch: 0.,
+            dt,
+            jump: 0,
+        }
+    }
+
+    #[test]
+    fn speed_and_dt_are_clamped() {
+        let mut player = Player::new(Vec3::new(0, 10, 0), Color::new(255, 0, 0));
+        let dt = player.apply_input(&input(Vec3::new(1000, 0, 0), 10.), |_, _| Some(0.), &[]);
+
+        assert_eq!(dt, MAX_INPUT_DT);
+        assert!((player.position().x - PLAYER_MAX_SPEED * MAX_INPUT_DT).abs() < 1e-4);
+        assert!((player.velocity.x - PLAYER_MAX_SPEED).abs() < 1e-4);
+    }
+
+    #[test]
+    fn stays_above_ground() {
+        let mut player = Player::new(Vec3::new(0, 20, 0), Color::new(255, 0, 0));
+        // walking can't push a player down, or up
+        let dt = player.apply_input(
+            &input(Vec3::new(0, -PLAYER_MAX_SPEED, 0), 0.25),
+            |_, _| Some(9.),
+            &[],
+        );
+        assert!((player.position().y - (20. - 10. * dt * dt)).abs() < 0.1);
+        for _ in 0..8 {
+            player.apply_input(
+                &input(Vec3::new(0, PLAYER_MAX_SPEED, 0), 0.25),
+                |_, _| Some(9.),
+                &[],
+            );
+        }
+        assert_eq!(player.position().y, 9. + PLAYER_SIZE / 2.);
+
+        // walking off the edge of the terrain stops at the edge
+        player.apply_input(
+            &input(Vec3::new(PLAYER_MAX_SPEED, 0, 0), 0.25),
+            |x, _| if x > 1. { None } else { Some(9.) },
+            &[],
+        );
+        assert!(player.position().x > 0. && player.position().x <= 1.);
+    }
+
+    #[test]
+    fn jumps() {
+        let mut player = Player::new(Vec3::new(0, PLAYER_SIZE / 2., 0), Color::new(255, 0, 0));
+        let jump = PlayerInput {
+            // any non-zero byte jumps
+            jump: 0xff,
+            ..input(Vec3::new_from_one(0), 0.1)
+        };
+        player.apply_input(&jump, |_, _| Some(0.), &[]);
+        assert!(player.position().y > PLAYER_SIZE / 2.);
+        assert!(player.velocity.y > 0.);
+    }
+
+    #[test]
+    fn rejects_garbage_input() {
+        let mut player = Player::new(Vec3::new(0, 10, 0), Color::new(255, 0, 0));
+        player.apply_input(
+            &input(Vec3::new(f32::NAN, 0, 0), f32::INFINITY),
+            |_, _| Some(0.),
+            &[],
+        );
+        assert_eq!(player.position(), Vec3::new(0, 10, 0));
+    }
+}
@@ -0,0 +1,525 @@
+use super::component::*;
+use super::cube::box_outline;
+use super::mesh::MeshData;
+use super::Camera;
+use super::Entity;
+use crate::ecs::components::{Collider, Transform};
+use crate::physics::Shape;
+use crate::proc_gen::primitives::{self, MAX_SEGMENTS, MAX_SUBDIVISIONS};
+use crate::rendering;
+use crate::snapshot::{fields, EntityState};
+use crate::spatial::{Aabb, BoundingSphere};
+use crate::vertex::*;
+use pantheon::context::Context;
+use pantheon::graphics::prelude::*;
+use pantheon::graphics::Drawable;
+use pantheon::math::*;
+
+/// What a `Primitive` is drawn as, centered on its position with y up. Only the parameters are
+/// kept so it stays `Copy` for snapshots, the mesh is generated whenever it's needed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrimitiveShape {
+    UvSphere {
+        radius: f32,
+        segments: u32,
+        rings: u32,
+    },
+    Icosphere {
+        radius: f32,
+        subdivisions: u32,
+    },
+    Cylinder {
+        radius: f32,
+        height: f32,
+        segments: u32,
+    },
+    Cone {
+        radius: f32,
+        height: f32,
+        segments: u32,
+    },
+    /// `height` from the very bottom to the very top
+    Capsule {
+        radius: f32,
+        height: f32,
+        segments: u32,
+        rings: u32,
+    },
+    /// `radius` out to the middle of the tube
+    Torus {
+        radius: f32,
+        tube_radius: f32,
+        segments: u32,
+        sides: u32,
+    },
+    Plane {
+        size: f32,
+        subdivisions: u32,
+    },
+}
+
+impl PrimitiveShape {
+    pub const NAMES: [&'static str; 7] = [
+        "sphere",
+        "icosphere",
+        "cylinder",
+        "cone",
+        "capsule",
+        "torus",
+        "plane",
+    ];
+
+    /// One of `NAMES` about `size` across with a reasonable amount of detail
+    pub fn from_name(name: &str, size: f32) -> Option<Self> {
+        let radius = size / 2.;
+        let shape = match name {
+            "sphere" => Self::UvSphere {
+                radius,
+                segments: 24,
+                rings: 12,
+            },
+            "icosphere" => Self::Icosphere {
+                radius,
+                subdivisions: 2,
+            },
+            "cylinder" => Self::Cylinder {
+                radius,
+                height: size,
+                segments: 24,
+            },
+            "cone" => Self::Cone {
+                radius,
+                height: size,
+                segments: 24,
+            },
+            "capsule" => Self::Capsule {
+                radius: size / 4.,
+                height: size,
+                segments: 16,
+                rings: 6,
+            },
+            "torus" => Self::Torus {
+                radius: size * 0.35,
+                tube_radius: size * 0.15,
+                segments: 32,
+                sides: 12,
+            },
+            "plane" => Self::Plane {
+                size,
+                subdivisions: 8,
+            },
+            _ => return None,
+        };
+
+        Some(shape)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UvSphere { .. } => "sphere",
+            Self::Icosphere { .. } => "icosphere",
+            Self::Cylinder { .. } => "cylinder",
+            Self::Cone { .. } => "cone",
+            Self::Capsule { .. } => "capsule",
+            Self::Torus { .. } => "torus",
+            Self::Plane { .. } => "plane",
+        }
+    }
+
+    pub fn mesh(&self, color: Color) -> MeshData {
+        match *self {
+            Self::UvSphere {
+                radius,
+                segments,
+                rings,
+            } => primitives::uv_sphere(radius, segments, rings, color),
+            Self::Icosphere {
+                radius,
+                subdivisions,
+            } => primitives::icosphere(radius, subdivisions, color),
+            Self::Cylinder {
+                radius,
+                height,
+                segments,
+            } => primitives::cylinder(radius, height, segments, color),
+            Self::Cone {
+                radius,
+                height,
+                segments,
+            } => primitives::cone(radius, height, segments, color),
+            Self::Capsule {
+                radius,
+                height,
+                segments,
+                rings,
+            } => primitives::capsule(radius, height, segments, rings, color),
+            Self::Torus {
+                radius,
+                tube_radius,
+                segments,
+                sides,
+            } => primitives::torus(radius, tube_radius, segments, sides, color),
+            Self::Plane { size, subdivisions } => primitives::plane(size, subdivisions, color),
+        }
+    }
+
+    /// how far the box around the shape reaches out from its center
+    pub fn half_extents(&self) -> Vec3 {
+        match *self {
+            Self::UvSphere { radius, .. } | Self::Icosphere { radius, .. } => {
+                Vec3::new_from_one(radius)
+            }
+            Self::Cylinder { radius, height, .. } | Self::Cone { radius, height, .. } => {
+                Vec3::new(radius, height / 2., radius)
+            }
+            Self::Capsule { radius, height, .. } => {
+                Vec3::new(radius, (height / 2.).max(radius), radius)
+            }
+            Self::Torus {
+                radius,
+                tube_radius,
+                ..
+            } => Vec3::new(radius + tube_radius, tube_radius, radius + tube_radius),
+            Self::Plane { size, .. } => Vec3::new(size / 2., 0, size / 2.),
+        }
+    }
+
+    pub fn bounding_radius(&self) -> f32 {
+        match *self {
+            Self::UvSphere { radius, .. } | Self::Icosphere { radius, .. } => radius,
+            _ => self.half_extents().magnitude(),
+        }
+    }
+
+    /// spheres collide as themselves and everything else as the box around it
+    pub fn collider(&self) -> Collider {
+        match *self {
+            Self::UvSphere { radius, .. } | Self::Icosphere { radius, .. } => {
+                Collider::sphere(radius)
+            }
+            _ => Collider::Box {
+                half_extents: self.half_extents(),
+            },
+        }
+    }
+
+    /// Sizes have to be positive and there can't be more detail than the generators allow.
+    /// Anything coming in over the network is checked with this first.
+    pub fn is_valid(&self) -> bool {
+        let size = |size: f32| size.is_finite() && size > 0.;
+        let detail = |count: u32| count <= MAX_SEGMENTS;
+        match *self {
+            Self::UvSphere {
+                radius,
+                segments,
+                rings,
+            } => size(radius) && detail(segments) && detail(rings),
+            Self::Icosphere {
+                radius,
+                subdivisions,
+            } => size(radius) && subdivisions <= MAX_SUBDIVISIONS,
+            Self::Cylinder {
+                radius,
+                height,
+                segments,
+            }
+            | Self::Cone {
+                radius,
+                height,
+                segments,
+            } => size(radius) && size(height) && detail(segments),
+            Self::Capsule {
+                radius,
+                height,
+                segments,
+                rings,
+            } => size(radius) && size(height) && detail(segments) && detail(rings),
+            Self::Torus {
+                radius,
+                tube_radius,
+                segments,
+                sides,
+            } => size(radius) && size(tube_radius) && detail(segments) && detail(sides),
+            Self::Plane {
+                size: side,
+                subdivisions,
+            } => size(side) && detail(subdivisions),
+        }
+    }
+}
+
+/// A `PrimitiveShape` in the world, picked and networked the same way as a `Cuboid`
+#[derive(Debug, Copy, Clone)]
+pub struct Primitive<'a> {
+    pub primitive: PrimitiveShape,
+    color: Color,
+    vertex_kind: VertexKind,
+    draw_call_handle: Option<DrawCallHandle<'a>>,
+    /// registered hidden along with the mesh, only drawn while it's `selected`
+    outline: Option<DrawCallHandle<'a>>,
+    pub topology: Topology,
+    pub position: Vec3,
+    pub rotation: Mat4,
+    pub moused_over: bool,
+    pub selected: bool,
+}
+
+impl<'a> Primitive<'a> {
+    pub fn new(
+        primitive: PrimitiveShape,
+        position: Vec3,
+        color: Option<Color>,
+        vertex_kind: VertexKind,
+        topology: Option<Topology>,
+    ) -> Self {
+        Self {
+            primitive,
+            color: color.unwrap_or(Color::new(60, 60, 60)),
+            vertex_kind,
+            draw_call_handle: None,
+            outline: None,
+            topology: topology.unwrap_or(Topology::TriangleList(PolygonMode::Fill)),
+            position,
+            rotation: Mat4::identity(),
+            moused_over: false,
+            selected: false,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    pub fn set_color(&mut self, new_color: Color) {
+        self.color = new_color;
+    }
+
+    pub fn vertex_kind(&self) -> VertexKind {
+        self.vertex_kind
+    }
+
+    /// in the primitive's own space
+    pub fn mesh(&self) -> MeshData {
+        self.primitive.mesh(self.color)
+    }
+
+    pub fn state(&self) -> EntityState {
+        EntityState {
+            position: self.position,
+            rotation: self.rotation,
+            color: self.color,
+            velocity: Vec3::new_from_one(0),
+        }
+    }
+
+    /// only the `fields` set in `changed` are copied over
+    pub fn apply_state(&mut self, changed: u8, state: &EntityState) {
+        if changed & fields::POSITION != 0 {
+            self.position = state.position;
+        }
+        if changed & fields::ROTATION != 0 {
+            self.rotation = state.rotation;
+        }
+        if changed & fields::COLOR != 0 {
+            self.set_color(state.color);
+        }
+    }
+
+    /// like `Cuboid::ray_pick`, against the generated mesh
+    pub fn ray_pick(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, f32)> {
+        self.mesh()
+            .ray_pick(&self.model_matrix(), origin, direction)
+    }
+
+    /// world space box around the primitive as it's turned right now
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_center(Vec3::new_from_one(0), self.primitive.half_extents())
+            .transformed(&self.model_matrix())
+    }
+
+    /// the primitive as something to bump into, see `PrimitiveShape::collider`
+    pub fn shape(&self) -> Shape {
+        let transform = Transform {
+            rotation: Quaternion::from_rotation_matrix(&self.rotation),
+            ..Transform::new(self.position)
+        };
+        Shape::new(&self.primitive.collider(), &transform)
+    }
+
+    /// around the primitive however it's turned, every shape is centered on its position
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere::new(self.position, self.primitive.bounding_radius())
+    }
+}
+
+impl<'a> Entity for Primitive<'a> {
+    fn update(&mut self, _ctx: &mut Context) {
+        self.moused_over = false;
+    }
+}
+
+impl<'a> DrawComponent<'a> for Primitive<'a> {
+    fn register(&mut self, ctx: &mut Context<'a>) {
+        let mesh = self.mesh();
+        let push_constant = Some(PushConstant::vertex_data(0, &[self.model_matrix()]));
+
+        self.draw_call_handle = Some(match self.vertex_kind {
+            VertexKind::Basic => rendering::register_indexed(
+                ctx,
+                &["reflection", "refraction", "shaded"],
+                "basic",
+                self.topology,
+                &mesh.basic_vertices(),
+                &mesh.indices,
+                0..1,
+                push_constant,
+                None,
+            ),
+            _ => rendering::register_indexed(
+                ctx,
+                &["reflection", "refraction", "shaded"],
+                "shaded",
+                self.topology,
+                &mesh.vertices,
+                &mesh.indices,
+                0..1,
+                push_constant,
+                None,
+            ),
+        });
+
+        // see `Cuboid::register`
+        self.outline = Some(rendering::register(
+            ctx,
+            &["shaded"],
+            "basic",
+            Topology::LineList(PolygonMode::Fill),
+            &box_outline(1.04 * self.primitive.half_extents()),
+            0..0,
+            Some(PushConstant::vertex_data(0, &[self.model_matrix()])),
+            None,
+        ));
+    }
+
+    fn unregister(&mut self, ctx: &mut Context<'a>) {
+        if let Some(draw_call_handle) = self.draw_call_handle.take() {
+            rendering::hide(ctx, &draw_call_handle);
+        }
+        if let Some(outline) = self.outline.take() {
+            rendering::hide(ctx, &outline);
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context<'a>) {
+        if let Some(draw_call_handle) = self.draw_call_handle {
+            draw_call_handle.set_push_constant_data(ctx, &[self.model_matrix()]);
+        }
+        if let Some(outline) = self.outline {
+            if self.selected {
+                outline.set_push_constant_data(ctx, &[self.model_matrix()]);
+                rendering::show(ctx, &outline);
+            } else {
+                rendering::hide(ctx, &outline);
+            }
+        }
+    }
+
+    fn debug_draw(&mut self, _ctx: &mut Context) {}
+}
+
+impl<'a> MouseComponent for Primitive<'a> {
+    fn click_start(&mut self, _ctx: &mut Context) {
+        self.selected = true;
+    }
+
+    fn click_end(&mut self, _ctx: &mut Context) {
+        self.selected = false;
+    }
+
+    fn mouse_over(&mut self, _ctx: &mut Context, _pos: Vec3, _camera: &Camera) {
+        self.moused_over = true;
+    }
+
+    fn check_collision(
+        &mut self,
+        _ctx: &mut Context,
+        camera_origin: Vec3,
+        mouse_direction: Vec3,
+    ) -> Option<MousePick<'_>> {
+        self.ray_pick(camera_origin, mouse_direction)
+            .map(move |(point, t)| MousePick::new(self, point, t))
+    }
+}
+
+impl<'a> Drawable for Primitive<'a> {
+    fn model_matrix(&self) -> Mat4 {
+        Mat4::translation::<f32>(self.position.into()) * self.rotation
+    }
+
+    fn rotate(&mut self, theta: f32, axis: Vec3) {
+        let rot = Mat4::rotation(theta, axis);
+        self.rotation = rot * self.rotation;
+    }
+
+    fn translate(&mut self, tuple: (f32, f32, f32)) {
+        self.position += tuple.into();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for name in PrimitiveShape::NAMES {
+            let shape = PrimitiveShape::from_name(name, 2.).unwrap();
+            assert_eq!(shape.name(), name);
+            assert!(shape.is_valid());
+
+            // everything fits in the box it says it does
+            let extents = shape.half_extents();
+            let aabb = shape.mesh(Color::new(0, 0, 0)).aabb().unwrap();
+            for corner in [aabb.min, aabb.max] {
+                for (a, b) in [
+                    (corner.x, extents.x),
+                    (corner.y, extents.y),
+                    (corner.z, extents.z),
+                ] {
+                    assert!(a.abs() <= b + 1e-4, "{} pokes out of its box", name);
+                }
+            }
+            assert!(shape.bounding_radius() >= 1.);
+        }
+        assert!(PrimitiveShape::from_name("teapot", 2.).is_none());
+
+        let too_fine = PrimitiveShape::Icosphere {
+            radius: 1.,
+            subdivisions: MAX_SUBDIVISIONS + 1,
+        };
+        let inside_out = PrimitiveShape::from_name("torus", -1.).unwrap();
+        assert!(!too_fine.is_valid() && !inside_out.is_valid());
+    }
+
+    #[test]
+    fn picks_and_collides_as_its_shape() {
+        let sphere = PrimitiveShape::from_name("sphere", 2.).unwrap();
+        let mut primitive =
+            Primitive::new(sphere, Vec3::new(10, 0, 0), None, VertexKind::Shaded, None);
+        let (point, _) = primitive
+            .ray_pick(Vec3::new(0, 0, 0), Vec3::new(1, 0, 0))
+            .unwrap();
+        assert!((point.x - 9.).abs() < 0.02);
+        assert!(matches!(primitive.shape(), Shape::Sphere { radius, .. } if radius == 1.));
+
+        primitive.primitive = PrimitiveShape::from_name("cylinder", 2.).unwrap();
+        primitive.rotate(std::f32::consts::FRAC_PI_2, Vec3::new(0, 0, 1));
+        // lying on its side, so its end faces along x
+        let (point, _) = primitive
+            .ray_pick(Vec3::new(0, 0, 0), Vec3::new(1, 0, 0))
+            .unwrap();
+        assert!((point.x - 9.).abs() < 1e-4);
+        let aabb = primitive.aabb();
+        assert!((aabb.max.x - 11.).abs() < 1e-4 && (aabb.max.z - 1.).abs() < 1e-4);
+    }
+}
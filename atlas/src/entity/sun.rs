@@ -2,6 +2,7 @@ use super::Cuboid;
 use super::DrawComponent;
 use super::Entity;
 use super::MouseComponent;
+use crate::snapshot::{fields, EntityState};
 use crate::vertex::*;
 use pantheon::Vec3;
 use pantheon::{Color, Mat4, PolygonMode, Topology};
@@ -54,6 +55,20 @@ impl<'a> Sun<'a> {
     pub fn projection_matrix(&self) -> Mat4 {
         Mat4::pyramidal(90., 1.0, 1.0, 500.0)
     }
+
+    pub fn state(&self) -> EntityState {
+        EntityState {
+            color: self.color,
+            ..self.cube.state()
+        }
+    }
+
+    pub fn apply_state(&mut self, changed: u8, state: &EntityState) {
+        self.cube.apply_state(changed, state);
+        if changed & fields::COLOR != 0 {
+            self.color = state.color;
+        }
+    }
 }
 
 impl<'a> Entity for Sun<'a> {
@@ -103,6 +118,10 @@ impl<'a> Entity for Sun<'a> {
 }
 
 impl<'a> DrawComponent<'a> for Sun<'a> {
+    fn unregister(&mut self, ctx: &mut pantheon::context::Context<'a>) {
+        self.cube.unregister(ctx);
+    }
+
     fn draw(&mut self, ctx: &mut pantheon::context::Context<'a>) {
         self.cube.draw(ctx);
     }
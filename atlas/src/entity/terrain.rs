@@ -1,6 +1,8 @@
 use super::component::*;
 use super::Camera;
+use crate::message::ChunkHeader;
 use crate::rendering;
+use crate::spatial::{ray_triangle, Aabb, Bvh};
 use crate::vertex::ShadedVertex;
 use pantheon::graphics::mode::DrawMode;
 use pantheon::graphics::prelude::*;
@@ -9,6 +11,7 @@ use pantheon::graphics::Color;
 use pantheon::graphics::Drawable;
 use pantheon::graphics::PolygonMode;
 use pantheon::graphics::Topology;
+use pantheon::math::{Dim, Vec4};
 use pantheon::Vec3;
 use pantheon::{context::Context, Mat4};
 
@@ -17,10 +20,16 @@ pub struct Terrain<'a> {
     pub verts: Vec<ShadedVertex>,
     pub indices: Vec<u32>,
     pub center: Vec3,
+    /// grid heights the mesh was built from, row major with `size + 1` heights per row. Empty
+    /// for terrain that was streamed over since only the mesh is sent
+    pub heights: Vec<f32>,
     norm_debug: Vec<Vertex>,
     draw_call_handle: Option<DrawCallHandle<'a>>,
     topology: Topology,
     pub scale: f32,
+    /// the mesh's triangles by index, in the mesh's own space. Built by `init`, nothing on
+    /// the terrain can be picked before that.
+    triangles: Option<Bvh<u32>>,
 }
 
 impl<'a> Terrain<'a> {
@@ -35,9 +44,11 @@ impl<'a> Terrain<'a> {
             verts,
             indices,
             center: Vec3::new_from_one(0),
+            heights: vec![],
             draw_call_handle: None,
             topology: Topology::TriangleList(PolygonMode::Fill),
             scale: 1.0,
+            triangles: None,
         }
     }
     pub fn init(&mut self, _ctx: &mut Context) {
@@ -56,10 +67,196 @@ impl<'a> Terrain<'a> {
                     .into(),
             );
         }
+
+        self.triangles = Some(self.triangle_bvh());
+    }
+
+    fn triangle(&self, index: u32) -> [Vec3; 3] {
+        let first = index as usize * 3;
+        [0, 1, 2].map(|i| self.verts[self.indices[first + i] as usize].position)
+    }
+
+    fn triangle_bvh(&self) -> Bvh<u32> {
+        // the mesh never changes, there's no need for any room to move
+        let mut bvh = Bvh::new(0.);
+        for index in 0..(self.indices.len() / 3) as u32 {
+            if let Some(aabb) = Aabb::from_points(self.triangle(index)) {
+                bvh.insert(aabb, index);
+            }
+        }
+        bvh
+    }
+
+    /// Like `Cuboid::ray_pick`, the closest point the ray hits one of the terrain's triangles.
+    /// Until `init` has been called only terrain with its heights can be picked, through
+    /// `heightfield_ray`.
+    pub fn ray_pick(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, f32)> {
+        let triangles = match &self.triangles {
+            Some(triangles) => triangles,
+            None => return self.heightfield_ray(origin, direction),
+        };
+        // into the mesh's own space, distances along the ray stay the same
+        let inverse = self.model_matrix().invert()?;
+        let local_origin = (inverse * Vec4::from_vec3(origin)).truncate(Dim::W);
+        let local_direction =
+            (inverse * Vec4::new(direction.x, direction.y, direction.z, 0.)).truncate(Dim::W);
+
+        triangles
+            .ray(local_origin, local_direction, |index| {
+                let [p0, p1, p2] = self.triangle(*index);
+                ray_triangle(local_origin, local_direction, p0, p1, p2)
+            })
+            .map(|(_, t)| ((t * direction) + origin, t))
     }
 
     pub fn update(&mut self, _ctx: &mut Context) {}
 
+    /// World space height of the surface at `x`, `z`, following the same triangles the mesh is
+    /// drawn with. `None` when the point is off the terrain or the heights aren't known.
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let (local_x, local_z) = (
+            x / self.scale - self.center.x,
+            z / self.scale - self.center.z,
+        );
+        let [p0, p1, p2] = self.grid_triangle(local_x, local_z)?;
+        let normal = (p1 - p0).cross(&(p2 - p0));
+        // the triangle's plane solved for y
+        let height = p0.y - (normal.x * (local_x - p0.x) + normal.z * (local_z - p0.z)) / normal.y;
+
+        Some(self.scale * (height + self.center.y))
+    }
+
+    /// Unit normal of the surface at `x`, `z`, pointing up. It's flat across each triangle, so
+    /// it's the same as the mesh is lit with.
+    pub fn normal_at(&self, x: f32, z: f32) -> Option<Vec3> {
+        let (local_x, local_z) = (
+            x / self.scale - self.center.x,
+            z / self.scale - self.center.z,
+        );
+        let [p0, p1, p2] = self.grid_triangle(local_x, local_z)?;
+        let normal = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+
+        Some(if normal.y < 0. { -1. * normal } else { normal })
+    }
+
+    /// Like `ray_pick` but against the heights rather than the mesh, so it works without
+    /// `init` and doesn't need a `Bvh`. Walks the grid squares under the ray in the order it
+    /// crosses them and stops at the first triangle it hits.
+    pub fn heightfield_ray(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, f32)> {
+        let row_len = self.row_len()?;
+        let last = (row_len - 1) as f32;
+        // into grid space, t is the same either way
+        let local_origin = (1. / self.scale) * origin - self.center;
+        let local_direction = (1. / self.scale) * direction;
+
+        let (lowest, highest) = self
+            .heights
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), height| {
+                (low.min(*height), high.max(*height))
+            });
+        let bounds = Aabb::new(Vec3::new(0., lowest, 0.), Vec3::new(last, highest, last));
+        let enter = bounds.ray(local_origin, local_direction)?;
+        let start = (enter * local_direction) + local_origin;
+
+        let cell = |position: f32| (position.floor().max(0.) as usize).min(row_len - 2);
+        let (mut col, mut row) = (cell(start.x), cell(start.z));
+        // how far along the ray the next column and row boundaries are, and how far apart
+        let crossing = |position: f32, direction: f32, cell: usize| {
+            if direction.abs() < f32::EPSILON {
+                (f32::INFINITY, f32::INFINITY)
+            } else {
+                let boundary = if direction > 0. { cell + 1 } else { cell } as f32;
+                ((boundary - position) / direction, 1. / direction.abs())
+            }
+        };
+        let (mut next_x, step_x) = crossing(local_origin.x, local_direction.x, col);
+        let (mut next_z, step_z) = crossing(local_origin.z, local_direction.z, row);
+
+        loop {
+            let hit = self
+                .square_triangles(col, row)
+                .iter()
+                .filter_map(|[p0, p1, p2]| {
+                    ray_triangle(local_origin, local_direction, *p0, *p1, *p2)
+                })
+                .min_by(|a, b| a.total_cmp(b));
+            if let Some(t) = hit {
+                return Some(((t * direction) + origin, t));
+            }
+
+            if next_x < next_z {
+                col = match (local_direction.x > 0., col) {
+                    (true, col) if col + 2 < row_len => col + 1,
+                    (false, col) if col > 0 => col - 1,
+                    _ => return None,
+                };
+                next_x += step_x;
+            } else {
+                row = match (local_direction.z > 0., row) {
+                    (true, row) if row + 2 < row_len => row + 1,
+                    (false, row) if row > 0 => row - 1,
+                    _ => return None,
+                };
+                next_z += step_z;
+            }
+        }
+    }
+
+    /// heights per row of the grid, `None` when there aren't any
+    fn row_len(&self) -> Option<usize> {
+        let row_len = (self.heights.len() as f64).sqrt() as usize;
+        (row_len >= 2 && row_len * row_len == self.heights.len()).then_some(row_len)
+    }
+
+    /// The triangle under grid space `x`, `z`, with its corners in grid space as well.
+    fn grid_triangle(&self, x: f32, z: f32) -> Option<[Vec3; 3]> {
+        let row_len = self.row_len()?;
+        let last = (row_len - 1) as f32;
+        if !(0. ..=last).contains(&x) || !(0. ..=last).contains(&z) {
+            return None;
+        }
+
+        // the far edges belong to the last square rather than one past it
+        let col = (x.floor() as usize).min(row_len - 2);
+        let row = (z.floor() as usize).min(row_len - 2);
+        let (fx, fz) = (x - col as f32, z - row as f32);
+
+        let [first, second] = self.square_triangles(col, row);
+        let in_first = if col % 2 != row % 2 {
+            fz >= fx
+        } else {
+            fx + fz <= 1.
+        };
+
+        Some(if in_first { first } else { second })
+    }
+
+    /// The two triangles a grid square is split into. Matches `GridSquare`, squares alternate
+    /// which diagonal they're split along.
+    fn square_triangles(&self, col: usize, row: usize) -> [[Vec3; 3]; 2] {
+        let row_len = self.row_len().unwrap_or_default();
+        let index = row * row_len + col;
+        let (x, z) = (col as f32, row as f32);
+        let p0 = Vec3::new(x, self.heights[index], z);
+        let p1 = Vec3::new(x, self.heights[index + row_len], z + 1.);
+        let p2 = Vec3::new(x + 1., self.heights[index + 1], z);
+        let p3 = Vec3::new(x + 1., self.heights[index + row_len + 1], z + 1.);
+
+        let right_handed = col % 2 != row % 2;
+        if right_handed {
+            [[p0, p1, p3], [p0, p2, p3]]
+        } else {
+            [[p0, p2, p1], [p3, p1, p2]]
+        }
+    }
+
+    pub fn unregister(&mut self, ctx: &mut Context<'a>) {
+        if let Some(draw_call_handle) = self.draw_call_handle.take() {
+            rendering::hide(ctx, &draw_call_handle);
+        }
+    }
+
     pub fn register(&mut self, ctx: &mut Context<'a>) {
         let push_constant = Some(PushConstant::vertex_data(0, &[self.model_matrix()]));
 
@@ -93,19 +290,22 @@ impl<'a> Terrain<'a> {
         );
         */
     }
+}
 
-    pub fn click_start(&mut self, _ctx: &mut Context) {}
-    pub fn click_end(&mut self, _ctx: &mut Context) {}
+impl<'a> MouseComponent for Terrain<'a> {
+    fn click_start(&mut self, _ctx: &mut Context) {}
+    fn click_end(&mut self, _ctx: &mut Context) {}
 
-    pub fn mouse_over(&mut self, _ctx: &mut Context, _pos: Vec3, _camera: &Camera) {}
+    fn mouse_over(&mut self, _ctx: &mut Context, _pos: Vec3, _camera: &Camera) {}
 
-    pub fn check_collision(
+    fn check_collision(
         &mut self,
         _ctx: &mut Context,
-        _camera_origin: Vec3,
-        _mouse_direction: Vec3,
+        camera_origin: Vec3,
+        mouse_direction: Vec3,
     ) -> Option<MousePick> {
-        None
+        self.ray_pick(camera_origin, mouse_direction)
+            .map(move |(point, t)| MousePick::new(self, point, t))
     }
 }
 
@@ -122,3 +322,210 @@ impl<'a> Drawable for Terrain<'a> {
 
     fn translate(&mut self, _tuple: (f32, f32, f32)) {}
 }
+
+/// Reassembles a `Terrain` whose vertex and index buffers are streamed over in chunks
+#[derive(Debug, Default)]
+pub struct TerrainStream {
+    verts: Vec<ShadedVertex>,
+    verts_received: usize,
+    verts_total: Option<usize>,
+    indices: Vec<u32>,
+    indices_received: usize,
+    indices_total: Option<usize>,
+    center: Option<Vec3>,
+    scale: f32,
+}
+
+impl TerrainStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_verts(&mut self, header: ChunkHeader, verts: &[ShadedVertex]) {
+        Self::copy_chunk(&mut self.verts, header, verts);
+        self.verts_received += verts.len();
+        self.verts_total = Some(header.total as usize);
+    }
+
+    pub fn push_indices(&mut self, header: ChunkHeader, indices: &[u32]) {
+        Self::copy_chunk(&mut self.indices, header, indices);
+        self.indices_received += indices.len();
+        self.indices_total = Some(header.total as usize);
+    }
+
+    /// the terrain's `center` and `scale`, sent ahead of the chunks
+    pub fn set_center(&mut self, center: Vec3, scale: f32) {
+        self.center = Some(center);
+        self.scale = scale;
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.center.is_some()
+            && self.verts_total == Some(self.verts_received)
+            && self.indices_total == Some(self.indices_received)
+    }
+
+    /// returns the rebuilt terrain once every chunk has arrived
+    pub fn finish(self) -> Option<Terrain<'static>> {
+        if !self.is_complete() {
+            return None;
+        }
+
+        let mut terrain = Terrain::from_data(self.verts, self.indices);
+        terrain.center = self.center?;
+        terrain.scale = self.scale;
+
+        Some(terrain)
+    }
+
+    fn copy_chunk<T: bytemuck::Zeroable + Copy>(
+        buffer: &mut Vec<T>,
+        header: ChunkHeader,
+        data: &[T],
+    ) {
+        let total = header.total as usize;
+        if buffer.len() != total {
+            buffer.resize(total, T::zeroed());
+        }
+
+        let start = (header.offset as usize).min(total);
+        let end = (start + data.len()).min(total);
+        buffer[start..end].copy_from_slice(&data[..end - start]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::proc_gen::terrain::TerrainParams;
+
+    #[test]
+    fn height_at_follows_the_mesh() {
+        let params = TerrainParams::new(42, 8);
+        let mut terrain = params.generate();
+        terrain.scale = 2.;
+
+        let row_len = params.size as usize + 1;
+        let center = terrain.center;
+        let world = |col: f32, row: f32| (2. * (col + center.x), 2. * (row + center.z));
+
+        // grid points land exactly on the heights the mesh was built from
+        for (i, height) in terrain.heights.iter().enumerate() {
+            let (x, z) = world((i % row_len) as f32, (i / row_len) as f32);
+            let expected = 2. * (height + center.y);
+            assert!((terrain.height_at(x, z).unwrap() - expected).abs() < 1e-3);
+        }
+
+        // the middle of a square sits on the diagonal it's split along
+        for (row, col) in [(0, 0), (0, 1), (3, 5), (7, 7)] {
+            let index = row * row_len + col;
+            let h = &terrain.heights;
+            let diagonal = if col % 2 != row % 2 {
+                (h[index] + h[index + row_len + 1]) / 2.
+            } else {
+                (h[index + 1] + h[index + row_len]) / 2.
+            };
+            let (x, z) = world(col as f32 + 0.5, row as f32 + 0.5);
+            let expected = 2. * (diagonal + center.y);
+            assert!((terrain.height_at(x, z).unwrap() - expected).abs() < 1e-3);
+        }
+
+        let (x, z) = world(-0.1, 4.);
+        assert_eq!(terrain.height_at(x, z), None);
+        let (x, z) = world(4., 8.1);
+        assert_eq!(terrain.height_at(x, z), None);
+
+        // streamed terrain only has the mesh
+        let streamed = Terrain::from_data(terrain.verts.clone(), terrain.indices.clone());
+        assert_eq!(streamed.height_at(0., 0.), None);
+    }
+
+    #[test]
+    fn rays_hit_the_surface() {
+        let params = TerrainParams::new(7, 16);
+        let mut terrain = params.generate();
+        terrain.scale = 3.;
+        let down = Vec3::new(0, -1, 0);
+        let above = |x: f32, z: f32| Vec3::new(x, 1000, z);
+        let (x, z) = (3. * (terrain.center.x + 5.3), 3. * (terrain.center.z + 9.6));
+        let streamed = Terrain::from_data(terrain.verts.clone(), terrain.indices.clone());
+        assert_eq!(streamed.ray_pick(above(x, z), down), None);
+
+        // the heights do until there's a mesh to pick
+        let height = terrain.height_at(x, z).unwrap();
+        let (point, t) = terrain.ray_pick(above(x, z), down).unwrap();
+        assert!((point.y - height).abs() < 1e-2);
+
+        terrain.triangles = Some(terrain.triangle_bvh());
+        let (point, t_mesh) = terrain.ray_pick(above(x, z), down).unwrap();
+        assert!((point.y - height).abs() < 1e-2);
+        assert!((t_mesh - (1000. - height)).abs() < 1e-2);
+        assert!((t_mesh - t).abs() < 1e-2);
+
+        // off the edge
+        let (x, z) = (3. * (terrain.center.x - 1.), 3. * terrain.center.z);
+        assert_eq!(terrain.ray_pick(above(x, z), down), None);
+        assert_eq!(terrain.heightfield_ray(above(x, z), down), None);
+    }
+
+    #[test]
+    fn heightfield_rays_match_the_mesh() {
+        let params = TerrainParams::new(3, 24);
+        let mut terrain = params.generate();
+        terrain.scale = 2.;
+        terrain.triangles = Some(terrain.triangle_bvh());
+        let center = 2. * terrain.center;
+
+        // slanted every which way so the walk has to cross rows and columns in both directions
+        for (from, to) in [
+            ((-10., 30., -10.), (20., -5., 15.)),
+            ((60., 25., 5.), (3., -10., 40.)),
+            ((25., 40., 60.), (30., -20., 1.)),
+            ((1., 50., 1.), (1.5, -50., 47.)),
+        ] {
+            let origin = center + Vec3::from(from);
+            let direction = Vec3::from(to) - Vec3::from(from);
+            let walked = terrain.heightfield_ray(origin, direction);
+            let (_, t) = terrain.ray_pick(origin, direction).unwrap();
+            let (point, walked) = walked.unwrap();
+            assert!((walked - t).abs() < 1e-4);
+
+            let height = terrain.height_at(point.x, point.z).unwrap();
+            assert!((point.y - height).abs() < 1e-2);
+        }
+
+        // heading away from the terrain altogether
+        let origin = center + Vec3::new(10, 30, 10);
+        assert_eq!(terrain.heightfield_ray(origin, Vec3::new(0, 1, 0)), None);
+    }
+
+    #[test]
+    fn normals_face_up() {
+        let params = TerrainParams::new(42, 8);
+        let mut terrain = params.generate();
+        terrain.scale = 2.;
+        let center = 2. * terrain.center;
+
+        for (x, z) in [(0.2, 0.3), (3.2, 1.6), (7.7, 6.1), (4.3, 4.6)] {
+            let (x, z) = (center.x + 2. * x, center.z + 2. * z);
+            let normal = terrain.normal_at(x, z).unwrap();
+            assert!(normal.y > 0.);
+            assert!((normal.magnitude() - 1.).abs() < 1e-5);
+
+            // moving a little across the surface stays on it
+            let along = Vec3::new(0.01, 0, 0.01);
+            let across = along - (along.dot(&normal) * normal);
+            let height = terrain.height_at(x, z).unwrap();
+            let next = terrain.height_at(x + across.x, z + across.z).unwrap();
+            assert!((height + across.y - next).abs() < 1e-3);
+        }
+
+        let mut flat = terrain.clone();
+        flat.heights.iter_mut().for_each(|height| *height = 1.);
+        assert_eq!(
+            flat.normal_at(center.x + 5., center.z + 5.),
+            Some(Vec3::new(0, 1, 0))
+        );
+        assert_eq!(terrain.normal_at(center.x - 1., center.z), None);
+    }
+}
@@ -38,6 +38,12 @@ impl<'a> Water<'a> {
         }
     }
 
+    pub fn unregister(&mut self, ctx: &mut Context<'a>) {
+        if let Some(draw_call_handle) = self.draw_call_handle.take() {
+            rendering::hide(ctx, &draw_call_handle);
+        }
+    }
+
     pub fn register(&mut self, ctx: &mut Context<'a>) {
         let push_constant = Some(PushConstant::vertex_data(
             0,
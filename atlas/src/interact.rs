@@ -0,0 +1,287 @@
+use crate::ecs::replicate::Replicated;
+use crate::entity::player::{PLAYER_EYE_HEIGHT, PLAYER_MAX_SPEED};
+use crate::snapshot::{fields, EntityId, EntityState, Snapshot, Tick};
+use pantheon::math::{Quaternion, Vec3};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+/// Furthest back the server rewinds for an `InteractRequest`, clicks from further in the past
+/// are checked against the oldest world it still has
+pub const MAX_REWIND_SECS: f32 = 0.5;
+
+/// How far past their eyes a player's ray can start from where the server rewound them to. A
+/// client's own player is predicted ahead of the rest of the world it draws, by at most as far
+/// as it can walk in the time the server rewinds.
+pub const MAX_ORIGIN_DRIFT: f32 = PLAYER_MAX_SPEED * MAX_REWIND_SECS;
+
+/// Sent by a client with `GameMessage::Interact` when its player clicks on the world
+#[derive(Clone, Copy, Debug)]
+pub struct InteractRequest {
+    /// where the ray starts, the camera for a mouse click
+    pub origin: Vec3,
+    pub direction: Vec3,
+    /// the server tick other entities were being drawn at when the click happened, plus how
+    /// far along to the next tick they were between 0 and 1
+    pub view_tick: Tick,
+    pub view_alpha: f32,
+}
+
+impl InteractRequest {
+    /// false for anything which isn't a ray, which can only come from a misbehaving client
+    pub fn is_valid(&self) -> bool {
+        let finite = |v: Vec3| v.x.is_finite() && v.y.is_finite() && v.z.is_finite();
+
+        finite(self.origin)
+            && finite(self.direction)
+            && self.direction.magnitude() > 0.
+            && self.view_alpha.is_finite()
+    }
+
+    /// whether the ray starts close enough to the eyes of a player standing at `position`
+    pub fn reaches_from(&self, position: Vec3) -> bool {
+        (self.origin - position).magnitude() <= PLAYER_EYE_HEIGHT + MAX_ORIGIN_DRIFT
+    }
+}
+
+/// What an `InteractRequest` ran into, `distance` is along the ray from its origin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub entity: EntityId,
+    pub point: Vec3,
+    pub distance: f32,
+}
+
+/// The server's verdict on an `InteractRequest`, sent to every client with
+/// `GameMessage::Interact`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InteractResult {
+    /// the player who clicked
+    pub player: EntityId,
+    /// the tick the world was rewound to
+    pub tick: Tick,
+    pub hit: Option<Hit>,
+}
+
+/// The last `MAX_REWIND_SECS` of snapshots, so the server can check interactions against the
+/// world as the client saw it rather than as it is by the time the click arrives
+#[derive(Debug, Default)]
+pub struct WorldHistory {
+    snapshots: VecDeque<Arc<Snapshot>>,
+}
+
+impl WorldHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// snapshots kept at `tick_rate` to cover `MAX_REWIND_SECS`
+    pub fn capacity(tick_rate: u64) -> usize {
+        (MAX_REWIND_SECS * tick_rate as f32).ceil() as usize + 1
+    }
+
+    /// snapshots have to come in tick order, the oldest are dropped past `capacity`
+    pub fn push(&mut self, snapshot: Arc<Snapshot>, capacity: usize) {
+        self.snapshots.push_back(snapshot);
+        while self.snapshots.len() > capacity.max(1) {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub fn oldest_tick(&self) -> Option<Tick> {
+        self.snapshots.front().map(|snapshot| snapshot.tick)
+    }
+
+    /// The world `alpha` of the way from `tick` to the snapshot after it, clamped to the
+    /// snapshots which are kept. Entities in both snapshots are blended the same way clients
+    /// interpolate them, ones which are about to despawn stay where they were. Comes back with
+    /// the tick which was rewound to.
+    pub fn rewind(&self, tick: Tick, alpha: f32) -> Option<(Tick, BTreeMap<EntityId, Replicated>)> {
+        let oldest = self.snapshots.front()?;
+        let newest = self.snapshots.back()?;
+        if tick < oldest.tick {
+            return Some((oldest.tick, oldest.entities.clone()));
+        }
+        if tick >= newest.tick {
+            return Some((newest.tick, newest.entities.clone()));
+        }
+
+        let next = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.tick <= tick);
+        let (from, to) = (&self.snapshots[next - 1], &self.snapshots[next]);
+        // ticks are normally back to back, but nothing breaks if some were skipped
+        let alpha = if alpha.is_finite() { alpha } else { 0. };
+        let t = (((tick - from.tick) as f32 + alpha.clamp(0., 1.)) / (to.tick - from.tick) as f32)
+            .clamp(0., 1.);
+
+        let mut entities = from.entities.clone();
+        for (id, entity) in entities.iter_mut() {
+            if let Some(next) = to.entities.get(id) {
+                let (a, b) = (entity.state(), next.state());
+                let from_rotation = Quaternion::from_rotation_matrix(&a.rotation);
+                let to_rotation = Quaternion::from_rotation_matrix(&b.rotation);
+                let state = EntityState {
+                    position: a.position.lerp(&b.position, t),
+                    rotation: from_rotation
+                        .nlerp(&from_rotation.closest(&to_rotation), t)
+                        .rotation_matrix(),
+                    ..a
+                };
+                entity.apply_state(fields::POSITION | fields::ROTATION, &state);
+            }
+        }
+
+        Some((from.tick, entities))
+    }
+}
+
+/// The closest of `entities` the ray hits, `skip` is left out so players can't click themselves
+pub fn pick(
+    entities: &BTreeMap<EntityId, Replicated>,
+    origin: Vec3,
+    direction: Vec3,
+    skip: Option<EntityId>,
+) -> Option<Hit> {
+    let direction = direction.make_unit_vector();
+
+    entities
+        .iter()
+        .filter(|(id, _)| Some(**id) != skip)
+        .filter_map(|(id, entity)| {
+            let (point, distance) = entity.ray_pick(origin, direction)?;
+            Some(Hit {
+                entity: *id,
+                point,
+                distance,
+            })
+        })
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecs::components::{Mesh, Transform};
+    use pantheon::Color;
+
+    fn cube(position: Vec3) -> Replicated {
+        Replicated::new(
+            Transform::new(position),
+            Mesh::cube(2.0, Color::new(60, 60, 60)),
+        )
+    }
+
+    fn id(index: u32) -> EntityId {
+        EntityId::new(index, 0)
+    }
+
+    fn world(tick: Tick, cubes: &[(EntityId, Vec3)]) -> Arc<Snapshot> {
+        let entities = cubes.iter().map(|(id, pos)| (*id, cube(*pos))).collect();
+        Arc::new(Snapshot::new(tick, entities))
+    }
+
+    #[test]
+    fn picks_the_closest() {
+        let snapshot = world(
+            0,
+            &[
+                (id(0), Vec3::new(0, 0, -10)),
+                (id(1), Vec3::new(0, 0, -5)),
+                (id(2), Vec3::new(5, 0, -5)),
+            ],
+        );
+        let origin = Vec3::new(0, 0, 0);
+        let forward = Vec3::new(0, 0, -3);
+
+        let hit = pick(&snapshot.entities, origin, forward, None).unwrap();
+        assert_eq!(hit.entity, id(1));
+        // the near face of a cube two units across
+        assert!((hit.distance - 4.).abs() < 1e-4);
+        assert!((hit.point.z + 4.).abs() < 1e-4);
+
+        let hit = pick(&snapshot.entities, origin, forward, Some(id(1))).unwrap();
+        assert_eq!(hit.entity, id(0));
+
+        assert!(pick(&snapshot.entities, origin, Vec3::new(0, 1, 0), None).is_none());
+    }
+
+    #[test]
+    fn rewinds_between_snapshots() {
+        let mut history = WorldHistory::new();
+        assert!(history.rewind(0, 0.).is_none());
+
+        let capacity = 3;
+        for tick in 0..5 {
+            // moves 10 units to the right every tick
+            let x = tick as f32 * 10.;
+            history.push(world(tick, &[(id(0), Vec3::new(x, 0, -5))]), capacity);
+        }
+        assert_eq!(history.oldest_tick(), Some(2));
+
+        let x_at = |tick, alpha| {
+            let (rewound, entities) = history.rewind(tick, alpha).unwrap();
+            (rewound, entities[&id(0)].state().position.x)
+        };
+        assert_eq!(x_at(3, 0.), (3, 30.));
+        assert_eq!(x_at(3, 0.5), (3, 35.));
+        // too old to rewind to, or not happened yet
+        assert_eq!(x_at(0, 0.5), (2, 20.));
+        assert_eq!(x_at(9, 0.), (4, 40.));
+
+        // the cube was at x = 25 in the client's view, where it is now the ray misses
+        let (_, entities) = history.rewind(2, 0.5).unwrap();
+        let origin = Vec3::new(25, 0, 0);
+        let forward = Vec3::new(0, 0, -1);
+        assert_eq!(
+            pick(&entities, origin, forward, None).unwrap().entity,
+            id(0)
+        );
+        let (_, entities) = history.rewind(4, 0.).unwrap();
+        assert!(pick(&entities, origin, forward, None).is_none());
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        let request = InteractRequest {
+            origin: Vec3::new(0, 0, 0),
+            direction: Vec3::new(0, 0, -1),
+            view_tick: 0,
+            view_alpha: 0.,
+        };
+        assert!(request.is_valid());
+        assert!(!InteractRequest {
+            direction: Vec3::new(0, 0, 0),
+            ..request
+        }
+        .is_valid());
+        assert!(!InteractRequest {
+            origin: Vec3::new(f32::NAN, 0, 0),
+            ..request
+        }
+        .is_valid());
+        assert!(!InteractRequest {
+            view_alpha: f32::INFINITY,
+            ..request
+        }
+        .is_valid());
+
+        // from around the eyes of a player standing at the origin
+        let feet = Vec3::new(0, 0, 0);
+        let eyes = Vec3::new(0., PLAYER_EYE_HEIGHT, 0.);
+        assert!(InteractRequest {
+            origin: eyes,
+            ..request
+        }
+        .reaches_from(feet));
+        assert!(InteractRequest {
+            origin: eyes + Vec3::new(MAX_ORIGIN_DRIFT, 0., 0.) * 0.9,
+            ..request
+        }
+        .reaches_from(feet));
+        assert!(!InteractRequest {
+            origin: Vec3::new(0., 50., 0.),
+            ..request
+        }
+        .reaches_from(feet));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::ecs::replicate::Replicated;
+use crate::snapshot::{EntityId, Snapshot};
+use pantheon::Vec3;
+use std::collections::{BTreeMap, HashMap};
+
+/// world units along each side of a cell
+pub const DEFAULT_CELL_SIZE: f32 = 16.;
+
+/// x and z of a cell on the grid, height doesn't matter for what's visible
+pub type Cell = (i32, i32);
+
+/// Splits the world into square cells on the xz plane, a client sees every cell within
+/// `radius` cells of the one it's standing in
+#[derive(Debug, Clone, Copy)]
+pub struct InterestGrid {
+    pub cell_size: f32,
+    pub radius: i32,
+}
+
+impl InterestGrid {
+    /// `view_distance` is rounded up to a whole number of cells
+    pub fn new(cell_size: f32, view_distance: f32) -> Self {
+        Self {
+            cell_size,
+            radius: (view_distance / cell_size).ceil() as i32,
+        }
+    }
+
+    pub fn cell_of(&self, position: Vec3) -> Cell {
+        (
+            (position.x / self.cell_size).floor() as i32,
+            (position.z / self.cell_size).floor() as i32,
+        )
+    }
+
+    /// the cells a client standing at `viewer` is sent entities from
+    pub fn area(&self, viewer: Vec3) -> InterestArea {
+        InterestArea {
+            center: self.cell_of(viewer),
+            radius: self.radius,
+        }
+    }
+
+    /// sorts every entity in `snapshot` into its cell, done once a tick and shared by every
+    /// client
+    pub fn index(&self, snapshot: &Snapshot) -> GridIndex {
+        let mut cells: HashMap<Cell, Vec<EntityId>> = HashMap::new();
+        let mut global = vec![];
+        for (id, entity) in snapshot.entities.iter() {
+            if is_global(entity) {
+                global.push(*id);
+            } else {
+                cells
+                    .entry(self.cell_of(entity.state().position))
+                    .or_default()
+                    .push(*id);
+            }
+        }
+
+        GridIndex {
+            grid: *self,
+            cells,
+            global,
+        }
+    }
+}
+
+/// Entities which matter to everyone no matter where they are, the sun lights the whole world
+pub fn is_global(entity: &Replicated) -> bool {
+    entity.light.is_some()
+}
+
+/// the square of cells a single client can see
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterestArea {
+    pub center: Cell,
+    pub radius: i32,
+}
+
+// far off positions saturate to the edge of the grid in `cell_of`, so everything here is
+// worked out in `i64` or clipped to the grid rather than overflowing past it
+impl InterestArea {
+    pub fn contains(&self, (x, z): Cell) -> bool {
+        let radius = i64::from(self.radius);
+        (i64::from(x) - i64::from(self.center.0)).abs() <= radius
+            && (i64::from(z) - i64::from(self.center.1)).abs() <= radius
+    }
+
+    /// how many cells `cells` goes through
+    pub fn cell_count(&self) -> usize {
+        let side = |center: i32| {
+            let span = self.span(center);
+            (i64::from(*span.end()) - i64::from(*span.start()) + 1) as usize
+        };
+        side(self.center.0).saturating_mul(side(self.center.1))
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = Cell> {
+        let xs = self.span(self.center.0);
+        let zs = self.span(self.center.1);
+        xs.flat_map(move |x| zs.clone().map(move |z| (x, z)))
+    }
+
+    fn span(&self, center: i32) -> std::ops::RangeInclusive<i32> {
+        center.saturating_sub(self.radius)..=center.saturating_add(self.radius)
+    }
+}
+
+/// Which entities are in which cell for a single tick
+#[derive(Debug)]
+pub struct GridIndex {
+    grid: InterestGrid,
+    cells: HashMap<Cell, Vec<EntityId>>,
+    global: Vec<EntityId>,
+}
+
+impl GridIndex {
+    /// The part of `snapshot` a client standing at `viewer` gets to see. Without a viewer only
+    /// the global entities are sent. Entities leaving the area are despawned by the usual
+    /// snapshot deltas since they're missing from the next filtered snapshot.
+    pub fn filter(&self, snapshot: &Snapshot, viewer: Option<Vec3>) -> Snapshot {
+        let mut entities = BTreeMap::new();
+        let mut keep = |id: &EntityId| {
+            if let Some(entity) = snapshot.entities.get(id) {
+                entities.insert(*id, *entity);
+            }
+        };
+
+        self.global.iter().for_each(&mut keep);
+        if let Some(viewer) = viewer {
+            let area = self.grid.area(viewer);
+            if self.is_sparse(&area) {
+                self.cells
+                    .iter()
+                    .filter(|(cell, _)| area.contains(**cell))
+                    .for_each(|(_, ids)| ids.iter().for_each(&mut keep));
+            } else {
+                area.cells()
+                    .filter_map(|cell| self.cells.get(&cell))
+                    .for_each(|ids| ids.iter().for_each(&mut keep));
+            }
+        }
+
+        Snapshot::new(snapshot.tick, entities)
+    }
+
+    /// A big view distance over a sparse world is cheaper to walk the other way round, going
+    /// through the occupied cells instead of every cell in the area
+    fn is_sparse(&self, area: &InterestArea) -> bool {
+        area.cell_count() > self.cells.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecs::components::{Light, Mesh, Transform};
+    use crate::snapshot::{pull_snapshot, ClientBaseline, SnapshotEvent, Tick};
+    use pantheon::Color;
+    use std::sync::Arc;
+
+    fn id(index: u32) -> EntityId {
+        EntityId::new(index, 0)
+    }
+
+    fn world(tick: Tick, far_x: f32) -> Snapshot {
+        let mut entities = BTreeMap::new();
+        let cube = |position| {
+            Replicated::new(
+                Transform::new(position),
+                Mesh::cube(1.0, Color::new(60, 60, 60)),
+            )
+        };
+        entities.insert(id(1), cube((1, 0, 1).into()));
+        entities.insert(id(2), cube((far_x, 0., 0.).into()));
+        let sun = Replicated {
+            light: Some(Light {
+                color: Color::new(255, 255, 255),
+                radians: 0.,
+                rotating: false,
+                rotation_axis: (0, 1, 0).into(),
+            }),
+            ..cube((500, 100, 500).into())
+        };
+        entities.insert(id(3), sun);
+
+        Snapshot::new(tick, entities)
+    }
+
+    fn send(baseline: &mut ClientBaseline, snapshot: Snapshot) -> Vec<SnapshotEvent> {
+        let tick = snapshot.tick;
+        let events = match baseline.delta_message(&Arc::new(snapshot)) {
+            Some(mut msg) => pull_snapshot(&mut msg).unwrap().1,
+            None => vec![],
+        };
+        baseline.ack(tick);
+
+        events
+    }
+
+    fn mentions(events: &[SnapshotEvent], index: u32) -> bool {
+        events.iter().any(|event| match *event {
+            SnapshotEvent::Spawn(other, _)
+            | SnapshotEvent::Update(other, _, _)
+            | SnapshotEvent::Despawn(other) => other == id(index),
+        })
+    }
+
+    #[test]
+    fn cells_and_areas() {
+        let grid = InterestGrid::new(16., 40.);
+        assert_eq!(grid.radius, 3);
+        assert_eq!(grid.cell_of((0, 0, 0).into()), (0, 0));
+        assert_eq!(grid.cell_of((-0.5, 100., 33.).into()), (-1, 2));
+
+        let area = grid.area((0, 0, 0).into());
+        assert_eq!(area.cells().count(), 49);
+        assert_eq!(area.cell_count(), 49);
+        assert!(area.contains((3, -3)));
+        assert!(!area.contains((4, 0)));
+    }
+
+    #[test]
+    fn far_off_positions_stay_on_the_grid() {
+        let grid = InterestGrid::new(16., 40.);
+        let corner = grid.cell_of((1e30, 0., -1e30).into());
+        assert_eq!(corner, (i32::MAX, i32::MIN));
+
+        let area = grid.area((1e30, 0., -1e30).into());
+        assert!(area.contains(corner));
+        assert!(!area.contains((0, 0)));
+        assert!(!area.contains((i32::MIN, i32::MAX)));
+        // only the cells on the grid are left
+        assert_eq!(area.cell_count(), 16);
+        assert_eq!(area.cells().count(), 16);
+
+        let home = grid.area((0, 0, 0).into());
+        assert!(!home.contains(corner));
+        let huge = InterestArea {
+            center: (0, 0),
+            radius: i32::MAX,
+        };
+        assert!(huge.contains((i32::MAX, i32::MIN + 1)));
+        assert!(huge.cell_count() > 1 << 60);
+    }
+
+    #[test]
+    fn sparse_worlds_walk_occupied_cells() {
+        let snapshot = world(1, 1000.);
+        let viewer = Vec3::new(0, 0, 0);
+        let visible = |grid: InterestGrid| {
+            let index = grid.index(&snapshot);
+            let sparse = index.is_sparse(&grid.area(viewer));
+            let ids: Vec<u32> = index
+                .filter(&snapshot, Some(viewer))
+                .entities
+                .keys()
+                .map(|id| id.index)
+                .collect();
+            (sparse, ids)
+        };
+
+        // only two cells are occupied, so any area bigger than that is walked the sparse way
+        assert_eq!(visible(InterestGrid::new(16., 0.)), (false, vec![1, 3]));
+        assert_eq!(visible(InterestGrid::new(16., 64.)), (true, vec![1, 3]));
+        assert_eq!(
+            visible(InterestGrid::new(16., 1000.)),
+            (true, vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn far_clients_never_hear_about_distant_entities() {
+        let grid = InterestGrid::new(DEFAULT_CELL_SIZE, 64.);
+        let mut near = ClientBaseline::new();
+        let mut far = ClientBaseline::new();
+        let far_viewer = Some(Vec3::new(1000, 0, 0));
+
+        for tick in 1..20 {
+            // entity 2 keeps moving around far away from the origin
+            let snapshot = world(tick, 1000. + tick as f32);
+            let index = grid.index(&snapshot);
+
+            let events = send(&mut near, index.filter(&snapshot, Some(Vec3::new(0, 0, 0))));
+            assert!(!mentions(&events, 2));
+            if tick == 1 {
+                assert!(mentions(&events, 1));
+                assert!(mentions(&events, 3));
+            }
+
+            let events = send(&mut far, index.filter(&snapshot, far_viewer));
+            assert!(!mentions(&events, 1));
+            if tick > 1 {
+                assert!(events.iter().any(
+                    |event| matches!(event, SnapshotEvent::Update(other, _, _) if *other == id(2))
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn entities_spawn_and_despawn_crossing_the_edge() {
+        let grid = InterestGrid::new(DEFAULT_CELL_SIZE, 32.);
+        let mut baseline = ClientBaseline::new();
+        let viewer = Some(Vec3::new(0, 0, 0));
+
+        let snapshot = world(1, 500.);
+        let first = send(
+            &mut baseline,
+            grid.index(&snapshot).filter(&snapshot, viewer),
+        );
+        assert!(!mentions(&first, 2));
+
+        let snapshot = world(2, 10.);
+        let entered = send(
+            &mut baseline,
+            grid.index(&snapshot).filter(&snapshot, viewer),
+        );
+        assert!(entered
+            .iter()
+            .any(|event| matches!(event, SnapshotEvent::Spawn(other, _) if *other == id(2))));
+
+        let snapshot = world(3, 500.);
+        let left = send(
+            &mut baseline,
+            grid.index(&snapshot).filter(&snapshot, viewer),
+        );
+        assert!(left
+            .iter()
+            .any(|event| matches!(event, SnapshotEvent::Despawn(other) if *other == id(2))));
+
+        // without a viewer only the sun is left
+        let snapshot = world(4, 10.);
+        let filtered = grid.index(&snapshot).filter(&snapshot, None);
+        assert_eq!(
+            filtered.entities.keys().copied().collect::<Vec<_>>(),
+            vec![id(3)]
+        );
+    }
+}
@@ -1,9 +1,20 @@
+pub mod asset;
 pub mod camera;
+pub mod chat;
+pub mod command;
+pub mod config;
+pub mod ecs;
 pub mod entity;
+pub mod interact;
+pub mod interest;
+pub mod logging;
 pub mod message;
+pub mod physics;
 pub mod proc_gen;
-pub mod vertex;
 pub mod rendering;
+pub mod snapshot;
+pub mod spatial;
+pub mod vertex;
 
 pub use rand;
 
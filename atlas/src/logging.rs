@@ -0,0 +1,98 @@
+use crate::config::LogLevel;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter, ParseError};
+use tracing_subscriber::prelude::*;
+
+#[derive(Error, Debug)]
+pub enum LoggingError {
+    #[error("failed to open log file {path:?}: {source}")]
+    File {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid RUST_LOG: {0}")]
+    Filter(#[from] ParseError),
+    #[error("failed to install the logger: {0}")]
+    Init(#[from] tracing_subscriber::util::TryInitError),
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// `RUST_LOG` style `directives` when there are any, like `server=debug,hermes=trace`,
+/// otherwise everything at `level`
+pub fn filter(level: LogLevel, directives: Option<&str>) -> Result<EnvFilter, ParseError> {
+    match directives {
+        Some(directives) if !directives.trim().is_empty() => EnvFilter::try_new(directives),
+        _ => Ok(EnvFilter::default().add_directive(LevelFilter::from(level).into())),
+    }
+}
+
+/// Installs the logger for the whole process, call it once at startup. Events go to stdout
+/// and, when there's a `log_file`, get appended to it as a line of JSON each along with the
+/// spans they happened in. Both are filtered by `RUST_LOG` if it's set, or `level` if not.
+pub fn init(level: LogLevel, log_file: Option<&Path>) -> Result<(), LoggingError> {
+    let directives = std::env::var(EnvFilter::DEFAULT_ENV).ok();
+
+    let stdout =
+        tracing_subscriber::fmt::layer().with_filter(filter(level, directives.as_deref())?);
+
+    let json = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|source| LoggingError::File {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+            let layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_span_list(true)
+                .with_writer(Mutex::new(file))
+                .with_filter(filter(level, directives.as_deref())?);
+            Some(layer)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(stdout)
+        .with(json)
+        .try_init()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filters() {
+        assert_eq!(LevelFilter::from(LogLevel::Warn), LevelFilter::WARN);
+
+        let fallback = filter(LogLevel::Debug, None).unwrap();
+        assert_eq!(fallback.max_level_hint(), Some(LevelFilter::DEBUG));
+        // blank directives don't count as being set
+        let blank = filter(LogLevel::Error, Some("  ")).unwrap();
+        assert_eq!(blank.max_level_hint(), Some(LevelFilter::ERROR));
+
+        let directives = filter(LogLevel::Error, Some("info,hermes=trace")).unwrap();
+        assert_eq!(directives.max_level_hint(), Some(LevelFilter::TRACE));
+        assert!(filter(LogLevel::Info, Some("hermes=loud")).is_err());
+    }
+}
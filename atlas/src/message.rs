@@ -1,35 +1,231 @@
-use hermes::message::Messageable;
+use hermes::message::{Message, MessageError, Messageable, Pod};
+use pantheon::Vec3;
+use thiserror::Error;
 #[derive(Clone, Copy, Debug)]
 pub enum GameMessage {
     GetId,
+    /// asks the server for a full `Snapshot`, also used to resync once a delta can't be applied
     SyncWorld,
+    Snapshot,
+    /// carries the `Tick` of the last snapshot the client applied
+    AckSnapshot,
     RegenerateTerrain(TerrainMessage),
     Ping,
+    /// carries an `InteractRequest` from a client, the server rewinds to what that client was
+    /// looking at and sends every client the `InteractResult`
     Interact,
+    /// carries a `PlayerInput` for the sender's own player, the server answers with a
+    /// `PlayerAck` whenever its view of that player changes
     MovePlayer,
+    /// sent by the server once a client's player has been spawned, carries its `EntityId`
     Player,
+    /// a line of text chat, see `chat::chat_message` for the layout
+    Chat,
+    /// carries an `EditRequest` from a client, whatever it changes reaches everyone through
+    /// snapshots and the terrain params
+    Edit,
 }
 
+/// The server owns the terrain, clients either ask for the `TerrainParams` and regenerate it
+/// themselves or ask for the mesh to be streamed over
 #[derive(Clone, Copy, Debug)]
 pub enum TerrainMessage {
+    /// sent empty by a client to request the params, the server answers with `TerrainParams`
     Generate,
+    /// sent empty by a client to request the mesh, the server answers with a `Center` followed by
+    /// chunks of `ShadedVertex` and then chunks of `u32` indices
     Verts,
     Indices,
+    /// the terrain's center followed by its scale
     Center,
 }
 
 impl Messageable for GameMessage {}
 
+/// Max number of elements sent per chunk when streaming large buffers like terrain meshes
+pub const CHUNK_LEN: usize = 512;
+
+#[derive(Error, Debug)]
+pub enum ChunkError {
+    #[error("chunk of {len} elements at {offset} is too long or runs past the {total} streamed")]
+    BadHeader { offset: u32, len: u32, total: u32 },
+    #[error(transparent)]
+    Message(#[from] MessageError),
+}
+
+/// Describes where a streamed chunk lands in the buffer being rebuilt on the other side
 #[derive(Clone, Copy, Debug)]
-pub struct F2 {
-    pub x: f32,
-    pub y: f32,
+pub struct ChunkHeader {
+    pub offset: u32,
+    pub len: u32,
+    pub total: u32,
+}
+
+/// Splits `data` into `CHUNK_LEN` sized messages of kind `id`
+pub fn chunk_messages<V: Pod>(id: GameMessage, data: &[V]) -> Vec<Message<GameMessage>> {
+    let total = data.len() as u32;
+
+    data.chunks(CHUNK_LEN)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut msg = Message::new(id);
+            for elem in chunk.iter().copied() {
+                msg.push(elem);
+            }
+            msg.push(ChunkHeader {
+                offset: (i * CHUNK_LEN) as u32,
+                len: chunk.len() as u32,
+                total,
+            });
+
+            msg
+        })
+        .collect()
 }
 
+/// Inverse of `chunk_messages` for a single message, elements come back in their original order.
+/// The header is checked against `CHUNK_LEN` and what's left of the body before anything is
+/// reserved for it.
+pub fn pull_chunk<V: Pod>(
+    msg: &mut Message<GameMessage>,
+) -> Result<(ChunkHeader, Vec<V>), ChunkError> {
+    let header: ChunkHeader = msg.pull()?;
+    let fits = header.len as usize <= CHUNK_LEN
+        && u64::from(header.offset) + u64::from(header.len) <= u64::from(header.total);
+    if !fits {
+        return Err(ChunkError::BadHeader {
+            offset: header.offset,
+            len: header.len,
+            total: header.total,
+        });
+    }
+    let bytes = header.len as usize * std::mem::size_of::<V>();
+    if bytes > msg.body.len() {
+        return Err(MessageError::NotEnoughBytes {
+            type_size: bytes,
+            remaining: msg.body.len(),
+        }
+        .into());
+    }
+
+    let mut data = Vec::with_capacity(header.len as usize);
+    for _ in 0..header.len {
+        data.push(msg.pull()?);
+    }
+    // pulling works from the back of the body
+    data.reverse();
+
+    Ok((header, data))
+}
+
+/// A single step of movement, the server clamps every field before applying it so clients can
+/// only ask to move, never put their player somewhere
 #[derive(Clone, Copy, Debug)]
-pub struct Complex {
-    pub a: u32,
-    pub b: bool,
-    pub c: f32,
-    pub d: [F2; 2],
+pub struct PlayerInput {
+    /// increases with every input, anything at or below the last applied one is dropped
+    pub sequence: u32,
+    /// units per second
+    pub velocity: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// seconds this input covers
+    pub dt: f32,
+    /// Anything but 0 jumps if the player is standing on something. A byte rather than a
+    /// `bool` since this is read straight out of what a client sent, where any byte can turn up.
+    pub jump: u8,
+}
+
+impl PlayerInput {
+    pub fn jumps(&self) -> bool {
+        self.jump != 0
+    }
+}
+
+/// Where the server put a client's player after applying every input up to and including
+/// `sequence`, the client replays anything newer on top of it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayerAck {
+    pub sequence: u32,
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entity::terrain::TerrainStream;
+    use crate::proc_gen::terrain::TerrainParams;
+
+    #[test]
+    fn chunk_round_trip() -> Result<(), ChunkError> {
+        let data: Vec<u32> = (0..(CHUNK_LEN as u32 * 2 + 7)).collect();
+        let messages = chunk_messages(GameMessage::Ping, &data);
+        assert_eq!(messages.len(), 3);
+
+        let mut out = vec![];
+        for mut msg in messages {
+            let (header, chunk) = pull_chunk::<u32>(&mut msg)?;
+            assert_eq!(header.offset as usize, out.len());
+            assert_eq!(header.total as usize, data.len());
+            assert!(msg.body.is_empty());
+            out.extend(chunk);
+        }
+
+        assert_eq!(data, out);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_bad_chunk_headers() {
+        let chunk = |offset: u32, len: u32, total: u32, elems: u32| {
+            let mut msg = Message::new(GameMessage::Ping);
+            (0..elems).for_each(|elem| msg.push(elem));
+            msg.push(ChunkHeader { offset, len, total });
+            pull_chunk::<u32>(&mut msg)
+        };
+
+        assert!(chunk(0, 4, 4, 4).is_ok());
+        // asking for more than a chunk holds is turned away before anything is reserved
+        assert!(matches!(
+            chunk(0, u32::MAX, u32::MAX, 4),
+            Err(ChunkError::BadHeader { .. })
+        ));
+        assert!(matches!(
+            chunk(u32::MAX, 4, 8, 4),
+            Err(ChunkError::BadHeader { .. })
+        ));
+        assert!(matches!(
+            chunk(0, CHUNK_LEN as u32, u32::MAX, 4),
+            Err(ChunkError::Message(MessageError::NotEnoughBytes { .. }))
+        ));
+    }
+
+    #[test]
+    fn terrain_stream_matches_params() -> Result<(), ChunkError> {
+        let params = TerrainParams::new(1234, 12);
+        let terrain = params.generate();
+
+        let mut stream = TerrainStream::new();
+        stream.set_center(terrain.center, terrain.scale);
+        for mut msg in chunk_messages(GameMessage::Ping, &terrain.verts) {
+            let (header, verts) = pull_chunk(&mut msg)?;
+            stream.push_verts(header, &verts);
+        }
+        assert!(!stream.is_complete());
+        for mut msg in chunk_messages(GameMessage::Ping, &terrain.indices) {
+            let (header, indices) = pull_chunk(&mut msg)?;
+            stream.push_indices(header, &indices);
+        }
+
+        let streamed = stream.finish().expect("every chunk was pushed");
+        let regenerated = params.generate();
+        assert_eq!(streamed.indices, regenerated.indices);
+        assert_eq!(streamed.verts.len(), regenerated.verts.len());
+        for (a, b) in streamed.verts.iter().zip(regenerated.verts.iter()) {
+            assert_eq!(a.position, b.position);
+        }
+        assert_eq!(streamed.center, regenerated.center);
+        assert_eq!(streamed.scale, regenerated.scale);
+        Ok(())
+    }
 }
@@ -0,0 +1,443 @@
+use super::contact::{collide, Contact, Shape};
+use super::STEP;
+use pantheon::Vec3;
+
+/// how close to the ground a character's feet have to be to count as standing on it
+const GROUNDED_DISTANCE: f32 = 0.05;
+
+/// times the capsule is pushed out of everything it's overlapping each step, once is usually
+/// enough but corners push into each other
+const RESOLVE_ITERATIONS: usize = 3;
+
+/// halvings used to find the smallest lift that gets a character up a step
+const STEP_SEARCH: usize = 8;
+
+/// Walks an upright capsule over the terrain and around `Shape`s. Moving only depends on what
+/// it's given, so the client predicting its own player and the server checking it come to the
+/// same place from the same inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CharacterController {
+    pub radius: f32,
+    /// from the very bottom of the capsule to the very top, at least twice `radius`
+    pub height: f32,
+    /// the tallest ledge which can be walked straight up onto
+    pub step_height: f32,
+    /// the steepest ground which can be stood on, in radians. Characters slide down anything
+    /// steeper and can't walk up it.
+    pub max_slope: f32,
+    /// upward speed a jump starts with
+    pub jump_speed: f32,
+    /// units per second per second, only ever pulling down
+    pub gravity: f32,
+}
+
+/// Where a character is, its capsule's center, and how fast it's going
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CharacterState {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    /// whether it was standing on something after the last step, it's worked out again at
+    /// the start of every step rather than trusted
+    pub grounded: bool,
+}
+
+impl CharacterState {
+    pub fn new(position: Vec3, velocity: Vec3) -> Self {
+        Self {
+            position,
+            velocity,
+            grounded: false,
+        }
+    }
+}
+
+impl CharacterController {
+    /// from the capsule's center to the center of either of its round ends
+    fn reach(&self) -> f32 {
+        (self.height / 2. - self.radius).max(0.)
+    }
+
+    fn walkable(&self, normal: Vec3) -> bool {
+        normal.y >= self.max_slope.cos()
+    }
+
+    /// Moves the character for `dt` seconds, walking at `walk` along the ground. Anything
+    /// vertical in `walk` is ignored, only gravity and jumping move it up and down. `ground`
+    /// is the terrain height at an x/z position and `obstacles` everything solid nearby. The
+    /// character won't walk off the edge of the terrain.
+    pub fn step(
+        &self,
+        mut state: CharacterState,
+        walk: Vec3,
+        mut jump: bool,
+        dt: f32,
+        ground: impl Fn(f32, f32) -> Option<f32>,
+        obstacles: &[Shape],
+    ) -> CharacterState {
+        let walk = Vec3::new(walk.x, 0., walk.z);
+
+        // in pieces no longer than a physics step, nothing's skipped over by a long input
+        let mut left = dt.max(0.);
+        while left > 0. {
+            let dt = left.min(STEP);
+            left -= dt;
+            state = self.substep(state, walk, jump, dt, &ground, obstacles);
+            // one jump per input, however many steps it's split into
+            jump &= state.velocity.y <= 0.;
+        }
+
+        // even without any time passing a character can't be left inside anything
+        state.position = self.resolve(state.position, &mut state.velocity, obstacles);
+        if let Some(height) = ground(state.position.x, state.position.z) {
+            let bottom = height + self.height / 2.;
+            if state.position.y < bottom {
+                state.position.y = bottom;
+            }
+        }
+        state.grounded = self.grounded(state.position, &ground, obstacles);
+
+        state
+    }
+
+    fn substep(
+        &self,
+        state: CharacterState,
+        walk: Vec3,
+        jump: bool,
+        dt: f32,
+        ground: &impl Fn(f32, f32) -> Option<f32>,
+        obstacles: &[Shape],
+    ) -> CharacterState {
+        let grounded = state.velocity.y <= 0. && self.grounded(state.position, ground, obstacles);
+        let mut velocity = walk;
+        velocity.y = match (grounded, jump) {
+            (true, true) => self.jump_speed,
+            (true, false) => 0.,
+            (false, _) => state.velocity.y - self.gravity * dt,
+        };
+
+        // ground too steep to stand on can't be walked up, only along or down
+        let mut walking = walk;
+        let ahead = state.position + dt * walk;
+        if let Some(normal) = self.ground_normal(ahead.x, ahead.z, ground) {
+            let downhill = Vec3::new(normal.x, 0., normal.z);
+            if !self.walkable(normal) && downhill.magnitude() > 0. {
+                let downhill = downhill.unit_vector();
+                let into = walking.dot(&downhill);
+                if into < 0. {
+                    walking -= into * downhill;
+                }
+            }
+        }
+
+        let mut position = state.position + dt * walking;
+        if ground(position.x, position.z).is_none() {
+            position.x = state.position.x;
+            position.z = state.position.z;
+        }
+        position.y += dt * velocity.y;
+        velocity.x = walking.x;
+        velocity.z = walking.z;
+
+        // the ground first, anything it puts the character into pushes it back out
+        self.follow_ground(&mut position, &mut velocity, grounded, dt, ground);
+        position = self.resolve(position, &mut velocity, obstacles);
+
+        CharacterState::new(position, velocity)
+    }
+
+    /// keeps a character on top of the terrain, sliding down anything too steep to stand on
+    fn follow_ground(
+        &self,
+        position: &mut Vec3,
+        velocity: &mut Vec3,
+        grounded: bool,
+        dt: f32,
+        ground: &impl Fn(f32, f32) -> Option<f32>,
+    ) {
+        let height = match ground(position.x, position.z) {
+            Some(height) => height,
+            None => return,
+        };
+        let feet = position.y - self.height / 2.;
+        // walking down a slope keeps to it rather than stepping off into the air every frame
+        let snap = if grounded && velocity.y <= 0. {
+            self.step_height
+        } else {
+            0.
+        };
+        if feet <= height + snap {
+            let normal = self
+                .ground_normal(position.x, position.z, ground)
+                .unwrap_or_else(|| Vec3::new(0, 1, 0));
+            if self.walkable(normal) {
+                position.y = height + self.height / 2.;
+                velocity.y = velocity.y.max(0.);
+            } else if feet < height {
+                // too steep, pushed back out and sliding down it
+                position.y = height + self.height / 2.;
+                let falling = Vec3::new(0., velocity.y.min(0.), 0.);
+                let slide = falling - falling.dot(&normal) * normal;
+                position.x += dt * slide.x;
+                position.z += dt * slide.z;
+            }
+        }
+    }
+
+    /// Whether a character at `position` is standing on walkable ground or on top of one
+    /// of the `obstacles`
+    pub fn grounded(
+        &self,
+        position: Vec3,
+        ground: impl Fn(f32, f32) -> Option<f32>,
+        obstacles: &[Shape],
+    ) -> bool {
+        let feet = position.y - self.height / 2.;
+        let on_ground = ground(position.x, position.z).is_some_and(|height| {
+            feet - height <= GROUNDED_DISTANCE
+                // right at the edge of the terrain there's nothing to tell the slope from
+                && self
+                    .ground_normal(position.x, position.z, &ground)
+                    .is_none_or(|normal| self.walkable(normal))
+        });
+        if on_ground {
+            return true;
+        }
+
+        let lowered = position - Vec3::new(0., GROUNDED_DISTANCE, 0.);
+        obstacles.iter().any(|shape| {
+            self.contact(lowered, shape)
+                .is_some_and(|contact| self.walkable(contact.normal))
+        })
+    }
+
+    /// Which way the ground faces at `x`, `z`, worked out from the heights a capsule's width
+    /// around it so small bumps don't count as slopes
+    pub fn ground_normal(
+        &self,
+        x: f32,
+        z: f32,
+        ground: impl Fn(f32, f32) -> Option<f32>,
+    ) -> Option<Vec3> {
+        let r = self.radius.max(0.1);
+        let dx = ground(x + r, z)? - ground(x - r, z)?;
+        let dz = ground(x, z + r)? - ground(x, z - r)?;
+
+        Some(Vec3::new(-dx, 2. * r, -dz).unit_vector())
+    }
+
+    /// Pushes the capsule out of anything it's overlapping, stepping up onto anything low
+    /// enough. Velocity into whatever it hit is taken away.
+    fn resolve(&self, mut position: Vec3, velocity: &mut Vec3, obstacles: &[Shape]) -> Vec3 {
+        for _ in 0..RESOLVE_ITERATIONS {
+            let mut moved = false;
+            for shape in obstacles {
+                let contact = match self.contact(position, shape) {
+                    Some(contact) => contact,
+                    None => continue,
+                };
+                moved = true;
+
+                if self.walkable(contact.normal) {
+                    // standing on it, straight up so it doesn't slide off slopes
+                    position.y += contact.depth / contact.normal.y;
+                    velocity.y = velocity.y.max(0.);
+                    continue;
+                }
+                if let Some(lift) = self.step_up(position, obstacles) {
+                    position.y += lift;
+                    continue;
+                }
+
+                position += contact.depth * contact.normal;
+                let into = velocity.dot(&contact.normal);
+                if into < 0. {
+                    *velocity -= into * contact.normal;
+                }
+            }
+            if !moved {
+                break;
+            }
+        }
+
+        position
+    }
+
+    /// the smallest lift up to `step_height` which gets the capsule clear of every obstacle
+    fn step_up(&self, position: Vec3, obstacles: &[Shape]) -> Option<f32> {
+        let clear = |lift: f32| {
+            let raised = position + Vec3::new(0., lift, 0.);
+            obstacles
+                .iter()
+                .all(|shape| self.contact(raised, shape).is_none())
+        };
+        if self.step_height <= 0. || !clear(self.step_height) {
+            return None;
+        }
+
+        let (mut low, mut high) = (0., self.step_height);
+        for _ in 0..STEP_SEARCH {
+            let middle = (low + high) / 2.;
+            if clear(middle) {
+                high = middle;
+            } else {
+                low = middle;
+            }
+        }
+
+        Some(high)
+    }
+
+    /// How far the capsule at `position` is into `shape`, `normal` pushes the capsule out. The
+    /// point along the capsule's spine nearest the shape is found by going back and forth
+    /// between the two, which is exact for spheres and close enough for boxes.
+    pub fn contact(&self, position: Vec3, shape: &Shape) -> Option<Contact> {
+        let reach = self.reach();
+        let spine = |point: Vec3| {
+            let y = (point.y - position.y).clamp(-reach, reach);
+            Vec3::new(position.x, position.y + y, position.z)
+        };
+
+        let mut nearest = spine(shape.center());
+        for _ in 0..3 {
+            nearest = spine(shape.closest_point(nearest));
+        }
+
+        collide(
+            shape,
+            &Shape::Sphere {
+                center: nearest,
+                radius: self.radius,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecs::components::{Collider, Transform};
+
+    const CHARACTER: CharacterController = CharacterController {
+        radius: 0.4,
+        height: 1.8,
+        step_height: 0.4,
+        max_slope: 0.8,
+        jump_speed: 6.,
+        gravity: 20.,
+    };
+
+    fn flat(_: f32, _: f32) -> Option<f32> {
+        Some(0.)
+    }
+
+    fn run(
+        mut state: CharacterState,
+        walk: Vec3,
+        secs: f32,
+        ground: impl Fn(f32, f32) -> Option<f32>,
+        obstacles: &[Shape],
+    ) -> CharacterState {
+        for _ in 0..(secs * 60.) as usize {
+            state = CHARACTER.step(state, walk, false, 1. / 60., &ground, obstacles);
+        }
+        state
+    }
+
+    fn cube(position: Vec3, size: f32) -> Shape {
+        Shape::new(&Collider::cube(size), &Transform::new(position))
+    }
+
+    #[test]
+    fn falls_and_jumps() {
+        let start = CharacterState::new(Vec3::new(0, 10, 0), Vec3::new_from_one(0));
+        let landed = run(start, Vec3::new_from_one(0), 2., flat, &[]);
+        assert!(landed.grounded);
+        assert_eq!(landed.position.y, 0.9);
+        assert_eq!(landed.velocity, Vec3::new_from_one(0));
+
+        // up and back down again, walking doesn't take it any higher
+        let mut state = CHARACTER.step(landed, Vec3::new(0, 50, 0), true, 1. / 60., flat, &[]);
+        assert!(!state.grounded);
+        let mut highest = state.position.y;
+        for _ in 0..120 {
+            state = CHARACTER.step(state, Vec3::new_from_one(0), false, 1. / 60., flat, &[]);
+            highest = highest.max(state.position.y);
+        }
+        // v squared over 2g
+        let expected = 0.9 + 6. * 6. / 40.;
+        assert!((highest - expected).abs() < 0.15, "jumped to {}", highest);
+        assert!(state.grounded);
+
+        // a jump can't be started in the air
+        let mut state = CharacterState::new(Vec3::new(0, 5, 0), Vec3::new_from_one(0));
+        state = CHARACTER.step(state, Vec3::new_from_one(0), true, 0.1, flat, &[]);
+        assert!(state.velocity.y < 0.);
+
+        // no ground at all, it just keeps on falling
+        let falling = run(start, Vec3::new_from_one(0), 1., |_, _| None, &[]);
+        assert!(falling.position.y < 10. - 9.);
+    }
+
+    #[test]
+    fn slopes() {
+        // rises one and a half for every one along x, steeper than `max_slope`
+        let steep = |x: f32, _: f32| Some(1.5 * x.max(0.));
+        let start = CharacterState::new(Vec3::new(-2, 0.9, 0), Vec3::new_from_one(0));
+        let blocked = run(start, Vec3::new(4, 0, 0), 2., steep, &[]);
+        assert!(
+            blocked.position.x < 0.5,
+            "walked up to {:?}",
+            blocked.position
+        );
+
+        // a gentle one is fine
+        let gentle = |x: f32, _: f32| Some(0.3 * x.max(0.));
+        let climbed = run(start, Vec3::new(4, 0, 0), 2., gentle, &[]);
+        assert!(climbed.position.x > 5.);
+        assert!(climbed.grounded);
+        assert!((climbed.position.y - (0.3 * climbed.position.x + 0.9)).abs() < 0.05);
+
+        // and walking back down sticks to it
+        let down = run(climbed, Vec3::new(-4, 0, 0), 0.5, gentle, &[]);
+        assert!(down.grounded);
+
+        // left on the steep one it slides off
+        let on_steep = CharacterState::new(Vec3::new(3, 5.4, 0), Vec3::new_from_one(0));
+        let slid = run(on_steep, Vec3::new_from_one(0), 2., steep, &[]);
+        assert!(slid.position.x < 1., "stayed at {:?}", slid.position);
+    }
+
+    #[test]
+    fn obstacles() {
+        let start = CharacterState::new(Vec3::new(-3, 0.9, 0), Vec3::new_from_one(0));
+
+        // a wall stops it
+        let wall = [cube(Vec3::new(0, 1, 0), 2.)];
+        let stopped = run(start, Vec3::new(4, 0, 0), 2., flat, &wall);
+        assert!((stopped.position.x - (-1. - CHARACTER.radius)).abs() < 0.05);
+        assert!(stopped.grounded);
+
+        // a ledge lower than `step_height` is walked up onto
+        let ledge = [cube(Vec3::new(2, -1.7, 0), 4.)];
+        let stepped = run(start, Vec3::new(4, 0, 0), 1.25, flat, &ledge);
+        assert!(stepped.position.x > 1.);
+        assert!((stepped.position.y - (0.3 + 0.9)).abs() < 0.05);
+        assert!(stepped.grounded);
+
+        // landing on a box
+        let above = CharacterState::new(Vec3::new(0, 5, 0), Vec3::new_from_one(0));
+        let landed = run(above, Vec3::new_from_one(0), 2., flat, &wall);
+        assert!((landed.position.y - (2. + 0.9)).abs() < 0.05);
+        assert!(landed.grounded);
+    }
+
+    #[test]
+    fn edge_of_the_terrain() {
+        let edge = |x: f32, _: f32| (x < 1.).then_some(0.);
+        let start = CharacterState::new(Vec3::new(0, 0.9, 0), Vec3::new_from_one(0));
+        let state = run(start, Vec3::new(4, 0, 0), 1., edge, &[]);
+        assert!(state.position.x < 1.);
+        assert!(state.grounded);
+    }
+}
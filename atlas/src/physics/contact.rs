@@ -0,0 +1,393 @@
+use crate::ecs::components::{Collider, Transform};
+use crate::entity::terrain::Terrain;
+use pantheon::Vec3;
+
+/// anything shorter is treated as no length at all
+const EPSILON: f32 = 1e-6;
+
+/// A `Collider` moved to where its entity is in the world
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Box {
+        center: Vec3,
+        /// the box's own x, y and z as unit vectors
+        axes: [Vec3; 3],
+        half_extents: Vec3,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+}
+
+impl Shape {
+    /// Scales the collider along with the entity. A sphere stays a sphere, as big as the
+    /// largest scale makes it.
+    pub fn new(collider: &Collider, transform: &Transform) -> Self {
+        let scale = transform.scale;
+        let scale = Vec3::new(scale.x.abs(), scale.y.abs(), scale.z.abs());
+
+        match *collider {
+            Collider::Box { half_extents } => Self::Box {
+                center: transform.position,
+                axes: [(1, 0, 0), (0, 1, 0), (0, 0, 1)]
+                    .map(|axis| transform.rotation.rotate(&axis.into())),
+                half_extents: Vec3::new(
+                    half_extents.x * scale.x,
+                    half_extents.y * scale.y,
+                    half_extents.z * scale.z,
+                ),
+            },
+            Collider::Sphere { radius } => Self::Sphere {
+                center: transform.position,
+                radius: radius * scale.x.max(scale.y).max(scale.z),
+            },
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        match *self {
+            Self::Box { center, .. } | Self::Sphere { center, .. } => center,
+        }
+    }
+
+    /// the point in or on the shape closest to `point`, `point` itself when it's inside
+    pub fn closest_point(&self, point: Vec3) -> Vec3 {
+        match *self {
+            Self::Box {
+                center,
+                axes,
+                half_extents,
+            } => {
+                let offset = point - center;
+                let half = [half_extents.x, half_extents.y, half_extents.z];
+                (0..3).fold(center, |closest, i| {
+                    closest + offset.dot(&axes[i]).clamp(-half[i], half[i]) * axes[i]
+                })
+            }
+            Self::Sphere { center, radius } => {
+                let offset = point - center;
+                let distance = offset.magnitude();
+                if distance <= radius {
+                    point
+                } else {
+                    center + (radius / distance) * offset
+                }
+            }
+        }
+    }
+}
+
+/// Where two shapes overlap. `normal` is the unit direction the second shape has to move in
+/// to get out of the first, `depth` how far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    pub normal: Vec3,
+    pub depth: f32,
+}
+
+impl Contact {
+    fn flipped(self) -> Self {
+        Self {
+            normal: -1. * self.normal,
+            ..self
+        }
+    }
+}
+
+/// `None` unless `a` and `b` overlap
+pub fn collide(a: &Shape, b: &Shape) -> Option<Contact> {
+    match (*a, *b) {
+        (
+            Shape::Box {
+                center: ca,
+                axes: aa,
+                half_extents: ha,
+            },
+            Shape::Box {
+                center: cb,
+                axes: ab,
+                half_extents: hb,
+            },
+        ) => box_box(ca, aa, ha, cb, ab, hb),
+        (
+            Shape::Box {
+                center,
+                axes,
+                half_extents,
+            },
+            Shape::Sphere {
+                center: sphere,
+                radius,
+            },
+        ) => sphere_box(sphere, radius, center, axes, half_extents),
+        (Shape::Sphere { .. }, Shape::Box { .. }) => collide(b, a).map(Contact::flipped),
+        (
+            Shape::Sphere {
+                center: ca,
+                radius: ra,
+            },
+            Shape::Sphere {
+                center: cb,
+                radius: rb,
+            },
+        ) => {
+            let offset = cb - ca;
+            let distance = offset.magnitude();
+            if distance > ra + rb {
+                return None;
+            }
+            // right on top of each other, either way out is as good as another
+            let normal = if distance < EPSILON {
+                Vec3::new(0, 1, 0)
+            } else {
+                (1. / distance) * offset
+            };
+
+            Some(Contact {
+                normal,
+                depth: ra + rb - distance,
+            })
+        }
+    }
+}
+
+/// How far `shape` has sunk into the terrain, `normal` points out of the ground. Only the
+/// box's corners and the sphere's lowest point are tested, so a box straddling a peak in
+/// the terrain can sink into it. `None` off the edge of the terrain.
+pub fn collide_terrain(shape: &Shape, terrain: &Terrain) -> Option<Contact> {
+    // how far below the surface a point is, measured straight out of the triangle it's over
+    let below = |point: Vec3| {
+        let height = terrain.height_at(point.x, point.z)?;
+        let normal = terrain.normal_at(point.x, point.z)?;
+        let surface = Vec3::new(point.x, height, point.z);
+        Some((normal, (surface - point).dot(&normal)))
+    };
+
+    let (normal, depth) = match *shape {
+        Shape::Box {
+            center,
+            axes,
+            half_extents,
+        } => corners(center, axes, half_extents)
+            .into_iter()
+            .filter_map(below)
+            .max_by(|a, b| a.1.total_cmp(&b.1))?,
+        Shape::Sphere { center, radius } => {
+            let (normal, depth) = below(center)?;
+            (normal, depth + radius)
+        }
+    };
+
+    (depth > 0.).then_some(Contact { normal, depth })
+}
+
+fn corners(center: Vec3, axes: [Vec3; 3], half_extents: Vec3) -> Vec<Vec3> {
+    let [x, y, z] = [
+        half_extents.x * axes[0],
+        half_extents.y * axes[1],
+        half_extents.z * axes[2],
+    ];
+
+    [-1., 1.]
+        .iter()
+        .flat_map(|i| [-1., 1.].iter().map(move |j| (*i, *j)))
+        .flat_map(|(i, j)| [-1., 1.].iter().map(move |k| (i, j, *k)))
+        .map(|(i, j, k)| center + i * x + j * y + k * z)
+        .collect()
+}
+
+/// Separating axis test between two oriented boxes. The contact is along whichever of the 15
+/// axes they overlap least on, preferring the boxes' faces to their edges.
+fn box_box(
+    ca: Vec3,
+    aa: [Vec3; 3],
+    ha: Vec3,
+    cb: Vec3,
+    ab: [Vec3; 3],
+    hb: Vec3,
+) -> Option<Contact> {
+    let offset = cb - ca;
+    let radius = |axes: &[Vec3; 3], half: Vec3, axis: Vec3| {
+        half.x * axes[0].dot(&axis).abs()
+            + half.y * axes[1].dot(&axis).abs()
+            + half.z * axes[2].dot(&axis).abs()
+    };
+
+    let faces = aa.iter().chain(ab.iter()).copied();
+    let edges = aa
+        .iter()
+        .flat_map(|a| ab.iter().map(move |b| a.cross(b)))
+        .filter(|axis| axis.magnitude() > EPSILON)
+        .map(|axis| axis.unit_vector());
+
+    let mut best: Option<Contact> = None;
+    for (i, axis) in faces.chain(edges).enumerate() {
+        let distance = offset.dot(&axis);
+        let depth = radius(&aa, ha, axis) + radius(&ab, hb, axis) - distance.abs();
+        if depth < 0. {
+            return None;
+        }
+
+        // edges only win when they're clearly better, resting boxes would otherwise pick
+        // an edge along a face whenever rounding favours it
+        let bias = if i < 6 { 0. } else { 1e-3 };
+        if best.is_none_or(|best| depth + bias < best.depth) {
+            let normal = if distance < 0. { -1. * axis } else { axis };
+            best = Some(Contact { normal, depth });
+        }
+    }
+
+    best
+}
+
+fn sphere_box(
+    sphere: Vec3,
+    radius: f32,
+    center: Vec3,
+    axes: [Vec3; 3],
+    half_extents: Vec3,
+) -> Option<Contact> {
+    let offset = sphere - center;
+    let local = axes.map(|axis| offset.dot(&axis));
+    let half = [half_extents.x, half_extents.y, half_extents.z];
+
+    let closest = Shape::Box {
+        center,
+        axes,
+        half_extents,
+    }
+    .closest_point(sphere);
+    let out = sphere - closest;
+    let distance = out.magnitude();
+    if distance > radius {
+        return None;
+    }
+    if distance > EPSILON {
+        return Some(Contact {
+            normal: (1. / distance) * out,
+            depth: radius - distance,
+        });
+    }
+
+    // the center's inside the box, out through the nearest face
+    let (i, inside) = (0..3)
+        .map(|i| (i, half[i] - local[i].abs()))
+        .min_by(|a, b| a.1.total_cmp(&b.1))?;
+    let normal = if local[i] < 0. {
+        -1. * axes[i]
+    } else {
+        axes[i]
+    };
+
+    Some(Contact {
+        normal,
+        depth: radius + inside,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::proc_gen::terrain::TerrainParams;
+    use pantheon::math::Quaternion;
+
+    fn cube(position: Vec3, size: f32) -> Shape {
+        Shape::new(&Collider::cube(size), &Transform::new(position))
+    }
+
+    fn sphere(position: Vec3, radius: f32) -> Shape {
+        Shape::new(&Collider::sphere(radius), &Transform::new(position))
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).magnitude() < 1e-4
+    }
+
+    #[test]
+    fn boxes() {
+        let a = cube(Vec3::new_from_one(0), 2.);
+        let contact = collide(&a, &cube(Vec3::new(1.5, 0.2, 0), 2.)).unwrap();
+        assert!(close(contact.normal, Vec3::new(1, 0, 0)));
+        assert!((contact.depth - 0.5).abs() < 1e-5);
+
+        // the other way around flips the normal
+        let contact = collide(&cube(Vec3::new(0, -1.8, 0), 2.), &a).unwrap();
+        assert!(close(contact.normal, Vec3::new(0, 1, 0)));
+        assert!((contact.depth - 0.2).abs() < 1e-5);
+
+        assert_eq!(collide(&a, &cube(Vec3::new(2.1, 0, 0), 2.)), None);
+
+        // turned an eighth around y its edge reaches root 2 out, the boxes' aabbs would
+        // overlap at 2.3 but the boxes don't
+        let turned = |x: f32| {
+            Shape::new(
+                &Collider::cube(2.),
+                &Transform {
+                    rotation: Quaternion::rotation_from_degrees(45., (0, 1, 0).into()),
+                    ..Transform::new(Vec3::new(x, 0, 0))
+                },
+            )
+        };
+        let contact = collide(&a, &turned(2.3)).unwrap();
+        assert!((contact.depth - (1. + 2f32.sqrt() - 2.3)).abs() < 1e-4);
+        assert!(close(contact.normal, Vec3::new(1, 0, 0)));
+        assert_eq!(collide(&a, &turned(2.5)), None);
+    }
+
+    #[test]
+    fn spheres() {
+        let a = cube(Vec3::new_from_one(0), 2.);
+        let contact = collide(&a, &sphere(Vec3::new(0, 1.5, 0), 1.)).unwrap();
+        assert!(close(contact.normal, Vec3::new(0, 1, 0)));
+        assert!((contact.depth - 0.5).abs() < 1e-5);
+        let contact = collide(&sphere(Vec3::new(0, 1.5, 0), 1.), &a).unwrap();
+        assert!(close(contact.normal, Vec3::new(0, -1, 0)));
+
+        // off the corner the box's aabb overlaps but the sphere misses
+        assert_eq!(collide(&a, &sphere(Vec3::new(1.8, 1.8, 0), 1.)), None);
+        let contact = collide(&a, &sphere(Vec3::new(1.5, 1.5, 0), 1.)).unwrap();
+        let out = Vec3::new(1, 1, 0).unit_vector();
+        assert!(close(contact.normal, out));
+        assert!((contact.depth - (1. - 0.5 * 2f32.sqrt())).abs() < 1e-5);
+
+        // deep inside, out through the closest face
+        let contact = collide(&a, &sphere(Vec3::new(0, 0, -0.7), 0.5)).unwrap();
+        assert!(close(contact.normal, Vec3::new(0, 0, -1)));
+        assert!((contact.depth - 0.8).abs() < 1e-5);
+
+        let contact = collide(
+            &sphere(Vec3::new_from_one(0), 1.),
+            &sphere(Vec3::new(0, 0, 1.5), 1.),
+        )
+        .unwrap();
+        assert!(close(contact.normal, Vec3::new(0, 0, 1)));
+        assert!((contact.depth - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn terrain() {
+        let terrain = TerrainParams::new(42, 16).generate();
+        let corner = terrain.scale * terrain.center;
+        let (x, z) = (corner.x + 5.2, corner.z + 7.7);
+        let ground = terrain.height_at(x, z).unwrap();
+        let normal = terrain.normal_at(x, z).unwrap();
+
+        let above = sphere(Vec3::new(x, ground + 2., z), 1.);
+        assert_eq!(collide_terrain(&above, &terrain), None);
+        let sunk = sphere(Vec3::new(x, ground + 0.5, z), 1.);
+        let contact = collide_terrain(&sunk, &terrain).unwrap();
+        assert!(close(contact.normal, normal));
+        assert!((contact.depth - (1. - 0.5 * normal.y)).abs() < 1e-4);
+
+        // a small box sits over the same triangle as its center, level ground or not its
+        // lowest corner is what's deepest
+        let boxed = cube(Vec3::new(x, ground, z), 0.1);
+        let contact = collide_terrain(&boxed, &terrain).unwrap();
+        assert!(contact.depth > 0.05 * normal.y);
+        assert!(contact.depth < 0.2);
+
+        let off = sphere(Vec3::new(corner.x - 5., 0, corner.z), 1.);
+        assert_eq!(collide_terrain(&off, &terrain), None);
+    }
+}
@@ -0,0 +1,557 @@
+use crate::ecs::bounds::SpatialIndex;
+use crate::ecs::components::{Collider, Parent, Transform, Velocity};
+use crate::ecs::{hierarchy, World};
+use crate::entity::terrain::Terrain;
+use crate::snapshot::EntityId;
+use crate::spatial::Aabb;
+use pantheon::Vec3;
+
+pub mod character;
+pub mod contact;
+
+pub use character::{CharacterController, CharacterState};
+pub use contact::{collide, collide_terrain, Contact, Shape};
+
+/// Physics always moves in steps this long, however often it's updated, so the same world
+/// always ends up in the same place
+pub const STEP: f32 = 1. / 60.;
+
+/// most steps `Physics::update` will catch up on at once, anything past that is dropped
+const MAX_STEPS: u32 = 8;
+
+/// times every contact is worked through each step, more settles stacks better
+const ITERATIONS: usize = 8;
+
+/// how far bodies can sink into each other before they're pushed apart, without it resting
+/// contacts come and go every step and jitter
+const SLOP: f32 = 0.01;
+
+/// how much of the overlap past `SLOP` is pushed out each step
+const CORRECTION: f32 = 0.8;
+
+/// impacts slower than this don't bounce, otherwise resting bodies never settle
+const BOUNCE_SPEED: f32 = 1.;
+
+/// a body slower than this for `SLEEP_SECS` is put to sleep
+const SLEEP_SPEED: f32 = 0.1;
+const SLEEP_SECS: f32 = 0.5;
+
+/// Makes an entity with a `Transform` and `Collider` fall and be pushed around by whatever
+/// it hits. Its speed is kept in a `Velocity`. Bodies don't spin, contacts only push them
+/// apart and slow them down. Only entities without a `Parent` are moved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RigidBody {
+    /// a body with no mass never moves, the same as a `Collider` without a `RigidBody`
+    pub mass: f32,
+    /// how much of the speed it hits something with it bounces back with, 0 to 1
+    pub restitution: f32,
+    /// how much it's slowed sliding along something, 0 for ice
+    pub friction: f32,
+    /// Left out of the simulation until something hits it or it's woken, see `wake`.
+    /// Anything which moves a body outside of physics should wake it.
+    pub sleeping: bool,
+    /// seconds it's been slow enough to sleep
+    resting: f32,
+}
+
+impl RigidBody {
+    pub fn new(mass: f32) -> Self {
+        Self {
+            mass,
+            restitution: 0.2,
+            friction: 0.5,
+            sleeping: false,
+            resting: 0.,
+        }
+    }
+
+    pub fn inverse_mass(&self) -> f32 {
+        if self.mass > 0. {
+            1. / self.mass
+        } else {
+            0.
+        }
+    }
+}
+
+/// puts the body `id` back into the simulation, returns false if it doesn't have one
+pub fn wake(world: &mut World, id: EntityId) -> bool {
+    match world.get_mut::<RigidBody>(id) {
+        Some(body) => {
+            body.sleeping = false;
+            body.resting = 0.;
+            true
+        }
+        None => false,
+    }
+}
+
+/// for after something's changed which any body could be resting on, like the terrain
+pub fn wake_all(world: &mut World) {
+    for (_, body) in world.query_mut::<RigidBody>() {
+        body.sleeping = false;
+        body.resting = 0.;
+    }
+}
+
+/// Moves every `RigidBody` in a `World` in fixed `STEP`s. Bodies collide with each other,
+/// with every other `Collider`, which never move, and with the terrain.
+#[derive(Debug)]
+pub struct Physics {
+    /// units per second per second
+    pub gravity: Vec3,
+    index: SpatialIndex,
+    /// time which didn't fill a whole step yet
+    leftover: f32,
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// one side of a contact, as much as the solver needs to know
+#[derive(Debug, Clone, Copy)]
+struct Side {
+    /// `None` for the terrain
+    id: Option<EntityId>,
+    inverse_mass: f32,
+    restitution: f32,
+    friction: f32,
+}
+
+/// a contact being worked through by `Physics::solve`
+#[derive(Debug, Clone, Copy)]
+struct Constraint {
+    a: Side,
+    b: Side,
+    contact: Contact,
+    /// the speed they'll bounce apart at
+    bounce: f32,
+    /// all the impulse pushing them apart so far this step, it can only ever push
+    impulse: f32,
+}
+
+impl Physics {
+    pub fn new() -> Self {
+        Self {
+            gravity: Vec3::new(0., -9.81, 0.),
+            index: SpatialIndex::new(),
+            leftover: 0.,
+        }
+    }
+
+    /// Runs as many steps as fit into `dt` along with whatever didn't fit last time, returns
+    /// how many that was
+    pub fn update(&mut self, world: &mut World, terrain: Option<&Terrain>, dt: f32) -> u32 {
+        self.leftover += dt;
+        let mut steps = 0;
+        while self.leftover >= STEP {
+            self.leftover -= STEP;
+            if steps == MAX_STEPS {
+                continue;
+            }
+            self.step(world, terrain);
+            steps += 1;
+        }
+
+        steps
+    }
+
+    /// moves everything on by a single `STEP`
+    pub fn step(&mut self, world: &mut World, terrain: Option<&Terrain>) {
+        let mut bodies: Vec<EntityId> = world
+            .query::<RigidBody>()
+            .map(|(id, _)| id)
+            .filter(|id| world.has::<Transform>(*id) && !world.has::<Parent>(*id))
+            .collect();
+        bodies.sort();
+
+        for id in bodies.iter().copied() {
+            if !awake(world, id) {
+                continue;
+            }
+            let velocity = velocity(world, id) + STEP * self.gravity;
+            world.insert(id, Velocity(velocity));
+            if let Some(transform) = world.get_mut::<Transform>(id) {
+                transform.position += STEP * velocity;
+            }
+            hierarchy::mark_dirty(world, id);
+        }
+
+        self.index.update(world);
+        let mut constraints = self.contacts(world, terrain, &bodies);
+        self.solve(world, &mut constraints);
+        self.push_apart(world, &constraints);
+        self.settle(world, &bodies);
+    }
+
+    /// everything touching an awake body, in the same order every time
+    fn contacts(
+        &self,
+        world: &mut World,
+        terrain: Option<&Terrain>,
+        bodies: &[EntityId],
+    ) -> Vec<Constraint> {
+        let mut pairs = vec![];
+        for id in bodies.iter().copied().filter(|id| awake(world, *id)) {
+            let aabb = match world.get::<Aabb>(id) {
+                Some(aabb) => *aabb,
+                None => continue,
+            };
+            for other in self.index.query_box(&aabb) {
+                if other != id {
+                    pairs.push((id.min(other), id.max(other)));
+                }
+            }
+        }
+        pairs.sort();
+        pairs.dedup();
+
+        let mut constraints = vec![];
+        for (a, b) in pairs {
+            let contact = match (shape(world, a), shape(world, b)) {
+                (Some(sa), Some(sb)) => collide(&sa, &sb),
+                _ => None,
+            };
+            if let Some(contact) = contact {
+                // whatever an awake body runs into joins in
+                wake(world, a);
+                wake(world, b);
+                constraints.push((Some(a), b, contact));
+            }
+        }
+        if let Some(terrain) = terrain {
+            for id in bodies.iter().copied().filter(|id| awake(world, *id)) {
+                if let Some(contact) = shape(world, id).and_then(|s| collide_terrain(&s, terrain)) {
+                    constraints.push((None, id, contact));
+                }
+            }
+        }
+
+        constraints
+            .into_iter()
+            .map(|(a, b, contact)| {
+                let a = side(world, a);
+                let b = side(world, Some(b));
+                let approaching =
+                    (velocity_of(world, b) - velocity_of(world, a)).dot(&contact.normal);
+                let bounce = if approaching < -BOUNCE_SPEED {
+                    -approaching * a.restitution.max(b.restitution)
+                } else {
+                    0.
+                };
+
+                Constraint {
+                    a,
+                    b,
+                    contact,
+                    bounce,
+                    impulse: 0.,
+                }
+            })
+            .collect()
+    }
+
+    /// sequential impulses, each contact in turn stops its bodies moving into each other and
+    /// slows them sliding along each other
+    fn solve(&self, world: &mut World, constraints: &mut [Constraint]) {
+        for _ in 0..ITERATIONS {
+            for constraint in constraints.iter_mut() {
+                let Constraint { a, b, contact, .. } = *constraint;
+                let inverse_mass = a.inverse_mass + b.inverse_mass;
+                if inverse_mass == 0. {
+                    continue;
+                }
+
+                let closing = (velocity_of(world, b) - velocity_of(world, a)).dot(&contact.normal);
+                let impulse =
+                    (constraint.impulse - (closing - constraint.bounce) / inverse_mass).max(0.);
+                let change = impulse - constraint.impulse;
+                constraint.impulse = impulse;
+                apply(world, &a, &b, change * contact.normal);
+
+                let relative = velocity_of(world, b) - velocity_of(world, a);
+                let sliding = relative - relative.dot(&contact.normal) * contact.normal;
+                let speed = sliding.magnitude();
+                if speed > 1e-6 {
+                    let friction = (a.friction * b.friction).sqrt() * constraint.impulse;
+                    let slowed = (speed / inverse_mass).min(friction);
+                    apply(world, &a, &b, (-slowed / speed) * sliding);
+                }
+            }
+        }
+    }
+
+    /// velocities alone let bodies sink in a little every step, this moves them back out
+    fn push_apart(&self, world: &mut World, constraints: &[Constraint]) {
+        for Constraint { a, b, contact, .. } in constraints {
+            let inverse_mass = a.inverse_mass + b.inverse_mass;
+            if inverse_mass == 0. {
+                continue;
+            }
+
+            let push =
+                (CORRECTION * (contact.depth - SLOP).max(0.) / inverse_mass) * contact.normal;
+            for (side, push) in [(a, -1. * push), (b, push)] {
+                match side.id {
+                    Some(id) if side.inverse_mass > 0. => {
+                        if let Some(transform) = world.get_mut::<Transform>(id) {
+                            transform.position += side.inverse_mass * push;
+                        }
+                        hierarchy::mark_dirty(world, id);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// puts bodies which have stopped moving to sleep
+    fn settle(&self, world: &mut World, bodies: &[EntityId]) {
+        for id in bodies.iter().copied() {
+            let speed = velocity(world, id).magnitude();
+            let body = match world.get_mut::<RigidBody>(id) {
+                Some(body) if !body.sleeping => body,
+                _ => continue,
+            };
+            if speed > SLEEP_SPEED {
+                body.resting = 0.;
+                continue;
+            }
+
+            body.resting += STEP;
+            if body.resting >= SLEEP_SECS {
+                body.sleeping = true;
+                world.insert(id, Velocity(Vec3::new_from_one(0)));
+            }
+        }
+    }
+}
+
+/// whether `id` is a body which is moving on its own
+fn awake(world: &World, id: EntityId) -> bool {
+    world
+        .get::<RigidBody>(id)
+        .is_some_and(|body| !body.sleeping && body.inverse_mass() > 0.)
+}
+
+/// where `id`'s `Collider` is in the world
+pub fn shape(world: &World, id: EntityId) -> Option<Shape> {
+    let collider = world.get::<Collider>(id)?;
+    let transform = hierarchy::world_transform(world, id)?;
+    Some(Shape::new(collider, &transform))
+}
+
+fn side(world: &World, id: Option<EntityId>) -> Side {
+    // the terrain and plain colliders are as rough as a default body and don't bounce
+    let body = id.and_then(|id| world.get::<RigidBody>(id));
+    let defaults = RigidBody {
+        restitution: 0.,
+        ..RigidBody::new(0.)
+    };
+    let body = body.copied().unwrap_or(defaults);
+
+    Side {
+        id,
+        inverse_mass: if body.sleeping {
+            0.
+        } else {
+            body.inverse_mass()
+        },
+        restitution: body.restitution,
+        friction: body.friction,
+    }
+}
+
+fn velocity(world: &World, id: EntityId) -> Vec3 {
+    world
+        .get::<Velocity>(id)
+        .map_or(Vec3::new_from_one(0), |velocity| velocity.0)
+}
+
+/// only bodies move, anything else is as good as still
+fn velocity_of(world: &World, side: Side) -> Vec3 {
+    match side.id {
+        Some(id) if side.inverse_mass > 0. => velocity(world, id),
+        _ => Vec3::new_from_one(0),
+    }
+}
+
+/// pushes `b` along `impulse` and `a` the other way
+fn apply(world: &mut World, a: &Side, b: &Side, impulse: Vec3) {
+    for (side, impulse) in [(a, -1. * impulse), (b, impulse)] {
+        if let (Some(id), true) = (side.id, side.inverse_mass > 0.) {
+            let velocity = velocity(world, id) + side.inverse_mass * impulse;
+            world.insert(id, Velocity(velocity));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::proc_gen::terrain::TerrainParams;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    fn body(world: &mut World, position: Vec3, collider: Collider) -> EntityId {
+        world
+            .build()
+            .with(Transform::new(position))
+            .with(collider)
+            .with(RigidBody::new(1.))
+            .id()
+    }
+
+    fn position(world: &World, id: EntityId) -> Vec3 {
+        world.get::<Transform>(id).unwrap().position
+    }
+
+    #[test]
+    fn fixed_steps() {
+        let mut world = World::new();
+        let mut physics = Physics::new();
+        assert_eq!(physics.update(&mut world, None, 0.025), 1);
+        // what was left over carries on into the next update
+        assert_eq!(physics.update(&mut world, None, 0.01), 1);
+        assert_eq!(physics.update(&mut world, None, 0.001), 0);
+        // a long stall is given up on rather than caught up with
+        assert_eq!(physics.update(&mut world, None, 10.), MAX_STEPS);
+        assert_eq!(physics.update(&mut world, None, 0.), 0);
+    }
+
+    #[test]
+    fn rests_on_the_terrain() {
+        let terrain = TerrainParams::new(42, 16).generate();
+        let corner = terrain.scale * terrain.center;
+        let (x, z) = (corner.x + 8.5, corner.z + 8.5);
+        let ground = terrain.height_at(x, z).unwrap();
+
+        let mut world = World::new();
+        let mut physics = Physics::new();
+        let cube = body(
+            &mut world,
+            Vec3::new(x, ground + 10., z),
+            Collider::cube(1.),
+        );
+        let ball = body(
+            &mut world,
+            Vec3::new(x + 3., ground + 10., z),
+            Collider::sphere(0.5),
+        );
+        for _ in 0..5 * 60 {
+            physics.step(&mut world, Some(&terrain));
+        }
+
+        for id in [cube, ball] {
+            let at = position(&world, id);
+            let ground = terrain.height_at(at.x, at.z).unwrap();
+            assert!(at.y > ground, "{} sank to {:?}", id, at);
+            assert!(at.y < ground + 1.5, "{} is floating at {:?}", id, at);
+            assert!(world.get::<RigidBody>(id).unwrap().sleeping);
+            assert_eq!(velocity(&world, id), Vec3::new_from_one(0));
+        }
+
+        // asleep nothing changes, woken up it's back to falling
+        let resting = position(&world, cube);
+        physics.step(&mut world, Some(&terrain));
+        assert_eq!(position(&world, cube), resting);
+        wake_all(&mut world);
+        physics.step(&mut world, Some(&terrain));
+        assert!(!world.get::<RigidBody>(cube).unwrap().sleeping);
+    }
+
+    #[test]
+    fn lands_on_colliders() {
+        let mut world = World::new();
+        let mut physics = Physics::new();
+        let floor = world
+            .build()
+            .with(Transform::new(Vec3::new_from_one(0)))
+            .with(Collider::cube(4.))
+            .id();
+        let bottom = body(&mut world, Vec3::new(0, 4, 0), Collider::cube(1.));
+        let top = body(&mut world, Vec3::new(0.2, 6, 0), Collider::cube(1.));
+        for _ in 0..4 * 60 {
+            physics.step(&mut world, None);
+        }
+
+        // colliders without a body stay put, bodies stack up on them
+        assert_eq!(position(&world, floor), Vec3::new_from_one(0));
+        assert!((position(&world, bottom).y - 2.5).abs() < 0.05);
+        assert!((position(&world, top).y - 3.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn bounces() {
+        let mut world = World::new();
+        let mut physics = Physics::new();
+        world
+            .build()
+            .with(Transform::new(Vec3::new_from_one(0)))
+            .with(Collider::cube(2.));
+        let ball = world
+            .build()
+            .with(Transform::new(Vec3::new(0, 6, 0)))
+            .with(Collider::sphere(1.))
+            .with(RigidBody {
+                restitution: 0.8,
+                ..RigidBody::new(1.)
+            })
+            .id();
+
+        let mut bounced = false;
+        let mut highest = 0f32;
+        for _ in 0..3 * 60 {
+            physics.step(&mut world, None);
+            let velocity = velocity(&world, ball);
+            bounced |= velocity.y > 0.;
+            if bounced {
+                highest = highest.max(position(&world, ball).y);
+            }
+        }
+        // dropped 4 above where it lands, it comes back up most of the way
+        assert!(bounced);
+        assert!(highest > 2. + 0.5 * 4., "only got to {}", highest);
+        assert!(highest < 6.);
+    }
+
+    #[test]
+    fn same_world_same_outcome() {
+        let terrain = TerrainParams::new(3, 16).generate();
+        let corner = terrain.scale * terrain.center;
+        let run = |seed: u64| {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut world = World::new();
+            let mut physics = Physics::new();
+            let bodies: Vec<_> = (0..20)
+                .map(|i| {
+                    let position = Vec3::new(
+                        corner.x + rng.gen_range(4.0..12.0),
+                        rng.gen_range(5.0..15.0),
+                        corner.z + rng.gen_range(4.0..12.0),
+                    );
+                    let collider = if i % 2 == 0 {
+                        Collider::cube(rng.gen_range(0.5..2.0))
+                    } else {
+                        Collider::sphere(rng.gen_range(0.25..1.0))
+                    };
+                    body(&mut world, position, collider)
+                })
+                .collect();
+            for _ in 0..3 * 60 {
+                physics.step(&mut world, Some(&terrain));
+            }
+
+            bodies
+                .iter()
+                .map(|id| position(&world, *id))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+}
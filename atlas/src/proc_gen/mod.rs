@@ -1,3 +1,4 @@
 pub mod color;
 pub mod noise;
+pub mod primitives;
 pub mod terrain;
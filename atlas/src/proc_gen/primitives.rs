@@ -0,0 +1,343 @@
+use crate::entity::mesh::MeshData;
+use pantheon::Color;
+use pantheon::Vec3;
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// most segments, rings or sides any of the generators will go around with
+pub const MAX_SEGMENTS: u32 = 256;
+/// every subdivision quadruples the triangles of an icosphere, 6 is already 81920 of them
+pub const MAX_SUBDIVISIONS: u32 = 6;
+
+/// A point on the outline `lathe` spins around the y axis. `normal` is in the same
+/// (distance from the axis, height) plane.
+#[derive(Debug, Clone, Copy)]
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    normal: (f32, f32),
+}
+
+impl ProfilePoint {
+    fn new(radius: f32, y: f32, normal: (f32, f32)) -> Self {
+        Self { radius, y, normal }
+    }
+}
+
+fn detail(count: u32, min: u32) -> u32 {
+    count.clamp(min, MAX_SEGMENTS)
+}
+
+/// `columns` by `rows` quads over a grid of `vertex(column, row)`. Each quad faces the way
+/// stepping along a row and then down a column turns counter-clockwise.
+fn grid(
+    columns: u32,
+    rows: u32,
+    color: Color,
+    vertex: impl Fn(u32, u32) -> (Vec3, Vec3),
+) -> MeshData {
+    let mut mesh = MeshData::default();
+    for row in 0..=rows {
+        for column in 0..=columns {
+            let (position, normal) = vertex(column, row);
+            mesh.vertices.push((position, color, normal).into());
+        }
+    }
+
+    let index = |column: u32, row: u32| row * (columns + 1) + column;
+    for row in 0..rows {
+        for column in 0..columns {
+            let (a, b) = (index(column, row), index(column + 1, row));
+            let (c, d) = (index(column + 1, row + 1), index(column, row + 1));
+            mesh.indices.extend_from_slice(&[a, b, c, a, c, d]);
+        }
+    }
+
+    mesh
+}
+
+/// Spins `profile` once around the y axis in `segments` steps. Drawn with the distance from
+/// the axis going right and y going up, the surface faces left of the way the profile runs.
+/// The seam is doubled up so both ends of a ring keep their own vertex.
+fn lathe(profile: &[ProfilePoint], segments: u32, color: Color) -> MeshData {
+    let rows = profile.len().saturating_sub(1) as u32;
+    grid(segments, rows, color, |column, row| {
+        let point = profile[row as usize];
+        let (sin, cos) = (TAU * column as f32 / segments as f32).sin_cos();
+        let (normal_radius, normal_y) = point.normal;
+        (
+            Vec3::new(point.radius * cos, point.y, point.radius * sin),
+            Vec3::new(normal_radius * cos, normal_y, normal_radius * sin).unit_vector(),
+        )
+    })
+}
+
+/// flat circle at height `y`, facing up or down
+fn disc(radius: f32, y: f32, up: bool, segments: u32, color: Color) -> MeshData {
+    let center = ProfilePoint::new(0., y, (0., 1.));
+    let rim = ProfilePoint::new(radius, y, (0., 1.));
+    if up {
+        lathe(&[center, rim], segments, color)
+    } else {
+        let down = |point: ProfilePoint| ProfilePoint::new(point.radius, y, (0., -1.));
+        lathe(&[down(rim), down(center)], segments, color)
+    }
+}
+
+/// `rings` bands from pole to pole and `segments` around, the poles are on the y axis
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32, color: Color) -> MeshData {
+    let rings = detail(rings, 2);
+    let profile: Vec<ProfilePoint> = (0..=rings)
+        .map(|ring| {
+            let (sin, cos) = (PI * ring as f32 / rings as f32).sin_cos();
+            ProfilePoint::new(radius * sin, radius * cos, (sin, cos))
+        })
+        .collect();
+
+    lathe(&profile, detail(segments, 3), color)
+}
+
+/// An icosahedron with each triangle split in four `subdivisions` times and pushed out onto
+/// the sphere, evenly spread out unlike `uv_sphere`
+pub fn icosphere(radius: f32, subdivisions: u32, color: Color) -> MeshData {
+    let t = (1. + 5f32.sqrt()) / 2.;
+    #[rustfmt::skip]
+    let mut points: Vec<Vec3> = [
+        (-1., t, 0.), (1., t, 0.), (-1., -t, 0.), (1., -t, 0.),
+        (0., -1., t), (0., 1., t), (0., -1., -t), (0., 1., -t),
+        (t, 0., -1.), (t, 0., 1.), (-t, 0., -1.), (-t, 0., 1.),
+    ]
+    .iter()
+    .map(|&(x, y, z)| Vec3::new(x, y, z).unit_vector())
+    .collect();
+    #[rustfmt::skip]
+    let mut faces: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions.min(MAX_SUBDIVISIONS) {
+        // neighbouring faces share the points on their common edge
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let middle = 0.5 * (points[a as usize] + points[b as usize]);
+                points.push(middle.unit_vector());
+                points.len() as u32 - 1
+            })
+        };
+        faces = faces
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    MeshData {
+        vertices: points
+            .iter()
+            .map(|&point| (radius * point, color, point).into())
+            .collect(),
+        indices: faces.concat(),
+    }
+}
+
+/// `height` tall along y with both ends capped
+pub fn cylinder(radius: f32, height: f32, segments: u32, color: Color) -> MeshData {
+    let segments = detail(segments, 3);
+    let (top, bottom) = (height / 2., -height / 2.);
+    let side = [
+        ProfilePoint::new(radius, top, (1., 0.)),
+        ProfilePoint::new(radius, bottom, (1., 0.)),
+    ];
+
+    let mut mesh = disc(radius, top, true, segments, color);
+    mesh.append(&lathe(&side, segments, color));
+    mesh.append(&disc(radius, bottom, false, segments, color));
+    mesh
+}
+
+/// `height` tall along y, with the point at the top and the capped base at the bottom
+pub fn cone(radius: f32, height: f32, segments: u32, color: Color) -> MeshData {
+    let segments = detail(segments, 3);
+    let (top, bottom) = (height / 2., -height / 2.);
+    // at right angles to the slope, which runs `radius` out for every `height` down
+    let slant = (height * height + radius * radius).sqrt();
+    let normal = (height / slant, radius / slant);
+    let side = [
+        ProfilePoint::new(0., top, normal),
+        ProfilePoint::new(radius, bottom, normal),
+    ];
+
+    let mut mesh = lathe(&side, segments, color);
+    mesh.append(&disc(radius, bottom, false, segments, color));
+    mesh
+}
+
+/// A cylinder with rounded ends `rings` bands deep, `height` from the very bottom to the very
+/// top like `CharacterController`
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32, color: Color) -> MeshData {
+    let rings = detail(rings, 1);
+    // how far each end's center is from the middle
+    let middle = (height / 2. - radius).max(0.);
+    let profile: Vec<ProfilePoint> = (0..=2 * rings + 1)
+        .map(|i| {
+            let (ring, y) = if i <= rings {
+                (i, middle)
+            } else {
+                (i - 1, -middle)
+            };
+            let (sin, cos) = (FRAC_PI_2 * ring as f32 / rings as f32).sin_cos();
+            ProfilePoint::new(radius * sin, y + radius * cos, (sin, cos))
+        })
+        .collect();
+
+    lathe(&profile, detail(segments, 3), color)
+}
+
+/// Lying flat around the y axis, `radius` out to the middle of a tube `tube_radius` thick which
+/// is `sides` steps around
+pub fn torus(radius: f32, tube_radius: f32, segments: u32, sides: u32, color: Color) -> MeshData {
+    let sides = detail(sides, 3);
+    // around the tube the other way from the top, so the outside is going down
+    let profile: Vec<ProfilePoint> = (0..=sides)
+        .map(|side| {
+            let (sin, cos) = (-TAU * side as f32 / sides as f32).sin_cos();
+            ProfilePoint::new(radius + tube_radius * cos, tube_radius * sin, (cos, sin))
+        })
+        .collect();
+
+    lathe(&profile, detail(segments, 3), color)
+}
+
+/// `size` across on y = 0 facing up, split into `subdivisions` squares along each side
+pub fn plane(size: f32, subdivisions: u32, color: Color) -> MeshData {
+    let cells = detail(subdivisions, 1);
+    let up = Vec3::new(0, 1, 0);
+    grid(cells, cells, color, |column, row| {
+        let x = column as f32 / cells as f32 - 0.5;
+        let z = 0.5 - row as f32 / cells as f32;
+        (Vec3::new(size * x, 0, size * z), up)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pantheon::Mat4;
+
+    fn color() -> Color {
+        Color::new(255, 255, 255)
+    }
+
+    /// every face turns counter-clockwise towards the normals of its corners
+    fn assert_faces_match_normals(mesh: &MeshData) {
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] =
+                [triangle[0], triangle[1], triangle[2]].map(|i| mesh.vertices[i as usize]);
+            let face = (b.position - a.position).cross(&(c.position - a.position));
+            // the rings around poles and apexes squash down to nothing
+            if face.magnitude() < 1e-6 {
+                continue;
+            }
+            for vertex in [a, b, c] {
+                assert!((vertex.normal.magnitude() - 1.).abs() < 1e-5);
+                assert!(
+                    face.dot(&vertex.normal) > 0.,
+                    "{:?} faces away from {:?}",
+                    face,
+                    vertex
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn faces_point_outward() {
+        for mesh in [
+            uv_sphere(2., 16, 8, color()),
+            icosphere(2., 2, color()),
+            cylinder(1., 3., 12, color()),
+            cone(1., 2., 12, color()),
+            capsule(0.5, 2., 12, 4, color()),
+            torus(2., 0.5, 16, 8, color()),
+            plane(4., 3, color()),
+        ] {
+            assert!(!mesh.is_empty());
+            assert_faces_match_normals(&mesh);
+        }
+
+        // closed around the origin, so every face looks away from it too
+        for mesh in [
+            uv_sphere(1., 8, 4, color()),
+            icosphere(1., 1, color()),
+            cylinder(1., 1., 8, color()),
+            capsule(1., 4., 8, 2, color()),
+        ] {
+            for [p0, p1, p2] in mesh.triangles() {
+                let face = (p1 - p0).cross(&(p2 - p0));
+                let center = (1. / 3.) * (p0 + p1 + p2);
+                assert!(face.magnitude() < 1e-6 || face.dot(&center) > 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn stays_on_the_surface() {
+        let sphere = icosphere(3., 1, color());
+        assert_eq!(sphere.vertices.len(), 42);
+        assert_eq!(sphere.triangle_count(), 80);
+        for vertex in sphere
+            .vertices
+            .iter()
+            .chain(&uv_sphere(3., 10, 6, color()).vertices)
+        {
+            assert!((vertex.position.magnitude() - 3.).abs() < 1e-4);
+        }
+
+        for vertex in torus(2., 0.5, 12, 6, color()).vertices {
+            let around = Vec3::new(vertex.position.x, 0, vertex.position.z).unit_vector();
+            let tube = vertex.position - 2. * around;
+            assert!((tube.magnitude() - 0.5).abs() < 1e-4);
+            assert!((vertex.normal - tube.unit_vector()).magnitude() < 1e-4);
+        }
+
+        let capsule = capsule(0.5, 3., 8, 3, color());
+        let aabb = capsule.aabb().unwrap();
+        assert!((aabb.max.y - 1.5).abs() < 1e-5 && (aabb.min.y + 1.5).abs() < 1e-5);
+
+        let plane = plane(2., 4, color());
+        assert_eq!(plane.triangle_count(), 32);
+        let aabb = plane.aabb().unwrap();
+        assert_eq!(
+            (aabb.min, aabb.max),
+            (Vec3::new(-1, 0, -1), Vec3::new(1, 0, 1))
+        );
+        assert!(plane
+            .basic_vertices()
+            .iter()
+            .all(|vertex| vertex.position.y == 0.));
+    }
+
+    #[test]
+    fn picks_the_near_side() {
+        let down = Vec3::new(0, -1, 0);
+        let model = Mat4::identity();
+        let ring = torus(2., 0.5, 24, 12, color());
+        for (mesh, x, top) in [
+            (cylinder(1., 2., 16, color()), 0.3, 1.),
+            (cone(1., 2., 16, color()), 0.5, 0.),
+            (uv_sphere(1., 16, 8, color()), 0.6, 0.8),
+            (ring.clone(), 2., 0.5),
+        ] {
+            let (point, _) = mesh.ray_pick(&model, Vec3::new(x, 5, 0.01), down).unwrap();
+            assert!((point.y - top).abs() < 0.05, "{:?}", point);
+        }
+        // straight down the hole in the middle
+        assert!(ring.ray_pick(&model, Vec3::new(0, 5, 0), down).is_none());
+    }
+}
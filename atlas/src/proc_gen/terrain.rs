@@ -2,11 +2,131 @@ use pantheon::Color;
 use pantheon::Vec3;
 
 use super::color::ColorGenerator;
+use super::noise;
 use super::noise::Perlin;
+use crate::config::MAX_TERRAIN_SIZE;
 use crate::entity::plane::Plane;
 use crate::entity::terrain::Terrain;
 use crate::vertex::ShadedVertex;
 
+pub const MAX_PALETTE_COLORS: usize = 8;
+/// colors are blended between neighbours, so there have to be at least two
+pub const MIN_PALETTE_COLORS: usize = 2;
+/// more than this takes too long to generate for anything it adds
+pub const MAX_OCTAVES: i32 = 16;
+pub const DEFAULT_SCALE: f32 = 2.;
+
+/// Everything needed to deterministically regenerate a `Terrain`, kept `Copy` with a fixed size
+/// palette so it can be pushed into a single `Message`
+#[derive(Debug, Clone, Copy)]
+pub struct TerrainParams {
+    pub seed: isize,
+    pub size: u32,
+    pub clamped: bool,
+    pub roughness: f32,
+    pub octaves: i32,
+    pub amplitude: f32,
+    /// base height the terrain sits at, the mesh is centered on the origin in x and z
+    pub height: f32,
+    /// How much the whole world is scaled up by, the server's physics and every client's
+    /// terrain and water use the same one so entities line up with the ground
+    pub scale: f32,
+    pub color_spread: f32,
+    pub palette_len: u32,
+    pub palette: [Color; MAX_PALETTE_COLORS],
+}
+
+pub fn default_palette() -> Vec<Color> {
+    vec![
+        (201, 178, 99).into(),
+        (164, 155, 98).into(),
+        (164, 155, 98).into(),
+        (229, 219, 164).into(),
+        (135, 184, 82).into(),
+        (120, 120, 120).into(),
+        (200, 200, 210).into(),
+    ]
+}
+
+impl TerrainParams {
+    pub fn new(seed: isize, size: u32) -> Self {
+        let mut params = Self {
+            seed,
+            size,
+            clamped: false,
+            roughness: noise::ROUGHNESS,
+            octaves: noise::OCTAVES,
+            amplitude: noise::AMP,
+            height: 3.,
+            scale: DEFAULT_SCALE,
+            color_spread: 0.45,
+            palette_len: 0,
+            palette: [Color::floats(0., 0., 0.); MAX_PALETTE_COLORS],
+        };
+        params.set_palette(&default_palette());
+
+        params
+    }
+
+    /// only the first `MAX_PALETTE_COLORS` colors are kept
+    pub fn set_palette(&mut self, colors: &[Color]) {
+        let len = colors.len().min(MAX_PALETTE_COLORS);
+        self.palette[..len].copy_from_slice(&colors[..len]);
+        self.palette_len = len as u32;
+    }
+
+    pub fn palette(&self) -> &[Color] {
+        &self.palette[..self.palette_len as usize]
+    }
+
+    /// Whether `generate` can be trusted with these, they're checked wherever they come from
+    /// outside of the server since a big enough size or too few colors take it down
+    pub fn is_valid(&self) -> bool {
+        (2..=MAX_TERRAIN_SIZE).contains(&self.size)
+            && (1..=MAX_OCTAVES).contains(&self.octaves)
+            && (MIN_PALETTE_COLORS..=MAX_PALETTE_COLORS).contains(&(self.palette_len as usize))
+            && [
+                self.roughness,
+                self.amplitude,
+                self.height,
+                self.color_spread,
+            ]
+            .iter()
+            .all(|v| v.is_finite())
+            && self.scale.is_normal()
+            && self.scale > 0.
+    }
+
+    pub fn center(&self) -> Vec3 {
+        let half_size = self.size as f32 / 2.;
+        (-half_size, self.height, -half_size).into()
+    }
+
+    pub fn generator(&self) -> TerrainGenerator {
+        let perlin = Perlin::new(self.seed, self.roughness, self.octaves, self.amplitude);
+        let color_gen = ColorGenerator::new(self.palette().to_vec(), self.color_spread);
+
+        TerrainGenerator::new(perlin, color_gen)
+    }
+
+    /// the same params always produce the same mesh, which is what lets clients regenerate the
+    /// server's terrain locally
+    pub fn generate(&self) -> Terrain<'static> {
+        let mut terrain = self.generator().generate(self.size as usize, self.clamped);
+        terrain.center = self.center();
+        terrain.scale = self.scale;
+
+        terrain
+    }
+}
+
+impl Default for TerrainParams {
+    fn default() -> Self {
+        let size = if cfg!(debug_assertions) { 50 } else { 250 };
+        Self::new(0, size)
+    }
+}
+
 pub struct TerrainGenerator {
     perlin_noise: Perlin,
     color_gen: ColorGenerator,
@@ -21,7 +141,8 @@ impl TerrainGenerator {
     }
 
     pub fn generate(&self, size: usize, clamped: bool) -> Terrain<'static> {
-        println!("[terrain proc gen] generating heights");
+        let _span = tracing::debug_span!("generate_terrain", size, clamped).entered();
+        tracing::debug!("generating heights");
         let heights: Vec<f32> = (0..(size + 1).pow(2))
             .map(|i| {
                 let x = i % (size + 1);
@@ -42,27 +163,27 @@ impl TerrainGenerator {
             })
             .collect();
 
-        println!("[terrain proc gen] generating colors");
+        tracing::debug!("generating colors");
         let colors = self
             .color_gen
             .generate(&heights, self.perlin_noise.amplitude);
 
-        println!("[terrain proc gen] generating mesh");
-        let mesh = if clamped {
-            Self::create_mesh(&clamped_heights, &colors, size + 1)
-        } else {
-            Self::create_mesh(&heights, &colors, size + 1)
-        };
+        tracing::debug!("generating mesh");
+        let heights = if clamped { clamped_heights } else { heights };
+        let mesh = Self::create_mesh(&heights, &colors, size + 1);
 
-        println!("[terrain proc gen] generating indices");
+        tracing::debug!("generating indices");
         let indices = index_gen::generate_index_buffer(size + 1);
-        println!("[terrain proc gen] done");
+        tracing::debug!(
+            verts = mesh.len(),
+            indices = indices.len(),
+            "generated terrain"
+        );
 
-        //println!("[Mesh] {:#?}", mesh);
-        println!("[Mesh] {:#?}", mesh.len());
-        println!("[Indices] {:#?}", indices.len());
+        let mut terrain = Terrain::from_data(mesh, indices);
+        terrain.heights = heights;
 
-        Terrain::from_data(mesh, indices)
+        terrain
     }
 
     fn create_mesh(heights: &Vec<f32>, colors: &Vec<Color>, size: usize) -> Vec<ShadedVertex> {
@@ -134,7 +134,7 @@ where
         bind_group_handle,
     };
 
-    println!("[register_indexed] draw_call: {:#?}", draw_call);
+    tracing::trace!(?draw_call, "registering indexed draw call");
 
     let handle = ctx.wrangler.add_draw_call(draw_call, vertex_label);
 
@@ -145,15 +145,29 @@ where
             .expect(&format!("No registered pass labeled {}", &label));
         let pass = ctx.wrangler.get_pass_mut(&pass_handle);
         pass.draw_call_handles.push(handle);
-        println!(
-            "[register_indexed] pass.draw_call_handles: {:#?}",
-            pass.draw_call_handles
+        tracing::trace!(
+            pass = label,
+            draw_calls = pass.draw_call_handles.len(),
+            "added draw call"
         );
     });
 
     handle
 }
 
+/// there is no way to free a registered draw call's buffer space yet, so this just stops it from
+/// being drawn
+pub fn hide<'a>(ctx: &mut Context<'a>, draw_call_handle: &DrawCallHandle<'a>) {
+    let draw_call = ctx.wrangler.get_draw_call_mut(draw_call_handle);
+    draw_call.instances = 0..0;
+}
+
+/// draws a single instance of something `hide`den again
+pub fn show<'a>(ctx: &mut Context<'a>, draw_call_handle: &DrawCallHandle<'a>) {
+    let draw_call = ctx.wrangler.get_draw_call_mut(draw_call_handle);
+    draw_call.instances = 0..1;
+}
+
 fn texture_bind_group<'a>(
     ctx: &mut Context<'a>,
     texture: &Texture,
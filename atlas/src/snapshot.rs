@@ -0,0 +1,496 @@
+use crate::ecs::replicate::Replicated;
+pub use crate::entity::id::EntityId;
+use crate::message::GameMessage;
+use hermes::message::{Message, MessageError};
+use pantheon::math::{Mat4, Vec3};
+use pantheon::Color;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+pub type Tick = u32;
+
+/// How many unacknowledged snapshots are kept around per client before its baseline is
+/// considered lost and a full resync is sent instead
+pub const MAX_PENDING_SNAPSHOTS: usize = 32;
+
+pub mod fields {
+    pub const POSITION: u8 = 1 << 0;
+    pub const ROTATION: u8 = 1 << 1;
+    pub const COLOR: u8 = 1 << 2;
+    pub const VELOCITY: u8 = 1 << 3;
+
+    pub const ALL: u8 = POSITION | ROTATION | COLOR | VELOCITY;
+}
+
+/// The parts of an entity which are allowed to change after it has been spawned
+#[derive(Debug, Clone, Copy)]
+pub struct EntityState {
+    pub position: Vec3,
+    pub rotation: Mat4,
+    pub color: Color,
+    /// only players move on their own, everything else stays at zero
+    pub velocity: Vec3,
+}
+
+impl EntityState {
+    /// bitmask of `fields` which differ between the two states
+    pub fn changed_fields(&self, other: &Self) -> u8 {
+        let mut changed = 0;
+        if self.position != other.position {
+            changed |= fields::POSITION;
+        }
+        if self.rotation != other.rotation {
+            changed |= fields::ROTATION;
+        }
+        let (a, b) = (self.color, other.color);
+        if a.r != b.r || a.g != b.g || a.b != b.b || a.a != b.a {
+            changed |= fields::COLOR;
+        }
+        if self.velocity != other.velocity {
+            changed |= fields::VELOCITY;
+        }
+
+        changed
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy)]
+pub enum SnapshotEvent {
+    Spawn(EntityId, Replicated),
+    Update(EntityId, u8, EntityState),
+    Despawn(EntityId),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SnapshotEventKind {
+    Spawn,
+    Update,
+    Despawn,
+}
+
+/// Leads every snapshot message, a `baseline` of `None` means the events describe the whole
+/// world and the receiver should start from scratch
+#[derive(Debug, Clone, Copy)]
+pub struct SnapshotHeader {
+    pub tick: Tick,
+    pub baseline: Option<Tick>,
+    pub event_count: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub tick: Tick,
+    pub entities: BTreeMap<EntityId, Replicated>,
+}
+
+impl Snapshot {
+    pub fn new(tick: Tick, entities: BTreeMap<EntityId, Replicated>) -> Self {
+        Self { tick, entities }
+    }
+
+    /// events which turn `baseline` into `self`, every entity is spawned when there is no
+    /// baseline
+    pub fn diff(&self, baseline: Option<&Snapshot>) -> Vec<SnapshotEvent> {
+        let empty = BTreeMap::new();
+        let old = baseline.map_or(&empty, |baseline| &baseline.entities);
+
+        let mut events = vec![];
+        for (id, entity) in self.entities.iter() {
+            match old.get(id) {
+                Some(old_entity) => {
+                    let state = entity.state();
+                    let changed = state.changed_fields(&old_entity.state());
+                    if changed != 0 {
+                        events.push(SnapshotEvent::Update(*id, changed, state));
+                    }
+                }
+                None => events.push(SnapshotEvent::Spawn(*id, *entity)),
+            }
+        }
+
+        for id in old.keys() {
+            if !self.entities.contains_key(id) {
+                events.push(SnapshotEvent::Despawn(*id));
+            }
+        }
+
+        events
+    }
+
+    /// builds the snapshot at `tick` by applying `events` on top of `self`
+    pub fn apply(&self, tick: Tick, events: &[SnapshotEvent]) -> Snapshot {
+        let mut entities = self.entities.clone();
+        for event in events {
+            match *event {
+                SnapshotEvent::Spawn(id, entity) => {
+                    entities.insert(id, entity);
+                }
+                SnapshotEvent::Update(id, changed, state) => {
+                    if let Some(entity) = entities.get_mut(&id) {
+                        entity.apply_state(changed, &state);
+                    }
+                }
+                SnapshotEvent::Despawn(id) => {
+                    entities.remove(&id);
+                }
+            }
+        }
+
+        Snapshot { tick, entities }
+    }
+}
+
+pub fn snapshot_message(header: SnapshotHeader, events: &[SnapshotEvent]) -> Message<GameMessage> {
+    let mut msg = Message::new(GameMessage::Snapshot);
+
+    // pulling works from the back of the body, so push in reverse to read them back in order
+    for event in events.iter().rev() {
+        match *event {
+            SnapshotEvent::Spawn(id, entity) => {
+                msg.push(entity);
+                msg.push(id);
+                msg.push(SnapshotEventKind::Spawn);
+            }
+            SnapshotEvent::Update(id, changed, state) => {
+                if changed & fields::POSITION != 0 {
+                    msg.push(state.position);
+                }
+                if changed & fields::ROTATION != 0 {
+                    msg.push(state.rotation);
+                }
+                if changed & fields::COLOR != 0 {
+                    msg.push(state.color);
+                }
+                if changed & fields::VELOCITY != 0 {
+                    msg.push(state.velocity);
+                }
+                msg.push(changed);
+                msg.push(id);
+                msg.push(SnapshotEventKind::Update);
+            }
+            SnapshotEvent::Despawn(id) => {
+                msg.push(id);
+                msg.push(SnapshotEventKind::Despawn);
+            }
+        }
+    }
+    msg.push(header);
+
+    msg
+}
+
+pub fn pull_snapshot(
+    msg: &mut Message<GameMessage>,
+) -> Result<(SnapshotHeader, Vec<SnapshotEvent>), MessageError> {
+    let header: SnapshotHeader = msg.pull()?;
+    let mut events = Vec::with_capacity(header.event_count as usize);
+
+    for _ in 0..header.event_count {
+        let event = match msg.pull::<SnapshotEventKind>()? {
+            SnapshotEventKind::Spawn => {
+                let id = msg.pull()?;
+                SnapshotEvent::Spawn(id, msg.pull()?)
+            }
+            SnapshotEventKind::Update => {
+                let id = msg.pull()?;
+                let changed: u8 = msg.pull()?;
+                // fields that didn't change are never read, the zeroed values are just filler
+                let mut state = EntityState {
+                    position: Vec3::new_from_one(0),
+                    rotation: Mat4::identity(),
+                    color: Color::floats(0., 0., 0.),
+                    velocity: Vec3::new_from_one(0),
+                };
+                if changed & fields::VELOCITY != 0 {
+                    state.velocity = msg.pull()?;
+                }
+                if changed & fields::COLOR != 0 {
+                    state.color = msg.pull()?;
+                }
+                if changed & fields::ROTATION != 0 {
+                    state.rotation = msg.pull()?;
+                }
+                if changed & fields::POSITION != 0 {
+                    state.position = msg.pull()?;
+                }
+                SnapshotEvent::Update(id, changed, state)
+            }
+            SnapshotEventKind::Despawn => SnapshotEvent::Despawn(msg.pull()?),
+        };
+        events.push(event);
+    }
+
+    Ok((header, events))
+}
+
+/// Server side bookkeeping of what a single client has acknowledged
+#[derive(Debug, Default)]
+pub struct ClientBaseline {
+    baseline: Option<Arc<Snapshot>>,
+    pending: VecDeque<Arc<Snapshot>>,
+}
+
+impl ClientBaseline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// forget everything, the next message will be a full snapshot
+    pub fn reset(&mut self) {
+        self.baseline = None;
+        self.pending.clear();
+    }
+
+    /// builds the message which brings the client from its acknowledged baseline up to
+    /// `current`, returns `None` when there is nothing new to tell it
+    pub fn delta_message(&mut self, current: &Arc<Snapshot>) -> Option<Message<GameMessage>> {
+        if self.pending.len() >= MAX_PENDING_SNAPSHOTS {
+            tracing::warn!("client stopped acking, falling back to a full snapshot");
+            self.reset();
+        }
+
+        let baseline = self.baseline.as_deref();
+        let events = current.diff(baseline);
+        if events.is_empty() && baseline.is_some() {
+            return None;
+        }
+
+        let header = SnapshotHeader {
+            tick: current.tick,
+            baseline: baseline.map(|baseline| baseline.tick),
+            event_count: events.len() as u32,
+        };
+        self.pending.push_back(Arc::clone(current));
+
+        Some(snapshot_message(header, &events))
+    }
+
+    pub fn ack(&mut self, tick: Tick) {
+        while let Some(snapshot) = self.pending.pop_front() {
+            if snapshot.tick == tick {
+                self.baseline = Some(snapshot);
+                return;
+            }
+        }
+    }
+
+    /// the last tick the client confirmed it has, `None` until it acks its first snapshot
+    pub fn acked_tick(&self) -> Option<Tick> {
+        self.baseline.as_ref().map(|baseline| baseline.tick)
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// the delta was built against a snapshot this side no longer has
+    BaselineLost {
+        baseline: Tick,
+    },
+    Stale {
+        tick: Tick,
+    },
+}
+
+/// Client side record of the snapshots received from the server, deltas are applied on top of
+/// whichever one they were built against
+#[derive(Debug, Default)]
+pub struct SnapshotHistory {
+    received: VecDeque<Snapshot>,
+}
+
+impl SnapshotHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.received.back()
+    }
+
+    /// rebuilds the snapshot described by `header` and `events`, returning the events which
+    /// take the latest snapshot to the new one
+    pub fn receive(
+        &mut self,
+        header: SnapshotHeader,
+        events: &[SnapshotEvent],
+    ) -> Result<Vec<SnapshotEvent>, SnapshotError> {
+        if let Some(latest) = self.latest() {
+            if header.baseline.is_some() && header.tick <= latest.tick {
+                return Err(SnapshotError::Stale { tick: header.tick });
+            }
+        }
+
+        let snapshot = match header.baseline {
+            Some(baseline) => match self.received.iter().find(|s| s.tick == baseline) {
+                Some(baseline) => baseline.apply(header.tick, events),
+                None => return Err(SnapshotError::BaselineLost { baseline }),
+            },
+            None => Snapshot::default().apply(header.tick, events),
+        };
+
+        let changes = snapshot.diff(self.latest());
+
+        if header.baseline.is_none() {
+            self.received.clear();
+        }
+        // anything older than the baseline will never be referenced again
+        if let Some(baseline) = header.baseline {
+            self.received.retain(|s| s.tick >= baseline);
+        }
+        self.received.push_back(snapshot);
+        while self.received.len() > MAX_PENDING_SNAPSHOTS {
+            self.received.pop_front();
+        }
+
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecs::components::{Mesh, Transform};
+
+    fn cube(position: Vec3) -> Replicated {
+        Replicated::new(
+            Transform::new(position),
+            Mesh::cube(1.0, Color::new(60, 60, 60)),
+        )
+    }
+
+    fn id(index: u32) -> EntityId {
+        EntityId::new(index, 0)
+    }
+
+    fn world(tick: Tick, cubes: &[(EntityId, Vec3)]) -> Arc<Snapshot> {
+        let entities = cubes.iter().map(|(id, pos)| (*id, cube(*pos))).collect();
+        Arc::new(Snapshot::new(tick, entities))
+    }
+
+    fn send(
+        baseline: &mut ClientBaseline,
+        history: &mut SnapshotHistory,
+        current: &Arc<Snapshot>,
+    ) -> Option<Vec<SnapshotEvent>> {
+        let mut msg = baseline.delta_message(current)?;
+        let (header, events) = pull_snapshot(&mut msg).unwrap();
+        assert!(msg.body.is_empty());
+        Some(history.receive(header, &events).unwrap())
+    }
+
+    #[test]
+    fn deltas_only_carry_changes() {
+        let mut baseline = ClientBaseline::new();
+        let mut history = SnapshotHistory::new();
+
+        let first = world(1, &[(id(1), (0, 0, 0).into()), (id(2), (5, 0, 0).into())]);
+        let changes = send(&mut baseline, &mut history, &first).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes
+            .iter()
+            .all(|event| matches!(event, SnapshotEvent::Spawn(..))));
+        baseline.ack(1);
+
+        // nothing changed, nothing is sent
+        assert!(send(
+            &mut baseline,
+            &mut history,
+            &world(2, &[(id(1), (0, 0, 0).into()), (id(2), (5, 0, 0).into())])
+        )
+        .is_none());
+
+        let third = world(3, &[(id(1), (0, 1, 0).into()), (id(3), (0, 0, 9).into())]);
+        let changes = send(&mut baseline, &mut history, &third).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert!(changes
+            .iter()
+            .any(|event| matches!(event, SnapshotEvent::Update(other, fields::POSITION, _) if *other == id(1))));
+        assert!(changes
+            .iter()
+            .any(|event| matches!(event, SnapshotEvent::Spawn(other, _) if *other == id(3))));
+        assert!(changes
+            .iter()
+            .any(|event| matches!(event, SnapshotEvent::Despawn(other) if *other == id(2))));
+
+        let latest = history.latest().unwrap();
+        assert_eq!(latest.tick, 3);
+        assert_eq!(latest.entities[&id(1)].state().position, Vec3::new(0, 1, 0));
+    }
+
+    #[test]
+    fn unacked_deltas_stack_on_the_same_baseline() {
+        let mut baseline = ClientBaseline::new();
+        let mut history = SnapshotHistory::new();
+
+        send(
+            &mut baseline,
+            &mut history,
+            &world(1, &[(id(1), (0, 0, 0).into())]),
+        );
+        baseline.ack(1);
+
+        // neither of these are acked so both are built against tick 1
+        send(
+            &mut baseline,
+            &mut history,
+            &world(2, &[(id(1), (1, 0, 0).into())]),
+        );
+        let changes = send(
+            &mut baseline,
+            &mut history,
+            &world(3, &[(id(1), (2, 0, 0).into())]),
+        )
+        .unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            history.latest().unwrap().entities[&id(1)].state().position,
+            Vec3::new(2, 0, 0)
+        );
+    }
+
+    #[test]
+    fn reused_indices_replace_the_stale_entity() {
+        let mut baseline = ClientBaseline::new();
+        let mut history = SnapshotHistory::new();
+
+        let old = EntityId::new(4, 0);
+        let new = EntityId::new(4, 1);
+        send(
+            &mut baseline,
+            &mut history,
+            &world(1, &[(old, (0, 0, 0).into())]),
+        );
+        baseline.ack(1);
+
+        let changes = send(
+            &mut baseline,
+            &mut history,
+            &world(2, &[(new, (0, 0, 0).into())]),
+        )
+        .unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes
+            .iter()
+            .any(|event| matches!(event, SnapshotEvent::Despawn(other) if *other == old)));
+        assert!(changes
+            .iter()
+            .any(|event| matches!(event, SnapshotEvent::Spawn(other, _) if *other == new)));
+        assert!(!history.latest().unwrap().entities.contains_key(&old));
+    }
+
+    #[test]
+    fn lost_baseline_is_detected() {
+        let mut history = SnapshotHistory::new();
+        let header = SnapshotHeader {
+            tick: 10,
+            baseline: Some(4),
+            event_count: 0,
+        };
+
+        assert!(matches!(
+            history.receive(header, &[]),
+            Err(SnapshotError::BaselineLost { baseline: 4 })
+        ));
+    }
+}
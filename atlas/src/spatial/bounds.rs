@@ -0,0 +1,302 @@
+use pantheon::math::{Mat4, Vec3};
+
+/// An axis aligned box, `min` is below `max` on every axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_center(center: Vec3, half_extents: Vec3) -> Self {
+        Self {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+
+    /// the smallest box around all of `points`, `None` without any
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+
+        Some(points.fold(Self::new(first, first), |aabb, point| {
+            aabb.union(&Self::new(point, point))
+        }))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        0.5 * (self.max - self.min)
+    }
+
+    /// what building a `Bvh` tries to keep small
+    pub fn surface_area(&self) -> f32 {
+        let size = self.max - self.min;
+        2. * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: Vec3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vec3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    /// grown by `margin` on every side
+    pub fn expand(&self, margin: f32) -> Self {
+        let margin = Vec3::new_from_one(margin);
+        Self {
+            min: self.min - margin,
+            max: self.max + margin,
+        }
+    }
+
+    pub fn contains(&self, other: &Self) -> bool {
+        self.min.x <= other.min.x
+            && self.min.y <= other.min.y
+            && self.min.z <= other.min.z
+            && other.max.x <= self.max.x
+            && other.max.y <= self.max.y
+            && other.max.z <= self.max.z
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.contains(&Self::new(point, point))
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+            && self.min.z <= other.max.z
+            && other.min.z <= self.max.z
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        let closest = Vec3::new(
+            sphere.center.x.clamp(self.min.x, self.max.x),
+            sphere.center.y.clamp(self.min.y, self.max.y),
+            sphere.center.z.clamp(self.min.z, self.max.z),
+        );
+
+        (closest - sphere.center).magnitude() <= sphere.radius
+    }
+
+    /// the box around this one once it's been through `matrix`, which is bigger than the box
+    /// itself when there's any rotation
+    pub fn transformed(&self, matrix: &Mat4) -> Self {
+        let center = (*matrix * self.center().vec4()).vec3();
+        let half = self.half_extents();
+        let abs = |v: Vec3| Vec3::new(v.x.abs(), v.y.abs(), v.z.abs());
+        let half_extents = half.x * abs(matrix.x.vec3())
+            + half.y * abs(matrix.y.vec3())
+            + half.z * abs(matrix.z.vec3());
+
+        Self::from_center(center, half_extents)
+    }
+
+    /// How far along the ray it enters the box, `0` when it starts inside. `direction` doesn't
+    /// have to be a unit vector, distances are in multiples of it.
+    pub fn ray(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        let mut near = 0f32;
+        let mut far = f32::INFINITY;
+
+        for (origin, direction, min, max) in [
+            (origin.x, direction.x, self.min.x, self.max.x),
+            (origin.y, direction.y, self.min.y, self.max.y),
+            (origin.z, direction.z, self.min.z, self.max.z),
+        ] {
+            // running parallel to this pair of faces, it's either between them or it misses
+            if direction.abs() < f32::EPSILON {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let (mut enter, mut exit) = ((min - origin) / direction, (max - origin) / direction);
+            if enter > exit {
+                std::mem::swap(&mut enter, &mut exit);
+            }
+            near = near.max(enter);
+            far = far.min(exit);
+            if near > far {
+                return None;
+            }
+        }
+
+        Some(near)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    /// the sphere through the box's corners
+    pub fn from_aabb(aabb: &Aabb) -> Self {
+        Self {
+            center: aabb.center(),
+            radius: aabb.half_extents().magnitude(),
+        }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_center(self.center, Vec3::new_from_one(self.radius))
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        (self.center - other.center).magnitude() <= self.radius + other.radius
+    }
+
+    /// like `Aabb::ray`
+    pub fn ray(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        let offset = origin - self.center;
+        let a = direction.dot(&direction);
+        let b = offset.dot(&direction);
+        let c = offset.dot(&offset) - self.radius * self.radius;
+        if c <= 0. {
+            return Some(0.);
+        }
+
+        let discriminant = b * b - a * c;
+        if discriminant < 0. || a == 0. {
+            return None;
+        }
+        let t = (-b - discriminant.sqrt()) / a;
+        (t >= 0.).then_some(t)
+    }
+}
+
+/// How far along the ray it hits the triangle, from either side. Möller–Trumbore, see
+/// https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
+pub fn ray_triangle(origin: Vec3, direction: Vec3, p0: Vec3, p1: Vec3, p2: Vec3) -> Option<f32> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let p = direction.cross(&edge2);
+    let determinant = edge1.dot(&p);
+    // parallel to the triangle, or the triangle has no area
+    if determinant.abs() < 1e-8 {
+        return None;
+    }
+    let inverse = 1. / determinant;
+
+    let offset = origin - p0;
+    let u = offset.dot(&p) * inverse;
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+    let q = offset.cross(&edge1);
+    let v = direction.dot(&q) * inverse;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+
+    let t = edge2.dot(&q) * inverse;
+    (t > 0.).then_some(t)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rays() {
+        let aabb = Aabb::new(Vec3::new(1, -1, -1), Vec3::new(3, 1, 1));
+        let x = Vec3::new(1, 0, 0);
+        assert_eq!(aabb.ray(Vec3::new_from_one(0), x), Some(1.));
+        assert_eq!(aabb.ray(Vec3::new(2, 0, 0), x), Some(0.));
+        assert_eq!(aabb.ray(Vec3::new(4, 0, 0), x), None);
+        assert_eq!(aabb.ray(Vec3::new(0, 2, 0), x), None);
+        // not a unit vector, twice as long gets there in half the distance
+        assert_eq!(aabb.ray(Vec3::new_from_one(0), 2. * x), Some(0.5));
+
+        let sphere = BoundingSphere::new(Vec3::new(5, 0, 0), 1.);
+        assert_eq!(sphere.ray(Vec3::new_from_one(0), x), Some(4.));
+        assert_eq!(sphere.ray(Vec3::new(5, 0, 0), x), Some(0.));
+        assert_eq!(sphere.ray(Vec3::new(6, 2, 0), -1. * x), None);
+
+        let (p0, p1, p2) = (Vec3::new(0, 0, 0), Vec3::new(1, 0, 0), Vec3::new(0, 0, 1));
+        let down = Vec3::new(0, -1, 0);
+        assert_eq!(
+            ray_triangle(Vec3::new(0.25, 2, 0.25), down, p0, p1, p2),
+            Some(2.)
+        );
+        // from underneath as well
+        assert_eq!(
+            ray_triangle(Vec3::new(0.25, -2, 0.25), -1. * down, p0, p1, p2),
+            Some(2.)
+        );
+        assert_eq!(
+            ray_triangle(Vec3::new(0.75, 2, 0.75), down, p0, p1, p2),
+            None
+        );
+        assert_eq!(
+            ray_triangle(Vec3::new(0.25, 2, 0.25), -1. * down, p0, p1, p2),
+            None
+        );
+    }
+
+    #[test]
+    fn overlaps() {
+        let a = Aabb::new(Vec3::new_from_one(0), Vec3::new_from_one(2));
+        let b = Aabb::from_center(Vec3::new_from_one(3), Vec3::new_from_one(1));
+        assert!(a.intersects(&b));
+        assert!(!a.intersects(&b.expand(-0.1)));
+        assert!(a.union(&b).contains(&b));
+        assert!(!a.contains(&b));
+        assert_eq!(a.surface_area(), 24.);
+
+        assert!(a.intersects_sphere(&BoundingSphere::new(Vec3::new(3, 1, 1), 1.)));
+        assert!(!a.intersects_sphere(&BoundingSphere::new(Vec3::new(3, 3, 3), 1.)));
+        let corners = BoundingSphere::from_aabb(&a);
+        assert!(corners.aabb().contains(&a));
+
+        let points = [Vec3::new(1, 5, -2), Vec3::new(-3, 0, 4), Vec3::new(0, 1, 0)];
+        assert_eq!(
+            Aabb::from_points(points),
+            Some(Aabb::new(Vec3::new(-3, 0, -2), Vec3::new(1, 5, 4)))
+        );
+    }
+
+    #[test]
+    fn transformed_boxes_still_fit() {
+        let unit = Aabb::from_center(Vec3::new_from_one(0), Vec3::new_from_one(1));
+        let moved = unit.transformed(&Mat4::translation((5., 0., 0.)));
+        assert_eq!(
+            moved,
+            Aabb::from_center(Vec3::new(5, 0, 0), Vec3::new_from_one(1))
+        );
+
+        // an eighth of a turn around y needs root 2 either way along x and z
+        let turned = unit.transformed(&Mat4::rotation_from_degrees(45., (0, 1, 0).into()));
+        let half = turned.half_extents();
+        assert!((half.x - 2f32.sqrt()).abs() < 1e-5);
+        assert!((half.y - 1.).abs() < 1e-5);
+        assert!((half.z - 2f32.sqrt()).abs() < 1e-5);
+    }
+}
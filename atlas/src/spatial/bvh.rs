@@ -0,0 +1,521 @@
+use super::bounds::{Aabb, BoundingSphere};
+use pantheon::math::Vec3;
+
+/// how far leaves are grown past what's put in them, see `Bvh::update`
+pub const DEFAULT_MARGIN: f32 = 0.5;
+
+/// Handed out by `Bvh::insert`, good until it's removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Proxy(usize);
+
+#[derive(Debug, Clone)]
+enum Kind<T> {
+    /// `bounds` is exactly what was put in, the node's own box has the margin around it
+    Leaf {
+        value: T,
+        bounds: Aabb,
+    },
+    Branch(usize, usize),
+    /// waiting to be reused
+    Free,
+}
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    aabb: Aabb,
+    parent: Option<usize>,
+    /// leaves are 0
+    height: u32,
+    kind: Kind<T>,
+}
+
+/// A dynamic bounding volume hierarchy, a binary tree of boxes where each branch's box holds
+/// both of its children. Leaves are added where they grow the tree's surface area the least
+/// and the tree is kept balanced with rotations on the way back up, so queries only visit the
+/// branches they overlap. See Erin Catto's "Dynamic Bounding Volume Hierarchies" (GDC 2019),
+/// Box2D's `b2DynamicTree` works the same way.
+#[derive(Debug, Clone)]
+pub struct Bvh<T> {
+    nodes: Vec<Node<T>>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    margin: f32,
+    len: usize,
+}
+
+impl<T> Default for Bvh<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_MARGIN)
+    }
+}
+
+impl<T> Bvh<T> {
+    /// leaves are grown by `margin` so whatever is in them can move that far before the tree
+    /// has to change
+    pub fn new(margin: f32) -> Self {
+        Self {
+            nodes: vec![],
+            free: vec![],
+            root: None,
+            margin: margin.max(0.),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// levels below the root, 0 for one leaf or none
+    pub fn height(&self) -> u32 {
+        self.root.map_or(0, |root| self.nodes[root].height)
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.free.clear();
+        self.root = None;
+        self.len = 0;
+    }
+
+    pub fn get(&self, proxy: Proxy) -> Option<&T> {
+        match &self.nodes.get(proxy.0)?.kind {
+            Kind::Leaf { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// what the leaf was last given, without the margin
+    pub fn bounds(&self, proxy: Proxy) -> Option<Aabb> {
+        match self.nodes.get(proxy.0)?.kind {
+            Kind::Leaf { bounds, .. } => Some(bounds),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, bounds: Aabb, value: T) -> Proxy {
+        let leaf = self.allocate(Node {
+            aabb: bounds.expand(self.margin),
+            parent: None,
+            height: 0,
+            kind: Kind::Leaf { value, bounds },
+        });
+        self.insert_leaf(leaf);
+        self.len += 1;
+
+        Proxy(leaf)
+    }
+
+    pub fn remove(&mut self, proxy: Proxy) -> Option<T> {
+        self.get(proxy)?;
+        self.remove_leaf(proxy.0);
+        self.len -= 1;
+
+        self.free.push(proxy.0);
+        match std::mem::replace(&mut self.nodes[proxy.0].kind, Kind::Free) {
+            Kind::Leaf { value, .. } => Some(value),
+            _ => unreachable!("checked it was a leaf"),
+        }
+    }
+
+    /// Gives the leaf new bounds. It's only moved in the tree once they leave the margin it was
+    /// given, or have shrunk well inside of it, returns whether it was. False for stale proxies.
+    pub fn update(&mut self, proxy: Proxy, aabb: Aabb) -> bool {
+        let fat = match self.nodes.get_mut(proxy.0) {
+            Some(Node {
+                aabb: fat,
+                kind: Kind::Leaf { bounds, .. },
+                ..
+            }) => {
+                *bounds = aabb;
+                *fat
+            }
+            _ => return false,
+        };
+        if fat.contains(&aabb) && !fat.contains(&aabb.expand(2. * self.margin)) {
+            return false;
+        }
+
+        self.remove_leaf(proxy.0);
+        self.nodes[proxy.0].aabb = aabb.expand(self.margin);
+        self.insert_leaf(proxy.0);
+        true
+    }
+
+    /// everything whose bounds overlap `aabb`
+    pub fn query_box(&self, aabb: &Aabb) -> Vec<&T> {
+        self.query(|node| node.intersects(aabb))
+    }
+
+    /// everything whose bounds overlap `sphere`
+    pub fn query_sphere(&self, sphere: &BoundingSphere) -> Vec<&T> {
+        self.query(|node| node.intersects_sphere(sphere))
+    }
+
+    fn query(&self, overlaps: impl Fn(&Aabb) -> bool) -> Vec<&T> {
+        let mut found = vec![];
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !overlaps(&node.aabb) {
+                continue;
+            }
+            match &node.kind {
+                Kind::Leaf { value, bounds } if overlaps(bounds) => found.push(value),
+                Kind::Branch(left, right) => stack.extend([*left, *right]),
+                _ => {}
+            }
+        }
+
+        found
+    }
+
+    /// The closest value along the ray and how far along it is. Only values whose bounds the
+    /// ray goes through are given to `hit`, which says how far along the ray the value itself
+    /// is if it's hit at all. Nearer branches are tried first, anything further than the
+    /// closest hit so far is skipped.
+    pub fn ray(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        mut hit: impl FnMut(&T) -> Option<f32>,
+    ) -> Option<(&T, f32)> {
+        let mut closest: Option<(&T, f32)> = None;
+        let mut stack = vec![];
+        if let Some(root) = self.root {
+            if let Some(enter) = self.nodes[root].aabb.ray(origin, direction) {
+                stack.push((root, enter));
+            }
+        }
+
+        while let Some((index, enter)) = stack.pop() {
+            if closest.is_some_and(|(_, distance)| enter > distance) {
+                continue;
+            }
+
+            match &self.nodes[index].kind {
+                Kind::Leaf { value, bounds } => {
+                    if bounds.ray(origin, direction).is_none() {
+                        continue;
+                    }
+                    if let Some(distance) = hit(value) {
+                        if closest.is_none_or(|(_, closest)| distance < closest) {
+                            closest = Some((value, distance));
+                        }
+                    }
+                }
+                Kind::Branch(left, right) => {
+                    let mut children = [*left, *right]
+                        .map(|child| (child, self.nodes[child].aabb.ray(origin, direction)));
+                    // the far one goes on first so the near one comes off first
+                    if children[0].1 < children[1].1 {
+                        children.swap(0, 1);
+                    }
+                    stack.extend(
+                        children
+                            .iter()
+                            .filter_map(|(child, enter)| Some((*child, (*enter)?))),
+                    );
+                }
+                Kind::Free => {}
+            }
+        }
+
+        closest
+    }
+
+    fn allocate(&mut self, node: Node<T>) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, index: usize) {
+        self.nodes[index].kind = Kind::Free;
+        self.nodes[index].parent = None;
+        self.free.push(index);
+    }
+
+    fn children(&self, index: usize) -> Option<(usize, usize)> {
+        match self.nodes[index].kind {
+            Kind::Branch(left, right) => Some((left, right)),
+            _ => None,
+        }
+    }
+
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if let Kind::Branch(left, right) = &mut self.nodes[parent].kind {
+            if *left == old {
+                *left = new;
+            } else {
+                *right = new;
+            }
+        }
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        let mut index = match self.root {
+            Some(root) => root,
+            None => {
+                self.root = Some(leaf);
+                self.nodes[leaf].parent = None;
+                return;
+            }
+        };
+
+        // walks down to the sibling which makes the tree grow the least, giving up on a
+        // branch once going further down can only cost more than stopping
+        let aabb = self.nodes[leaf].aabb;
+        while let Some((left, right)) = self.children(index) {
+            let area = self.nodes[index].aabb.surface_area();
+            let combined = self.nodes[index].aabb.union(&aabb).surface_area();
+            let cost = 2. * combined;
+            // every branch above the new leaf grows by at least this much
+            let inherited = 2. * (combined - area);
+
+            let descend = |child: usize| {
+                let node = &self.nodes[child];
+                let grown = node.aabb.union(&aabb).surface_area();
+                match node.kind {
+                    Kind::Leaf { .. } => grown + inherited,
+                    _ => grown - node.aabb.surface_area() + inherited,
+                }
+            };
+            let (left_cost, right_cost) = (descend(left), descend(right));
+            if cost < left_cost && cost < right_cost {
+                break;
+            }
+            index = if left_cost < right_cost { left } else { right };
+        }
+
+        let sibling = index;
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.allocate(Node {
+            aabb: aabb.union(&self.nodes[sibling].aabb),
+            parent: old_parent,
+            height: self.nodes[sibling].height + 1,
+            kind: Kind::Branch(sibling, leaf),
+        });
+        match old_parent {
+            Some(old_parent) => self.replace_child(old_parent, sibling, parent),
+            None => self.root = Some(parent),
+        }
+        self.nodes[sibling].parent = Some(parent);
+        self.nodes[leaf].parent = Some(parent);
+
+        self.refit(old_parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        let parent = match self.nodes[leaf].parent {
+            Some(parent) => parent,
+            None => {
+                self.root = None;
+                return;
+            }
+        };
+
+        let (left, right) = self.children(parent).expect("a leaf's parent is a branch");
+        let sibling = if left == leaf { right } else { left };
+        let grandparent = self.nodes[parent].parent;
+        match grandparent {
+            Some(grandparent) => self.replace_child(grandparent, parent, sibling),
+            None => self.root = Some(sibling),
+        }
+        self.nodes[sibling].parent = grandparent;
+        self.nodes[leaf].parent = None;
+        self.release(parent);
+
+        self.refit(grandparent);
+    }
+
+    /// fixes boxes and heights from `index` up to the root, balancing along the way
+    fn refit(&mut self, mut index: Option<usize>) {
+        while let Some(next) = index {
+            let next = self.balance(next);
+            self.fit(next);
+            index = self.nodes[next].parent;
+        }
+    }
+
+    fn fit(&mut self, index: usize) {
+        if let Some((left, right)) = self.children(index) {
+            let (left, right) = (&self.nodes[left], &self.nodes[right]);
+            let aabb = left.aabb.union(&right.aabb);
+            let height = 1 + left.height.max(right.height);
+
+            let node = &mut self.nodes[index];
+            node.aabb = aabb;
+            node.height = height;
+        }
+    }
+
+    /// Rotates the taller child up when one side is more than a level taller than the other,
+    /// returns whatever is in `index`'s place afterwards
+    fn balance(&mut self, index: usize) -> usize {
+        let (left, right) = match self.children(index) {
+            Some(children) if self.nodes[index].height >= 2 => children,
+            _ => return index,
+        };
+
+        let difference = self.nodes[right].height as i64 - self.nodes[left].height as i64;
+        if difference > 1 {
+            self.rotate_up(index, right, left)
+        } else if difference < -1 {
+            self.rotate_up(index, left, right)
+        } else {
+            index
+        }
+    }
+
+    /// `up` takes `index`'s place, keeping its taller child and handing the shorter one down
+    /// to `index` alongside `other`
+    fn rotate_up(&mut self, index: usize, up: usize, other: usize) -> usize {
+        let (a, b) = match self.children(up) {
+            Some(children) => children,
+            None => return index,
+        };
+        let (keep, give) = if self.nodes[a].height > self.nodes[b].height {
+            (a, b)
+        } else {
+            (b, a)
+        };
+
+        let parent = self.nodes[index].parent;
+        match parent {
+            Some(parent) => self.replace_child(parent, index, up),
+            None => self.root = Some(up),
+        }
+        self.nodes[up].parent = parent;
+        self.nodes[up].kind = Kind::Branch(index, keep);
+        self.nodes[index].parent = Some(up);
+        self.nodes[index].kind = Kind::Branch(other, give);
+        self.nodes[give].parent = Some(index);
+
+        self.fit(index);
+        self.fit(up);
+        up
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cube(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb::from_center(Vec3::new(x, y, z), Vec3::new_from_one(0.5))
+    }
+
+    /// every branch holds its children and points back at its parent
+    fn check<T>(bvh: &Bvh<T>, index: usize) -> (u32, usize) {
+        let node = &bvh.nodes[index];
+        match node.kind {
+            Kind::Leaf { bounds, .. } => {
+                assert!(node.aabb.contains(&bounds));
+                (0, 1)
+            }
+            Kind::Branch(left, right) => {
+                for child in [left, right] {
+                    assert_eq!(bvh.nodes[child].parent, Some(index));
+                    assert!(node.aabb.contains(&bvh.nodes[child].aabb));
+                }
+                let (left, left_leaves) = check(bvh, left);
+                let (right, right_leaves) = check(bvh, right);
+                assert_eq!(node.height, 1 + left.max(right));
+                (node.height, left_leaves + right_leaves)
+            }
+            Kind::Free => panic!("{} is free but still in the tree", index),
+        }
+    }
+
+    #[test]
+    fn stays_balanced() {
+        let mut bvh = Bvh::new(0.1);
+        // sorted inserts would make a list out of a tree which isn't rebalanced
+        let proxies: Vec<_> = (0..256)
+            .map(|i| bvh.insert(cube(i as f32 * 2., 0., 0.), i))
+            .collect();
+        let (height, leaves) = check(&bvh, bvh.root.unwrap());
+        assert_eq!(leaves, 256);
+        assert!(height <= 2 * 8, "height {}", height);
+
+        for proxy in proxies.iter().step_by(2) {
+            assert!(bvh.remove(*proxy).is_some());
+        }
+        assert!(bvh.remove(proxies[0]).is_none());
+        assert_eq!(bvh.len(), 128);
+        let (_, leaves) = check(&bvh, bvh.root.unwrap());
+        assert_eq!(leaves, 128);
+
+        // freed nodes are reused
+        let nodes = bvh.nodes.len();
+        bvh.insert(cube(0., 10., 0.), 1000);
+        assert_eq!(bvh.nodes.len(), nodes);
+    }
+
+    #[test]
+    fn moving_within_the_margin() {
+        let mut bvh = Bvh::new(0.5);
+        let a = bvh.insert(cube(0., 0., 0.), 'a');
+        bvh.insert(cube(5., 0., 0.), 'b');
+
+        assert!(!bvh.update(a, cube(0.25, 0., 0.)));
+        assert_eq!(bvh.bounds(a), Some(cube(0.25, 0., 0.)));
+        assert!(bvh.update(a, cube(10., 0., 0.)));
+        check(&bvh, bvh.root.unwrap());
+        assert_eq!(bvh.query_box(&cube(10., 0., 0.)), vec![&'a']);
+        // the margin isn't counted as overlapping
+        assert!(bvh.query_box(&cube(11.2, 0., 0.)).is_empty());
+    }
+
+    #[test]
+    fn queries() {
+        let mut bvh = Bvh::default();
+        for i in 0..10 {
+            for j in 0..10 {
+                bvh.insert(cube(i as f32 * 3., 0., j as f32 * 3.), (i, j));
+            }
+        }
+
+        let mut found = bvh.query_box(&Aabb::new(Vec3::new(2, -1, 2), Vec3::new(7, 1, 4)));
+        found.sort();
+        assert_eq!(found, vec![&(1, 1), &(2, 1)]);
+        let found = bvh.query_sphere(&BoundingSphere::new(Vec3::new(27, 0, 27), 1.));
+        assert_eq!(found, vec![&(9, 9)]);
+
+        // down the row of cubes at z = 6, the first one is hit and most of the others are
+        // never looked at
+        let origin = Vec3::new(-5, 0, 6);
+        let x = Vec3::new(1, 0, 0);
+        let mut tested = 0;
+        let (hit, distance) = bvh
+            .ray(origin, x, |(i, j)| {
+                tested += 1;
+                cube(*i as f32 * 3., 0., *j as f32 * 3.).ray(origin, x)
+            })
+            .unwrap();
+        assert_eq!((*hit, distance), ((0, 2), 4.5));
+        assert!(tested < 10, "tested {}", tested);
+
+        // the callback has the final say
+        let (hit, distance) = bvh
+            .ray(origin, x, |(i, j)| {
+                (*i >= 4).then(|| cube(*i as f32 * 3., 0., *j as f32 * 3.).ray(origin, x))?
+            })
+            .unwrap();
+        assert_eq!(*hit, (4, 2));
+        assert_eq!(distance, 16.5);
+        assert!(bvh.ray(Vec3::new(-5, 5, 0), x, |_| Some(0.)).is_none());
+    }
+}
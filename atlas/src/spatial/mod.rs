@@ -0,0 +1,5 @@
+pub mod bounds;
+pub mod bvh;
+
+pub use bounds::{ray_triangle, Aabb, BoundingSphere};
+pub use bvh::{Bvh, Proxy};
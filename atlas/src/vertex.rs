@@ -2,6 +2,7 @@ use pantheon::math::Vec2;
 use pantheon::math::Vec3;
 use pantheon::Color;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum VertexKind {
     Basic,
     Shaded,
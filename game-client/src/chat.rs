@@ -0,0 +1,376 @@
+use crate::ui::font::{self, ADVANCE, GLYPH_HEIGHT};
+use crate::ui::{TexturableQuad, TexturedQuad};
+use atlas::chat::{validate_text, ChatChannel, ChatMessage, MAX_CHAT_BYTES};
+use atlas::rendering;
+use atlas::snapshot::EntityId;
+use pantheon::context::Context;
+use pantheon::graphics::texture::Texture;
+use pantheon::image::{DynamicImage, Rgba, RgbaImage};
+use pantheon::wgpu;
+use std::collections::VecDeque;
+
+/// lines of scrollback kept around, only the newest `VISIBLE_LINES` are drawn
+pub const SCROLLBACK: usize = 100;
+pub const VISIBLE_LINES: usize = 8;
+/// characters per line, longer lines wrap
+pub const COLUMNS: usize = 64;
+/// leaves room for a `/w <player>` in front of a message of the longest size
+const MAX_INPUT_BYTES: usize = MAX_CHAT_BYTES + 16;
+
+const PADDING: u32 = 2;
+const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 2;
+const WIDTH: u32 = COLUMNS as u32 * ADVANCE + 2 * PADDING;
+/// the scrollback and the input line under it
+const HEIGHT: u32 = (VISIBLE_LINES as u32 + 1) * LINE_HEIGHT + 2 * PADDING;
+
+const BACKGROUND: Rgba<u8> = Rgba([20, 20, 24, 255]);
+const GLOBAL_COLOR: Rgba<u8> = Rgba([235, 235, 235, 255]);
+const TEAM_COLOR: Rgba<u8> = Rgba([120, 200, 255, 255]);
+const WHISPER_COLOR: Rgba<u8> = Rgba([240, 140, 220, 255]);
+const SYSTEM_COLOR: Rgba<u8> = Rgba([240, 210, 90, 255]);
+const INPUT_COLOR: Rgba<u8> = Rgba([140, 240, 140, 255]);
+
+/// Turns what was typed into the message to send. Plain text goes to everyone, `/t` sends to
+/// the team and `/w <player>` whispers to the player with that index, `resolve` looks up the
+/// player's current id.
+pub fn parse_input(
+    line: &str,
+    resolve: impl Fn(u32) -> Option<EntityId>,
+) -> Result<ChatMessage, String> {
+    let (channel, text) = match line.strip_prefix('/') {
+        None => (ChatChannel::Global, line),
+        Some(command) => {
+            let (name, rest) = command.split_once(' ').unwrap_or((command, ""));
+            match name {
+                "g" | "all" => (ChatChannel::Global, rest),
+                "t" | "team" => (ChatChannel::Team, rest),
+                "w" | "whisper" => {
+                    let (player, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+                    let index: u32 = player
+                        .parse()
+                        .map_err(|_| format!("/w expects a player number, not {:?}", player))?;
+                    let target =
+                        resolve(index).ok_or_else(|| format!("there's no player {}", index))?;
+                    (ChatChannel::Whisper(target), rest)
+                }
+                _ => {
+                    return Err(format!(
+                        "unknown command /{}, try /t, /w <player> or /g",
+                        name
+                    ))
+                }
+            }
+        }
+    };
+
+    validate_text(text).map_err(|e| e.to_string())?;
+    Ok(ChatMessage::new(channel, text))
+}
+
+/// hours and minutes in UTC
+fn format_time(timestamp_ms: u64) -> String {
+    let minutes = timestamp_ms / 60_000;
+    format!("{:02}:{:02}", minutes / 60 % 24, minutes % 60)
+}
+
+/// Scrollback and the line being typed, drawn into an image for `ChatPanel`
+#[derive(Debug, Default)]
+pub struct ChatBox {
+    lines: VecDeque<(String, Rgba<u8>)>,
+    /// `Some` while typing
+    input: Option<String>,
+    dirty: bool,
+}
+
+impl ChatBox {
+    pub fn new() -> Self {
+        Self {
+            dirty: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_typing(&self) -> bool {
+        self.input.is_some()
+    }
+
+    /// Enter starts typing and sends what was typed, giving back the line to send. Backspace
+    /// works as expected, everything else which isn't printable is ignored.
+    pub fn text_input(&mut self, c: char) -> Option<String> {
+        let input = match self.input.as_mut() {
+            Some(input) => input,
+            None => {
+                if c == '\r' || c == '\n' {
+                    self.input = Some(String::new());
+                    self.dirty = true;
+                }
+                return None;
+            }
+        };
+
+        match c {
+            '\r' | '\n' => {
+                self.dirty = true;
+                return self.input.take().filter(|line| !line.trim().is_empty());
+            }
+            '\u{8}' | '\u{7f}' => self.dirty |= input.pop().is_some(),
+            c if c.is_control() => {}
+            c => {
+                if input.len() + c.len_utf8() <= MAX_INPUT_BYTES {
+                    input.push(c);
+                    self.dirty = true;
+                }
+            }
+        }
+
+        None
+    }
+
+    /// stops typing, throwing away whatever was typed
+    pub fn close(&mut self) {
+        self.dirty |= self.input.take().is_some();
+    }
+
+    /// adds `text` to the scrollback, wrapping it at `COLUMNS`
+    pub fn push_line(&mut self, text: &str, color: Rgba<u8>) {
+        let chars: Vec<char> = text.chars().collect();
+        for line in chars.chunks(COLUMNS) {
+            self.lines.push_back((line.iter().collect(), color));
+        }
+        while self.lines.len() > SCROLLBACK {
+            self.lines.pop_front();
+        }
+        self.dirty = true;
+    }
+
+    /// `me` is our own player, so whispers we sent read differently to ones we got
+    pub fn receive(&mut self, chat: &ChatMessage, me: Option<EntityId>) {
+        let time = format_time(chat.timestamp_ms);
+        let sender = chat
+            .sender
+            .map_or("?".to_string(), |id| id.index.to_string());
+
+        let (line, color) = match chat.channel {
+            ChatChannel::Global => (
+                format!("[{}] {}: {}", time, sender, chat.text),
+                GLOBAL_COLOR,
+            ),
+            ChatChannel::Team => (
+                format!("[{}] (team) {}: {}", time, sender, chat.text),
+                TEAM_COLOR,
+            ),
+            ChatChannel::Whisper(target) if chat.sender == me => (
+                format!("[{}] to {}: {}", time, target.index, chat.text),
+                WHISPER_COLOR,
+            ),
+            ChatChannel::Whisper(_) => (
+                format!("[{}] {} whispers: {}", time, sender, chat.text),
+                WHISPER_COLOR,
+            ),
+            ChatChannel::System => (format!("[{}] * {}", time, chat.text), SYSTEM_COLOR),
+        };
+
+        self.push_line(&line, color);
+    }
+
+    /// a line which only we see, like a command that didn't parse
+    pub fn notice(&mut self, text: &str) {
+        self.push_line(&format!("* {}", text), SYSTEM_COLOR);
+    }
+
+    /// true once since the last time anything changed
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    pub fn render(&self) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+
+        let skip = self.lines.len().saturating_sub(VISIBLE_LINES);
+        for (row, (line, color)) in self.lines.iter().skip(skip).enumerate() {
+            let y = PADDING + row as u32 * LINE_HEIGHT;
+            font::draw_text(&mut image, PADDING, y, 1, line, *color);
+        }
+
+        if let Some(input) = self.input.as_ref() {
+            // only the end of a long line fits, which is the part being typed
+            let line: Vec<char> = format!("> {}_", input).chars().collect();
+            let visible: String = line[line.len().saturating_sub(COLUMNS)..].iter().collect();
+            let y = PADDING + VISIBLE_LINES as u32 * LINE_HEIGHT;
+            font::draw_text(&mut image, PADDING, y, 1, &visible, INPUT_COLOR);
+        }
+
+        image
+    }
+}
+
+/// Draws a `ChatBox` in the bottom left corner, its texture is swapped out whenever the chat
+/// changes
+pub struct ChatPanel<'a> {
+    pub chat: ChatBox,
+    #[allow(dead_code)]
+    quad: TexturedQuad<'a>,
+    sampler: wgpu::Sampler,
+}
+
+impl<'a> ChatPanel<'a> {
+    const LABEL: &'static str = "chat";
+
+    pub fn new(ctx: &mut Context<'a>) -> Self {
+        // nearest so the glyphs stay sharp however much the panel gets stretched
+        let sampler = ctx.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(Self::LABEL),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let chat = ChatBox::new();
+        let (bind_group_handle, texture_handle) = rendering::register_texture(
+            ctx,
+            Self::texture(ctx, &chat),
+            Self::LABEL,
+            "basic_textured",
+            Some(&sampler),
+        );
+        let mut quad = TexturedQuad::new_with_handles(
+            TexturableQuad::new((-0.98, -0.98).into(), (-0.18, -0.6).into()),
+            bind_group_handle,
+            texture_handle,
+            Self::LABEL,
+        );
+        quad.register(ctx);
+
+        Self {
+            chat,
+            quad,
+            sampler,
+        }
+    }
+
+    fn texture(ctx: &Context, chat: &ChatBox) -> Texture {
+        let image = DynamicImage::ImageRgba8(chat.render());
+        // the image is always a valid size, nothing else can go wrong
+        Texture::from_image(&ctx.device, &ctx.queue, &image, Some(Self::LABEL)).unwrap()
+    }
+
+    /// re-uploads the chat if anything changed since the last call
+    pub fn update(&mut self, ctx: &mut Context<'a>) {
+        if !self.chat.take_dirty() {
+            return;
+        }
+
+        let texture = Self::texture(ctx, &self.chat);
+        rendering::register_texture(
+            ctx,
+            texture,
+            Self::LABEL,
+            "basic_textured",
+            Some(&self.sampler),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn resolve(index: u32) -> Option<EntityId> {
+        (index == 3).then_some(EntityId::new(3, 1))
+    }
+
+    #[test]
+    fn parses_commands() {
+        let parse = |line| parse_input(line, resolve);
+
+        assert_eq!(
+            parse("hi all"),
+            Ok(ChatMessage::new(ChatChannel::Global, "hi all"))
+        );
+        assert_eq!(
+            parse("/t go left"),
+            Ok(ChatMessage::new(ChatChannel::Team, "go left"))
+        );
+        assert_eq!(
+            parse("/w 3 psst"),
+            Ok(ChatMessage::new(
+                ChatChannel::Whisper(EntityId::new(3, 1)),
+                "psst"
+            ))
+        );
+
+        assert!(parse("/w 4 psst").is_err());
+        assert!(parse("/w bob psst").is_err());
+        assert!(parse("/dance").is_err());
+        assert!(parse("/t   ").is_err());
+        assert!(parse(&"a".repeat(MAX_CHAT_BYTES + 1)).is_err());
+    }
+
+    #[test]
+    fn typing() {
+        let mut chat = ChatBox::new();
+        assert!(chat.take_dirty());
+
+        // keys do nothing until Enter opens the input
+        assert_eq!(chat.text_input('a'), None);
+        assert!(!chat.is_typing() && !chat.take_dirty());
+
+        chat.text_input('\r');
+        assert!(chat.is_typing());
+        for c in "hey\u{1b}x\u{8}!".chars() {
+            assert_eq!(chat.text_input(c), None);
+        }
+        assert_eq!(chat.text_input('\r'), Some("hey!".to_string()));
+        assert!(!chat.is_typing());
+
+        // sending nothing just closes the input
+        chat.text_input('\r');
+        chat.text_input(' ');
+        assert_eq!(chat.text_input('\r'), None);
+        assert!(!chat.is_typing());
+
+        chat.text_input('\r');
+        for _ in 0..MAX_INPUT_BYTES + 10 {
+            chat.text_input('a');
+        }
+        chat.close();
+        assert!(!chat.is_typing());
+    }
+
+    #[test]
+    fn scrollback() {
+        let mut chat = ChatBox::new();
+        chat.push_line(&"x".repeat(COLUMNS * 2 + 1), GLOBAL_COLOR);
+        assert_eq!(chat.lines.len(), 3);
+
+        for i in 0..SCROLLBACK {
+            chat.push_line(&i.to_string(), GLOBAL_COLOR);
+        }
+        assert_eq!(chat.lines.len(), SCROLLBACK);
+        assert_eq!(chat.lines.back().unwrap().0, (SCROLLBACK - 1).to_string());
+
+        let me = EntityId::new(1, 0);
+        let mut whisper = ChatMessage::new(ChatChannel::Whisper(EntityId::new(2, 0)), "hi");
+        whisper.sender = Some(me);
+        whisper.timestamp_ms = (13 * 60 + 5) * 60_000;
+        chat.receive(&whisper, Some(me));
+        assert_eq!(chat.lines.back().unwrap().0, "[13:05] to 2: hi");
+        chat.receive(&whisper, None);
+        assert_eq!(chat.lines.back().unwrap().0, "[13:05] 1 whispers: hi");
+    }
+
+    #[test]
+    fn renders_text() {
+        let mut chat = ChatBox::new();
+        let blank = chat.render();
+        assert_eq!((blank.width(), blank.height()), (WIDTH, HEIGHT));
+        assert!(blank.pixels().all(|pixel| *pixel == BACKGROUND));
+
+        chat.push_line("hello", GLOBAL_COLOR);
+        chat.text_input('\r');
+        chat.text_input('h');
+        let image = chat.render();
+        assert!(image.pixels().any(|pixel| *pixel == GLOBAL_COLOR));
+        assert!(image.pixels().any(|pixel| *pixel == INPUT_COLOR));
+    }
+}
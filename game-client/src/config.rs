@@ -0,0 +1,164 @@
+use atlas::config::{self, ConfigError, LogLevel};
+use serde::Deserialize;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: game-client [--config <file.toml|file.ron>] [flags]
+
+flags override whatever the config file sets:
+    --server <host:port>      server to connect to, without one the client hosts its own
+    --water-height <height>   height of the water plane (0.0)
+    --interp-delay <secs>     how far in the past other entities are drawn (0.1)
+    --log-level <level>       error, warn, info, debug or trace (info), RUST_LOG overrides it
+    --log-file <path>         also append logs to this file as JSON lines";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    /// `None` plays on a server embedded in the client
+    pub server: Option<String>,
+    // @NOTE this has to be 0 unless we want out camera to be paramertized against the water's
+    // height which I think is a bit much, probably easier to just approach life as water == 0
+    // height
+    pub water_height: f32,
+    /// seconds, other entities are drawn this far behind the latest snapshot so there's
+    /// always a pair of snapshots to blend between
+    pub interpolation_delay: f32,
+    pub log_level: LogLevel,
+    /// logs are appended here as JSON on top of going to stdout
+    pub log_file: Option<PathBuf>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            server: None,
+            water_height: 0.,
+            interpolation_delay: 0.1,
+            log_level: LogLevel::default(),
+            log_file: None,
+        }
+    }
+}
+
+impl ClientConfig {
+    /// defaults, then the `--config` file if there is one, then the rest of the flags
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, ConfigError> {
+        let pairs = config::flag_pairs(args)?;
+
+        let mut out = match pairs.iter().find(|(flag, _)| flag == "--config") {
+            Some((_, path)) => config::load_file(path)?,
+            None => Self::default(),
+        };
+
+        for (flag, value) in pairs.iter() {
+            let (flag, value) = (flag.as_str(), value.as_str());
+            match flag {
+                "--config" => {}
+                "--server" => out.server = Some(value.to_string()),
+                "--water-height" => out.water_height = config::parse_flag(flag, value)?,
+                "--interp-delay" => out.interpolation_delay = config::parse_flag(flag, value)?,
+                "--log-level" => out.log_level = config::parse_flag(flag, value)?,
+                "--log-file" => out.log_file = Some(PathBuf::from(value)),
+                _ => {
+                    return Err(ConfigError::UnknownArgument {
+                        arg: flag.to_string(),
+                    })
+                }
+            }
+        }
+
+        out.validate()?;
+        Ok(out)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.server_host_port()?;
+
+        if !self.water_height.is_finite() {
+            return Err(ConfigError::Invalid {
+                field: "water_height",
+                reason: format!("{} has to be a finite number", self.water_height),
+            });
+        }
+
+        if !self.interpolation_delay.is_finite() || self.interpolation_delay < 0. {
+            return Err(ConfigError::Invalid {
+                field: "interpolation_delay",
+                reason: format!(
+                    "{} has to be zero or more seconds",
+                    self.interpolation_delay
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// `None` when there's no server to connect to and one should be embedded instead
+    pub fn server_host_port(&self) -> Result<Option<(&str, u16)>, ConfigError> {
+        let server = match self.server.as_deref() {
+            Some(server) => server,
+            None => return Ok(None),
+        };
+        let invalid = |reason: &str| ConfigError::Invalid {
+            field: "server",
+            reason: format!("{:?} {}", server, reason),
+        };
+
+        let (host, port) = server
+            .rsplit_once(':')
+            .ok_or_else(|| invalid("should look like host:port"))?;
+        if host.is_empty() {
+            return Err(invalid("is missing a host"));
+        }
+        let port = port
+            .parse()
+            .map_err(|_| invalid("doesn't end in a valid port"))?;
+
+        Ok(Some((host, port)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn example_config_parses() -> Result<(), ConfigError> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("client.example.ron");
+        let config: ClientConfig = config::load_file(path)?;
+        config.validate()?;
+        assert_eq!(config.server_host_port()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        let args = |args: &[&str]| {
+            ClientConfig::from_args(
+                args.iter()
+                    .map(|arg| arg.to_string())
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            )
+        };
+
+        assert_eq!(
+            args(&["--server", "localhost:9000"])
+                .unwrap()
+                .server_host_port()
+                .unwrap(),
+            Some(("localhost", 9000))
+        );
+        assert_eq!(args(&[]).unwrap().server_host_port().unwrap(), None);
+        assert!(args(&["--server", "localhost"]).is_err());
+        assert!(args(&["--server", "localhost:http"]).is_err());
+        // the server picks the world scale
+        assert!(args(&["--world-scale", "2"]).is_err());
+        assert!(args(&["--interp-delay", "0"]).is_ok());
+        assert!(args(&["--interp-delay", "-0.1"]).is_err());
+        assert!(args(&["--fullscreen", "yes"]).is_err());
+    }
+}
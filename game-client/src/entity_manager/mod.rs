@@ -2,6 +2,7 @@ use atlas::entity::terrain::Terrain;
 use pantheon::context::Context;
 use pantheon::input::mouse;
 use pantheon::math::Dim;
+use pantheon::math::Quaternion;
 use pantheon::math::Vec3;
 use pantheon::math::Vec4;
 
@@ -10,18 +11,26 @@ use pantheon::winit::window::CursorIcon;
 use atlas::camera::Camera;
 
 // use component::AsComponent;
+use crate::interpolation::{InterpolationBuffer, Sample};
 use atlas::entity::component::DrawComponent;
 use atlas::entity::component::MouseComponent;
-use atlas::entity::component::MousePick;
 use atlas::entity::cube;
+use atlas::entity::player::{Player, OBSTACLE_REACH};
 use atlas::entity::sun::Sun;
 use atlas::entity::water::Water;
 use atlas::entity::{Entity, EntityKind};
+use atlas::physics::Shape;
+use atlas::snapshot::{fields, EntityId, EntityState, Snapshot, SnapshotEvent};
+use atlas::spatial::{Bvh, Proxy};
 use atlas::Color;
+use std::collections::HashMap;
 
 // @NOTE this probably should move over to `atlas` but I will hold off on doing that until
 // the server code gets more complex
 
+/// streamed terrain is found by casting down from this high up
+const GROUND_RAY_HEIGHT: f32 = 10_000.;
+
 #[allow(dead_code)]
 pub struct EntityManager<'a> {
     pub camera: Camera,
@@ -30,20 +39,28 @@ pub struct EntityManager<'a> {
     pub water: Water<'a>,
     new_entities: Vec<EntityKind<'a>>,
     entities: Vec<EntityKind<'a>>,
-    commands: Vec<CommandKind>,
+    /// entities owned by the server, kept in sync through snapshots
+    networked: HashMap<EntityId, EntityKind<'a>>,
+    interpolation: HashMap<EntityId, InterpolationBuffer>,
+    /// the id currently using each index, anything else with that index is stale
+    live: HashMap<u32, EntityId>,
+    sun_id: Option<EntityId>,
+    /// bounds of everything but the terrain, which has its own, so picking doesn't have to try
+    /// every entity
+    bvh: Bvh<Pickable>,
+    proxies: HashMap<Pickable, Proxy>,
 }
 
-// scaffolding to allow for undoable/redoable actions
-//#[enum_dispatch(CommandKind)]
-pub trait Command {
-    fn execute(&mut self);
-    fn undo(&mut self);
+/// whatever the mouse can be over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Pickable {
+    /// in `EntityManager::entities`
+    Local(usize),
+    Networked(EntityId),
+    Sun,
+    Terrain,
 }
 
-#[allow(dead_code)]
-//#[enum_dispatch]
-pub enum CommandKind {}
-
 impl<'a> EntityManager<'a> {
     pub fn new(camera: Camera, terrain: Terrain<'a>, water: Water<'a>) -> Self {
         Self {
@@ -57,12 +74,18 @@ impl<'a> EntityManager<'a> {
             ),
             new_entities: vec![],
             entities: vec![],
-            commands: vec![],
+            networked: HashMap::new(),
+            interpolation: HashMap::new(),
+            live: HashMap::new(),
+            sun_id: None,
+            bvh: Bvh::default(),
+            proxies: HashMap::new(),
             water,
         }
     }
 
-    fn get_mouse_ray(&self, ctx: &mut Context) -> Option<Vec3> {
+    /// direction from the camera through the cursor
+    pub fn get_mouse_ray(&self, ctx: &mut Context) -> Option<Vec3> {
         let mouse_pos = mouse::position(ctx);
         let ndc_x = (2.0 * mouse_pos.x) / ctx.gfx_context.window_dims.width - 1.0;
         let ndc_y = 1.0 - (2.0 * mouse_pos.y) / ctx.gfx_context.window_dims.height;
@@ -94,64 +117,140 @@ impl<'a> EntityManager<'a> {
 
         self.sun.update(ctx);
         self.water.update(ctx);
-
-        let mouse_ray = self.get_mouse_ray(ctx);
+        self.entities
+            .iter_mut()
+            .chain(self.networked.values_mut())
+            .for_each(|entity| entity.update(ctx));
+        self.refresh_bounds();
 
         let camera_origin = self.camera.origin;
         let before = std::time::Instant::now();
-        let mut closest: Option<MousePick> = None;
-        if let Some(mouse_ray) = mouse_ray {
-            closest = self.sun.check_collision(ctx, camera_origin, mouse_ray);
-            self.entities.iter_mut().for_each(|entity| {
-                entity.update(ctx);
-                {
-                    if let Some(hit) = entity.check_collision(ctx, camera_origin, mouse_ray) {
-                        if let Some(other) = &closest {
-                            if (hit.point - camera_origin).magnitude()
-                                < (other.point - camera_origin).magnitude()
-                            {
-                                // hit is closer
-                                closest = Some(hit);
-                            }
-                        } else {
-                            // no other hit yet
-                            closest = Some(hit);
-                        }
-                    }
-                }
-            });
-        }
+        let closest = self
+            .get_mouse_ray(ctx)
+            .and_then(|mouse_ray| self.ray_pick(camera_origin, mouse_ray, |_| true));
         let after = std::time::Instant::now();
         if ctx.timer_context.frame_count % (pantheon::timer::MAX_SAMPLES) == 0 {
-            println!(
-                "Iterate turnaround time: ns {:#?}",
-                (after - before).subsec_nanos()
+            tracing::trace!(
+                nanos = (after - before).subsec_nanos(),
+                "mouse picking turnaround"
             );
         }
 
-        if let Some(hit) = closest {
-            //println!("hit pos : {:#?}, t: {:#?} ", hit.1, hit.2);
-            hit.entity.mouse_over(ctx, hit.point, &self.camera);
-            ctx.set_cursor_icon(CursorIcon::Move);
+        let (key, point) = match closest {
+            Some((key, point, _)) => (key, point),
+            None => {
+                ctx.set_cursor_icon(CursorIcon::Default);
+                return;
+            }
+        };
+        let entity: Option<&mut dyn MouseComponent> = match key {
+            Pickable::Local(index) => self
+                .entities
+                .get_mut(index)
+                .map(|entity| entity as &mut dyn MouseComponent),
+            Pickable::Networked(id) => self
+                .networked
+                .get_mut(&id)
+                .map(|entity| entity as &mut dyn MouseComponent),
+            Pickable::Sun => Some(&mut self.sun),
+            Pickable::Terrain => Some(&mut self.terrain),
+        };
+        if let Some(entity) = entity {
+            entity.mouse_over(ctx, point, &self.camera);
+        }
+        // the terrain can't be moved around
+        ctx.set_cursor_icon(if key == Pickable::Terrain {
+            CursorIcon::Default
         } else {
-            ctx.set_cursor_icon(CursorIcon::Default);
+            CursorIcon::Move
+        });
+    }
+
+    /// keeps `bvh` in step with wherever everything has moved to
+    fn refresh_bounds(&mut self) {
+        let bounds: Vec<_> = self
+            .entities
+            .iter()
+            .enumerate()
+            .map(|(index, entity)| (Pickable::Local(index), entity.bounding_sphere()))
+            .chain(
+                self.networked
+                    .iter()
+                    .map(|(id, entity)| (Pickable::Networked(*id), entity.bounding_sphere())),
+            )
+            .chain(std::iter::once((
+                Pickable::Sun,
+                self.sun.cube.bounding_sphere(),
+            )))
+            .collect();
+
+        for (key, sphere) in bounds {
+            match self.proxies.get(&key) {
+                Some(proxy) => {
+                    self.bvh.update(*proxy, sphere.aabb());
+                }
+                None => {
+                    let proxy = self.bvh.insert(sphere.aabb(), key);
+                    self.proxies.insert(key, proxy);
+                }
+            }
+        }
+    }
+
+    /// The closest thing along the ray which `pickable` lets through, where the ray hits it and
+    /// how far along the ray that is. Nothing is picked through the terrain.
+    fn ray_pick(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        pickable: impl Fn(&Pickable) -> bool,
+    ) -> Option<(Pickable, Vec3, f32)> {
+        let entity = self
+            .bvh
+            .ray(origin, direction, |key| {
+                if !pickable(key) {
+                    return None;
+                }
+                let hit = match *key {
+                    Pickable::Local(index) => self.entities.get(index)?.ray_pick(origin, direction),
+                    Pickable::Networked(id) => self.networked.get(&id)?.ray_pick(origin, direction),
+                    Pickable::Sun => self.sun.cube.ray_pick(origin, direction),
+                    Pickable::Terrain => None,
+                };
+                hit.map(|(_, t)| t)
+            })
+            .map(|(key, t)| (*key, (t * direction) + origin, t));
+        let terrain = self
+            .terrain
+            .ray_pick(origin, direction)
+            .map(|(point, t)| (Pickable::Terrain, point, t));
+
+        match (entity, terrain) {
+            (Some(entity), Some(terrain)) if terrain.2 < entity.2 => Some(terrain),
+            (entity, terrain) => entity.or(terrain),
         }
     }
 
     pub fn draw(&mut self, ctx: &mut Context<'a>) {
         self.terrain.draw(ctx);
         self.sun.draw(ctx);
-        self.entities.iter_mut().for_each(|entity| {
-            entity.draw(ctx);
-        });
+        self.entities
+            .iter_mut()
+            .chain(self.networked.values_mut())
+            .for_each(|entity| {
+                entity.draw(ctx);
+            });
     }
 
     pub fn debug_draw(&mut self, ctx: &mut Context<'a>) {
         self.terrain.debug_draw(ctx);
         self.sun.debug_draw(ctx);
-        self.entities.iter_mut().for_each(|entity| {
-            entity.debug_draw(ctx);
-        });
+        self.entities
+            .iter_mut()
+            .chain(self.networked.values_mut())
+            .for_each(|entity| {
+                entity.debug_draw(ctx);
+            });
     }
 
     pub fn push_entity(&mut self, ctx: &mut Context<'a>, mut entity: EntityKind<'a>) {
@@ -159,6 +258,207 @@ impl<'a> EntityManager<'a> {
         self.new_entities.push(entity);
     }
 
+    /// brings the networked entities in line with the latest snapshot from the server
+    pub fn apply_snapshot_events(&mut self, ctx: &mut Context<'a>, events: &[SnapshotEvent]) {
+        for event in events {
+            if let SnapshotEvent::Spawn(id, _) = *event {
+                // the server only reuses an index once it's done with the old entity, if that
+                // despawn never made it here the leftover would otherwise stick around forever
+                if let Some(stale) = self.live.insert(id.index, id).filter(|old| *old != id) {
+                    self.remove_networked(ctx, stale);
+                }
+            }
+
+            match *event {
+                // everything is drawn as whatever its components make it
+                SnapshotEvent::Spawn(id, components) => match components.drawable() {
+                    EntityKind::Sun(sun) => {
+                        let selected = self.sun_id == Some(id) && self.sun.cube.selected;
+                        self.sun = sun;
+                        self.sun.cube.selected = selected;
+                        self.sun.init(ctx);
+                        self.sun_id = Some(id);
+                    }
+                    mut entity => {
+                        entity.register(ctx);
+                        entity.init(ctx);
+                        if let Some(mut old) = self.networked.insert(id, entity) {
+                            // resyncs spawn everything again, what was selected stays selected
+                            if old.selected() {
+                                self.set_selected(ctx, id, true);
+                            }
+                            old.unregister(ctx);
+                        }
+                        self.interpolation.remove(&id);
+                    }
+                },
+                SnapshotEvent::Update(id, changed, state) => {
+                    if self.sun_id == Some(id) {
+                        self.sun.apply_state(changed, &state);
+                    } else if let Some(entity) = self.networked.get_mut(&id) {
+                        entity.apply_state(changed, &state);
+                    }
+                }
+                SnapshotEvent::Despawn(id) => {
+                    if self.live.get(&id.index) == Some(&id) {
+                        self.live.remove(&id.index);
+                    }
+                    self.remove_networked(ctx, id);
+                }
+            }
+        }
+    }
+
+    fn remove_networked(&mut self, ctx: &mut Context<'a>, id: EntityId) {
+        if let Some(mut entity) = self.networked.remove(&id) {
+            entity.unregister(ctx);
+        }
+        if let Some(proxy) = self.proxies.remove(&Pickable::Networked(id)) {
+            self.bvh.remove(proxy);
+        }
+        self.interpolation.remove(&id);
+    }
+
+    /// Records where every networked entity was as of `snapshot`, entities that didn't change
+    /// still get a sample so they're known to have stayed put
+    pub fn push_snapshot_samples(&mut self, time: f64, snapshot: &Snapshot) {
+        for (id, entity) in snapshot.entities.iter() {
+            if self.networked.contains_key(id) {
+                self.interpolation
+                    .entry(*id)
+                    .or_default()
+                    .push(Sample::new(time, &entity.state()));
+            }
+        }
+    }
+
+    /// moves networked entities to where they were at `time`, `skip` is left alone so it can be
+    /// predicted instead
+    pub fn interpolate(&mut self, time: f64, skip: Option<EntityId>) {
+        for (id, buffer) in self.interpolation.iter_mut() {
+            if Some(*id) == skip {
+                continue;
+            }
+
+            if let (Some(entity), Some((position, rotation))) =
+                (self.networked.get_mut(id), buffer.sample(time))
+            {
+                let state = EntityState {
+                    position,
+                    rotation: rotation.rotation_matrix(),
+                    ..entity.state()
+                };
+                entity.apply_state(fields::POSITION | fields::ROTATION, &state);
+            }
+        }
+    }
+
+    /// The closest networked entity along the ray which can be edited. Players are left out,
+    /// they only move through their own inputs.
+    pub fn pick(&self, origin: Vec3, direction: Vec3) -> Option<EntityId> {
+        let editable = |key: &Pickable| match key {
+            Pickable::Networked(id) => {
+                !matches!(self.networked.get(id), Some(EntityKind::Player(_)))
+            }
+            Pickable::Sun => self.sun_id.is_some(),
+            _ => false,
+        };
+
+        match self.ray_pick(origin, direction, editable)?.0 {
+            Pickable::Networked(id) => Some(id),
+            Pickable::Sun => self.sun_id,
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.sun_id == Some(id) || self.networked.contains_key(&id)
+    }
+
+    /// where a networked entity is drawn and which way it's turned
+    pub fn transform(&self, id: EntityId) -> Option<(Vec3, Quaternion)> {
+        let state = if self.sun_id == Some(id) {
+            self.sun.state()
+        } else {
+            self.networked.get(&id)?.state()
+        };
+
+        Some((
+            state.position,
+            Quaternion::from_rotation_matrix(&state.rotation),
+        ))
+    }
+
+    /// selects or deselects a networked entity through its `MouseComponent`
+    pub fn set_selected(&mut self, ctx: &mut Context, id: EntityId, selected: bool) {
+        let entity: &mut dyn MouseComponent = if self.sun_id == Some(id) {
+            &mut self.sun
+        } else {
+            match self.networked.get_mut(&id) {
+                Some(entity) => entity,
+                None => return,
+            }
+        };
+
+        if selected {
+            entity.click_start(ctx);
+        } else {
+            entity.click_end(ctx);
+        }
+    }
+
+    /// the id currently using `index`, if anything is
+    pub fn live_id(&self, index: u32) -> Option<EntityId> {
+        self.live.get(&index).copied()
+    }
+
+    pub fn player_mut(&mut self, id: EntityId) -> Option<&mut Player<'a>> {
+        match self.networked.get_mut(&id) {
+            Some(EntityKind::Player(player)) => Some(player),
+            _ => None,
+        }
+    }
+
+    /// Terrain height under `x`, `z`. Streamed terrain has no heights, its mesh is cast down
+    /// onto instead.
+    pub fn ground_height(&self, x: f32, z: f32) -> Option<f32> {
+        if self.terrain.heights.is_empty() {
+            let origin = Vec3::new(x, GROUND_RAY_HEIGHT, z);
+            return self
+                .terrain
+                .ray_pick(origin, Vec3::new(0, -1, 0))
+                .map(|(point, _)| point.y);
+        }
+
+        self.terrain.height_at(x, z)
+    }
+
+    /// What a player at `around` could bump into with its next input, everything the server
+    /// sent other than `except` itself
+    pub fn obstacles(&self, except: EntityId, around: Vec3) -> Vec<Shape> {
+        let sun = self.sun_id.map(|_| self.sun.cube.shape());
+        self.networked
+            .iter()
+            .filter(|&(&id, _)| id != except)
+            .map(|(_, entity)| entity.shape())
+            .chain(sun)
+            .filter(|shape| (shape.closest_point(around) - around).magnitude() <= OBSTACLE_REACH)
+            .collect()
+    }
+
+    /// swaps in terrain received from the server, the water is scaled to match it
+    pub fn replace_terrain(&mut self, ctx: &mut Context<'a>, mut terrain: Terrain<'a>) {
+        self.terrain.unregister(ctx);
+        if self.water.scale != terrain.scale {
+            self.water.unregister(ctx);
+            self.water.scale = terrain.scale;
+            self.water.register(ctx);
+        }
+        terrain.init(ctx);
+        terrain.register(ctx);
+        self.terrain = terrain;
+    }
+
     pub fn get_sun_mesh(&self) -> Sun {
         self.sun
     }
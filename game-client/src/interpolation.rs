@@ -0,0 +1,218 @@
+use atlas::snapshot::{EntityState, Tick};
+use pantheon::math::{Quaternion, Vec3};
+use std::collections::VecDeque;
+
+/// past this an entity stops where it was heading instead of drifting off
+pub const MAX_EXTRAPOLATION: f64 = 0.25;
+/// plenty for any sane delay at any tick rate, older samples are dropped as they're passed
+pub const MAX_SAMPLES: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    /// seconds on the client's clock when the snapshot arrived
+    pub time: f64,
+    pub position: Vec3,
+    pub rotation: Quaternion,
+    /// only used to tell whether the entity was still on the move
+    pub velocity: Vec3,
+}
+
+impl Sample {
+    pub fn new(time: f64, state: &EntityState) -> Self {
+        Self {
+            time,
+            position: state.position,
+            rotation: Quaternion::from_rotation_matrix(&state.rotation),
+            velocity: state.velocity,
+        }
+    }
+}
+
+/// Every snapshot a remote entity shows up in, so it can be drawn somewhere in between two of
+/// them rather than jumping to each one as it arrives
+#[derive(Debug, Default)]
+pub struct InterpolationBuffer {
+    samples: VecDeque<Sample>,
+}
+
+impl InterpolationBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// samples have to come in order, anything older than the newest one is ignored
+    pub fn push(&mut self, sample: Sample) {
+        if let Some(last) = self.samples.back() {
+            if sample.time <= last.time {
+                return;
+            }
+        }
+
+        if self.samples.len() >= MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Position and rotation at `time`, blending between the samples on either side of it. Past
+    /// the newest sample the last two are extrapolated for up to `MAX_EXTRAPOLATION` seconds, as
+    /// long as the entity was still moving. The server only sends snapshots when something
+    /// changed, so an entity that has stopped just doesn't get any newer samples.
+    /// Samples which can't be needed anymore are dropped along the way.
+    pub fn sample(&mut self, time: f64) -> Option<(Vec3, Quaternion)> {
+        while self.samples.len() > 2 && self.samples[1].time <= time {
+            self.samples.pop_front();
+        }
+
+        let (from, to) = match self.samples.len() {
+            0 => return None,
+            1 => return Some((self.samples[0].position, self.samples[0].rotation)),
+            _ => (self.samples[0], self.samples[1]),
+        };
+        // `to` can only be older than `time` once it's the newest sample, which is when
+        // extrapolating kicks in
+        let extrapolation = if to.velocity.magnitude() > 0. {
+            MAX_EXTRAPOLATION
+        } else {
+            0.
+        };
+        let time = time.clamp(from.time, to.time + extrapolation);
+        let t = ((time - from.time) / (to.time - from.time)) as f32;
+
+        Some((
+            from.position.lerp(&to.position, t),
+            from.rotation.nlerp(&from.rotation.closest(&to.rotation), t),
+        ))
+    }
+}
+
+/// When each snapshot arrived, so a time on the client's clock can be turned back into the
+/// server tick entities were being drawn at then
+#[derive(Debug, Default)]
+pub struct TickTimeline {
+    arrivals: VecDeque<(f64, Tick)>,
+}
+
+impl TickTimeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// same rules as `InterpolationBuffer::push`, late or out of order arrivals are ignored
+    pub fn push(&mut self, time: f64, tick: Tick) {
+        if let Some((last_time, last_tick)) = self.arrivals.back() {
+            if time <= *last_time || tick <= *last_tick {
+                return;
+            }
+        }
+
+        if self.arrivals.len() >= MAX_SAMPLES {
+            self.arrivals.pop_front();
+        }
+        self.arrivals.push_back((time, tick));
+    }
+
+    /// The tick being shown at `time` and how far towards the next one it was, held at the
+    /// oldest and newest arrivals rather than guessing past them
+    pub fn tick_at(&self, time: f64) -> Option<(Tick, f32)> {
+        let next = self
+            .arrivals
+            .partition_point(|(arrival, _)| *arrival <= time);
+        if next == 0 {
+            return self.arrivals.front().map(|(_, tick)| (*tick, 0.));
+        }
+        let (from_time, from_tick) = self.arrivals[next - 1];
+        let (to_time, to_tick) = match self.arrivals.get(next) {
+            Some(to) => *to,
+            None => return Some((from_tick, 0.)),
+        };
+
+        let t = (time - from_time) / (to_time - from_time);
+        let tick = from_tick as f64 + t * (to_tick - from_tick) as f64;
+        Some((tick.floor() as Tick, tick.fract() as f32))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample(time: f64, x: f32, degrees: f32) -> Sample {
+        Sample {
+            time,
+            position: Vec3::new(x, 0, 0),
+            rotation: Quaternion::rotation_from_degrees(degrees, (0, 1, 0).into()),
+            velocity: Vec3::new(10, 0, 0),
+        }
+    }
+
+    fn degrees(rotation: Quaternion) -> f32 {
+        2. * rotation.scalar.clamp(-1., 1.).acos().to_degrees()
+    }
+
+    #[test]
+    fn blends_between_samples() {
+        let mut buffer = InterpolationBuffer::new();
+        assert!(buffer.sample(0.).is_none());
+
+        buffer.push(sample(1., 0., 0.));
+        assert_eq!(buffer.sample(0.5).unwrap().0, Vec3::new(0, 0, 0));
+
+        buffer.push(sample(1.1, 1., 20.));
+        buffer.push(sample(1.2, 3., 40.));
+        // out of order samples are ignored
+        buffer.push(sample(1.15, 100., 0.));
+
+        let (position, rotation) = buffer.sample(1.05).unwrap();
+        assert!((position.x - 0.5).abs() < 1e-4);
+        assert!((degrees(rotation) - 10.).abs() < 0.1);
+
+        let (position, rotation) = buffer.sample(1.15).unwrap();
+        assert!((position.x - 2.).abs() < 1e-4);
+        assert!((degrees(rotation) - 30.).abs() < 0.1);
+
+        // before the oldest sample it's held there
+        let (position, _) = buffer.sample(1.0).unwrap();
+        assert!((position.x - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn extrapolates_for_a_while() {
+        let mut buffer = InterpolationBuffer::new();
+        buffer.push(sample(1., 0., 0.));
+        buffer.push(sample(1.1, 1., 0.));
+
+        let (position, _) = buffer.sample(1.15).unwrap();
+        assert!((position.x - 1.5).abs() < 1e-4);
+
+        let (position, _) = buffer.sample(10.).unwrap();
+        let furthest = 1. + (MAX_EXTRAPOLATION / 0.1) as f32;
+        assert!((position.x - furthest).abs() < 1e-3);
+
+        // something that came to a stop stays put
+        buffer.push(Sample {
+            velocity: Vec3::new_from_one(0),
+            ..sample(1.2, 2., 0.)
+        });
+        let (position, _) = buffer.sample(1.3).unwrap();
+        assert!((position.x - 2.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn maps_time_to_ticks() {
+        let mut timeline = TickTimeline::new();
+        assert!(timeline.tick_at(1.).is_none());
+
+        timeline.push(1., 10);
+        // nothing changed for a few ticks so the next snapshot skips ahead
+        timeline.push(1.2, 14);
+        timeline.push(1.1, 20);
+
+        assert_eq!(timeline.tick_at(0.5), Some((10, 0.)));
+        assert_eq!(timeline.tick_at(1.), Some((10, 0.)));
+        let (tick, alpha) = timeline.tick_at(1.125).unwrap();
+        assert_eq!(tick, 12);
+        assert!((alpha - 0.5).abs() < 1e-4);
+        assert_eq!(timeline.tick_at(5.), Some((14, 0.)));
+    }
+}
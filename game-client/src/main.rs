@@ -14,33 +14,47 @@ use pantheon::winit::event::VirtualKeyCode;
 use pantheon::graphics::prelude::*;
 use pantheon::math::prelude::*;
 
-use atlas::camera::Camera;
+use atlas::camera::{Camera, CameraMode};
 
 use atlas::rendering::init::*;
 use atlas::rendering::prelude::*;
+use chat::ChatPanel;
 use entity_manager::EntityManager;
+use interpolation::TickTimeline;
+use prediction::Prediction;
+use selection::{GizmoMode, Selection};
 
 use ui::*;
 
+use atlas::chat::{chat_message, pull_chat};
+use atlas::command::{edit_message, EditRequest};
+use atlas::entity::player::{PLAYER_EYE_HEIGHT, PLAYER_MAX_SPEED};
+use atlas::entity::terrain::TerrainStream;
 use atlas::entity::water::*;
-use atlas::entity::Entity;
 use atlas::entity::EntityKind;
-use atlas::message::GameMessage;
+use atlas::interact::{InteractRequest, InteractResult};
+use atlas::message::{pull_chunk, GameMessage, PlayerAck, PlayerInput, TerrainMessage};
 use atlas::prelude::*;
-use atlas::proc_gen;
-use atlas::proc_gen::terrain::TerrainGenerator;
+use atlas::proc_gen::terrain::TerrainParams;
+use atlas::snapshot::{pull_snapshot, EntityId, SnapshotError, SnapshotHistory};
 use atlas::vertex::*;
-use proc_gen::color::ColorGenerator;
-use proc_gen::noise::Perlin;
 
 use hermes::client::ClientInterface;
 use hermes::message::Message;
 
 use hermes::tokio;
 
+use server::config::ServerConfig;
+use tracing::{debug, error, info, trace, warn};
+
 use pantheon::wgpu;
 
+pub mod chat;
+pub mod config;
 pub mod entity_manager;
+pub mod interpolation;
+pub mod prediction;
+pub mod selection;
 pub mod ui;
 
 struct State<'a> {
@@ -49,6 +63,22 @@ struct State<'a> {
     mouse_down: bool,
     network_client: ClientInterface<GameMessage>,
     network_queue: Vec<(std::net::SocketAddr, Message<GameMessage>)>,
+    terrain_stream: Option<TerrainStream>,
+    snapshots: SnapshotHistory,
+    /// when snapshots arrived, interactions tell the server which tick we were looking at
+    timeline: TickTimeline,
+    /// our own player, `None` until the server has spawned it
+    player_id: Option<EntityId>,
+    /// starts once our player has shown up in a snapshot
+    prediction: Option<Prediction<'a>>,
+    player_moving: bool,
+    /// what we're editing, Delete deletes it
+    selection: Selection<'a>,
+    modifiers: ModifiersState,
+    /// everything is timed off of this, remote entities are drawn `interpolation_delay` behind it
+    clock: std::time::Instant,
+    interpolation_delay: f64,
+    chat: ChatPanel<'a>,
     fps: f32,
     debug: bool,
     //texture: Texture,
@@ -57,12 +87,158 @@ struct State<'a> {
     handles: Handles<'a>,
 }
 
+impl<'a> State<'a> {
+    /// Arrow keys move our player along the ground relative to where the camera is facing, as
+    /// do WASD in first person, and space jumps. Inputs are applied locally straight away and
+    /// sent to the server, which has the final say through `PlayerAck`s
+    fn update_player(&mut self, ctx: &Context, delta_time: f32) {
+        let player_id = match self.player_id {
+            Some(id) => id,
+            None => return,
+        };
+        if self.prediction.is_none() {
+            // there's nothing to predict until our player shows up in a snapshot
+            match self.entity_manager.player_mut(player_id) {
+                Some(player) => self.prediction = Some(Prediction::new(*player)),
+                None => return,
+            }
+        }
+
+        let camera = &self.entity_manager.camera;
+        // the camera looks down -w
+        let forward = Vec3::new(-camera.w.x, 0., -camera.w.z);
+        let right = Vec3::new(camera.u.x, 0., camera.u.z);
+        let first_person = camera.mode == CameraMode::FirstPerson;
+        let mut direction = Vec3::new_from_one(0);
+        let mut jump = false;
+        let typing = self.chat.chat.is_typing();
+        for key in keyboard::pressed_keys(ctx).iter().filter(|_| !typing) {
+            match key {
+                VirtualKeyCode::Up => direction += forward,
+                VirtualKeyCode::Down => direction -= forward,
+                VirtualKeyCode::Left => direction -= right,
+                VirtualKeyCode::Right => direction += right,
+                VirtualKeyCode::W if first_person => direction += forward,
+                VirtualKeyCode::S if first_person => direction -= forward,
+                VirtualKeyCode::A if first_person => direction -= right,
+                VirtualKeyCode::D if first_person => direction += right,
+                VirtualKeyCode::Space => jump = true,
+                _ => {}
+            }
+        }
+        let moving = direction.magnitude() > 0.;
+        let velocity = if moving {
+            PLAYER_MAX_SPEED * direction.unit_vector()
+        } else {
+            Vec3::new_from_one(0)
+        };
+
+        let entity_manager = &self.entity_manager;
+        let prediction = match self.prediction.as_mut() {
+            Some(prediction) => prediction,
+            None => return,
+        };
+
+        // one last input once the keys are let go so the player stops, and every frame while
+        // it's in the air so it comes back down
+        let airborne = !prediction.player().grounded;
+        if moving || jump || airborne || self.player_moving {
+            let obstacles = entity_manager.obstacles(player_id, prediction.player().position());
+            let input = prediction.push_input(
+                PlayerInput {
+                    sequence: 0,
+                    velocity,
+                    yaw: camera.yaw.to_radians(),
+                    pitch: camera.pitch.to_radians(),
+                    dt: delta_time,
+                    jump: jump as u8,
+                },
+                |x, z| entity_manager.ground_height(x, z),
+                &obstacles,
+            );
+
+            let mut message = Message::new(GameMessage::MovePlayer);
+            message.push(input);
+            if let Err(e) = self.network_client.try_send(message) {
+                warn!("failed to send player input: {}", e);
+            }
+        }
+        self.player_moving = moving;
+        prediction.update(delta_time);
+
+        // snapshots keep putting our player where the server last had it, draw the prediction
+        if let Some(player) = self.entity_manager.player_mut(player_id) {
+            let predicted = prediction.player();
+            player.cube.position = prediction.position();
+            player.cube.rotation = predicted.cube.rotation;
+            player.velocity = predicted.velocity;
+        }
+        let eye = prediction.position() + Vec3::new(0., PLAYER_EYE_HEIGHT, 0.);
+        self.entity_manager.camera.follow(eye);
+    }
+
+    /// Asks the server what's under the cursor. Other entities are drawn in the past, so the
+    /// server is told when we were looking at them and checks against that instead of now.
+    fn send_interact(&mut self, ctx: &mut Context) {
+        // the server only takes rays from around our player's eyes
+        if self.player_id.is_none() || self.entity_manager.camera.mode != CameraMode::FirstPerson {
+            return;
+        }
+        let render_time = self.clock.elapsed().as_secs_f64() - self.interpolation_delay;
+        let (view_tick, view_alpha) = match self.timeline.tick_at(render_time) {
+            Some(view) => view,
+            None => return,
+        };
+        let direction = match self.entity_manager.get_mouse_ray(ctx) {
+            Some(direction) => direction,
+            None => return,
+        };
+
+        let mut message = Message::new(GameMessage::Interact);
+        message.push(InteractRequest {
+            origin: self.entity_manager.camera.origin,
+            direction,
+            view_tick,
+            view_alpha,
+        });
+        if let Err(e) = self.network_client.try_send(message) {
+            warn!("failed to send interaction: {}", e);
+        }
+    }
+
+    /// edits are made on the server, which keeps our undo history and sends back whatever
+    /// changed with the next snapshot
+    fn send_edit(&mut self, request: EditRequest) {
+        if let Err(e) = self.network_client.try_send(edit_message(&request)) {
+            warn!("failed to send edit: {}", e);
+        }
+    }
+
+    /// sends a line typed into the chat, anything wrong with it is only shown to us
+    fn send_chat(&mut self, line: &str) {
+        let entity_manager = &self.entity_manager;
+        let message = chat::parse_input(line, |index| entity_manager.live_id(index))
+            .and_then(|chat| chat_message(&chat).map_err(|e| e.to_string()));
+
+        match message {
+            std::result::Result::Ok(message) => {
+                if let Err(e) = self.network_client.try_send(message) {
+                    warn!("failed to send chat: {}", e);
+                    self.chat.chat.notice("couldn't reach the server");
+                }
+            }
+            Err(e) => self.chat.chat.notice(&e),
+        }
+    }
+}
+
 impl<'a> EventHandler<'a> for State<'a> {
     fn draw(&mut self, ctx: &mut Context<'a>) -> Result<()> {
         ctx.start_drawing();
         self.frame += 1;
 
         self.entity_manager.draw(ctx);
+        self.selection.draw(ctx, &self.entity_manager);
 
         if self.debug {
             self.entity_manager.debug_draw(ctx);
@@ -92,22 +268,104 @@ impl<'a> EventHandler<'a> for State<'a> {
             .drain_message_queue(&mut self.network_queue);
 
         for (_source, mut message) in self.network_queue.drain(..) {
-            println!("[Networking] Got msg {}", message);
+            debug!("got message {}", message);
             match message.header.id {
                 GameMessage::GetId => {
                     let id: usize = message.pull().unwrap();
-                    println!("[Networking] Got id {}", id);
+                    debug!(id, "got id");
+                }
+                GameMessage::Player => {
+                    let id: EntityId = message.pull().unwrap();
+                    info!(%id, "playing as entity");
+                    if self.player_id != Some(id) {
+                        self.prediction = None;
+                    }
+                    self.player_id = Some(id);
+                }
+                GameMessage::MovePlayer => {
+                    let ack: PlayerAck = message.pull().unwrap();
+                    if let (Some(prediction), Some(player_id)) =
+                        (self.prediction.as_mut(), self.player_id)
+                    {
+                        let entity_manager = &self.entity_manager;
+                        let obstacles = entity_manager.obstacles(player_id, ack.position);
+                        prediction.reconcile(
+                            &ack,
+                            |x, z| entity_manager.ground_height(x, z),
+                            &obstacles,
+                        );
+                    }
                 }
-                GameMessage::SyncWorld => {
-                    while !message.body.is_empty() {
-                        let entity: EntityKind = message.pull().unwrap();
-                        //println!("[Networking] Got entity: {:#?}", entity);
-                        match entity {
-                            EntityKind::Sun(s) => {
-                                self.entity_manager.sun = s;
-                                self.entity_manager.sun.init(ctx);
+                GameMessage::Snapshot => {
+                    let (header, events) = pull_snapshot(&mut message).unwrap();
+                    match self.snapshots.receive(header, &events) {
+                        std::result::Result::Ok(changes) => {
+                            self.entity_manager.apply_snapshot_events(ctx, &changes);
+                            let now = self.clock.elapsed().as_secs_f64();
+                            if let Some(snapshot) = self.snapshots.latest() {
+                                self.entity_manager.push_snapshot_samples(now, snapshot);
                             }
-                            _ => self.entity_manager.push_entity(ctx, entity),
+                            self.timeline.push(now, header.tick);
+
+                            let mut ack = Message::new(GameMessage::AckSnapshot);
+                            ack.push(header.tick);
+                            if let Err(e) = self.network_client.try_send(ack) {
+                                warn!("failed to ack snapshot: {}", e);
+                            }
+                        }
+                        Err(SnapshotError::Stale { tick }) => {
+                            debug!(tick, "ignoring stale snapshot");
+                        }
+                        Err(SnapshotError::BaselineLost { baseline }) => {
+                            warn!(baseline, "lost baseline, resyncing");
+                            let resync = Message::new(GameMessage::SyncWorld);
+                            if let Err(e) = self.network_client.try_send(resync) {
+                                warn!("failed to request resync: {}", e);
+                            }
+                        }
+                    }
+                }
+                GameMessage::Interact => {
+                    let result: InteractResult = message.pull().unwrap();
+                    debug!(player = %result.player, tick = result.tick, hit = ?result.hit, "interaction");
+                }
+                GameMessage::Chat => match pull_chat(&mut message) {
+                    std::result::Result::Ok(chat) => self.chat.chat.receive(&chat, self.player_id),
+                    Err(e) => warn!("bad chat message: {}", e),
+                },
+                GameMessage::RegenerateTerrain(TerrainMessage::Generate) => {
+                    let params: TerrainParams = message.pull().unwrap();
+                    info!(?params, "regenerating terrain");
+                    self.entity_manager.replace_terrain(ctx, params.generate());
+                }
+                GameMessage::RegenerateTerrain(TerrainMessage::Center) => {
+                    let mut stream = TerrainStream::new();
+                    let scale = message.pull().unwrap();
+                    stream.set_center(message.pull().unwrap(), scale);
+                    self.terrain_stream = Some(stream);
+                }
+                GameMessage::RegenerateTerrain(TerrainMessage::Verts) => {
+                    let (header, verts) = pull_chunk::<ShadedVertex>(&mut message).unwrap();
+                    if let Some(stream) = self.terrain_stream.as_mut() {
+                        stream.push_verts(header, &verts);
+                    }
+                }
+                GameMessage::RegenerateTerrain(TerrainMessage::Indices) => {
+                    let (header, indices) = pull_chunk::<u32>(&mut message).unwrap();
+                    if let Some(stream) = self.terrain_stream.as_mut() {
+                        stream.push_indices(header, &indices);
+                    }
+
+                    let complete = self
+                        .terrain_stream
+                        .as_ref()
+                        .map_or(false, TerrainStream::is_complete);
+                    if complete {
+                        if let Some(terrain) =
+                            self.terrain_stream.take().and_then(TerrainStream::finish)
+                        {
+                            info!("finished streaming terrain");
+                            self.entity_manager.replace_terrain(ctx, terrain);
                         }
                     }
                 }
@@ -116,11 +374,14 @@ impl<'a> EventHandler<'a> for State<'a> {
         }
 
         let delta_time = ctx.timer_context.delta_time();
-        for key in keyboard::pressed_keys(ctx).iter() {
+        // keys typed into the chat don't also fly the camera around
+        let typing = self.chat.chat.is_typing();
+        for key in keyboard::pressed_keys(ctx).iter().filter(|_| !typing) {
             self.entity_manager
                 .camera
                 .process_keypress(*key, delta_time);
         }
+        self.update_player(ctx, delta_time);
 
         if self.mouse_down {
             let delta = mouse::delta(ctx);
@@ -135,7 +396,7 @@ impl<'a> EventHandler<'a> for State<'a> {
             self.camera_uniforms
                 .push(ctx, &self.handles.camera_uniforms);
 
-            println!("[CAMERA UNIFORMS] {:#?}", self.camera_uniforms);
+            trace!(uniforms = ?self.camera_uniforms, "camera moved");
 
             // @NOTE reflected camera uniform's position is not updated ever because
             // it is only used for the reflection pass, which doesn't care about the
@@ -152,15 +413,23 @@ impl<'a> EventHandler<'a> for State<'a> {
         }
 
         self.entity_manager.update(ctx);
+        // after `update` so nothing moves them again before they're drawn
+        let render_time = self.clock.elapsed().as_secs_f64() - self.interpolation_delay;
+        self.entity_manager.interpolate(render_time, self.player_id);
+
+        self.selection.retain(&self.entity_manager);
+        for request in self.selection.drag_to(ctx, &self.entity_manager) {
+            self.send_edit(request);
+        }
+        self.chat.update(ctx);
 
         self.fps = 1.0 / ctx.timer_context.average_tick;
         if ctx.timer_context.frame_count % (pantheon::timer::MAX_SAMPLES) == 0 {
-            println!(
-                "FPS: {:#?}, sample_sum {}, MAX_SAMPLES {}, average_tick {}",
-                self.fps,
-                ctx.timer_context.sample_sum,
-                pantheon::timer::MAX_SAMPLES,
-                ctx.timer_context.average_tick
+            debug!(
+                fps = self.fps,
+                sample_sum = ctx.timer_context.sample_sum,
+                average_tick = ctx.timer_context.average_tick,
+                "frame timing"
             );
         }
 
@@ -168,10 +437,28 @@ impl<'a> EventHandler<'a> for State<'a> {
     }
 
     fn key_down_event(&mut self, ctx: &mut Context<'a>, keycode: VirtualKeyCode, _repeat: bool) {
+        // while typing keys only go to the chat, through `text_input_event`
+        if self.chat.chat.is_typing() {
+            if keycode == VirtualKeyCode::Escape {
+                self.chat.chat.close();
+            }
+            return;
+        }
+
         if keycode == VirtualKeyCode::Escape {
             pantheon::event::quit(ctx);
         }
 
+        // undo and redo our own edits, the server has the history
+        if self.modifiers.ctrl() {
+            match keycode {
+                VirtualKeyCode::Z => self.send_edit(EditRequest::Undo),
+                VirtualKeyCode::Y => self.send_edit(EditRequest::Redo),
+                _ => {}
+            }
+            return;
+        }
+
         if keycode == VirtualKeyCode::L {
             if let Some(_) = ctx.forced_draw_mode {
                 ctx.forced_draw_mode = None;
@@ -198,16 +485,52 @@ impl<'a> EventHandler<'a> for State<'a> {
             ctx.reload_shaders();
         }
 
+        // V switches between flying the camera around and walking our player in first person
+        if keycode == VirtualKeyCode::V {
+            let camera = &mut self.entity_manager.camera;
+            camera.mode = camera.mode.toggle();
+        }
+
+        if keycode == VirtualKeyCode::Delete {
+            for request in self.selection.delete() {
+                self.send_edit(request);
+            }
+        }
+
+        // 1 and 2 switch between the translate and rotate gizmos, X between world and local axes
+        if keycode == VirtualKeyCode::Key1 {
+            self.selection.mode = GizmoMode::Translate;
+        }
+
+        if keycode == VirtualKeyCode::Key2 {
+            self.selection.mode = GizmoMode::Rotate;
+        }
+
+        if keycode == VirtualKeyCode::X {
+            self.selection.space = self.selection.space.toggle();
+        }
+
+        // the server owns the terrain, T asks for its params to regenerate it locally while Y
+        // asks for the whole mesh to be streamed over
         if keycode == VirtualKeyCode::T {
-            let terrain_size = if cfg!(debug_assertions) { 10 } else { 250 };
-            self.entity_manager.terrain = generate_terrain(terrain_size, true, None);
-            self.entity_manager.terrain.init(ctx);
+            let message = Message::new(GameMessage::RegenerateTerrain(TerrainMessage::Generate));
+            if let Err(e) = self.network_client.try_send(message) {
+                warn!("failed to request terrain params: {}", e);
+            }
         }
 
         if keycode == VirtualKeyCode::Y {
-            let terrain_size = if cfg!(debug_assertions) { 10 } else { 250 };
-            self.entity_manager.terrain = generate_terrain(terrain_size, false, None);
-            self.entity_manager.terrain.init(ctx);
+            let message = Message::new(GameMessage::RegenerateTerrain(TerrainMessage::Verts));
+            if let Err(e) = self.network_client.try_send(message) {
+                warn!("failed to request terrain mesh: {}", e);
+            }
+        }
+    }
+
+    /// Enter opens the chat and sends whatever was typed into it
+    fn text_input_event(&mut self, _ctx: &mut Context<'a>, character: char) {
+        if let Some(line) = self.chat.chat.text_input(character) {
+            self.send_chat(&line);
         }
     }
 
@@ -215,27 +538,45 @@ impl<'a> EventHandler<'a> for State<'a> {
         self.entity_manager.camera.process_keyrelease(keycode);
     }
 
-    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
-        println!("Mouse button pressed: {:?}, x: {}, y: {}", button, x, y);
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        trace!(?button, x, y, "mouse button pressed");
         //self.points.push(Vec3::new(x, y, 0.0));
 
-        if let MouseButton::Right = button {
-            self.mouse_down = true;
+        match button {
+            MouseButton::Left => {
+                // shift-clicking adds to the selection
+                let additive = self.modifiers.shift();
+                if let Some(request) = self
+                    .selection
+                    .press(ctx, &mut self.entity_manager, additive)
+                {
+                    self.send_edit(request);
+                }
+                self.send_interact(ctx);
+            }
+            MouseButton::Right => self.mouse_down = true,
+            _ => {}
         }
     }
 
     fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
-        println!("Mouse button pressed: {:?}, x: {}, y: {}", button, x, y);
+        trace!(?button, x, y, "mouse button released");
         //self.points.push(Vec3::new(x, y, 0.0));
         //self.camera.update_pitch_and_angle(ctx);
 
-        if let MouseButton::Right = button {
-            self.mouse_down = false;
+        match button {
+            MouseButton::Left => {
+                if let Some(request) = self.selection.release() {
+                    self.send_edit(request);
+                }
+            }
+            MouseButton::Right => self.mouse_down = false,
+            _ => {}
         }
     }
 
     fn mouse_wheel_event(&mut self, _ctx: &mut Context, x: f32, y: f32) {
-        println!("Mouse wheel scrolled: x: {}, y: {}", x, y);
+        trace!(x, y, "mouse wheel scrolled");
         //self.camera.update_zoom(Vec2::new(x, y));
     }
 
@@ -256,7 +597,7 @@ impl<'a> EventHandler<'a> for State<'a> {
         // prevent degenerate case where things go wrong if resize while moving camera orientation
         self.mouse_down = false;
 
-        println!("resize_event: width: {}, height: {}", width, height);
+        debug!(width, height, "window resized");
         self.entity_manager.camera.set_aspect((width, height));
 
         self.camera_uniforms.projection = self.entity_manager.camera.update_projection_matrix();
@@ -322,7 +663,9 @@ impl<'a> EventHandler<'a> for State<'a> {
         ctx.wrangler.validate_resize();
     }
 
-    fn key_mods_changed(&mut self, _ctx: &mut Context, _modifiers_state: ModifiersState) {}
+    fn key_mods_changed(&mut self, _ctx: &mut Context, modifiers_state: ModifiersState) {
+        self.modifiers = modifiers_state;
+    }
 }
 
 #[allow(dead_code)]
@@ -366,48 +709,55 @@ fn populate_grid(grid: &mut Vec<(Vec3, Color, Vec3)>, size: i32, y: f32) {
     }
 }
 
-fn generate_terrain(
-    terrain_size: usize,
-    clamped: bool,
-    seed: Option<isize>,
-) -> atlas::entity::terrain::Terrain<'static> {
-    let mut perlin = Perlin::default();
-    if let Some(seed) = seed {
-        perlin.seed = seed;
+#[tokio::main]
+async fn main() {
+    if atlas::config::wants_help(std::env::args()) {
+        println!("{}", config::USAGE);
+        return;
     }
 
-    let color_gen = ColorGenerator::new(
-        vec![
-            (201, 178, 99).into(),
-            (164, 155, 98).into(),
-            (164, 155, 98).into(),
-            (229, 219, 164).into(),
-            (135, 184, 82).into(),
-            //(80, 171, 93).into(),
-            (120, 120, 120).into(),
-            (200, 200, 210).into(),
-        ],
-        0.45,
-    );
-
-    let terrain_gen = TerrainGenerator::new(perlin, color_gen);
-    let mut terrain = terrain_gen.generate(terrain_size, clamped);
-    terrain.center = (terrain_size as f32 / 2., 0., terrain_size as f32 / 2.).into();
-
-    terrain
-}
+    let config = match config::ClientConfig::from_args(std::env::args().skip(1)) {
+        std::result::Result::Ok(config) => config,
+        Err(e) => {
+            eprintln!("[Config] {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = atlas::logging::init(config.log_level, config.log_file.as_deref()) {
+        eprintln!("[Logging] {}", e);
+        std::process::exit(1);
+    }
 
-#[tokio::main]
-async fn main() {
     let mut network_client: ClientInterface<GameMessage> = ClientInterface::new();
-    println!(
-        "Connectinon status: {:?}",
-        network_client.connect("127.0.0.1", 8080).await
-    );
+    // already validated along with the rest of the config
+    let connection = match config.server_host_port().unwrap() {
+        Some((host, port)) => network_client.connect(host, port).await,
+        // single player, the same protocol just never leaves the process
+        None => {
+            let server_config = ServerConfig {
+                max_players: 1,
+                log_level: config.log_level,
+                ..ServerConfig::default()
+            };
+            match server::spawn_embedded(server_config) {
+                std::result::Result::Ok(connector) => {
+                    network_client.connect_local(&connector).await
+                }
+                Err(e) => Err(Box::new(e) as Box<dyn std::error::Error + Send>),
+            }
+        }
+    };
+    match &connection {
+        std::result::Result::Ok(_) => info!("connected"),
+        Err(e) => error!("failed to connect: {}", e),
+    }
     let message: Message<GameMessage> = Message::new(GameMessage::GetId);
     network_client.send(message).await.unwrap();
     let message: Message<GameMessage> = Message::new(GameMessage::SyncWorld);
     network_client.send(message).await.unwrap();
+    let message: Message<GameMessage> =
+        Message::new(GameMessage::RegenerateTerrain(TerrainMessage::Generate));
+    network_client.send(message).await.unwrap();
 
     let shader_path = std::path::PathBuf::from("game-client/assets/shaders");
     let (mut ctx, event_loop) = Context::new(
@@ -416,12 +766,7 @@ async fn main() {
         shader_path,
     );
 
-    // @NOTE this has to be 0 unless we want out camera to be paramertized against the water's
-    // height which I think is a bit much, probably easier to just approach life as water == 0
-    // height
-    let water_height = 0.;
-    let terrain_height = 3.;
-    let world_scale = 2.0;
+    let water_height = config.water_height;
     let direction = Vec3::new(0.3, -1, 0.5).make_unit_vector();
     let color = Vec3::new(1, 0.95, 0.95);
     let bias = Vec2::new(0.3, 0.8);
@@ -452,29 +797,22 @@ async fn main() {
     let depth_texture_handle = ctx.wrangler.handle_to_texture("depth").expect(":)");
 
     //populate_grid(&mut grid, 50, 15.);
-    println!(
-        "{:#?}",
-        (
-            ctx.gfx_context.window_dims.width,
-            ctx.gfx_context.window_dims.height,
-        )
+    debug!(
+        width = ctx.gfx_context.window_dims.width,
+        height = ctx.gfx_context.window_dims.height,
+        "window created"
     );
 
-    let terrain_size = if cfg!(debug_assertions) { 50 } else { 250 };
-    let mut terrain = generate_terrain(terrain_size, false, Some(0));
-    terrain.center = (
-        -(terrain_size as f32) / 2.,
-        terrain_height,
-        -(terrain_size as f32) / 2.,
-    )
-        .into();
-    terrain.scale = world_scale;
+    // placeholder until the server's terrain params arrive
+    let terrain_params = TerrainParams::default();
+    let terrain_size = terrain_params.size as usize;
+    let mut terrain = terrain_params.generate();
     terrain.init(&mut ctx);
     terrain.register(&mut ctx);
 
     let mut water = generate_water(terrain_size);
-    water.center = terrain.center - (0., terrain_height, 0.).into();
-    water.scale = world_scale;
+    water.center = terrain.center - (0., terrain_params.height, 0.).into();
+    water.scale = terrain.scale;
     water.register(&mut ctx);
 
     /*
@@ -590,12 +928,25 @@ async fn main() {
     camera_uniforms.push(&mut ctx, &handles.camera_uniforms);
     reflected_camera_uniforms.push(&mut ctx, &handles.reflected_camera_uniforms);
 
+    let chat = ChatPanel::new(&mut ctx);
+
     let mut my_game = State {
         frame: 0,
         entity_manager,
         mouse_down: false,
         network_client,
         network_queue: vec![],
+        terrain_stream: None,
+        snapshots: SnapshotHistory::new(),
+        timeline: TickTimeline::new(),
+        player_id: None,
+        prediction: None,
+        player_moving: false,
+        selection: Selection::new(),
+        modifiers: ModifiersState::empty(),
+        clock: std::time::Instant::now(),
+        interpolation_delay: config.interpolation_delay as f64,
+        chat,
         fps: 0.,
         debug: false,
         camera_uniforms,
@@ -0,0 +1,191 @@
+use atlas::entity::player::Player;
+use atlas::message::{PlayerAck, PlayerInput};
+use atlas::physics::Shape;
+use pantheon::math::Vec3;
+use std::collections::VecDeque;
+
+/// the oldest inputs are dropped past this, an ack that far behind means a resync is coming
+pub const MAX_PENDING_INPUTS: usize = 256;
+/// roughly how long a correction from the server takes to fade out
+pub const CORRECTION_SECS: f32 = 0.1;
+/// corrections bigger than this are snapped to rather than smoothed over
+pub const SNAP_DISTANCE: f32 = 5.;
+
+/// Runs our own player ahead of the server. Inputs are applied the moment they're made and kept
+/// until the server acks them, every ack resets the player to the server's state and replays
+/// whatever hasn't been acked yet on top.
+pub struct Prediction<'a> {
+    player: Player<'a>,
+    pending: VecDeque<PlayerInput>,
+    next_sequence: u32,
+    /// how far off the drawn player is from the predicted one, shrinks back to zero so
+    /// corrections don't pop
+    correction: Vec3,
+}
+
+impl<'a> Prediction<'a> {
+    /// starts predicting from the player as the server last sent it
+    pub fn new(player: Player<'a>) -> Self {
+        Self {
+            player,
+            pending: VecDeque::new(),
+            next_sequence: 1,
+            correction: Vec3::new_from_one(0),
+        }
+    }
+
+    /// Tags the input with the next sequence number, replacing whatever it had, and applies it
+    /// straight away. The returned input is what should be sent to the server.
+    pub fn push_input(
+        &mut self,
+        mut input: PlayerInput,
+        ground_height: impl Fn(f32, f32) -> Option<f32>,
+        obstacles: &[Shape],
+    ) -> PlayerInput {
+        input.sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        self.player.apply_input(&input, ground_height, obstacles);
+        if self.pending.len() >= MAX_PENDING_INPUTS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(input);
+
+        input
+    }
+
+    pub fn reconcile(
+        &mut self,
+        ack: &PlayerAck,
+        ground_height: impl Fn(f32, f32) -> Option<f32>,
+        obstacles: &[Shape],
+    ) {
+        while let Some(input) = self.pending.front() {
+            if input.sequence > ack.sequence {
+                break;
+            }
+            self.pending.pop_front();
+        }
+
+        let predicted = self.position();
+        self.player.cube.position = ack.position;
+        self.player.velocity = ack.velocity;
+        for input in self.pending.iter() {
+            self.player.apply_input(input, &ground_height, obstacles);
+        }
+
+        // keep drawing where we were and ease over to the new prediction from there
+        self.correction = predicted - self.player.position();
+        if self.correction.magnitude() > SNAP_DISTANCE {
+            self.correction = Vec3::new_from_one(0);
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.correction *= (-delta_time / CORRECTION_SECS).exp();
+    }
+
+    /// where the player should be drawn
+    pub fn position(&self) -> Vec3 {
+        self.player.position() + self.correction
+    }
+
+    pub fn player(&self) -> &Player<'a> {
+        &self.player
+    }
+
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atlas::Color;
+
+    fn ground(_x: f32, _z: f32) -> Option<f32> {
+        Some(-100.)
+    }
+
+    fn walk() -> PlayerInput {
+        PlayerInput {
+            sequence: 0,
+            velocity: Vec3::new(1, 0, 0),
+            yaw: 0.,
+            pitch: 0.,
+            dt: 0.1,
+            jump: 0,
+        }
+    }
+
+    fn prediction() -> Prediction<'static> {
+        Prediction::new(Player::new(Vec3::new(0, 0, 0), Color::new(255, 0, 0)))
+    }
+
+    #[test]
+    fn replays_unacked_inputs() {
+        let mut prediction = prediction();
+        let mut server = Player::new(Vec3::new(0, 0, 0), Color::new(255, 0, 0));
+
+        let inputs: Vec<PlayerInput> = (0..3)
+            .map(|_| prediction.push_input(walk(), ground, &[]))
+            .collect();
+        assert_eq!(inputs[2].sequence, 3);
+        assert!((prediction.position().x - 0.3).abs() < 1e-5);
+
+        // the server agrees with the first input, nothing should move
+        server.apply_input(&inputs[0], ground, &[]);
+        prediction.reconcile(
+            &PlayerAck {
+                sequence: inputs[0].sequence,
+                position: server.position(),
+                velocity: server.velocity,
+            },
+            ground,
+            &[],
+        );
+        assert_eq!(prediction.pending_len(), 2);
+        assert!((prediction.position().x - 0.3).abs() < 1e-5);
+    }
+
+    #[test]
+    fn corrections_are_smoothed() {
+        let mut prediction = prediction();
+        let input = prediction.push_input(walk(), ground, &[]);
+        prediction.push_input(walk(), ground, &[]);
+
+        // the server pushed the player a bit to the side
+        prediction.reconcile(
+            &PlayerAck {
+                sequence: input.sequence,
+                position: Vec3::new(0.1, 0, 1),
+                velocity: Vec3::new(1, 0, 0),
+            },
+            ground,
+            &[],
+        );
+        let target = prediction.player().position();
+        assert!((target.z - 1.).abs() < 1e-5);
+        // still drawn where it was
+        assert!(prediction.position().z.abs() < 1e-5);
+
+        for _ in 0..60 {
+            prediction.update(1. / 60.);
+        }
+        assert!((prediction.position() - target).magnitude() < 1e-3);
+
+        // anything big is snapped to
+        prediction.reconcile(
+            &PlayerAck {
+                sequence: input.sequence + 1,
+                position: Vec3::new(50, 0, 0),
+                velocity: Vec3::new_from_one(0),
+            },
+            ground,
+            &[],
+        );
+        assert_eq!(prediction.pending_len(), 0);
+        assert_eq!(prediction.position(), Vec3::new(50, 0, 0));
+    }
+}
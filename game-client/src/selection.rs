@@ -0,0 +1,542 @@
+use crate::entity_manager::EntityManager;
+use atlas::command::{Command, EditRequest};
+use atlas::ecs::components::Transform;
+use atlas::rendering;
+use atlas::snapshot::EntityId;
+use atlas::vertex::BasicVertex;
+use atlas::Color;
+use pantheon::context::Context;
+use pantheon::graphics::prelude::*;
+use pantheon::math::{Mat4, Quaternion, Vec3};
+
+/// gizmos are drawn this much of their distance from the camera across, so they stay the same
+/// size on screen
+const GIZMO_SCALE: f32 = 0.15;
+/// how close the cursor has to pass to a handle to grab it, relative to the gizmo's size
+const HANDLE_TOLERANCE: f32 = 0.08;
+const RING_SEGMENTS: usize = 48;
+
+const AXIS_COLORS: [(u8, u8, u8); 3] = [(230, 40, 40), (40, 200, 40), (40, 90, 230)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+}
+
+/// which axes the gizmo moves things along
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    World,
+    /// those of the last entity to be selected
+    Local,
+}
+
+impl Space {
+    pub fn toggle(self) -> Self {
+        match self {
+            Space::World => Space::Local,
+            Space::Local => Space::World,
+        }
+    }
+}
+
+/// x, y and z turned by `rotation`
+pub fn axes(rotation: Quaternion) -> [Vec3; 3] {
+    [
+        rotation.rotate(&Vec3::new(1, 0, 0)),
+        rotation.rotate(&Vec3::new(0, 1, 0)),
+        rotation.rotate(&Vec3::new(0, 0, 1)),
+    ]
+}
+
+/// Two unit vectors at right angles to `axis` and each other. `u` × `v` is `axis`, so angles
+/// measured from `u` towards `v` turn the same way as `Quaternion::rotation`.
+fn perpendicular(axis: Vec3) -> (Vec3, Vec3) {
+    let other = if axis.x.abs() < 0.9 {
+        Vec3::new(1, 0, 0)
+    } else {
+        Vec3::new(0, 1, 0)
+    };
+    let u = axis.cross(&other).make_unit_vector();
+    let v = axis.cross(&u);
+
+    (u, v)
+}
+
+/// How far along `axis` from `pivot` the point closest to the ray is, and how far the ray
+/// passes from that point. `None` when the ray runs parallel to the axis or only gets closest
+/// behind the camera. `direction` and `axis` are unit vectors.
+pub fn closest_on_axis(
+    origin: Vec3,
+    direction: Vec3,
+    pivot: Vec3,
+    axis: Vec3,
+) -> Option<(f32, f32)> {
+    let offset = origin - pivot;
+    let b = direction.dot(&axis);
+    let d = direction.dot(&offset);
+    let e = axis.dot(&offset);
+    let denominator = 1.0 - b * b;
+    if denominator < 1e-6 {
+        return None;
+    }
+
+    let t = (b * e - d) / denominator;
+    if t < 0.0 {
+        return None;
+    }
+    let s = (e - b * d) / denominator;
+    let distance = ((origin + t * direction) - (pivot + s * axis)).magnitude();
+
+    Some((s, distance))
+}
+
+/// Where the ray crosses the plane through `pivot` facing along `axis`, as an angle around the
+/// axis and a distance from the pivot
+pub fn angle_around(origin: Vec3, direction: Vec3, pivot: Vec3, axis: Vec3) -> Option<(f32, f32)> {
+    let facing = direction.dot(&axis);
+    if facing.abs() < 1e-6 {
+        return None;
+    }
+    let t = (pivot - origin).dot(&axis) / facing;
+    if t < 0.0 {
+        return None;
+    }
+
+    let offset = (origin + t * direction) - pivot;
+    let (u, v) = perpendicular(axis);
+    Some((offset.dot(&v).atan2(offset.dot(&u)), offset.magnitude()))
+}
+
+/// The axis whose handle the ray passes closest to, as long as it's close enough to grab.
+/// Translate handles run from the pivot out to `size`, rotate handles are rings `size` across.
+pub fn pick_handle(
+    mode: GizmoMode,
+    axes: &[Vec3; 3],
+    pivot: Vec3,
+    size: f32,
+    origin: Vec3,
+    direction: Vec3,
+) -> Option<usize> {
+    let tolerance = size * HANDLE_TOLERANCE;
+
+    axes.iter()
+        .enumerate()
+        .filter_map(|(i, axis)| {
+            let miss = match mode {
+                GizmoMode::Translate => {
+                    let (along, miss) = closest_on_axis(origin, direction, pivot, *axis)?;
+                    (0.0..=size).contains(&along).then_some(miss)
+                }
+                GizmoMode::Rotate => {
+                    let (_, radius) = angle_around(origin, direction, pivot, *axis)?;
+                    Some((radius - size).abs())
+                }
+            }?;
+            (miss < tolerance).then_some((i, miss))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+/// A handle being dragged, everything is worked out from where the drag started so the
+/// selection can't drift while the server catches up
+#[derive(Debug, Clone)]
+struct Drag {
+    mode: GizmoMode,
+    axis: Vec3,
+    pivot: Vec3,
+    /// distance along the axis or angle around it the cursor started at
+    start: f32,
+    /// where the cursor was when edits were last sent
+    last: f32,
+    /// the selection as it was when the drag started
+    from: Vec<(EntityId, Vec3, Quaternion)>,
+}
+
+impl Drag {
+    fn cursor(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        match self.mode {
+            GizmoMode::Translate => {
+                closest_on_axis(origin, direction, self.pivot, self.axis).map(|(along, _)| along)
+            }
+            GizmoMode::Rotate => {
+                angle_around(origin, direction, self.pivot, self.axis).map(|(angle, _)| angle)
+            }
+        }
+    }
+
+    /// edits taking the selection from where it started to wherever `cursor` puts it
+    fn edits(&self, cursor: f32) -> Vec<Command> {
+        let amount = cursor - self.start;
+        let mut commands = vec![];
+
+        match self.mode {
+            GizmoMode::Translate => {
+                for (id, position, _) in self.from.iter() {
+                    commands.push(Command::move_to(*id, *position + amount * self.axis));
+                }
+            }
+            GizmoMode::Rotate => {
+                let turn = Quaternion::rotation(amount, self.axis);
+                for (id, position, rotation) in self.from.iter() {
+                    // everything swings around the pivot, which only the others are away from
+                    if *position != self.pivot {
+                        let to = self.pivot + turn.rotate(&(*position - self.pivot));
+                        commands.push(Command::move_to(*id, to));
+                    }
+                    commands.push(Command::rotate_to(*id, turn * *rotation));
+                }
+            }
+        }
+
+        commands
+    }
+}
+
+/// The entities picked for editing and the gizmo used to move them. Nothing is moved locally,
+/// drags are sent to the server as edits and come back with the next snapshots.
+pub struct Selection<'a> {
+    /// in the order they were picked, the gizmo sits on the last one
+    ids: Vec<EntityId>,
+    pub mode: GizmoMode,
+    pub space: Space,
+    drag: Option<Drag>,
+    /// the translate and rotate gizmos, registered the first time either is drawn
+    handles: Option<[DrawCallHandle<'a>; 2]>,
+}
+
+impl<'a> Default for Selection<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Selection<'a> {
+    pub fn new() -> Self {
+        Self {
+            ids: vec![],
+            mode: GizmoMode::Translate,
+            space: Space::World,
+            drag: None,
+            handles: None,
+        }
+    }
+
+    /// where the gizmo is, how it's turned and how big it is, `None` with nothing selected
+    fn gizmo(&self, entity_manager: &EntityManager) -> Option<(Vec3, Quaternion, f32)> {
+        let (position, rotation) = entity_manager.transform(*self.ids.last()?)?;
+        let rotation = match self.space {
+            Space::World => Quaternion::identity(),
+            Space::Local => rotation,
+        };
+        let size = (position - entity_manager.camera.origin).magnitude() * GIZMO_SCALE;
+
+        Some((position, rotation, size))
+    }
+
+    /// A left click. Grabbing one of the gizmo's handles starts a drag and returns the
+    /// `BeginGroup` to send, otherwise whatever is under the cursor is selected instead.
+    /// `additive` clicks add to the selection, or take something back out of it.
+    pub fn press(
+        &mut self,
+        ctx: &mut Context,
+        entity_manager: &mut EntityManager,
+        additive: bool,
+    ) -> Option<EditRequest> {
+        let direction = entity_manager.get_mouse_ray(ctx)?;
+        let origin = entity_manager.camera.origin;
+
+        if let Some((pivot, rotation, size)) = self.gizmo(entity_manager) {
+            let axes = axes(rotation);
+            if let Some(handle) = pick_handle(self.mode, &axes, pivot, size, origin, direction) {
+                let from = self
+                    .ids
+                    .iter()
+                    .filter_map(|id| {
+                        let (position, rotation) = entity_manager.transform(*id)?;
+                        Some((*id, position, rotation))
+                    })
+                    .collect();
+                let mut drag = Drag {
+                    mode: self.mode,
+                    axis: axes[handle],
+                    pivot,
+                    start: 0.,
+                    last: 0.,
+                    from,
+                };
+                drag.start = drag.cursor(origin, direction)?;
+                drag.last = drag.start;
+                self.drag = Some(drag);
+
+                return Some(EditRequest::BeginGroup);
+            }
+        }
+
+        let picked = entity_manager.pick(origin, direction);
+        if !additive {
+            for id in self.ids.drain(..) {
+                if Some(id) != picked {
+                    entity_manager.set_selected(ctx, id, false);
+                }
+            }
+        }
+        if let Some(id) = picked {
+            match self.ids.iter().position(|selected| *selected == id) {
+                Some(index) => {
+                    self.ids.remove(index);
+                    entity_manager.set_selected(ctx, id, false);
+                }
+                None => {
+                    self.ids.push(id);
+                    entity_manager.set_selected(ctx, id, true);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// edits for wherever the cursor has dragged the selection since last time
+    pub fn drag_to(
+        &mut self,
+        ctx: &mut Context,
+        entity_manager: &EntityManager,
+    ) -> Vec<EditRequest> {
+        let drag = match self.drag.as_mut() {
+            Some(drag) => drag,
+            None => return vec![],
+        };
+        let cursor = entity_manager
+            .get_mouse_ray(ctx)
+            .and_then(|direction| drag.cursor(entity_manager.camera.origin, direction));
+
+        match cursor {
+            Some(cursor) if cursor != drag.last => {
+                drag.last = cursor;
+                drag.edits(cursor)
+                    .into_iter()
+                    .map(EditRequest::Apply)
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// ends a drag, returning the `EndGroup` to send if there was one
+    pub fn release(&mut self) -> Option<EditRequest> {
+        self.drag.take().map(|_| EditRequest::EndGroup)
+    }
+
+    /// edits deleting everything selected, undone in one go
+    pub fn delete(&mut self) -> Vec<EditRequest> {
+        if self.ids.is_empty() {
+            return vec![];
+        }
+
+        let mut requests = vec![EditRequest::BeginGroup];
+        requests.extend(
+            self.ids
+                .drain(..)
+                .map(|id| EditRequest::Apply(Command::delete(id))),
+        );
+        requests.push(EditRequest::EndGroup);
+        requests
+    }
+
+    /// forgets about anything which has been despawned
+    pub fn retain(&mut self, entity_manager: &EntityManager) {
+        self.ids.retain(|id| entity_manager.contains(*id));
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context<'a>, entity_manager: &EntityManager) {
+        let handles = match self.handles {
+            Some(handles) => handles,
+            None => {
+                let handles = [
+                    register(ctx, &translate_vertices()),
+                    register(ctx, &rotate_vertices()),
+                ];
+                self.handles = Some(handles);
+                handles
+            }
+        };
+
+        let gizmo = self.gizmo(entity_manager);
+        for (mode, handle) in [GizmoMode::Translate, GizmoMode::Rotate]
+            .iter()
+            .zip(handles.iter())
+        {
+            match gizmo {
+                Some((position, rotation, size)) if *mode == self.mode => {
+                    let matrix = Transform {
+                        position,
+                        rotation,
+                        scale: Vec3::new_from_one(size),
+                    }
+                    .matrix();
+                    handle.set_push_constant_data(ctx, &[matrix]);
+                    rendering::show(ctx, handle);
+                }
+                _ => rendering::hide(ctx, handle),
+            }
+        }
+    }
+}
+
+fn register<'a>(ctx: &mut Context<'a>, vertices: &[BasicVertex]) -> DrawCallHandle<'a> {
+    rendering::register(
+        ctx,
+        &["shaded"],
+        "basic",
+        Topology::LineList(PolygonMode::Fill),
+        vertices,
+        0..0,
+        Some(PushConstant::vertex_data(0, &[Mat4::identity()])),
+        None,
+    )
+}
+
+/// a line out along each axis, one unit long
+fn translate_vertices() -> Vec<BasicVertex> {
+    let mut vertices = vec![];
+    for (axis, color) in axes(Quaternion::identity()).iter().zip(AXIS_COLORS.iter()) {
+        let color = Color::new(color.0, color.1, color.2);
+        vertices.push((Vec3::new_from_one(0), color).into());
+        vertices.push((*axis, color).into());
+    }
+    vertices
+}
+
+/// a ring around each axis, one unit out
+fn rotate_vertices() -> Vec<BasicVertex> {
+    let mut vertices = vec![];
+    for (axis, color) in axes(Quaternion::identity()).iter().zip(AXIS_COLORS.iter()) {
+        let color = Color::new(color.0, color.1, color.2);
+        let (u, v) = perpendicular(*axis);
+        let point = |i: usize| {
+            let angle = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+            angle.cos() * u + angle.sin() * v
+        };
+        for i in 0..RING_SEGMENTS {
+            vertices.push((point(i), color).into());
+            vertices.push((point(i + 1), color).into());
+        }
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).magnitude() < 1e-4
+    }
+
+    #[test]
+    fn dragging_along_an_axis() {
+        // looking straight down from above the x axis
+        let origin = Vec3::new(3, 10, 0);
+        let down = Vec3::new(0, -1, 0);
+        let (along, miss) =
+            closest_on_axis(origin, down, Vec3::new(1, 0, 0), Vec3::new(1, 0, 0)).unwrap();
+        assert!((along - 2.).abs() < 1e-5);
+        assert!(miss.abs() < 1e-5);
+        assert!(closest_on_axis(origin, down, Vec3::new_from_one(0), Vec3::new(0, 1, 0)).is_none());
+
+        let axes = axes(Quaternion::identity());
+        let pivot = Vec3::new(2, 0, 0);
+        assert_eq!(
+            pick_handle(GizmoMode::Translate, &axes, pivot, 2., origin, down),
+            Some(0)
+        );
+        // past the end of the handle
+        assert_eq!(
+            pick_handle(GizmoMode::Translate, &axes, pivot, 0.5, origin, down),
+            None
+        );
+
+        let drag = Drag {
+            mode: GizmoMode::Translate,
+            axis: axes[0],
+            pivot,
+            start: 1.,
+            last: 1.,
+            from: vec![(
+                EntityId::new(0, 0),
+                Vec3::new(2, 0, 0),
+                Quaternion::identity(),
+            )],
+        };
+        match drag.edits(4.)[..] {
+            [Command::Move { to, .. }] => assert!(close(to, Vec3::new(5, 0, 0))),
+            ref other => panic!("unexpected edits {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rotating_around_the_pivot() {
+        // looking down at the ring around y, the angle is measured the same way a rotation
+        // around y turns things
+        let up = Vec3::new(0, 1, 0);
+        let down = Vec3::new(0, -1, 0);
+        let (u, _) = perpendicular(up);
+        let turned = Quaternion::rotation(1., up).rotate(&(3. * u));
+        let (angle, radius) = angle_around(
+            turned + Vec3::new(0, 10, 0),
+            down,
+            Vec3::new_from_one(0),
+            up,
+        )
+        .unwrap();
+        assert!((angle - 1.).abs() < 1e-4);
+        assert!((radius - 3.).abs() < 1e-4);
+        assert_eq!(
+            pick_handle(
+                GizmoMode::Rotate,
+                &axes(Quaternion::identity()),
+                Vec3::new_from_one(0),
+                3.,
+                turned + Vec3::new(0, 10, 0),
+                down
+            ),
+            Some(1)
+        );
+
+        let drag = Drag {
+            mode: GizmoMode::Rotate,
+            axis: up,
+            pivot: Vec3::new_from_one(0),
+            start: 0.,
+            last: 0.,
+            from: vec![
+                (
+                    EntityId::new(0, 0),
+                    Vec3::new_from_one(0),
+                    Quaternion::identity(),
+                ),
+                (
+                    EntityId::new(1, 0),
+                    Vec3::new(0, 0, 2),
+                    Quaternion::identity(),
+                ),
+            ],
+        };
+        let edits = drag.edits(std::f32::consts::FRAC_PI_2);
+        // the pivot only turns
+        assert_eq!(edits.len(), 3);
+        match edits[1] {
+            Command::Move { to, .. } => assert!(close(to, Vec3::new(2, 0, 0))),
+            other => panic!("unexpected edit {:?}", other),
+        }
+        match edits[2] {
+            Command::Rotate { to, .. } => {
+                assert!(close(to.rotate(&Vec3::new(1, 0, 0)), Vec3::new(0, 0, -1)))
+            }
+            other => panic!("unexpected edit {:?}", other),
+        }
+    }
+}
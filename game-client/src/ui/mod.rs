@@ -1,3 +1,5 @@
+pub mod font;
+
 use atlas::rendering;
 use atlas::vertex::BasicTexturedVertex;
 use pantheon::graphics::prelude::*;
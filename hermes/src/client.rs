@@ -1,5 +1,6 @@
 use crate::connection::Connection;
 use crate::message::{Message, Messageable};
+use crate::server::{LocalConnector, LOCAL_SERVER_ADDR};
 use crate::AddressedMessageQueue;
 use crate::Command;
 use parking_lot::Mutex;
@@ -29,7 +30,7 @@ impl<T: Messageable> ClientInterface<T> {
             while let Some(cmd) = cmd_rx.recv().await {
                 match cmd {
                     Command::Connect { addr, resp } => {
-                        println!("trying to connect to {}", addr);
+                        tracing::info!(%addr, "connecting");
                         let res = connection.connect_to_server(&addr).await;
                         if res.is_ok() {
                             connection.start_read_loop();
@@ -37,6 +38,12 @@ impl<T: Messageable> ClientInterface<T> {
                         }
                         let _ = resp.send(res);
                     }
+                    Command::ConnectLocal { stream, resp } => {
+                        connection.attach(stream, LOCAL_SERVER_ADDR);
+                        connection.start_read_loop();
+                        connection.start_write_loop();
+                        let _ = resp.send(Ok(()));
+                    }
                     Command::Send { msg, resp } => {
                         connection.send(msg).await;
                         let _ = resp.send(Ok(()));
@@ -75,6 +82,27 @@ impl<T: Messageable> ClientInterface<T> {
         resp_rx.await.expect("client sender dropped")
     }
 
+    /// joins a server in the same process, fails if the server is full
+    pub async fn connect_local(&mut self, connector: &LocalConnector<T>) -> ClientResult<()> {
+        let stream = match connector.connect() {
+            Ok(stream) => stream,
+            Err(e) => return Err(Box::new(e)),
+        };
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        let cmd = Command::ConnectLocal {
+            stream,
+            resp: resp_tx,
+        };
+
+        match self.connection_tx.clone().send(cmd).await {
+            Ok(_) => {}
+            Err(e) => return Err(Box::new(e)),
+        }
+
+        resp_rx.await.expect("client sender dropped")
+    }
+
     pub async fn send(&mut self, msg: Message<T>) -> ClientResult<()> {
         let (resp_tx, resp_rx) = oneshot::channel();
 
@@ -88,6 +116,19 @@ impl<T: Messageable> ClientInterface<T> {
         resp_rx.await.expect("client sender dropped")
     }
 
+    /// non async version of `send` for callers outside of the runtime, like the game loop, this
+    /// doesn't wait for the connection task to pick the message up
+    pub fn try_send(&mut self, msg: Message<T>) -> ClientResult<()> {
+        let (resp_tx, _resp_rx) = oneshot::channel();
+
+        let cmd = Command::Send { msg, resp: resp_tx };
+
+        match self.connection_tx.try_send(cmd) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
     pub fn drain_message_queue(&mut self, out: &mut Vec<(std::net::SocketAddr, Message<T>)>) {
         out.extend(self.messages_in.lock().drain(..));
     }
@@ -3,9 +3,15 @@ use crate::AddressedMessageQueue;
 use parking_lot::Mutex;
 use std::collections::VecDeque;
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::time::{sleep, Duration};
+use tracing::Instrument;
+
+/// Either half of whatever the bytes go over, a `TcpStream` or an in memory pipe for local
+/// connections, messages are framed the same way over both
+type ReadStream = Box<dyn AsyncRead + Send + Unpin>;
+type WriteStream = Box<dyn AsyncWrite + Send + Unpin>;
 
 pub struct Connection<T: Messageable> {
     messages_in: Arc<Mutex<AddressedMessageQueue<T>>>,
@@ -14,8 +20,8 @@ pub struct Connection<T: Messageable> {
     is_connected: Arc<Mutex<bool>>,
     pub peer_addr: Option<std::net::SocketAddr>,
 
-    read_stream: Option<ReadHalf<tokio::net::TcpStream>>,
-    write_stream: Option<WriteHalf<tokio::net::TcpStream>>,
+    read_stream: Option<ReadStream>,
+    write_stream: Option<WriteStream>,
 }
 
 impl<T: Messageable> Connection<T> {
@@ -36,19 +42,11 @@ impl<T: Messageable> Connection<T> {
         messages_in: Arc<Mutex<AddressedMessageQueue<T>>>,
         stream: tokio::net::TcpStream,
     ) -> Self {
-        let messages_out = Arc::new(Mutex::new(VecDeque::new()));
-        let peer_addr = Some(stream.peer_addr().unwrap());
-
-        let (read_stream, write_stream) = tokio::io::split(stream);
+        let peer_addr = stream.peer_addr().unwrap();
+        let mut connection = Self::new(messages_in);
+        connection.attach(stream, peer_addr);
 
-        Self {
-            messages_in,
-            messages_out,
-            peer_addr,
-            is_connected: Arc::new(Mutex::new(true)),
-            write_stream: Some(write_stream),
-            read_stream: Some(read_stream),
-        }
+        connection
     }
 
     pub async fn connect_to_server(
@@ -57,130 +55,122 @@ impl<T: Messageable> Connection<T> {
     ) -> Result<(), Box<dyn std::error::Error + Send>> {
         match TcpStream::connect(addr).await {
             Ok(stream) => {
-                let peer_addr = Some(stream.peer_addr().unwrap());
-                self.peer_addr = peer_addr;
-                let (read_stream, write_stream) = tokio::io::split(stream);
-                self.read_stream = Some(read_stream);
-                self.write_stream = Some(write_stream);
-                *self.is_connected.lock() = true;
+                let peer_addr = stream.peer_addr().unwrap();
+                self.attach(stream, peer_addr);
             }
             Err(e) => return Err(Box::new(e)),
         }
         Ok(())
     }
 
+    /// Hands the connection something to read and write messages over, the loops still have to
+    /// be started. `peer_addr` is what messages read from it are tagged with.
+    pub fn attach<S>(&mut self, stream: S, peer_addr: std::net::SocketAddr)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (read_stream, write_stream) = tokio::io::split(stream);
+        self.peer_addr = Some(peer_addr);
+        self.read_stream = Some(Box::new(read_stream));
+        self.write_stream = Some(Box::new(write_stream));
+        *self.is_connected.lock() = true;
+    }
+
     pub fn start_read_loop(&mut self) {
         if let Some(mut stream) = self.read_stream.take() {
             let messages_in = self.messages_in.clone();
             let is_connected = Arc::clone(&self.is_connected);
             let peer_addr = self.peer_addr.unwrap();
-            tokio::spawn(async move {
-                let mut buf = [0; 1024];
+            let span = tracing::info_span!("connection", peer = %peer_addr);
+            let read_loop = async move {
+                let header_size = std::mem::size_of::<MessageHeader<T>>();
+                let mut header_buf = vec![0; header_size];
 
                 loop {
-                    let byte_count = match stream.read(&mut buf).await {
-                        Ok(n) if n == 0 => return,
-                        Ok(n) => n,
-                        Err(e) => {
-                            eprintln!("[Read Loop] failed to read from socket; err = {:?}", e);
-                            match e.kind() {
-                                std::io::ErrorKind::BrokenPipe => {
-                                    *is_connected.lock() = false;
-                                }
-                                _ => {
-                                    unimplemented!()
-                                }
-                            }
+                    // @NOTE messages are framed by their header, so read exactly one header and
+                    // then exactly the body it describes, otherwise back to back messages can
+                    // end up sharing a single read
+                    if let Err(e) = stream.read_exact(&mut header_buf).await {
+                        Self::handle_read_error(e, &is_connected);
+                        return;
+                    }
 
-                            return;
-                        }
-                    };
-                    //println!("bytes read: {}", byte_count);
-                    let header: MessageHeader<T> = MessageHeader::from(&buf[0..byte_count]);
+                    let header: MessageHeader<T> = MessageHeader::from(&header_buf[..]);
+                    let body_size = (header.size as usize).saturating_sub(header_size);
+                    // @TODO @SPEED, probably shouldn't allocate a vector everytime, may be worth
+                    // keeping a buffer of vectors or messages and reusing them
                     let mut msg = Message {
                         header,
-                        // @TODO @SPEED, probably shouldn't allocate a vector everytime, may be worth
-                        // keeping a buffer of vectors or messages and reusing them
-                        body: Vec::from(&buf[std::mem::size_of::<MessageHeader<T>>()..byte_count]),
+                        body: vec![0; body_size],
                     };
-                    //println!("header size {} | byte_count {}", header.size, byte_count);
-                    if header.size > byte_count as u32 {
-                        let mut running_count = byte_count as u32;
-
-                        while running_count < header.size {
-                            let byte_count = match stream.read(&mut buf).await {
-                                Ok(n) if n == 0 => return,
-                                Ok(n) => n,
-                                Err(e) => {
-                                    eprintln!(
-                                        "[Read Loop] failed to read from socket; err = {:?}",
-                                        e
-                                    );
-                                    match e.kind() {
-                                        std::io::ErrorKind::BrokenPipe => {
-                                            *is_connected.lock() = false;
-                                        }
-                                        _ => {
-                                            unimplemented!()
-                                        }
-                                    }
-                                    return;
-                                }
-                            };
-
-                            msg.body.extend_from_slice(&buf[0..byte_count]);
-                            running_count += byte_count as u32;
+
+                    if body_size > 0 {
+                        if let Err(e) = stream.read_exact(&mut msg.body).await {
+                            Self::handle_read_error(e, &is_connected);
+                            return;
                         }
                     }
 
-                    //println!("Got msg: {:#?}", msg);
+                    tracing::trace!(id = ?msg.header.id, size = msg.header.size, "received");
                     messages_in.lock().push_back((peer_addr, msg));
                 }
-            });
+            };
+            tokio::spawn(read_loop.instrument(span));
         }
     }
 
+    fn handle_read_error(e: std::io::Error, is_connected: &Arc<Mutex<bool>>) {
+        match e.kind() {
+            // the peer closed the connection
+            std::io::ErrorKind::UnexpectedEof => {}
+            _ => tracing::warn!(error = %e, "failed to read from the connection"),
+        }
+        tracing::debug!("disconnected");
+
+        *is_connected.lock() = false;
+    }
+
     pub fn start_write_loop(&mut self) {
         if let Some(mut stream) = self.write_stream.take() {
             let messages_out = Arc::clone(&self.messages_out);
             let is_connected = Arc::clone(&self.is_connected);
             let peer_addr = self.peer_addr.unwrap();
-            tokio::spawn(async move {
+            let span = tracing::info_span!("connection", peer = %peer_addr);
+            let write_loop = async move {
                 loop {
-                    if messages_out.lock().len() > 0 {
-                        let next = {
-                            let mut write = messages_out.lock();
-                            write.pop_front()
+                    // closed from our side, shutting the stream down lets the peer see the
+                    // disconnect which in turn ends the read loop
+                    if !*is_connected.lock() {
+                        let _ = stream.shutdown().await;
+                        return;
+                    }
+
+                    // drain everything that is queued up before going back to sleep, large
+                    // payloads get split into many messages and shouldn't be throttled
+                    loop {
+                        let next = messages_out.lock().pop_front();
+                        let msg = match next {
+                            Some(msg) => msg,
+                            None => break,
                         };
-                        if let Some(msg) = next {
-                            println!("[TO:{:?}] trying to send: {:?}", peer_addr, msg.header);
-                            // @TODO @SPEED, probably shouldn't do an allocate like this should
-                            // consider having buffers on hand ready to be written to
-                            let bytes: Vec<u8> = Vec::from(msg);
-                            //println!("bytes: {:?}", bytes);
-
-                            if let Err(e) = stream.write(&bytes).await {
-                                eprintln!(
-                                    "[Write Loop]failed to write to socket; addr:{:?} err = {:?}",
-                                    peer_addr, e
-                                );
-
-                                match e.kind() {
-                                    std::io::ErrorKind::BrokenPipe => {
-                                        *is_connected.lock() = false;
-                                    }
-                                    _ => {
-                                        unimplemented!()
-                                    }
-                                }
-
-                                return;
-                            }
+
+                        tracing::trace!(id = ?msg.header.id, size = msg.header.size, "sending");
+                        // @TODO @SPEED, probably shouldn't do an allocate like this should
+                        // consider having buffers on hand ready to be written to
+                        let bytes: Vec<u8> = Vec::from(msg);
+                        //println!("bytes: {:?}", bytes);
+
+                        if let Err(e) = stream.write_all(&bytes).await {
+                            tracing::warn!(error = %e, "failed to write to the connection");
+
+                            *is_connected.lock() = false;
+                            return;
                         }
                     }
                     sleep(Duration::from_millis(100)).await;
                 }
-            });
+            };
+            tokio::spawn(write_loop.instrument(span));
         }
     }
 
@@ -188,6 +178,11 @@ impl<T: Messageable> Connection<T> {
         todo!()
     }
 
+    /// the write loop shuts the stream down the next time it wakes up
+    pub fn close(&mut self) {
+        *self.is_connected.lock() = false;
+    }
+
     pub fn is_connected(&self) -> bool {
         *self.is_connected.lock()
     }
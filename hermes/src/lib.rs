@@ -22,6 +22,11 @@ pub enum Command<T: Messageable> {
         addr: String,
         resp: Responder<()>,
     },
+    /// the client's end of a `LocalConnector` connection
+    ConnectLocal {
+        stream: tokio::io::DuplexStream,
+        resp: Responder<()>,
+    },
     Send {
         msg: Message<T>,
         resp: Responder<()>,
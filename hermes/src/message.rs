@@ -93,18 +93,17 @@ impl<T: Messageable> Message<T> {
             // We know the message has enough bytes to pull an instance of V out based on the
             // checks done above
             let data_ptr = self.body.as_ptr().add(new_len);
-            let byte_slice: &[u8] = std::slice::from_raw_parts(data_ptr, bytes);
 
             // SAFETY:
-            // We know that the slice of bytes has the proper number of bytes to transmute into an
-            // instance of `V` due to the above code deriving the values based on the `size_of` calls
-            // on `V`
+            // We know that there are enough bytes after the pointer to read an instance of `V` due
+            // to the above code deriving the values based on the `size_of` calls on `V`, the body
+            // makes no alignment guarantees so the read has to be unaligned
             //
             // Caveat:
             // This call will reinterpet the bytes as an instance of `V`, there is currently not
             // parity check on the result of this reinterpetation, the burden of doing some
             // validation is currently on the caller
-            std::mem::transmute_copy(&byte_slice[0])
+            std::ptr::read_unaligned(data_ptr as *const V)
         };
 
         self.body.resize(new_len, 0);
@@ -150,7 +149,8 @@ impl<T: Messageable> From<&[u8]> for Message<T> {
             panic!("no, this is not header");
         }
 
-        let header: MessageHeader<T> = unsafe { std::mem::transmute_copy(&bytes[0]) };
+        let header: MessageHeader<T> =
+            unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const MessageHeader<T>) };
 
         if header.size != bytes_len as u32 {
             panic!(
@@ -175,7 +175,7 @@ impl<T: Messageable> From<&[u8]> for MessageHeader<T> {
             panic!("no, this is not header");
         }
 
-        unsafe { std::mem::transmute_copy(&bytes[0]) }
+        unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const MessageHeader<T>) }
     }
 }
 
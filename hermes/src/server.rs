@@ -3,34 +3,99 @@ use crate::message::{Message, Messageable};
 use crate::AddressedMessageQueue;
 use parking_lot::Mutex;
 use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU16, Ordering};
 use std::sync::Arc;
+use tokio::io::DuplexStream;
 use tokio::net::TcpListener;
 
+/// bytes either side of a local connection can have in flight before writes wait on the reader
+const LOCAL_BUFFER_SIZE: usize = 64 * 1024;
+
+/// What local clients see as the server's address
+pub const LOCAL_SERVER_ADDR: std::net::SocketAddr =
+    std::net::SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED), 0);
+
+type Connections<T> = Arc<Mutex<HashMap<std::net::SocketAddr, Connection<T>>>>;
+
 pub struct ServerInterface<T: Messageable> {
-    port: u16,
+    /// `None` for servers only reachable through a `LocalConnector`
+    addr: Option<std::net::SocketAddr>,
+    /// connections past this are closed as soon as they're accepted
+    max_connections: Option<usize>,
     messages_in: Arc<Mutex<AddressedMessageQueue<T>>>,
-    connections: Arc<Mutex<HashMap<std::net::SocketAddr, Connection<T>>>>,
+    connections: Connections<T>,
+    next_local_port: Arc<AtomicU16>,
 }
 
 impl<T: Messageable> ServerInterface<T> {
     pub fn new(port: u16) -> Self {
+        Self::bind(std::net::SocketAddr::from(([0, 0, 0, 0], port)))
+    }
+
+    pub fn bind(addr: std::net::SocketAddr) -> Self {
+        Self {
+            addr: Some(addr),
+            ..Self::local()
+        }
+    }
+
+    /// A server which never listens on a socket, clients in the same process join it through
+    /// `local_connector` instead
+    pub fn local() -> Self {
         Self {
-            port,
+            addr: None,
+            max_connections: None,
             messages_in: Arc::new(Mutex::new(VecDeque::new())),
             connections: Arc::new(Mutex::new(HashMap::new())),
+            next_local_port: Arc::new(AtomicU16::new(1)),
         }
     }
 
-    pub async fn start(&mut self) {
-        self.listen_for_connections();
+    pub fn set_max_connections(&mut self, max_connections: usize) {
+        self.max_connections = Some(max_connections);
+    }
+
+    /// binds the listener up front so a bad or taken address is reported to the caller, local
+    /// servers have nothing to bind
+    pub async fn start(&mut self) -> std::io::Result<()> {
+        let addr = match self.addr {
+            Some(addr) => addr,
+            None => {
+                tracing::info!("starting, only reachable locally");
+                return Ok(());
+            }
+        };
+
+        let listener = TcpListener::bind(addr).await?;
+        tracing::info!(%addr, "starting");
+        self.listen_for_connections(listener);
+
+        Ok(())
+    }
+
+    /// Lets clients in the same process connect without going through a socket. Works whether
+    /// or not the server also listens on one, and counts towards `max_connections` as it stands
+    /// when this is called.
+    pub fn local_connector(&self) -> LocalConnector<T> {
+        LocalConnector {
+            max_connections: self.max_connections,
+            messages_in: self.messages_in.clone(),
+            connections: self.connections.clone(),
+            next_port: self.next_local_port.clone(),
+        }
     }
 
-    pub async fn update(&mut self) {
-        /* @TODO drop dead connections
-        self.connections
-            .lock()
-            .retain(|connection| connection.is_connected());
-        */
+    /// drops dead connections, returning the addresses of the clients which went away
+    pub async fn update(&mut self) -> Vec<std::net::SocketAddr> {
+        let mut dropped = vec![];
+        self.connections.lock().retain(|addr, connection| {
+            if !connection.is_connected() {
+                dropped.push(*addr);
+            }
+            connection.is_connected()
+        });
+
+        dropped
     }
 
     pub async fn send_to_all(&mut self, msg: Message<T>) {
@@ -45,6 +110,17 @@ impl<T: Messageable> ServerInterface<T> {
         }
     }
 
+    /// closes the connection to `client_id`, returns false if there was no such client
+    pub fn kick(&mut self, client_id: std::net::SocketAddr) -> bool {
+        match self.connections.lock().remove(&client_id) {
+            Some(mut connection) => {
+                connection.close();
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn stop(&mut self) {
         todo!()
     }
@@ -53,30 +129,34 @@ impl<T: Messageable> ServerInterface<T> {
         self.connections.lock().len()
     }
 
+    pub fn client_addrs(&self) -> Vec<std::net::SocketAddr> {
+        self.connections.lock().keys().copied().collect()
+    }
+
     pub fn pop_message(&mut self) -> Option<(std::net::SocketAddr, Message<T>)> {
         self.messages_in.lock().pop_front()
     }
 
-    fn listen_for_connections(&self) {
-        let port = self.port;
+    fn listen_for_connections(&self, listener: TcpListener) {
+        let max_connections = self.max_connections;
         let connections = self.connections.clone();
         let messages_in = self.messages_in.clone();
 
         tokio::spawn(async move {
-            let addr = format!("0.0.0.0:{}", port);
-            println!("[Server] starting on {}", addr);
-            let listener = match TcpListener::bind(addr).await {
-                Ok(listener) => listener,
-                Err(_) => unimplemented!(),
-            };
-
             loop {
                 let (socket, _) = match listener.accept().await {
                     Ok(accept) => accept,
                     Err(_) => unimplemented!(),
                 };
 
-                println!("[Server] new client on {:#?}", socket.peer_addr().unwrap());
+                if let Some(max_connections) = max_connections {
+                    if connections.lock().len() >= max_connections {
+                        tracing::warn!(peer = ?socket.peer_addr(), "full, turning a client away");
+                        continue;
+                    }
+                }
+
+                tracing::info!(peer = ?socket.peer_addr(), "new client");
                 let mut connection = Connection::from_stream(messages_in.clone(), socket);
                 //connection.ping().await;
                 connection.start_read_loop();
@@ -87,3 +167,132 @@ impl<T: Messageable> ServerInterface<T> {
         });
     }
 }
+
+/// Opens connections to a `ServerInterface` over an in memory pipe, the messages are framed
+/// exactly like they are over TCP. Cheap to clone and can be sent to other threads.
+pub struct LocalConnector<T: Messageable> {
+    max_connections: Option<usize>,
+    messages_in: Arc<Mutex<AddressedMessageQueue<T>>>,
+    connections: Connections<T>,
+    next_port: Arc<AtomicU16>,
+}
+
+impl<T: Messageable> Clone for LocalConnector<T> {
+    fn clone(&self) -> Self {
+        Self {
+            max_connections: self.max_connections,
+            messages_in: self.messages_in.clone(),
+            connections: self.connections.clone(),
+            next_port: self.next_port.clone(),
+        }
+    }
+}
+
+impl<T: Messageable> LocalConnector<T> {
+    /// Adds a connection on the server's side and returns the client's end of it, see
+    /// `ClientInterface::connect_local`. Local clients show up to the server as `0.0.0.0:<n>`
+    /// which no socket can connect from.
+    pub fn connect(&self) -> std::io::Result<DuplexStream> {
+        let mut connections = self.connections.lock();
+        if let Some(max_connections) = self.max_connections {
+            if connections.len() >= max_connections {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionRefused,
+                    "the server is full",
+                ));
+            }
+        }
+
+        let port = self.next_port.fetch_add(1, Ordering::Relaxed);
+        let peer_addr = std::net::SocketAddr::new(LOCAL_SERVER_ADDR.ip(), port);
+        let (client, server) = tokio::io::duplex(LOCAL_BUFFER_SIZE);
+
+        tracing::info!(peer = %peer_addr, "new local client");
+        let mut connection = Connection::new(self.messages_in.clone());
+        connection.attach(server, peer_addr);
+        connection.start_read_loop();
+        connection.start_write_loop();
+        connections.insert(peer_addr, connection);
+
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::ClientInterface;
+    use tokio::time::{sleep, Duration, Instant};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum TestMsg {
+        Hello,
+        Reply,
+    }
+
+    impl Messageable for TestMsg {}
+
+    /// the write loops only wake up every so often, give them a while
+    async fn wait_for<V>(mut poll: impl FnMut() -> Option<V>) -> Option<V> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if let Some(out) = poll() {
+                return Some(out);
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        None
+    }
+
+    #[tokio::test]
+    async fn local_round_trip() {
+        let mut server: ServerInterface<TestMsg> = ServerInterface::local();
+        server.set_max_connections(1);
+        server.start().await.unwrap();
+        let connector = server.local_connector();
+
+        let mut client = ClientInterface::new();
+        client.connect_local(&connector).await.unwrap();
+        assert!(client.is_connected().await.unwrap());
+        // a second client doesn't fit
+        assert!(ClientInterface::<TestMsg>::new()
+            .connect_local(&connector)
+            .await
+            .is_err());
+
+        let mut hello = Message::new(TestMsg::Hello);
+        hello.push(7u32);
+        client.send(hello).await.unwrap();
+
+        let (client_id, mut msg) = wait_for(|| server.pop_message()).await.unwrap();
+        assert_eq!(msg.header.id, TestMsg::Hello);
+        assert_eq!(msg.pull::<u32>().unwrap(), 7);
+        assert_eq!(server.client_addrs(), vec![client_id]);
+
+        server
+            .send_to(client_id, Message::new(TestMsg::Reply))
+            .await;
+        let mut inbox = vec![];
+        let (from, msg) = wait_for(|| {
+            client.drain_message_queue(&mut inbox);
+            inbox.pop()
+        })
+        .await
+        .unwrap();
+        assert_eq!(from, LOCAL_SERVER_ADDR);
+        assert_eq!(msg.header.id, TestMsg::Reply);
+
+        // kicking closes the pipe, which the client notices
+        assert!(server.kick(client_id));
+        let mut disconnected = false;
+        for _ in 0..500 {
+            if !client.is_connected().await.unwrap() {
+                disconnected = true;
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert!(disconnected);
+    }
+}
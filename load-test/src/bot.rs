@@ -0,0 +1,222 @@
+use crate::config::Movement;
+use crate::stats::BotEvent;
+use atlas::entity::player::PLAYER_MAX_SPEED;
+use atlas::message::{GameMessage, PlayerAck, PlayerInput};
+use atlas::snapshot::pull_snapshot;
+use hermes::tokio;
+use hermes::tokio::sync::mpsc::UnboundedSender;
+use hermes::{ClientInterface, Message};
+use pantheon::Vec3;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// an in process server may still be generating its world when the bots start up
+const CONNECT_ATTEMPTS: u32 = 50;
+const CONNECT_RETRY: Duration = Duration::from_millis(100);
+/// inputs still waiting on an ack past this are forgotten, they'd never be acked
+const MAX_IN_FLIGHT: usize = 1024;
+/// radians per second bots on `Movement::Circle` turn at
+const CIRCLE_TURN_RATE: f32 = 1.;
+
+/// Where a bot wants to go next
+pub struct Script {
+    movement: Movement,
+    rng: SmallRng,
+    yaw: f32,
+    speed: f32,
+    /// seconds until `Movement::Random` picks a new heading
+    turn_in: f32,
+}
+
+impl Script {
+    pub fn new(movement: Movement, seed: u64) -> Self {
+        Self {
+            movement,
+            rng: SmallRng::seed_from_u64(seed),
+            yaw: 0.,
+            speed: 0.,
+            turn_in: 0.,
+        }
+    }
+
+    /// the velocity and yaw of the next input covering `dt` seconds
+    pub fn next(&mut self, dt: f32) -> (Vec3, f32) {
+        match self.movement {
+            Movement::Random => {
+                self.turn_in -= dt;
+                if self.turn_in <= 0. {
+                    self.yaw = self.rng.gen_range(0. ..std::f32::consts::TAU);
+                    self.speed = self.rng.gen_range(0. ..=PLAYER_MAX_SPEED);
+                    self.turn_in = self.rng.gen_range(0.5..3.);
+                }
+            }
+            Movement::Circle => {
+                self.yaw = (self.yaw + CIRCLE_TURN_RATE * dt) % std::f32::consts::TAU;
+                self.speed = PLAYER_MAX_SPEED;
+            }
+            Movement::Still => self.speed = 0.,
+        }
+
+        let velocity = Vec3::new(self.yaw.cos(), 0, self.yaw.sin()) * self.speed;
+        (velocity, self.yaw)
+    }
+}
+
+pub struct Bot {
+    index: usize,
+    client: ClientInterface<GameMessage>,
+    script: Script,
+    next_sequence: u32,
+    /// sequences sent but not acked yet, and when they went out
+    in_flight: VecDeque<(u32, Instant)>,
+    events: UnboundedSender<BotEvent>,
+}
+
+impl Bot {
+    pub fn new(index: usize, script: Script, events: UnboundedSender<BotEvent>) -> Self {
+        Self {
+            index,
+            client: ClientInterface::new(),
+            script,
+            next_sequence: 1,
+            in_flight: VecDeque::new(),
+            events,
+        }
+    }
+
+    /// Connects, does the same `GetId` and `SyncWorld` handshake as the game client and then
+    /// sends `input_rate` inputs a second until the connection drops
+    pub async fn run(mut self, host: String, port: u16, input_rate: u32) {
+        if !self.connect(&host, port).await {
+            tracing::warn!(bot = self.index, %host, port, "couldn't connect");
+            self.record(BotEvent::ConnectFailed);
+            return;
+        }
+        self.record(BotEvent::Connected);
+
+        for id in [GameMessage::GetId, GameMessage::SyncWorld] {
+            if self.client.send(Message::new(id)).await.is_err() {
+                self.record(BotEvent::Disconnected);
+                return;
+            }
+        }
+
+        let dt = 1. / input_rate as f32;
+        let mut ticker = tokio::time::interval(Duration::from_secs_f32(dt));
+        let mut inbox = vec![];
+        loop {
+            ticker.tick().await;
+            if !self.client.is_connected().await.unwrap_or(false) {
+                tracing::warn!(bot = self.index, "lost its connection");
+                self.record(BotEvent::Disconnected);
+                return;
+            }
+
+            self.client.drain_message_queue(&mut inbox);
+            for (_, msg) in inbox.drain(..) {
+                self.handle_message(msg);
+            }
+
+            let (velocity, yaw) = self.script.next(dt);
+            let input = PlayerInput {
+                sequence: self.next_sequence,
+                velocity,
+                yaw,
+                pitch: 0.,
+                dt,
+                jump: 0,
+            };
+            self.next_sequence += 1;
+
+            let mut msg = Message::new(GameMessage::MovePlayer);
+            msg.push(input);
+            if self.client.try_send(msg).is_ok() {
+                if self.in_flight.len() >= MAX_IN_FLIGHT {
+                    self.in_flight.pop_front();
+                }
+                self.in_flight.push_back((input.sequence, Instant::now()));
+                self.record(BotEvent::InputSent);
+            }
+        }
+    }
+
+    async fn connect(&mut self, host: &str, port: u16) -> bool {
+        for _ in 0..CONNECT_ATTEMPTS {
+            if self.client.connect(host, port).await.is_ok() {
+                return true;
+            }
+            tokio::time::sleep(CONNECT_RETRY).await;
+        }
+
+        false
+    }
+
+    fn handle_message(&mut self, mut msg: Message<GameMessage>) {
+        self.record(BotEvent::Received { bytes: msg.size() });
+
+        match msg.header.id {
+            // acked so the server keeps sending deltas like it would to a real client
+            GameMessage::Snapshot => {
+                if let Ok((header, _)) = pull_snapshot(&mut msg) {
+                    let mut ack = Message::new(GameMessage::AckSnapshot);
+                    ack.push(header.tick);
+                    let _ = self.client.try_send(ack);
+                }
+            }
+            // only the newest input the server got to is acked, the ones before it never will be
+            GameMessage::MovePlayer => {
+                if let Ok(ack) = msg.pull::<PlayerAck>() {
+                    while let Some((sequence, sent)) = self.in_flight.front().copied() {
+                        if sequence > ack.sequence {
+                            break;
+                        }
+                        self.in_flight.pop_front();
+                        if sequence == ack.sequence {
+                            self.record(BotEvent::RoundTrip(sent.elapsed()));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn record(&self, event: BotEvent) {
+        // the main task only stops listening once it's done reporting
+        let _ = self.events.send(event);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scripts_stay_within_max_speed() {
+        for movement in [Movement::Random, Movement::Circle, Movement::Still] {
+            let mut script = Script::new(movement, 3);
+            for _ in 0..500 {
+                let (velocity, yaw) = script.next(0.05);
+                assert!(velocity.magnitude() <= PLAYER_MAX_SPEED + 1e-4);
+                assert!(velocity.y == 0. && yaw.is_finite());
+            }
+        }
+
+        let mut circle = Script::new(Movement::Circle, 0);
+        let (_, first) = circle.next(0.5);
+        let (velocity, second) = circle.next(0.5);
+        assert!((second - first - 0.5 * CIRCLE_TURN_RATE).abs() < 1e-5);
+        assert!((velocity.magnitude() - PLAYER_MAX_SPEED).abs() < 1e-4);
+    }
+
+    #[test]
+    fn same_seed_same_inputs() {
+        let mut a = Script::new(Movement::Random, 42);
+        let mut b = Script::new(Movement::Random, 42);
+        for _ in 0..100 {
+            assert_eq!(a.next(0.1), b.next(0.1));
+        }
+    }
+}
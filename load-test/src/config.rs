@@ -0,0 +1,227 @@
+use atlas::config::{self, ConfigError};
+use serde::Deserialize;
+use server::config::ServerConfig;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+usage: load-test [--config <file.toml|file.ron>] [flags]
+
+flags override whatever the config file sets:
+    --server <host:port>      server to connect to (127.0.0.1:8080)
+    --in-process <bool>       host a fresh server on --server's port instead (false)
+    --bots <n>                headless clients to connect (16)
+    --input-rate <hz>         MovePlayer inputs each bot sends per second (20)
+    --movement <mode>         random, circle or still (random)
+    --seed <n>                seed for random movement, each bot adds its index (0)
+    --duration <secs>         how long to run for, 0 runs until ctrl-c (30)
+    --report <secs>           seconds between progress reports, 0 only reports at the end (5)
+
+reports go to stderr, only warnings and errors are logged unless RUST_LOG says otherwise";
+
+pub const MAX_INPUT_RATE: u32 = 1000;
+
+/// How bots pick the inputs they send
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Movement {
+    /// a new heading and speed every so often
+    Random,
+    /// full speed around a circle
+    Circle,
+    /// inputs which don't go anywhere, only the networking is exercised
+    Still,
+}
+
+impl FromStr for Movement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "random" => Ok(Self::Random),
+            "circle" => Ok(Self::Circle),
+            "still" => Ok(Self::Still),
+            _ => Err("expected one of random, circle or still".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoadTestConfig {
+    pub server: String,
+    pub in_process: bool,
+    pub bots: usize,
+    pub input_rate: u32,
+    pub movement: Movement,
+    pub seed: u64,
+    pub duration_secs: u64,
+    pub report_secs: u64,
+}
+
+impl Default for LoadTestConfig {
+    fn default() -> Self {
+        Self {
+            server: "127.0.0.1:8080".to_string(),
+            in_process: false,
+            bots: 16,
+            input_rate: 20,
+            movement: Movement::Random,
+            seed: 0,
+            duration_secs: 30,
+            report_secs: 5,
+        }
+    }
+}
+
+impl LoadTestConfig {
+    /// defaults, then the `--config` file if there is one, then the rest of the flags
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, ConfigError> {
+        let pairs = config::flag_pairs(args)?;
+
+        let mut out = match pairs.iter().find(|(flag, _)| flag == "--config") {
+            Some((_, path)) => config::load_file(path)?,
+            None => Self::default(),
+        };
+
+        for (flag, value) in pairs.iter() {
+            let (flag, value) = (flag.as_str(), value.as_str());
+            match flag {
+                "--config" => {}
+                "--server" => out.server = value.to_string(),
+                "--in-process" => out.in_process = config::parse_flag(flag, value)?,
+                "--bots" => out.bots = config::parse_flag(flag, value)?,
+                "--input-rate" => out.input_rate = config::parse_flag(flag, value)?,
+                "--movement" => out.movement = config::parse_flag(flag, value)?,
+                "--seed" => out.seed = config::parse_flag(flag, value)?,
+                "--duration" => out.duration_secs = config::parse_flag(flag, value)?,
+                "--report" => out.report_secs = config::parse_flag(flag, value)?,
+                _ => {
+                    return Err(ConfigError::UnknownArgument {
+                        arg: flag.to_string(),
+                    })
+                }
+            }
+        }
+
+        out.validate()?;
+        Ok(out)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.server_host_port()?;
+
+        if self.bots == 0 {
+            return Err(ConfigError::Invalid {
+                field: "bots",
+                reason: "at least one bot has to connect".to_string(),
+            });
+        }
+
+        if self.input_rate == 0 || self.input_rate > MAX_INPUT_RATE {
+            return Err(ConfigError::Invalid {
+                field: "input_rate",
+                reason: format!("{} is outside of 1..={}", self.input_rate, MAX_INPUT_RATE),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn server_host_port(&self) -> Result<(&str, u16), ConfigError> {
+        let invalid = |reason: &str| ConfigError::Invalid {
+            field: "server",
+            reason: format!("{:?} {}", self.server, reason),
+        };
+
+        let (host, port) = self
+            .server
+            .rsplit_once(':')
+            .ok_or_else(|| invalid("should look like host:port"))?;
+        if host.is_empty() {
+            return Err(invalid("is missing a host"));
+        }
+        let port = port
+            .parse()
+            .map_err(|_| invalid("doesn't end in a valid port"))?;
+
+        Ok((host, port))
+    }
+
+    /// The server hosted with `--in-process`, only reachable locally and with room for every
+    /// bot. Autosaving is off so a load test never writes a save.
+    pub fn server_config(&self) -> ServerConfig {
+        // already validated along with the rest of the config
+        let (_, port) = self.server_host_port().unwrap();
+        ServerConfig {
+            bind: SocketAddr::from(([127, 0, 0, 1], port)),
+            max_players: self.bots,
+            autosave_secs: 0,
+            ..ServerConfig::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn flags_override_defaults() -> Result<(), ConfigError> {
+        let config = LoadTestConfig::from_args(args(&[
+            "--server",
+            "localhost:9000",
+            "--in-process",
+            "true",
+            "--bots",
+            "200",
+            "--movement",
+            "Circle",
+        ]))?;
+
+        assert_eq!(config.server_host_port()?, ("localhost", 9000));
+        assert_eq!(config.bots, 200);
+        assert_eq!(config.movement, Movement::Circle);
+        assert_eq!(config.input_rate, 20);
+
+        let server = config.server_config();
+        assert_eq!(server.bind, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(server.max_players, 200);
+        assert_eq!(server.autosave_secs, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        assert!(matches!(
+            LoadTestConfig::from_args(args(&["--bots", "0"])),
+            Err(ConfigError::Invalid { field: "bots", .. })
+        ));
+        assert!(matches!(
+            LoadTestConfig::from_args(args(&["--input-rate", "5000"])),
+            Err(ConfigError::Invalid {
+                field: "input_rate",
+                ..
+            })
+        ));
+        assert!(matches!(
+            LoadTestConfig::from_args(args(&["--server", "8080"])),
+            Err(ConfigError::Invalid {
+                field: "server",
+                ..
+            })
+        ));
+        assert!(matches!(
+            LoadTestConfig::from_args(args(&["--movement", "dance"])),
+            Err(ConfigError::InvalidArgument { .. })
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+use atlas::config::LogLevel;
+use bot::{Bot, Script};
+use config::LoadTestConfig;
+use hermes::tokio;
+use hermes::tokio::sync::mpsc;
+use stats::Stats;
+use std::time::{Duration, Instant};
+
+mod bot;
+mod config;
+mod stats;
+
+#[tokio::main]
+async fn main() {
+    if atlas::config::wants_help(std::env::args()) {
+        println!("{}", config::USAGE);
+        return;
+    }
+
+    let config = match LoadTestConfig::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("[Config] {}", e);
+            std::process::exit(1);
+        }
+    };
+    // every bot connecting would drown out the reports otherwise
+    if let Err(e) = atlas::logging::init(LogLevel::Warn, None) {
+        eprintln!("[Logging] {}", e);
+        std::process::exit(1);
+    }
+    // already validated along with the rest of the config
+    let (host, port) = config.server_host_port().unwrap();
+
+    // polled along with everything else below, with `pending` as its shutdown it only ever
+    // finishes if it fails to start
+    let server_config = config.in_process.then(|| config.server_config());
+    let server = async move {
+        match server_config {
+            Some(server_config) => server::run(server_config, None, std::future::pending()).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(server);
+
+    let (events_tx, mut events) = mpsc::unbounded_channel();
+    for index in 0..config.bots {
+        let script = Script::new(config.movement, config.seed.wrapping_add(index as u64));
+        let bot = Bot::new(index, script, events_tx.clone());
+        tokio::spawn(bot.run(host.to_string(), port, config.input_rate));
+    }
+    // once every bot is gone the channel closes and the run is over
+    drop(events_tx);
+
+    eprintln!(
+        "[LoadTest] {} bots against {}{} sending {}hz {:?} inputs",
+        config.bots,
+        config.server,
+        if config.in_process {
+            " (in process)"
+        } else {
+            ""
+        },
+        config.input_rate,
+        config.movement,
+    );
+
+    let start = Instant::now();
+    let duration = tokio::time::sleep(Duration::from_secs(config.duration_secs));
+    tokio::pin!(duration);
+    let mut report = tokio::time::interval(Duration::from_secs(config.report_secs.max(1)));
+    report.tick().await;
+
+    let mut total = Stats::default();
+    let mut window = Stats::default();
+    let mut window_start = start;
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(event) => window.record(event),
+                None => {
+                    eprintln!("[LoadTest] every bot has disconnected");
+                    break;
+                }
+            },
+            _ = report.tick(), if config.report_secs > 0 => {
+                let connected = total.connects + window.connects
+                    - total.disconnects
+                    - window.disconnects;
+                eprintln!(
+                    "[Report] {:.0}s, {} connected: {}",
+                    start.elapsed().as_secs_f64(),
+                    connected,
+                    window.summary(window_start.elapsed().as_secs_f64()),
+                );
+                total.merge(std::mem::take(&mut window));
+                window_start = Instant::now();
+            }
+            Err(e) = &mut server => {
+                tracing::error!("{}", e);
+                std::process::exit(1);
+            }
+            _ = &mut duration, if config.duration_secs > 0 => break,
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    total.merge(window);
+    eprintln!(
+        "[Report] total over {:.1}s: {}",
+        start.elapsed().as_secs_f64(),
+        total.summary(start.elapsed().as_secs_f64()),
+    );
+}
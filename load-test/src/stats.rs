@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+/// What bots tell the main task about, everything is tallied up there
+#[derive(Debug, Clone, Copy)]
+pub enum BotEvent {
+    Connected,
+    /// gave up trying to connect, a full server turns bots away like this too
+    ConnectFailed,
+    /// a connected bot lost its connection
+    Disconnected,
+    InputSent,
+    Received {
+        bytes: u32,
+    },
+    /// from sending an input to the server acking it
+    RoundTrip(Duration),
+}
+
+/// Counters for some stretch of the run
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    pub connects: u64,
+    pub failed_connects: u64,
+    pub disconnects: u64,
+    pub inputs_sent: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    /// milliseconds
+    pub round_trips: Vec<f64>,
+}
+
+impl Stats {
+    pub fn record(&mut self, event: BotEvent) {
+        match event {
+            BotEvent::Connected => self.connects += 1,
+            BotEvent::ConnectFailed => self.failed_connects += 1,
+            BotEvent::Disconnected => self.disconnects += 1,
+            BotEvent::InputSent => self.inputs_sent += 1,
+            BotEvent::Received { bytes } => {
+                self.messages_received += 1;
+                self.bytes_received += bytes as u64;
+            }
+            BotEvent::RoundTrip(rtt) => self.round_trips.push(rtt.as_secs_f64() * 1000.),
+        }
+    }
+
+    pub fn merge(&mut self, other: Stats) {
+        self.connects += other.connects;
+        self.failed_connects += other.failed_connects;
+        self.disconnects += other.disconnects;
+        self.inputs_sent += other.inputs_sent;
+        self.messages_received += other.messages_received;
+        self.bytes_received += other.bytes_received;
+        self.round_trips.extend(other.round_trips);
+    }
+
+    /// one line covering `secs` seconds worth of counters
+    pub fn summary(&self, secs: f64) -> String {
+        let secs = secs.max(f64::EPSILON);
+        let mut sorted = self.round_trips.clone();
+        sorted.sort_by(f64::total_cmp);
+        let rtt = match (
+            percentile(&sorted, 50.),
+            percentile(&sorted, 90.),
+            percentile(&sorted, 99.),
+            sorted.last(),
+        ) {
+            (Some(p50), Some(p90), Some(p99), Some(max)) => format!(
+                "rtt p50 {:.1}ms p90 {:.1}ms p99 {:.1}ms max {:.1}ms",
+                p50, p90, p99, max
+            ),
+            _ => "no round trips".to_string(),
+        };
+
+        format!(
+            "{:.1} inputs/s out, {:.1} msgs/s ({:.1} KiB/s) in, {}, {} connects, {} failed, {} disconnects",
+            self.inputs_sent as f64 / secs,
+            self.messages_received as f64 / secs,
+            self.bytes_received as f64 / 1024. / secs,
+            rtt,
+            self.connects,
+            self.failed_connects,
+            self.disconnects,
+        )
+    }
+}
+
+/// nearest rank percentile of already sorted `values`
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let rank = (p / 100. * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        assert_eq!(percentile(&[], 50.), None);
+
+        let values: Vec<f64> = (1..=100).map(|i| i as f64).collect();
+        assert_eq!(percentile(&values, 50.), Some(50.));
+        assert_eq!(percentile(&values, 99.), Some(99.));
+        assert_eq!(percentile(&values, 100.), Some(100.));
+        assert_eq!(percentile(&values, 0.), Some(1.));
+        assert_eq!(percentile(&[7.], 90.), Some(7.));
+    }
+
+    #[test]
+    fn tallies_events() {
+        let mut window = Stats::default();
+        window.record(BotEvent::Connected);
+        window.record(BotEvent::Received { bytes: 100 });
+        window.record(BotEvent::Received { bytes: 28 });
+        window.record(BotEvent::RoundTrip(Duration::from_millis(40)));
+
+        let mut total = Stats::default();
+        total.record(BotEvent::Disconnected);
+        total.merge(window);
+
+        assert_eq!(total.connects, 1);
+        assert_eq!(total.disconnects, 1);
+        assert_eq!(total.messages_received, 2);
+        assert_eq!(total.bytes_received, 128);
+        assert_eq!(total.round_trips, vec![40.]);
+        assert!(total.summary(1.).contains("p50 40.0ms"));
+    }
+}
@@ -53,8 +53,8 @@ impl<'a, 'winit> Context<'a> {
         }))
         .unwrap();
 
-        println!("[adapter.backend] {:#?}", adapter.get_info().backend);
-        println!("[adapter.features] {:#?}", adapter.features());
+        tracing::info!(backend = ?adapter.get_info().backend, "picked adapter");
+        tracing::debug!(features = ?adapter.features(), "adapter features");
 
         let mut features = wgpu::Features::empty();
         // @TODO need to wrap this so that non Vulkan/DX12 don't offer multiple pipelines
@@ -78,7 +78,7 @@ impl<'a, 'winit> Context<'a> {
         )) {
             Ok(stuff) => stuff,
             Err(e) => {
-                eprintln!("[request_device] {:#?}", e);
+                tracing::warn!(error = ?e, "falling back to a device without extra features");
                 block_on(adapter.request_device(
                     &wgpu::DeviceDescriptor {
                         label: Some("Fallback Device"),
@@ -90,7 +90,7 @@ impl<'a, 'winit> Context<'a> {
                 .unwrap()
             }
         };
-        println!("[device.features] {:#?}", device.features());
+        tracing::debug!(features = ?device.features(), "device features");
 
         let surface_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
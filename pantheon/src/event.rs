@@ -74,7 +74,7 @@ pub trait EventHandler<'a> {
     /// Called upon a quit event.  If it returns false,
     /// the game does not exit (the quit event is cancelled).
     fn quit_event(&mut self, _ctx: &mut Context<'a>) -> bool {
-        println!("quit_event() callback called, quitting...");
+        tracing::info!("quitting");
         true
     }
 
@@ -187,7 +187,6 @@ where
                     button,
                     ..
                 } => {
-                    println!("input detected");
                     let position = mouse::position(&ctx);
                     match element_state {
                         ElementState::Pressed => {
@@ -212,7 +211,7 @@ where
                 WindowEvent::TouchpadPressure { .. } => {}
 
                 x => {
-                    eprintln!("ignoring window event {:?}", x);
+                    tracing::trace!(event = ?x, "ignoring window event");
                 }
             },
             Event::DeviceEvent { .. } => (),
@@ -231,8 +230,14 @@ where
                     *control_flow = ControlFlow::Exit;
                 } else {
                     ctx.timer_context.tick();
-                    let _ = state.update(&mut ctx);
-                    let _ = state.draw(&mut ctx);
+                    let _frame =
+                        tracing::debug_span!("frame", n = ctx.timer_context.frame_count).entered();
+                    if let Err(e) = state.update(&mut ctx) {
+                        tracing::error!(error = %e, "update failed");
+                    }
+                    if let Err(e) = state.draw(&mut ctx) {
+                        tracing::error!(error = %e, "draw failed");
+                    }
 
                     // CLEAR VALUES
                     ctx.mouse_context.set_last_delta((0.0, 0.0).into());
@@ -632,7 +632,7 @@ impl<'a> RenderWrangler<'a> {
             label,
             entry: push_constant,
         });
-        println!("[add_push_constant] idx: {}, label {}", idx, label);
+        tracing::debug!(idx, label, "added push constant");
         PushConstantHandle {
             label,
             idx,
@@ -797,7 +797,7 @@ impl<'a> RenderWrangler<'a> {
                     );
                 }
 
-                println!("[reload_shaders] {:#?} {:#?}", pass.label, layouts);
+                tracing::debug!(pass = pass.label, ?layouts, "reloading shaders");
 
                 pipeline_ctx.recreate_pipelines(
                     &mut pass.pipelines,
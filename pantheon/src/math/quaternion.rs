@@ -1,14 +1,20 @@
-use super::Vec3;
+use super::{Mat4, Vec3, Vec4};
 
 use std::ops::{Add, Mul, Sub};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Quaternion {
     pub vector: Vec3,
     pub scalar: f32,
 }
 
 impl Quaternion {
+    /// no rotation at all
+    #[inline]
+    pub fn identity() -> Self {
+        Self::from_parts(Vec3::new_from_one(0), 1.0)
+    }
+
     /// returns unit Quaternion
     #[inline]
     pub fn rotation(radians: f32, axis: Vec3) -> Self {
@@ -82,6 +88,78 @@ impl Quaternion {
 
         (1.0 / r.magnitude()) * r
     }
+
+    #[inline]
+    pub fn dot(&self, other: &Self) -> f32 {
+        self.vector.dot(&other.vector) + self.scalar * other.scalar
+    }
+
+    /// `q` and `-q` are the same rotation, blending towards whichever of the two is closer takes
+    /// the short way around
+    #[inline]
+    pub fn closest(&self, to: &Self) -> Self {
+        if self.dot(to) < 0.0 {
+            -1.0 * to
+        } else {
+            *to
+        }
+    }
+
+    /// expects the upper 3x3 of `mat` to be a pure rotation, returns a unit Quaternion
+    /// https://www.euclideanspace.com/maths/geometry/rotations/conversions/matrixToQuaternion/
+    pub fn from_rotation_matrix(mat: &Mat4) -> Self {
+        // column major, so `mat.y.x` is row 0 column 1
+        let (m00, m01, m02) = (mat.x.x, mat.y.x, mat.z.x);
+        let (m10, m11, m12) = (mat.x.y, mat.y.y, mat.z.y);
+        let (m20, m21, m22) = (mat.x.z, mat.y.z, mat.z.z);
+        let trace = m00 + m11 + m22;
+
+        let (x, y, z, w) = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            ((m21 - m12) / s, (m02 - m20) / s, (m10 - m01) / s, 0.25 * s)
+        } else if m00 > m11 && m00 > m22 {
+            let s = (1.0 + m00 - m11 - m22).sqrt() * 2.0;
+            (0.25 * s, (m01 + m10) / s, (m02 + m20) / s, (m21 - m12) / s)
+        } else if m11 > m22 {
+            let s = (1.0 + m11 - m00 - m22).sqrt() * 2.0;
+            ((m01 + m10) / s, 0.25 * s, (m12 + m21) / s, (m02 - m20) / s)
+        } else {
+            let s = (1.0 + m22 - m00 - m11).sqrt() * 2.0;
+            ((m02 + m20) / s, (m12 + m21) / s, 0.25 * s, (m10 - m01) / s)
+        };
+
+        let q = Self::from_parts(Vec3::new(x, y, z), w);
+        (1.0 / q.magnitude()) * q
+    }
+
+    /// expects a unit Quaternion
+    pub fn rotation_matrix(&self) -> Mat4 {
+        let Vec3 { x, y, z } = self.vector;
+        let w = self.scalar;
+
+        // column major
+        Mat4::new(
+            Vec4::new(
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y + w * z),
+                2.0 * (x * z - w * y),
+                0.0,
+            ),
+            Vec4::new(
+                2.0 * (x * y - w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z + w * x),
+                0.0,
+            ),
+            Vec4::new(
+                2.0 * (x * z + w * y),
+                2.0 * (y * z - w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        )
+    }
 }
 
 impl Mul<Quaternion> for f32 {
@@ -155,6 +233,7 @@ impl Sub for Quaternion {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::math::Dim;
 
     #[test]
     fn test_conjugate() {
@@ -262,4 +341,37 @@ mod tests {
         println!("nlerp {:#?}", nlerp);
         assert!(nlerp.approx_eq(&expected_nlerp));
     }
+
+    #[test]
+    fn test_rotation_matrix_round_trip() {
+        let axes: [Vec3; 4] = [
+            (0, 1, 0).into(),
+            (1, 0, 0).into(),
+            (1, 4, 2).into(),
+            (-3.0, 1.0, 0.5).into(),
+        ];
+        for axis in axes {
+            for degrees in [0.0, 30.0, 90.0, 179.0, 200.0, 300.0] {
+                let mat = Mat4::rotation_from_degrees(degrees, axis);
+                let q = Quaternion::from_rotation_matrix(&mat);
+                let expected = Quaternion::rotation_from_degrees(degrees, axis);
+                assert!((q.dot(&expected).abs() - 1.0).abs() < 1e-4);
+
+                let point = Vec3::new(0.3, -2, 5);
+                let by_mat = (mat * Vec4::from_vec3(point)).truncate(Dim::W);
+                let by_q_mat = (q.rotation_matrix() * Vec4::from_vec3(point)).truncate(Dim::W);
+                assert!((by_mat - by_q_mat).magnitude() < 1e-4);
+                assert!((by_mat - q.rotate(&point)).magnitude() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_closest() {
+        let from = Quaternion::rotation_from_degrees(10.0, (0, 1, 0).into());
+        let to = Quaternion::rotation_from_degrees(350.0, (0, 1, 0).into());
+        let halfway = from.nlerp(&from.closest(&to), 0.5);
+        let expected = Quaternion::rotation_from_degrees(0.0, (0, 1, 0).into());
+        assert!((halfway.dot(&expected).abs() - 1.0).abs() < 1e-5);
+    }
 }
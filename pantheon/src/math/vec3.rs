@@ -118,6 +118,12 @@ impl Vec3 {
         }
     }
 
+    /// `t` outside of 0..=1 extrapolates along the line
+    #[inline]
+    pub fn lerp(&self, to: &Self, t: f32) -> Self {
+        *self + t * (*to - *self)
+    }
+
     #[inline]
     pub fn make_unit_vector(&self) -> Self {
         let scalar = 1.0 / self.magnitude();
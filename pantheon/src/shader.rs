@@ -80,7 +80,7 @@ pub fn start_hotloader(dirty_flag: Arc<AtomicBool>, shader_path: std::path::Path
                 if !output.is_dir() {
                     panic!("what");
                 }
-                println!("starting launch sequence");
+                tracing::debug!(path = ?output, "watching shaders");
                 output
             }
         } else {
@@ -96,7 +96,7 @@ pub fn start_hotloader(dirty_flag: Arc<AtomicBool>, shader_path: std::path::Path
             match rx.recv() {
                 Ok(event) => match event {
                     DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => {
-                        println!("[Pantheon][SHADER HOTLOAD] recompiling {:?}", path);
+                        tracing::info!(?path, "recompiling shader");
                         let shader = ShaderData::load(path).unwrap();
                         let compiled = compiler.compile_into_spirv(
                             &shader.src,
@@ -112,18 +112,18 @@ pub fn start_hotloader(dirty_flag: Arc<AtomicBool>, shader_path: std::path::Path
                                 dirty_flag.store(true, Ordering::Release);
                             }
                             Err(e) => {
-                                eprintln!("[Pantheon][SHADER HOTLOAD][ERROR] {:#?}", e);
+                                tracing::error!(error = %e, "failed to compile shader");
                             }
                         }
                     }
                     // nvim triggers this on write
                     DebouncedEvent::NoticeRemove(_) => (),
                     _ => {
-                        println!("[Pantheon][SHADER HOTLOAD] unhandled event: {:?}", event);
+                        tracing::debug!(?event, "unhandled shader watch event");
                     }
                 },
                 Err(e) => {
-                    println!("[Pantheon][SHADER HOTLOAD] watch error: {:?}", e);
+                    tracing::warn!(error = ?e, "shader watch error");
                 }
             }
         }
@@ -0,0 +1,268 @@
+use atlas::config::{self, ConfigError, LogLevel, WorldConfig};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: server [--config <file.toml|file.ron>] [flags]
+
+flags override whatever the config file sets:
+    --bind <addr:port>        address to listen on (0.0.0.0:8080)
+    --tick-rate <hz>          simulation ticks per second (20)
+    --max-players <n>         connections past this are turned away (16)
+    --view-distance <units>   how far around their player clients are sent entities (64)
+    --log-level <level>       error, warn, info, debug or trace (info), RUST_LOG overrides it
+    --log-file <path>         also append logs to this file as JSON lines
+    --seed <n>                terrain seed
+    --terrain-size <n>        terrain size in grid squares
+    --world-scale <scale>     how much the terrain is scaled up by, sent to clients (2.0)
+    --load <path>             save to load on start, a fresh world is generated without one
+    --save <path>             where the world is saved, defaults to --load or world.save
+    --autosave <secs>         seconds between autosaves, 0 turns them off (60)";
+
+pub const MAX_TICK_RATE: u64 = 1000;
+/// enough to see across the largest terrain at the default scale, the cells walked for each
+/// client grow with the square of it
+pub const MAX_VIEW_DISTANCE: f32 = 4096.;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+    pub tick_rate: u64,
+    pub max_players: usize,
+    /// entities further than this from a client's player aren't sent to it, rounded up to a
+    /// whole number of interest cells
+    pub view_distance: f32,
+    pub log_level: LogLevel,
+    /// logs are appended here as JSON on top of going to stdout
+    pub log_file: Option<PathBuf>,
+    pub load: Option<PathBuf>,
+    pub save: Option<PathBuf>,
+    /// 0 turns autosaving off, the world is still saved on shutdown
+    pub autosave_secs: u64,
+    pub world: WorldConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            tick_rate: 20,
+            max_players: 16,
+            view_distance: 64.,
+            log_level: LogLevel::default(),
+            log_file: None,
+            load: None,
+            save: None,
+            autosave_secs: 60,
+            world: WorldConfig::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// defaults, then the `--config` file if there is one, then the rest of the flags
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, ConfigError> {
+        let pairs = config::flag_pairs(args)?;
+
+        let mut out = match pairs.iter().find(|(flag, _)| flag == "--config") {
+            Some((_, path)) => config::load_file(path)?,
+            None => Self::default(),
+        };
+
+        for (flag, value) in pairs.iter() {
+            let (flag, value) = (flag.as_str(), value.as_str());
+            match flag {
+                "--config" => {}
+                "--bind" => out.bind = config::parse_flag(flag, value)?,
+                "--tick-rate" => out.tick_rate = config::parse_flag(flag, value)?,
+                "--max-players" => out.max_players = config::parse_flag(flag, value)?,
+                "--view-distance" => out.view_distance = config::parse_flag(flag, value)?,
+                "--log-level" => out.log_level = config::parse_flag(flag, value)?,
+                "--log-file" => out.log_file = Some(PathBuf::from(value)),
+                "--seed" => out.world.seed = config::parse_flag(flag, value)?,
+                "--terrain-size" => out.world.terrain_size = config::parse_flag(flag, value)?,
+                "--world-scale" => out.world.scale = config::parse_flag(flag, value)?,
+                "--load" => out.load = Some(PathBuf::from(value)),
+                "--save" => out.save = Some(PathBuf::from(value)),
+                "--autosave" => out.autosave_secs = config::parse_flag(flag, value)?,
+                _ => {
+                    return Err(ConfigError::UnknownArgument {
+                        arg: flag.to_string(),
+                    })
+                }
+            }
+        }
+
+        out.validate()?;
+        Ok(out)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.tick_rate == 0 || self.tick_rate > MAX_TICK_RATE {
+            return Err(ConfigError::Invalid {
+                field: "tick_rate",
+                reason: format!("{} is outside of 1..={}", self.tick_rate, MAX_TICK_RATE),
+            });
+        }
+
+        if self.max_players == 0 {
+            return Err(ConfigError::Invalid {
+                field: "max_players",
+                reason: "at least one player has to be able to join".to_string(),
+            });
+        }
+
+        if !self.view_distance.is_finite() || self.view_distance <= 0. {
+            return Err(ConfigError::Invalid {
+                field: "view_distance",
+                reason: format!("{} has to be a positive number", self.view_distance),
+            });
+        }
+        if self.view_distance > MAX_VIEW_DISTANCE {
+            return Err(ConfigError::Invalid {
+                field: "view_distance",
+                reason: format!(
+                    "{} is further than the supported {}",
+                    self.view_distance, MAX_VIEW_DISTANCE
+                ),
+            });
+        }
+
+        self.world.validate()
+    }
+
+    /// the loaded save keeps getting updated unless told otherwise
+    pub fn save_path(&self) -> PathBuf {
+        self.save
+            .clone()
+            .or_else(|| self.load.clone())
+            .unwrap_or_else(|| PathBuf::from("world.save"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn flags_override_defaults() -> Result<(), ConfigError> {
+        let config = ServerConfig::from_args(args(&[
+            "--bind",
+            "127.0.0.1:9000",
+            "--tick-rate",
+            "30",
+            "--seed",
+            "-5",
+            "--world-scale",
+            "1.5",
+            "--log-level",
+            "DEBUG",
+            "--load",
+            "a.save",
+            "--log-file",
+            "server.log",
+        ]))?;
+
+        assert_eq!(config.bind, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(config.tick_rate, 30);
+        assert_eq!(config.world.seed, -5);
+        assert_eq!(config.world.scale, 1.5);
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert_eq!(config.log_file, Some(PathBuf::from("server.log")));
+        assert_eq!(config.max_players, 16);
+        assert_eq!(config.save_path(), PathBuf::from("a.save"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn example_config_parses() -> Result<(), ConfigError> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("server.example.toml");
+        let config: ServerConfig = config::load_file(path)?;
+        config.validate()?;
+        assert_eq!(config.world.palette.len(), 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        let path = Path::new("server.toml");
+        let parse = |text: &str| -> Result<ServerConfig, ConfigError> {
+            let config: ServerConfig = config::ConfigFormat::Toml.parse(text, path)?;
+            config.validate()?;
+            Ok(config)
+        };
+
+        assert!(parse("tick_rate = 20").is_ok());
+        assert!(matches!(
+            parse("tick_rate = 0"),
+            Err(ConfigError::Invalid {
+                field: "tick_rate",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("max_players = 0"),
+            Err(ConfigError::Invalid {
+                field: "max_players",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("view_distance = -1.0"),
+            Err(ConfigError::Invalid {
+                field: "view_distance",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("view_distance = 1e9"),
+            Err(ConfigError::Invalid {
+                field: "view_distance",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("bind = \"localhost\""),
+            Err(ConfigError::Parse { .. })
+        ));
+        assert!(matches!(
+            parse("tickrate = 20"),
+            Err(ConfigError::Parse { .. })
+        ));
+        assert!(matches!(
+            parse("[world]\npalette = [\"red\"]"),
+            Err(ConfigError::Invalid {
+                field: "world.palette",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("[world]\npalette = [\"#ff0000\"]"),
+            Err(ConfigError::Invalid {
+                field: "world.palette",
+                ..
+            })
+        ));
+        assert!(parse("[world]\npalette = []").is_ok());
+        assert!(matches!(
+            ServerConfig::from_args(args(&["--tick-rate", "fast"])),
+            Err(ConfigError::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            ServerConfig::from_args(args(&["--port", "1"])),
+            Err(ConfigError::UnknownArgument { .. })
+        ));
+    }
+}
@@ -0,0 +1,211 @@
+use atlas::entity::primitive::PrimitiveShape;
+use hermes::tokio;
+use hermes::tokio::io::AsyncBufReadExt;
+use hermes::tokio::sync::mpsc;
+use pantheon::Vec3;
+
+pub const HELP: &str = "\
+commands:
+    status                            tick, tick rate, clients, entities and terrain
+    list clients                      connected clients and the last tick they acked
+    kick <index|addr>                 disconnect a client, index is from `list clients`
+    spawn cube <x> <y> <z> <size>     add a cube to the world
+    spawn <shape> <x> <y> <z> <size>  add a sphere, icosphere, cylinder, cone,
+                                      capsule, torus or plane
+    set sun <x> <y> <z>               move the sun
+    regen terrain <seed>              regenerate the terrain and send it to every client
+    save                              save the world now
+    tickrate <hz>                     change the simulation rate
+    help                              show this";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientRef {
+    Index(usize),
+    Addr(std::net::SocketAddr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Status,
+    ListClients,
+    Kick(ClientRef),
+    SpawnCube {
+        position: Vec3,
+        size: f32,
+    },
+    SpawnPrimitive {
+        shape: PrimitiveShape,
+        position: Vec3,
+    },
+    SetSun(Vec3),
+    RegenTerrain {
+        seed: isize,
+    },
+    Save,
+    TickRate(u64),
+    Help,
+}
+
+impl Command {
+    /// positions can be given as `x y z` or `x,y,z`
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        match words.as_slice() {
+            ["status"] => Ok(Self::Status),
+            ["list", "clients"] => Ok(Self::ListClients),
+            ["kick", client] => {
+                if let Ok(index) = client.parse() {
+                    Ok(Self::Kick(ClientRef::Index(index)))
+                } else if let Ok(addr) = client.parse() {
+                    Ok(Self::Kick(ClientRef::Addr(addr)))
+                } else {
+                    Err(format!(
+                        "{:?} is neither a client index nor an address",
+                        client
+                    ))
+                }
+            }
+            ["spawn", "cube", args @ ..] => match numbers(args)?.as_slice() {
+                [x, y, z, size] if *size > 0. => Ok(Self::SpawnCube {
+                    position: Vec3::new(*x, *y, *z),
+                    size: *size,
+                }),
+                [_, _, _, size] => Err(format!("cube size has to be positive, got {}", size)),
+                _ => Err("usage: spawn cube <x> <y> <z> <size>".to_string()),
+            },
+            ["spawn", name, args @ ..] if PrimitiveShape::NAMES.contains(name) => {
+                match numbers(args)?.as_slice() {
+                    [x, y, z, size] if *size > 0. => Ok(Self::SpawnPrimitive {
+                        shape: PrimitiveShape::from_name(name, *size).unwrap(),
+                        position: Vec3::new(*x, *y, *z),
+                    }),
+                    [_, _, _, size] => {
+                        Err(format!("{} size has to be positive, got {}", name, size))
+                    }
+                    _ => Err(format!("usage: spawn {} <x> <y> <z> <size>", name)),
+                }
+            }
+            ["set", "sun", args @ ..] => match numbers(args)?.as_slice() {
+                [x, y, z] => Ok(Self::SetSun(Vec3::new(*x, *y, *z))),
+                _ => Err("usage: set sun <x> <y> <z>".to_string()),
+            },
+            ["regen", "terrain", seed] => seed
+                .parse()
+                .map(|seed| Self::RegenTerrain { seed })
+                .map_err(|_| format!("{:?} is not a valid seed", seed)),
+            ["save"] => Ok(Self::Save),
+            ["tickrate", rate] => rate
+                .parse()
+                .map(Self::TickRate)
+                .map_err(|_| format!("{:?} is not a valid tick rate", rate)),
+            ["help"] => Ok(Self::Help),
+            [] => Err("empty command".to_string()),
+            _ => Err(format!("unknown command {:?}, try `help`", line.trim())),
+        }
+    }
+}
+
+fn numbers(words: &[&str]) -> Result<Vec<f32>, String> {
+    words
+        .iter()
+        .map(|word| match word.parse::<f32>() {
+            Ok(n) if n.is_finite() => Ok(n),
+            _ => Err(format!("{:?} is not a number", word)),
+        })
+        .collect()
+}
+
+/// Reads lines off of stdin on its own task, the server loop drains the receiver every tick so
+/// commands run in between ticks and never race the simulation.
+pub fn spawn_stdin_reader() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    if tx.send(line).is_err() {
+                        return;
+                    }
+                }
+                // stdin closed, e.g. running in the background
+                Ok(None) => return,
+                Err(e) => {
+                    tracing::warn!("failed to read stdin: {:?}", e);
+                    return;
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::parse("status"), Ok(Command::Status));
+        assert_eq!(
+            Command::parse("  list   clients "),
+            Ok(Command::ListClients)
+        );
+        assert_eq!(
+            Command::parse("kick 2"),
+            Ok(Command::Kick(ClientRef::Index(2)))
+        );
+        assert_eq!(
+            Command::parse("kick 127.0.0.1:5000"),
+            Ok(Command::Kick(ClientRef::Addr(
+                "127.0.0.1:5000".parse().unwrap()
+            )))
+        );
+        assert_eq!(
+            Command::parse("spawn cube 1,2,3 4"),
+            Ok(Command::SpawnCube {
+                position: Vec3::new(1, 2, 3),
+                size: 4.
+            })
+        );
+        assert_eq!(
+            Command::parse("spawn torus 0 5 0 2"),
+            Ok(Command::SpawnPrimitive {
+                shape: PrimitiveShape::from_name("torus", 2.).unwrap(),
+                position: Vec3::new(0, 5, 0),
+            })
+        );
+        assert_eq!(
+            Command::parse("set sun 0 20 -5"),
+            Ok(Command::SetSun(Vec3::new(0, 20, -5)))
+        );
+        assert_eq!(
+            Command::parse("regen terrain -12"),
+            Ok(Command::RegenTerrain { seed: -12 })
+        );
+        assert_eq!(Command::parse("tickrate 30"), Ok(Command::TickRate(30)));
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("dance").is_err());
+        assert!(Command::parse("kick someone").is_err());
+        assert!(Command::parse("spawn cube 1 2 3").is_err());
+        assert!(Command::parse("spawn cube 1 2 3 -1").is_err());
+        assert!(Command::parse("spawn sphere 1 2 3 0").is_err());
+        assert!(Command::parse("spawn teapot 1 2 3 1").is_err());
+        assert!(Command::parse("set sun 1 2 nan").is_err());
+        assert!(Command::parse("regen terrain").is_err());
+        assert!(Command::parse("tickrate fast").is_err());
+    }
+}
@@ -0,0 +1,960 @@
+use atlas::chat::{chat_message, pull_chat, ChatChannel, ChatMessage};
+use atlas::command::{pull_edit, Applied, EditRequest, History, Scene};
+use atlas::interact::{pick, InteractRequest, InteractResult, WorldHistory};
+use atlas::interest::{InterestGrid, DEFAULT_CELL_SIZE};
+use atlas::message::{self, GameMessage, PlayerAck, PlayerInput, TerrainMessage};
+use atlas::snapshot::{ClientBaseline, EntityId, Tick};
+use hermes::tokio;
+use hermes::Message;
+use hermes::{LocalConnector, ServerInterface};
+use pantheon::Vec3;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tracing::{debug, debug_span, error, info, warn, Instrument};
+
+use atlas::ecs::components::{Collider, Light, Look, Mesh, Networked, Transform, Velocity};
+use atlas::ecs::replicate::{self, Replicated};
+use atlas::ecs::{hierarchy, systems, World};
+use atlas::entity::player::PLAYER_SIZE;
+use atlas::entity::primitive::PrimitiveShape;
+use atlas::physics::{self, Physics, RigidBody};
+use atlas::vertex::VertexKind;
+use atlas::Color;
+
+use atlas::entity::terrain::Terrain;
+use atlas::proc_gen::terrain::TerrainParams;
+
+use config::ServerConfig;
+use console::{ClientRef, Command};
+use limiter::RateLimiter;
+use save::{BodyRecord, EntityRecord, SaveError, WorldSave};
+
+pub mod config;
+pub mod console;
+mod limiter;
+pub mod save;
+
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error("failed to load {path:?}: {source}")]
+    Load { path: PathBuf, source: SaveError },
+    #[error("failed to listen on {addr}: {source}")]
+    Bind {
+        addr: SocketAddr,
+        source: std::io::Error,
+    },
+}
+
+/// Most simulated time a client can bank up, stops a client which went quiet from sending a
+/// burst of inputs to cover a long distance at once
+const MAX_INPUT_BUDGET: f32 = 0.5;
+
+const PLAYER_COLORS: [(u8, u8, u8); 6] = [
+    (230, 57, 70),
+    (69, 123, 157),
+    (42, 157, 143),
+    (233, 196, 106),
+    (244, 162, 97),
+    (131, 56, 236),
+];
+
+/// players are split between this many teams as they join, for `ChatChannel::Team`
+const TEAM_COUNT: usize = 2;
+
+struct PlayerSlot {
+    entity_id: EntityId,
+    /// seconds of movement the client is still allowed to spend, refilled every tick
+    input_budget: f32,
+    last_sequence: Option<u32>,
+    /// the last `PlayerAck` sent, a new one only goes out once something differs
+    last_ack: Option<PlayerAck>,
+    team: usize,
+    chat: RateLimiter,
+    interact: RateLimiter,
+    edit: RateLimiter,
+    /// on top of `edit`, for anything regenerating the terrain
+    terrain_edit: RateLimiter,
+    /// edits made by this client, which it can undo and redo
+    history: History,
+}
+
+struct ServerState {
+    world: World,
+    id_counter: usize,
+    tick: Tick,
+    tick_rate: u64,
+    /// clients which have asked to be kept in sync with the world
+    baselines: HashMap<SocketAddr, ClientBaseline>,
+    players: HashMap<SocketAddr, PlayerSlot>,
+    /// clients are only sent the entities in cells around their player
+    interest: InterestGrid,
+    /// recent snapshots, for checking interactions against what clients were looking at
+    history: WorldHistory,
+    terrain_params: TerrainParams,
+    terrain: Terrain<'static>,
+    physics: Physics,
+}
+
+impl ServerState {
+    pub fn new(terrain_params: TerrainParams) -> Self {
+        Self {
+            id_counter: 1,
+            tick: 0,
+            tick_rate: ServerConfig::default().tick_rate,
+            baselines: HashMap::new(),
+            players: HashMap::new(),
+            interest: InterestGrid::new(DEFAULT_CELL_SIZE, ServerConfig::default().view_distance),
+            history: WorldHistory::new(),
+            world: World::new(),
+            terrain: terrain_params.generate(),
+            terrain_params,
+            physics: Physics::new(),
+        }
+    }
+
+    pub fn from_save(save: WorldSave) -> Self {
+        let mut world = World::from_ids(save.ids);
+        for (id, record) in save.entities.iter() {
+            record.to_replicated().insert(&mut world, *id);
+            if let Some(body) = save.bodies.get(id) {
+                world.insert(*id, body.to_body());
+            }
+        }
+        // every entity is saved where it is in the world, so they're attached keeping that
+        for (child, parent) in save.parents.iter() {
+            if !hierarchy::reparent(&mut world, *child, Some(*parent)) {
+                warn!(%child, %parent, "couldn't attach saved entity to its parent");
+            }
+        }
+
+        Self {
+            id_counter: save.id_counter,
+            tick: save.tick,
+            world,
+            ..Self::new(save.terrain_params)
+        }
+    }
+
+    pub fn to_save(&self) -> WorldSave {
+        let mut ids = self.world.ids().clone();
+        let mut entities = vec![];
+        let mut bodies = BTreeMap::new();
+        let mut parents = BTreeMap::new();
+        let live = self.world.ids().slots().iter().enumerate();
+        for (index, slot) in live.filter(|(_, slot)| slot.alive) {
+            let id = EntityId::new(index as u32, slot.generation);
+            let record = Replicated::from_world(&self.world, id)
+                .and_then(|entity| EntityRecord::from_replicated(&entity));
+            match record {
+                Some(record) => {
+                    entities.push((id, record));
+                    if let Some(body) = self.world.get::<RigidBody>(id) {
+                        bodies.insert(id, BodyRecord::from_body(body));
+                    }
+                    if let Some(parent) = hierarchy::parent(&self.world, id) {
+                        parents.insert(id, parent);
+                    }
+                }
+                // whatever isn't saved won't be around after a restart, neither is its id
+                None => {
+                    ids.free(id);
+                }
+            }
+        }
+
+        // a parent which wasn't saved leaves its children where they are
+        parents.retain(|_, parent| ids.is_alive(*parent));
+
+        WorldSave {
+            tick: self.tick,
+            id_counter: self.id_counter,
+            ids,
+            terrain_params: self.terrain_params,
+            entities,
+            bodies,
+            parents,
+        }
+    }
+}
+
+fn save_world(state: &ServerState, path: &Path) {
+    match state.to_save().write_to(path) {
+        Ok(_) => info!(tick = state.tick, ?path, "saved world"),
+        Err(e) => error!(?path, "failed to save world: {}", e),
+    }
+}
+
+/// players drop in around the middle of the terrain, standing on it
+fn spawn_player(state: &mut ServerState, client_id: SocketAddr) -> EntityId {
+    let offset = (state.players.len() % 8) as f32 * 2. * PLAYER_SIZE;
+    let (x, z) = (offset, offset);
+    let ground = state.terrain.height_at(x, z).unwrap_or(0.);
+    let position = Vec3::new(x, ground + PLAYER_SIZE / 2., z);
+
+    let (r, g, b) = PLAYER_COLORS[state.world.len() % PLAYER_COLORS.len()];
+    let team = state.players.len() % TEAM_COUNT;
+    let entity_id = state
+        .world
+        .build()
+        .with(Transform::new(position))
+        .with(Mesh::cube(PLAYER_SIZE, Color::new(r, g, b)))
+        .with(Collider::cube(PLAYER_SIZE))
+        .with(Velocity(Vec3::new_from_one(0)))
+        .with(Look::default())
+        .with(Networked)
+        .id();
+    state.players.insert(
+        client_id,
+        PlayerSlot {
+            entity_id,
+            input_budget: 0.,
+            last_sequence: None,
+            last_ack: None,
+            team,
+            chat: RateLimiter::chat(),
+            interact: RateLimiter::interact(),
+            edit: RateLimiter::edit(),
+            terrain_edit: RateLimiter::terrain_edit(),
+            history: History::default(),
+        },
+    );
+    info!(%entity_id, %client_id, team, ?position, "spawned player");
+
+    entity_id
+}
+
+/// forgets everything about a client which left or was kicked
+fn remove_client(state: &mut ServerState, client_id: SocketAddr) {
+    state.baselines.remove(&client_id);
+    if let Some(slot) = state.players.remove(&client_id) {
+        state.world.despawn(slot.entity_id);
+        info!(entity_id = %slot.entity_id, %client_id, "despawned player");
+    }
+}
+
+fn move_player(state: &mut ServerState, client_id: SocketAddr, mut input: PlayerInput) {
+    let slot = match state.players.get_mut(&client_id) {
+        Some(slot) => slot,
+        None => return,
+    };
+
+    // inputs can't be replayed, and one arriving late after a newer one has nothing to add
+    if slot
+        .last_sequence
+        .is_some_and(|last| input.sequence <= last)
+    {
+        return;
+    }
+    slot.last_sequence = Some(input.sequence);
+
+    // clients can't move for longer than time has actually passed on the server
+    if input.dt.is_finite() {
+        input.dt = input.dt.min(slot.input_budget);
+    }
+
+    let terrain = &state.terrain;
+    let moved = systems::move_player(&mut state.world, slot.entity_id, &input, |x, z| {
+        terrain.height_at(x, z)
+    });
+    if let Some(dt) = moved {
+        slot.input_budget -= dt;
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Stamps a chat message from `client_id` with its sender and the time and passes it on to
+/// whoever its channel reaches. Anything rejected is explained back to the sender.
+async fn relay_chat(
+    state: &mut ServerState,
+    server: &mut ServerInterface<GameMessage>,
+    client_id: SocketAddr,
+    mut chat: ChatMessage,
+) {
+    let reject = |reason: String| ChatMessage::system(reason, now_ms());
+    let slot = match state.players.get_mut(&client_id) {
+        Some(slot) => slot,
+        // only players can chat, a client gets one with `SyncWorld`
+        None => return,
+    };
+    let (sender, team) = (slot.entity_id, slot.team);
+
+    let recipients: Result<Vec<SocketAddr>, ChatMessage> = if !slot.chat.try_send() {
+        Err(reject("you're sending messages too quickly".to_string()))
+    } else {
+        match chat.channel {
+            ChatChannel::Global => Ok(state.players.keys().copied().collect()),
+            ChatChannel::Team => Ok(state
+                .players
+                .iter()
+                .filter(|(_, slot)| slot.team == team)
+                .map(|(client, _)| *client)
+                .collect()),
+            ChatChannel::Whisper(target) => state
+                .players
+                .iter()
+                .find(|(_, slot)| slot.entity_id == target)
+                .map(|(client, _)| vec![*client, client_id])
+                .ok_or_else(|| reject(format!("there's no player {} to whisper to", target))),
+            ChatChannel::System => Err(reject(
+                "only the server can send system messages".to_string(),
+            )),
+        }
+    };
+
+    let (chat, recipients) = match recipients {
+        Ok(mut recipients) => {
+            recipients.sort();
+            recipients.dedup();
+            chat.sender = Some(sender);
+            chat.timestamp_ms = now_ms();
+            info!(channel = ?chat.channel, %sender, "{}", chat.text);
+            (chat, recipients)
+        }
+        Err(rejection) => (rejection, vec![client_id]),
+    };
+
+    match chat_message(&chat) {
+        Ok(msg) => {
+            for client in recipients {
+                server.send_to(client, msg.clone()).await;
+            }
+        }
+        Err(e) => warn!(text = ?chat.text, "couldn't send chat: {}", e),
+    }
+}
+
+/// Picks against the world rewound to when the client clicked, so what they hit is what they saw
+/// under their cursor however far behind the server their view was. The ray has to start from
+/// around the clicking player's eyes as of then too. Everyone hears about it.
+async fn interact(
+    state: &mut ServerState,
+    server: &mut ServerInterface<GameMessage>,
+    client_id: SocketAddr,
+    request: InteractRequest,
+) {
+    let slot = match state.players.get_mut(&client_id) {
+        Some(slot) => slot,
+        None => return,
+    };
+    let player = slot.entity_id;
+    if !request.is_valid() {
+        warn!(%client_id, ?request, "rejected interaction");
+        return;
+    }
+    // every one clones the rewound world and goes out to everyone
+    if !slot.interact.try_send() {
+        debug!(%client_id, "interacting too quickly");
+        return;
+    }
+
+    let (tick, entities) = match state.history.rewind(request.view_tick, request.view_alpha) {
+        Some(rewound) => rewound,
+        None => return,
+    };
+    let reaches = entities
+        .get(&player)
+        .is_some_and(|rewound| request.reaches_from(rewound.transform.position));
+    if !reaches {
+        warn!(%client_id, ?request, tick, "interaction from too far away");
+        return;
+    }
+    let hit = pick(&entities, request.origin, request.direction, Some(player));
+    debug!(%client_id, tick, rewound = state.tick - tick, ?hit, "interaction");
+
+    let mut msg = Message::new(GameMessage::Interact);
+    msg.push(InteractResult { player, tick, hit });
+    server.send_to_all(msg).await;
+}
+
+/// a plain cube in the same grey `Cuboid::cube` defaults to, which falls until it lands on
+/// something
+fn spawn_cube(world: &mut World, position: Vec3, size: f32) -> EntityId {
+    world
+        .build()
+        .with(Transform::new(position))
+        .with(Mesh::cube(size, Color::new(60, 60, 60)))
+        .with(Collider::cube(size))
+        .with(Velocity(Vec3::new_from_one(0)))
+        .with(RigidBody::new(1.))
+        .with(Networked)
+        .id()
+}
+
+/// Primitives stay where they're put, only cubes get a `RigidBody`
+fn spawn_primitive(world: &mut World, position: Vec3, shape: PrimitiveShape) -> EntityId {
+    world
+        .build()
+        .with(Transform::new(position))
+        .with(Mesh::primitive(shape, Color::new(60, 60, 60)))
+        .with(shape.collider())
+        .with(Networked)
+        .id()
+}
+
+/// Regenerates the terrain from `terrain_params` after they've changed and has every client
+/// do the same
+async fn regenerate_terrain(state: &mut ServerState, server: &mut ServerInterface<GameMessage>) {
+    state.terrain = state.terrain_params.generate();
+    // whatever was resting on the old ground has to find the new ground
+    physics::wake_all(&mut state.world);
+
+    // an input that goes nowhere still puts players back on top of the new ground
+    let terrain = &state.terrain;
+    for slot in state.players.values() {
+        if let Some(look) = state.world.get::<Look>(slot.entity_id).copied() {
+            let stand = PlayerInput {
+                sequence: 0,
+                velocity: Vec3::new_from_one(0),
+                yaw: look.yaw,
+                pitch: look.pitch,
+                dt: 0.,
+                jump: 0,
+            };
+            systems::move_player(&mut state.world, slot.entity_id, &stand, |x, z| {
+                terrain.height_at(x, z)
+            });
+        }
+    }
+    server
+        .send_to_all(terrain_params_message(state.terrain_params))
+        .await;
+}
+
+/// Applies an edit from `client_id` through its own undo history. Whatever changed goes out
+/// with the next snapshot, anything rejected is explained back to the sender.
+async fn edit(
+    state: &mut ServerState,
+    server: &mut ServerInterface<GameMessage>,
+    client_id: SocketAddr,
+    request: EditRequest,
+) {
+    let slot = match state.players.get_mut(&client_id) {
+        Some(slot) => slot,
+        // only players can edit, a client gets one with `SyncWorld`
+        None => return,
+    };
+    // grouping changes nothing by itself, and dropping the end of a group would glue the
+    // player's next edits onto it
+    let limited = !matches!(request, EditRequest::BeginGroup | EditRequest::EndGroup);
+    if limited && !slot.edit.try_send() {
+        debug!(%client_id, "editing too quickly");
+        return;
+    }
+    let changes_terrain = match request {
+        EditRequest::Apply(command) => command.changes_terrain(),
+        EditRequest::Undo => slot.history.undo_changes_terrain(),
+        EditRequest::Redo => slot.history.redo_changes_terrain(),
+        EditRequest::BeginGroup | EditRequest::EndGroup => false,
+    };
+    if changes_terrain && !slot.terrain_edit.try_send() {
+        debug!(%client_id, "editing the terrain too quickly");
+        let reason = "the terrain can only be changed every few seconds".to_string();
+        if let Ok(reply) = chat_message(&ChatMessage::system(reason, now_ms())) {
+            server.send_to(client_id, reply).await;
+        }
+        return;
+    }
+
+    let history = &mut slot.history;
+    let mut scene = Scene {
+        world: &mut state.world,
+        terrain: &mut state.terrain_params,
+    };
+    let result = match request {
+        EditRequest::Apply(command) => Some(match history.apply(&mut scene, command) {
+            Ok(applied) => (applied, None),
+            Err(e) => (Applied::default(), Some(e)),
+        }),
+        EditRequest::Undo => history.undo(&mut scene),
+        EditRequest::Redo => history.redo(&mut scene),
+        EditRequest::BeginGroup => {
+            history.begin_group();
+            None
+        }
+        EditRequest::EndGroup => {
+            history.end_group();
+            None
+        }
+    };
+    let (applied, error) = match result {
+        Some(result) => result,
+        None => return,
+    };
+
+    // an undo which failed halfway has still changed whatever it got through first, and
+    // anything edited could have been holding something else up
+    physics::wake_all(&mut state.world);
+    // other clients' edits can still point at an entity which came back under a new id
+    for respawned in applied.respawned {
+        for slot in state.players.values_mut() {
+            slot.history.remap(respawned);
+        }
+    }
+    if applied.terrain_changed {
+        regenerate_terrain(state, server).await;
+    }
+
+    match error {
+        None => {
+            debug!(%client_id, ?request, "edit");
+        }
+        Some(e) => {
+            warn!(%client_id, ?request, "rejected edit: {}", e);
+            let reason = format!("couldn't edit: {}", e);
+            if let Ok(reply) = chat_message(&ChatMessage::system(reason, now_ms())) {
+                server.send_to(client_id, reply).await;
+            }
+        }
+    }
+}
+
+fn generate_cubes(state: &mut ServerState) {
+    spawn_cube(&mut state.world, (0, 0, 0).into(), 5.0);
+
+    let color = Color::new(255, 250, 209);
+    state
+        .world
+        .build()
+        .with(Transform::new((0, 10, 0).into()))
+        .with(Mesh {
+            vertex_kind: VertexKind::Basic,
+            ..Mesh::cube(5.0, color)
+        })
+        .with(Collider::cube(5.0))
+        .with(Light {
+            color,
+            radians: 90.0f32.to_radians(),
+            rotating: false,
+            rotation_axis: (0, 1, 0).into(),
+        })
+        .with(Networked);
+
+    /*
+    spawn_cube(&mut state.world, (10, 0, 10).into(), 1.0);
+    spawn_cube(&mut state.world, (0, 0, 10).into(), 1.0);
+    spawn_cube(&mut state.world, (10, 0, 0).into(), 1.0);
+    spawn_cube(&mut state.world, (10, 10, 10).into(), 1.0);
+    spawn_cube(&mut state.world, (0, 10, 10).into(), 1.0);
+    spawn_cube(&mut state.world, (10, 10, 0).into(), 1.0);
+    spawn_cube(&mut state.world, (0, 10, 0).into(), 1.0);
+    spawn_cube(&mut state.world, (5, 5, 5).into(), 5.0);
+    */
+    //spawn_cube(&mut state.world, (0, -105, 0).into(), 100.0);
+}
+
+fn terrain_params_message(params: TerrainParams) -> Message<GameMessage> {
+    let mut msg = Message::new(GameMessage::RegenerateTerrain(TerrainMessage::Generate));
+    msg.push(params);
+    msg
+}
+
+/// the center and scale go first so the client knows where to put the terrain once the last
+/// chunk lands
+fn terrain_stream_messages(terrain: &Terrain) -> Vec<Message<GameMessage>> {
+    let mut center = Message::new(GameMessage::RegenerateTerrain(TerrainMessage::Center));
+    center.push(terrain.center);
+    center.push(terrain.scale);
+
+    let mut messages = vec![center];
+    messages.extend(message::chunk_messages(
+        GameMessage::RegenerateTerrain(TerrainMessage::Verts),
+        &terrain.verts,
+    ));
+    messages.extend(message::chunk_messages(
+        GameMessage::RegenerateTerrain(TerrainMessage::Indices),
+        &terrain.indices,
+    ));
+
+    messages
+}
+
+async fn handle_message(
+    state: &mut ServerState,
+    server: &mut ServerInterface<GameMessage>,
+    client_id: SocketAddr,
+    mut msg: Message<GameMessage>,
+) {
+    debug!(%client_id, header = ?msg.header, "popped message");
+    match msg.header.id {
+        GameMessage::GetId => {
+            let id = state.id_counter;
+            msg.push(id);
+            server.send_to(client_id, msg).await;
+            state.id_counter += 1;
+        }
+        GameMessage::SyncWorld => {
+            debug!(
+                %client_id,
+                entities = state.world.len(),
+                "syncing world"
+            );
+
+            // a fresh baseline means the next tick sends the whole world
+            state.baselines.entry(client_id).or_default().reset();
+
+            let entity_id = match state.players.get(&client_id) {
+                Some(slot) => slot.entity_id,
+                None => spawn_player(state, client_id),
+            };
+            let mut player = Message::new(GameMessage::Player);
+            player.push(entity_id);
+            server.send_to(client_id, player).await;
+        }
+        GameMessage::AckSnapshot => match msg.pull::<Tick>() {
+            Ok(tick) => {
+                if let Some(baseline) = state.baselines.get_mut(&client_id) {
+                    baseline.ack(tick);
+                }
+            }
+            Err(e) => warn!(%client_id, "bad snapshot ack: {:?}", e),
+        },
+        GameMessage::RegenerateTerrain(TerrainMessage::Generate) => {
+            debug!(%client_id, params = ?state.terrain_params, "sending terrain params");
+            server
+                .send_to(client_id, terrain_params_message(state.terrain_params))
+                .await;
+        }
+        GameMessage::RegenerateTerrain(TerrainMessage::Verts) => {
+            let messages = terrain_stream_messages(&state.terrain);
+            debug!(%client_id, messages = messages.len(), "streaming terrain mesh");
+            for msg in messages {
+                server.send_to(client_id, msg).await;
+            }
+        }
+        GameMessage::RegenerateTerrain(_) => {}
+        GameMessage::Snapshot => {}
+        GameMessage::Player => {}
+        GameMessage::Ping => {}
+        GameMessage::Edit => match pull_edit(&mut msg) {
+            Ok(request) => edit(state, server, client_id, request).await,
+            Err(e) => warn!(%client_id, "bad edit: {:?}", e),
+        },
+        GameMessage::Interact => match msg.pull::<InteractRequest>() {
+            Ok(request) => interact(state, server, client_id, request).await,
+            Err(e) => warn!(%client_id, "bad interaction: {:?}", e),
+        },
+        GameMessage::MovePlayer => match msg.pull::<PlayerInput>() {
+            Ok(input) => move_player(state, client_id, input),
+            Err(e) => warn!(%client_id, "bad player input: {:?}", e),
+        },
+        // the size and encoding are checked while pulling, the rest in `relay_chat`
+        GameMessage::Chat => match pull_chat(&mut msg) {
+            Ok(chat) => relay_chat(state, server, client_id, chat).await,
+            Err(e) => {
+                warn!(%client_id, "rejected chat message: {}", e);
+                if let Ok(reply) = chat_message(&ChatMessage::system(e.to_string(), now_ms())) {
+                    server.send_to(client_id, reply).await;
+                }
+            }
+        },
+    }
+}
+
+/// sorted so the indices printed by `list clients` can be used with `kick`
+fn sorted_clients(server: &ServerInterface<GameMessage>) -> Vec<SocketAddr> {
+    let mut clients = server.client_addrs();
+    clients.sort();
+    clients
+}
+
+async fn handle_command(
+    state: &mut ServerState,
+    server: &mut ServerInterface<GameMessage>,
+    save_path: &Path,
+    command: Command,
+) {
+    match command {
+        Command::Status => {
+            println!(
+                "tick: {} at {}hz, clients: {}, players: {}, entities: {}, terrain: seed {} size {}",
+                state.tick,
+                state.tick_rate,
+                server.connection_count(),
+                state.players.len(),
+                state.world.len(),
+                state.terrain_params.seed,
+                state.terrain_params.size,
+            );
+        }
+        Command::ListClients => {
+            for (i, client_id) in sorted_clients(server).iter().enumerate() {
+                let acked = state
+                    .baselines
+                    .get(client_id)
+                    .and_then(|baseline| baseline.acked_tick());
+                match acked {
+                    Some(tick) => println!("{}: {} acked tick {}", i, client_id, tick),
+                    None => println!("{}: {} not synced", i, client_id),
+                }
+            }
+        }
+        Command::Kick(client) => {
+            let client_id = match client {
+                ClientRef::Index(i) => sorted_clients(server).get(i).copied(),
+                ClientRef::Addr(addr) => Some(addr),
+            };
+
+            match client_id {
+                Some(client_id) if server.kick(client_id) => {
+                    remove_client(state, client_id);
+                    info!(%client_id, "kicked");
+                }
+                _ => println!("no such client {:?}", client),
+            }
+        }
+        Command::SpawnCube { position, size } => {
+            // clients pick the new cube up with the next snapshot
+            let id = spawn_cube(&mut state.world, position, size);
+            info!(%id, ?position, "spawned cube");
+        }
+        Command::SpawnPrimitive { shape, position } => {
+            let id = spawn_primitive(&mut state.world, position, shape);
+            info!(%id, ?position, shape = shape.name(), "spawned primitive");
+        }
+        Command::SetSun(position) => {
+            let sun = state
+                .world
+                .query2::<Transform, Light>()
+                .map(|(id, transform, _)| (id, *transform))
+                .next();
+            match sun {
+                Some((id, transform)) => {
+                    let moved = Transform {
+                        position,
+                        ..transform
+                    };
+                    hierarchy::set_transform(&mut state.world, id, moved);
+                    info!(%id, ?position, "moved sun");
+                }
+                None => println!("there is no sun"),
+            }
+        }
+        Command::RegenTerrain { seed } => {
+            state.terrain_params.seed = seed;
+            regenerate_terrain(state, server).await;
+            info!(seed, "regenerated terrain");
+        }
+        Command::Save => save_world(state, save_path),
+        Command::TickRate(tick_rate) => {
+            if tick_rate == 0 || tick_rate > config::MAX_TICK_RATE {
+                println!("tick rate has to be within 1..={}", config::MAX_TICK_RATE);
+            } else {
+                state.tick_rate = tick_rate;
+                info!(tick_rate, "tick rate changed");
+            }
+        }
+        Command::Help => println!("{}", console::HELP),
+    }
+}
+
+fn tick_interval(tick_rate: u64) -> tokio::time::Interval {
+    tokio::time::interval(std::time::Duration::from_millis(1000 / tick_rate))
+}
+
+async fn send_snapshots(state: &mut ServerState, server: &mut ServerInterface<GameMessage>) {
+    hierarchy::propagate(&mut state.world);
+    let snapshot = replicate::snapshot(&state.world, state.tick);
+    let index = state.interest.index(&snapshot);
+
+    for (client_id, baseline) in state.baselines.iter_mut() {
+        let mut viewer = None;
+        // the ack goes first so the client can reconcile before the snapshot moves its player
+        if let Some(slot) = state.players.get_mut(client_id) {
+            let player = snapshot.entities.get(&slot.entity_id);
+            if let Some(player) = player.filter(|player| player.is_player()) {
+                let state = player.state();
+                viewer = Some(state.position);
+                let ack = PlayerAck {
+                    sequence: slot.last_sequence.unwrap_or(0),
+                    position: state.position,
+                    velocity: state.velocity,
+                };
+                if slot.last_ack != Some(ack) {
+                    let mut msg = Message::new(GameMessage::MovePlayer);
+                    msg.push(ack);
+                    server.send_to(*client_id, msg).await;
+                    slot.last_ack = Some(ack);
+                }
+            }
+        }
+
+        let visible = Arc::new(index.filter(&snapshot, viewer));
+        if let Some(msg) = baseline.delta_message(&visible) {
+            server.send_to(*client_id, msg).await;
+        }
+    }
+
+    let capacity = WorldHistory::capacity(state.tick_rate);
+    state.history.push(Arc::new(snapshot), capacity);
+}
+
+/// Listens on `config.bind` and serves the world until `shutdown` resolves, see `serve`
+pub async fn run(
+    config: ServerConfig,
+    console: Option<mpsc::UnboundedReceiver<String>>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), ServerError> {
+    let mut server: ServerInterface<GameMessage> = ServerInterface::bind(config.bind);
+    server.set_max_connections(config.max_players);
+    server.start().await.map_err(|source| ServerError::Bind {
+        addr: config.bind,
+        source,
+    })?;
+
+    serve(config, server, console, shutdown).await
+}
+
+/// Starts a server on its own thread which only clients in this process can join, through the
+/// returned connector. `config.bind` is ignored. The server lives as long as the process does,
+/// so anything after its last autosave is lost.
+pub fn spawn_embedded(config: ServerConfig) -> std::io::Result<LocalConnector<GameMessage>> {
+    let mut server: ServerInterface<GameMessage> = ServerInterface::local();
+    server.set_max_connections(config.max_players);
+    let connector = server.local_connector();
+
+    // the server's future isn't `Send`, so it gets a runtime of its own rather than a task
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    std::thread::Builder::new()
+        .name("embedded-server".to_string())
+        .spawn(move || {
+            let result = runtime.block_on(async {
+                // nothing to bind, this can't fail
+                let _ = server.start().await;
+                serve(config, server, None, std::future::pending()).await
+            });
+            if let Err(e) = result {
+                error!("embedded server stopped: {}", e);
+            }
+        })?;
+
+    Ok(connector)
+}
+
+/// Loads the world from `config.load` or generates a fresh one, then runs it on an already
+/// started `server` until `shutdown` resolves and the world is saved. Console commands are only
+/// read when there is a `console`.
+pub async fn serve(
+    config: ServerConfig,
+    mut server: ServerInterface<GameMessage>,
+    mut console: Option<mpsc::UnboundedReceiver<String>>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), ServerError> {
+    let save_path = config.save_path();
+
+    let mut state = match config.load {
+        Some(ref path) => {
+            let save = WorldSave::read_from(path).map_err(|source| ServerError::Load {
+                path: path.clone(),
+                source,
+            })?;
+            info!(
+                entities = save.entities.len(),
+                tick = save.tick,
+                ?path,
+                "loaded world"
+            );
+            ServerState::from_save(save)
+        }
+        None => {
+            // already validated along with the rest of the config
+            let terrain_params = config.world.terrain_params().unwrap();
+            let mut state = ServerState::new(terrain_params);
+            generate_cubes(&mut state);
+            state
+        }
+    };
+    state.tick_rate = config.tick_rate;
+    state.interest = InterestGrid::new(DEFAULT_CELL_SIZE, config.view_distance);
+
+    let mut connection_count: usize = 0;
+
+    tokio::pin!(shutdown);
+    let mut tick_rate = state.tick_rate;
+    let mut ticker = tick_interval(tick_rate);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = &mut shutdown => {
+                info!("shutting down");
+                save_world(&state, &save_path);
+                return Ok(());
+            }
+        }
+        // everything logged while handling a tick is tagged with the tick being worked towards
+        let span = debug_span!("tick", tick = state.tick + 1);
+        async {
+            for client_id in server.update().await {
+                debug!(%client_id, "dropping client");
+                remove_client(&mut state, client_id);
+            }
+
+            let curr_connection_count: usize = server.connection_count();
+            if connection_count != curr_connection_count {
+                debug!(
+                    old = connection_count,
+                    new = curr_connection_count,
+                    "connection count changed"
+                );
+                let ping = Message::new(GameMessage::Ping);
+                server.send_to_all(ping).await;
+                connection_count = curr_connection_count;
+            }
+
+            while let Some((client_id, msg)) = server.pop_message() {
+                handle_message(&mut state, &mut server, client_id, msg).await;
+            }
+
+            if let Some(console) = console.as_mut() {
+                while let Ok(line) = console.try_recv() {
+                    match Command::parse(&line) {
+                        Ok(command) => {
+                            handle_command(&mut state, &mut server, &save_path, command).await
+                        }
+                        Err(e) => println!("{}", e),
+                    }
+                }
+            }
+
+            if tick_rate != state.tick_rate {
+                tick_rate = state.tick_rate;
+                ticker = tick_interval(tick_rate);
+            }
+
+            let tick_secs = 1. / state.tick_rate as f32;
+            for slot in state.players.values_mut() {
+                slot.input_budget = (slot.input_budget + tick_secs).min(MAX_INPUT_BUDGET);
+                slot.chat.refill(tick_secs);
+                slot.interact.refill(tick_secs);
+                slot.edit.refill(tick_secs);
+                slot.terrain_edit.refill(tick_secs);
+            }
+
+            state
+                .physics
+                .update(&mut state.world, Some(&state.terrain), tick_secs);
+
+            state.tick += 1;
+            send_snapshots(&mut state, &mut server).await;
+
+            let autosave_ticks = (config.autosave_secs * state.tick_rate) as Tick;
+            if autosave_ticks > 0 && state.tick % autosave_ticks == 0 {
+                save_world(&state, &save_path);
+            }
+        }
+        .instrument(span)
+        .await;
+    }
+}
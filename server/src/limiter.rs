@@ -0,0 +1,114 @@
+/// Most chat messages a player can send back to back
+pub const CHAT_BURST: f32 = 5.;
+/// chat messages a second a player earns back after a burst
+pub const CHAT_RATE: f32 = 1.;
+
+/// Most interactions a player can send back to back, each one rewinds the world and goes out
+/// to everyone
+pub const INTERACT_BURST: f32 = 10.;
+/// interactions a second a player earns back after a burst
+pub const INTERACT_RATE: f32 = 4.;
+
+/// Most edits a player can send back to back, dragging sends one for every selected entity
+/// each frame the cursor moves
+pub const EDIT_BURST: f32 = 120.;
+/// edits a second a player earns back after a burst
+pub const EDIT_RATE: f32 = 60.;
+
+/// Most terrain edits a player can send back to back, each one regenerates the terrain on the
+/// tick and streams it to everyone
+pub const TERRAIN_EDIT_BURST: f32 = 1.;
+/// terrain edits a second a player earns back
+pub const TERRAIN_EDIT_RATE: f32 = 0.2;
+
+/// Token bucket limiting how fast a single player can do something, refilled every tick like
+/// the input budget is
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimiter {
+    tokens: f32,
+    burst: f32,
+    rate: f32,
+}
+
+impl RateLimiter {
+    /// starts out with a full `burst`, `rate` is earned back a second
+    pub fn new(burst: f32, rate: f32) -> Self {
+        Self {
+            tokens: burst,
+            burst,
+            rate,
+        }
+    }
+
+    pub fn chat() -> Self {
+        Self::new(CHAT_BURST, CHAT_RATE)
+    }
+
+    pub fn interact() -> Self {
+        Self::new(INTERACT_BURST, INTERACT_RATE)
+    }
+
+    pub fn edit() -> Self {
+        Self::new(EDIT_BURST, EDIT_RATE)
+    }
+
+    pub fn terrain_edit() -> Self {
+        Self::new(TERRAIN_EDIT_BURST, TERRAIN_EDIT_RATE)
+    }
+
+    pub fn refill(&mut self, secs: f32) {
+        self.tokens = (self.tokens + secs * self.rate).min(self.burst);
+    }
+
+    /// false once the player is going too quickly, whatever they sent should be dropped then
+    pub fn try_send(&mut self) -> bool {
+        if self.tokens < 1. {
+            return false;
+        }
+
+        self.tokens -= 1.;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limits_bursts() {
+        let mut limiter = RateLimiter::chat();
+        for _ in 0..CHAT_BURST as usize {
+            assert!(limiter.try_send());
+        }
+        assert!(!limiter.try_send());
+
+        limiter.refill(0.5 / CHAT_RATE);
+        assert!(!limiter.try_send());
+        limiter.refill(0.5 / CHAT_RATE);
+        assert!(limiter.try_send());
+
+        // going quiet for a long time only banks up a single burst
+        limiter.refill(1000.);
+        for _ in 0..CHAT_BURST as usize {
+            assert!(limiter.try_send());
+        }
+        assert!(!limiter.try_send());
+
+        let mut limiter = RateLimiter::interact();
+        for _ in 0..INTERACT_BURST as usize {
+            assert!(limiter.try_send());
+        }
+        assert!(!limiter.try_send());
+        limiter.refill(1. / INTERACT_RATE);
+        assert!(limiter.try_send());
+
+        let mut limiter = RateLimiter::terrain_edit();
+        assert!(limiter.try_send());
+        assert!(!limiter.try_send());
+        limiter.refill(0.9 / TERRAIN_EDIT_RATE);
+        assert!(!limiter.try_send());
+        limiter.refill(0.1 / TERRAIN_EDIT_RATE);
+        assert!(limiter.try_send());
+    }
+}
@@ -1,166 +1,32 @@
-use atlas::message::{Complex, GameMessage};
 use hermes::tokio;
-use hermes::Message;
-use hermes::ServerInterface;
-
-use atlas::entity::cube::Cuboid;
-use atlas::entity::sun::Sun;
-use atlas::entity::EntityKind;
-use atlas::Color;
-
-use atlas::proc_gen;
-use proc_gen::color::ColorGenerator;
-use proc_gen::noise::Perlin;
-use proc_gen::terrain::TerrainGenerator;
-
-
-use entity_manager::EntityManager;
-
-mod entity_manager;
-
-struct ServerState<'a> {
-    entity_manager: EntityManager<'a>,
-    id_counter: usize,
-}
-
-impl<'a> ServerState<'a> {
-    pub fn new() -> Self {
-        Self {
-            id_counter: 1,
-            entity_manager: EntityManager::new(),
-        }
-    }
-}
-
-fn generate_cubes(state: &mut ServerState) {
-    let cube = Cuboid::cube(
-        5.0,
-        (0, 0, 0).into(),
-        None,
-        atlas::vertex::VertexKind::Shaded,
-        None,
-    );
-    state.entity_manager.push_entity(EntityKind::from(cube));
-
-    let sun = Sun::new(
-        (0, 10, 0).into(),
-        5.0,
-        Color::new(255, 250, 209),
-        Color::new(255, 250, 209),
-    );
-    state.entity_manager.push_entity(EntityKind::from(sun));
-
-    /*
-    let cube = Cuboid::cube(1.0, (10, 0, 10).into(), None);
-    state.entity_manager.push_entity(EntityKind::from(cube));
-
-    let cube = Cuboid::cube(1.0, (0, 0, 10).into(), None);
-    state.entity_manager.push_entity(EntityKind::from(cube));
-
-    let cube = Cuboid::cube(1.0, (10, 0, 0).into(), None);
-    state.entity_manager.push_entity(EntityKind::from(cube));
-
-    let cube = Cuboid::cube(1.0, (10, 10, 10).into(), None);
-    state.entity_manager.push_entity(EntityKind::from(cube));
-
-    let cube = Cuboid::cube(1.0, (0, 10, 10).into(), None);
-    state.entity_manager.push_entity(EntityKind::from(cube));
-
-    let cube = Cuboid::cube(1.0, (10, 10, 0).into(), None);
-    state.entity_manager.push_entity(EntityKind::from(cube));
-
-    let cube = Cuboid::cube(1.0, (0, 10, 0).into(), None);
-    state.entity_manager.push_entity(EntityKind::from(cube));
-
-    let cube = Cuboid::cube(5.0, (5, 5, 5).into(), None);
-    state.entity_manager.push_entity(EntityKind::from(cube));
-
-    */
-    //let cube = Cuboid::cube(100.0, (0, -105, 0).into(), None);
-    //state.entity_manager.push_entity(EntityKind::from(cube));
-}
-
-#[allow(dead_code)]
-fn generate_terrain(seed: Option<isize>) -> atlas::entity::terrain::Terrain<'static> {
-    let mut perlin = Perlin::default();
-    if let Some(seed) = seed {
-        perlin.seed = seed;
-    }
-
-    let color_gen = ColorGenerator::new(
-        vec![
-            (201, 178, 99).into(),
-            (135, 184, 82).into(),
-            (80, 171, 93).into(),
-            (120, 120, 120).into(),
-            (200, 200, 210).into(),
-        ],
-        0.45,
-    );
-
-    let terrain_gen = TerrainGenerator::new(perlin, color_gen);
-    let terrain_size = if cfg!(debug_assertions) { 10 } else { 250 };
-    let mut terrain = terrain_gen.generate(terrain_size, false);
-    terrain.center = (terrain_size as f32 / 2., 0., terrain_size as f32 / 2.).into();
-
-    terrain
-}
+use server::config::{self, ServerConfig};
+use server::console;
 
 #[tokio::main]
 async fn main() {
-    let mut state = ServerState::new();
-    let mut server: ServerInterface<GameMessage> = ServerInterface::new(8080);
-    server.start().await;
-    let mut connection_count: usize = 0;
-
-    generate_cubes(&mut state);
+    if atlas::config::wants_help(std::env::args()) {
+        println!("{}", config::USAGE);
+        return;
+    }
 
-    loop {
-        //server.update().await;
-        let curr_connection_count: usize = server.connection_count();
-        if connection_count != curr_connection_count {
-            println!(
-                "[Driver] change in connection count: old:{} new:{}",
-                connection_count, curr_connection_count
-            );
-            let ping = Message::new(GameMessage::Ping);
-            server.send_to_all(ping).await;
-            connection_count = curr_connection_count;
+    let config = match ServerConfig::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("[Config] {}", e);
+            std::process::exit(1);
         }
+    };
 
-        if let Some((client_id, mut msg)) = server.pop_message() {
-            println!("popped msg: {:?}", msg.header);
-            match msg.header.id {
-                GameMessage::GetId => {
-                    let id = state.id_counter;
-                    msg.push(id);
-                    server.send_to(client_id, msg).await;
-                    state.id_counter += 1;
-                }
-                GameMessage::SyncWorld => {
-                    println!(
-                        "[SyncWorld] Entities count: {:#?}",
-                        state.entity_manager.entities.len()
-                    );
-
-                    for entity in state.entity_manager.entities.iter().copied() {
-                        msg.push(entity);
-                    }
-                    println!("[SyncWorld] Final msg header {:#?}", msg.header);
+    if let Err(e) = atlas::logging::init(config.log_level, config.log_file.as_deref()) {
+        eprintln!("[Logging] {}", e);
+        std::process::exit(1);
+    }
 
-                    server.send_to(client_id, msg).await;
-                }
-                GameMessage::RegenerateTerrain(_) => {
-                    println!("[RegenerateTerrain] regenerating terrain");
-                }
-                GameMessage::Player => {}
-                GameMessage::Ping => {}
-                GameMessage::Interact => {}
-                GameMessage::MovePlayer => {
-                    let parse: Complex = msg.pull().unwrap();
-                    println!("parsed bytes for MovePlayer: {:#?}", parse);
-                }
-            }
-        }
+    let shutdown = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    if let Err(e) = server::run(config, Some(console::spawn_stdin_reader()), shutdown).await {
+        tracing::error!("{}", e);
+        std::process::exit(1);
     }
 }
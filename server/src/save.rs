@@ -0,0 +1,1234 @@
+use atlas::ecs::components::{Light, Mesh, Transform};
+use atlas::ecs::replicate::Replicated;
+use atlas::entity::id::{IdAllocator, Slot};
+use atlas::entity::primitive::PrimitiveShape;
+use atlas::physics::RigidBody;
+use atlas::proc_gen::terrain::{TerrainParams, MAX_PALETTE_COLORS};
+use atlas::snapshot::{fields, EntityId, EntityState, Tick};
+use atlas::vertex::VertexKind;
+use atlas::Color;
+use pantheon::{Mat4, PolygonMode, Topology, Vec3, Vec4};
+use std::collections::BTreeMap;
+use std::path::Path;
+use thiserror::Error;
+
+const SAVE_MAGIC: [u8; 4] = *b"ATWS";
+
+/// bump this whenever the layout changes and teach `WorldSave::decode` how to read the old one
+///
+/// 1: terrain params, entity id counter, entities
+/// 2: adds the tick and the `GetId` counter
+/// 3: generational entity ids, the id counter is replaced by the id allocator's slots
+/// 4: adds primitive entities
+/// 5: adds the world scale after the terrain params
+/// 6: adds whether each entity has a rigid body, and its body if it does
+/// 7: adds each entity's parent, if it has one
+pub const SAVE_VERSION: u32 = 7;
+
+/// Ids in saves from before v3 are plain counters and every one below the highest gets a slot,
+/// anything past this is a corrupt file rather than a world anyone built
+pub const MAX_LEGACY_IDS: u64 = 1 << 20;
+
+#[derive(Error, Debug)]
+pub enum SaveError {
+    #[error("failed to access save file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("file is not a world save")]
+    BadMagic,
+    #[error("save version {version} is newer than the supported version {SAVE_VERSION}")]
+    UnsupportedVersion { version: u32 },
+    #[error("save ended early, needed {needed} bytes but only {remaining} were left")]
+    Truncated { needed: usize, remaining: usize },
+    #[error("unknown {what} tag {tag} in save")]
+    UnknownTag { what: &'static str, tag: u8 },
+    #[error("entity {id} in save was never handed out by its id allocator")]
+    DeadEntityId { id: EntityId },
+    #[error("entity id {id} in save is past the supported {MAX_LEGACY_IDS}")]
+    IdOutOfRange { id: u64 },
+    #[error("rigid body of entity {id} in save has a broken mass, restitution or friction")]
+    BadBody { id: EntityId },
+    #[error("terrain params in save can't be generated from: {params:?}")]
+    BadTerrain { params: Box<TerrainParams> },
+}
+
+/// Everything needed to bring the server back up where it left off. Entities are stored as
+/// records rather than raw bytes so the file doesn't depend on the in memory layout of their
+/// components.
+#[derive(Debug, Clone)]
+pub struct WorldSave {
+    pub tick: Tick,
+    /// counter behind `GameMessage::GetId`
+    pub id_counter: usize,
+    /// every entity in `entities` is alive in here, reloading keeps handing out the same ids
+    pub ids: IdAllocator,
+    pub terrain_params: TerrainParams,
+    pub entities: Vec<(EntityId, EntityRecord)>,
+    /// whichever of `entities` physics moves
+    pub bodies: BTreeMap<EntityId, BodyRecord>,
+    /// Child to parent, both in `entities`. Entities are saved where they are in the world,
+    /// so attaching them again has to keep them there.
+    pub parents: BTreeMap<EntityId, EntityId>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CuboidRecord {
+    pub size: f32,
+    pub vertex_kind: VertexKind,
+    pub topology: Topology,
+    pub state: EntityState,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SunRecord {
+    pub size: f32,
+    pub light_color: Color,
+    pub radians: f32,
+    pub rotating: bool,
+    pub rotation_axis: Vec3,
+    pub state: EntityState,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PrimitiveRecord {
+    pub primitive: PrimitiveShape,
+    pub vertex_kind: VertexKind,
+    pub topology: Topology,
+    pub state: EntityState,
+}
+
+/// A `RigidBody` without whatever it was doing when it was saved, everything wakes up again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BodyRecord {
+    pub mass: f32,
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+impl BodyRecord {
+    pub fn from_body(body: &RigidBody) -> Self {
+        Self {
+            mass: body.mass,
+            restitution: body.restitution,
+            friction: body.friction,
+        }
+    }
+
+    pub fn to_body(&self) -> RigidBody {
+        let mut body = RigidBody::new(self.mass);
+        body.restitution = self.restitution;
+        body.friction = self.friction;
+
+        body
+    }
+
+    fn is_valid(&self) -> bool {
+        self.mass.is_finite()
+            && self.mass >= 0.
+            && (0. ..=1.).contains(&self.restitution)
+            && self.friction.is_finite()
+            && self.friction >= 0.
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum EntityRecord {
+    Cuboid(CuboidRecord),
+    Sun(SunRecord),
+    Primitive(PrimitiveRecord),
+}
+
+impl EntityRecord {
+    /// players belong to whoever is connected, so they're left out of saves
+    pub fn from_replicated(entity: &Replicated) -> Option<Self> {
+        if entity.is_player() {
+            return None;
+        }
+
+        let mesh = &entity.mesh;
+        let state = entity.state();
+        let record = match (entity.light, mesh.primitive) {
+            (Some(light), _) => Self::Sun(SunRecord {
+                size: mesh.size,
+                light_color: light.color,
+                radians: light.radians,
+                rotating: light.rotating,
+                rotation_axis: light.rotation_axis,
+                state,
+            }),
+            (None, Some(primitive)) => Self::Primitive(PrimitiveRecord {
+                primitive,
+                vertex_kind: mesh.vertex_kind,
+                topology: mesh.topology,
+                state,
+            }),
+            (None, None) => Self::Cuboid(CuboidRecord {
+                size: mesh.size,
+                vertex_kind: mesh.vertex_kind,
+                topology: mesh.topology,
+                state,
+            }),
+        };
+
+        Some(record)
+    }
+
+    pub fn to_replicated(&self) -> Replicated {
+        let (state, mut entity) = match self {
+            Self::Cuboid(record) => (
+                record.state,
+                Replicated::new(
+                    Transform::default(),
+                    Mesh {
+                        vertex_kind: record.vertex_kind,
+                        topology: record.topology,
+                        ..Mesh::cube(record.size, record.state.color)
+                    },
+                ),
+            ),
+            Self::Sun(record) => (
+                record.state,
+                Replicated {
+                    light: Some(Light {
+                        color: record.light_color,
+                        radians: record.radians,
+                        rotating: record.rotating,
+                        rotation_axis: record.rotation_axis,
+                    }),
+                    ..Replicated::new(
+                        Transform::default(),
+                        Mesh {
+                            vertex_kind: VertexKind::Basic,
+                            ..Mesh::cube(record.size, record.state.color)
+                        },
+                    )
+                },
+            ),
+            Self::Primitive(record) => (
+                record.state,
+                Replicated::new(
+                    Transform::default(),
+                    Mesh {
+                        vertex_kind: record.vertex_kind,
+                        topology: record.topology,
+                        ..Mesh::primitive(record.primitive, record.state.color)
+                    },
+                ),
+            ),
+        };
+        // only moving players have a velocity
+        entity.apply_state(fields::POSITION | fields::ROTATION, &state);
+
+        entity
+    }
+}
+
+impl WorldSave {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = SaveWriter::default();
+        writer.bytes.extend_from_slice(&SAVE_MAGIC);
+        writer.u32(SAVE_VERSION);
+
+        writer.u32(self.tick);
+        writer.u64(self.id_counter as u64);
+        writer.ids(&self.ids);
+        writer.terrain_params(&self.terrain_params);
+        writer.f32(self.terrain_params.scale);
+
+        writer.u32(self.entities.len() as u32);
+        for (id, record) in self.entities.iter() {
+            writer.entity_id(*id);
+            writer.entity(record);
+            writer.body(self.bodies.get(id));
+            writer.parent(self.parents.get(id).copied());
+        }
+
+        writer.bytes
+    }
+
+    /// reads any version up to `SAVE_VERSION`, fields an older version didn't have are filled in
+    /// here so the rest of the server only ever sees the current layout
+    pub fn decode(bytes: &[u8]) -> Result<Self, SaveError> {
+        let mut reader = SaveReader::new(bytes);
+        if reader.take(SAVE_MAGIC.len())? != SAVE_MAGIC {
+            return Err(SaveError::BadMagic);
+        }
+
+        let version = reader.u32()?;
+        if version > SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion { version });
+        }
+
+        let (tick, id_counter) = if version >= 2 {
+            (reader.u32()?, Some(reader.u64()? as usize))
+        } else {
+            (0, None)
+        };
+
+        let (ids, next_entity_id) = if version >= 3 {
+            (Some(reader.ids()?), 0)
+        } else {
+            (None, legacy_id(reader.u64()?)?)
+        };
+        let mut terrain_params = reader.terrain_params()?;
+        // older servers ran their physics on unscaled terrain, which is where the entities are
+        terrain_params.scale = if version >= 5 { reader.f32()? } else { 1. };
+        if !terrain_params.is_valid() {
+            return Err(SaveError::BadTerrain {
+                params: Box::new(terrain_params),
+            });
+        }
+
+        // `count` comes straight from the file, a corrupt one runs out of bytes long before it
+        // could run out of memory as long as nothing is reserved up front
+        let count = reader.u32()?;
+        let mut entities = vec![];
+        let mut bodies = BTreeMap::new();
+        let mut parents = BTreeMap::new();
+        for _ in 0..count {
+            let id = if version >= 3 {
+                reader.entity_id()?
+            } else {
+                EntityId::new(legacy_id(reader.u64()?)? as u32, 0)
+            };
+            let record = reader.entity()?;
+            let body = if version >= 6 {
+                reader.body()?
+            } else {
+                // older servers gave every loaded cube a body whether or not it had one
+                matches!(record, EntityRecord::Cuboid(_))
+                    .then(|| BodyRecord::from_body(&RigidBody::new(1.)))
+            };
+            if let Some(body) = body {
+                if !body.is_valid() {
+                    return Err(SaveError::BadBody { id });
+                }
+                bodies.insert(id, body);
+            }
+            if version >= 7 {
+                if let Some(parent) = reader.parent()? {
+                    parents.insert(id, parent);
+                }
+            }
+            entities.push((id, record));
+        }
+
+        let ids = match ids {
+            Some(ids) => ids,
+            None => legacy_ids(next_entity_id, &entities),
+        };
+        if let Some((id, _)) = entities.iter().find(|(id, _)| !ids.is_alive(*id)) {
+            return Err(SaveError::DeadEntityId { id: *id });
+        }
+        if let Some(parent) = parents.values().find(|parent| !ids.is_alive(**parent)) {
+            return Err(SaveError::DeadEntityId { id: *parent });
+        }
+
+        Ok(Self {
+            tick,
+            // v1 saves didn't keep the counter, starting past every entity id keeps handed out ids
+            // from colliding with anything in the world
+            id_counter: id_counter.unwrap_or(next_entity_id as usize),
+            ids,
+            terrain_params,
+            entities,
+            bodies,
+            parents,
+        })
+    }
+
+    /// writes to a temporary file first so a crash mid save doesn't clobber the last good one
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, self.encode())?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    pub fn read_from(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        Self::decode(&std::fs::read(path)?)
+    }
+}
+
+/// checks a counter or index from before v3 fits in `MAX_LEGACY_IDS`
+fn legacy_id(id: u64) -> Result<u64, SaveError> {
+    if id > MAX_LEGACY_IDS {
+        return Err(SaveError::IdOutOfRange { id });
+    }
+
+    Ok(id)
+}
+
+/// Before v3 ids were a plain counter, they become generation 0 of the same index. Indices
+/// nothing was using start out a generation ahead so they never come back as an id from before.
+fn legacy_ids(next_entity_id: u64, entities: &[(EntityId, EntityRecord)]) -> IdAllocator {
+    let len = entities
+        .iter()
+        .map(|(id, _)| id.index as u64 + 1)
+        .fold(next_entity_id, u64::max);
+    let mut slots = vec![
+        Slot {
+            generation: 1,
+            alive: false,
+        };
+        len as usize
+    ];
+    for (id, _) in entities {
+        slots[id.index as usize] = Slot {
+            generation: 0,
+            alive: true,
+        };
+    }
+
+    IdAllocator::from_slots(slots, [])
+}
+
+// everything is written little endian field by field, so the format stays the same no matter
+// how the compiler decides to lay the structs out
+#[derive(Default)]
+struct SaveWriter {
+    bytes: Vec<u8>,
+}
+
+impl SaveWriter {
+    fn u8(&mut self, v: u8) {
+        self.bytes.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i64(&mut self, v: i64) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn f32(&mut self, v: f32) {
+        self.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    fn vec3(&mut self, v: Vec3) {
+        self.f32(v.x);
+        self.f32(v.y);
+        self.f32(v.z);
+    }
+
+    fn vec4(&mut self, v: Vec4) {
+        self.f32(v.x);
+        self.f32(v.y);
+        self.f32(v.z);
+        self.f32(v.w);
+    }
+
+    fn mat4(&mut self, m: Mat4) {
+        self.vec4(m.x);
+        self.vec4(m.y);
+        self.vec4(m.z);
+        self.vec4(m.w);
+    }
+
+    fn color(&mut self, c: Color) {
+        self.f32(c.r);
+        self.f32(c.g);
+        self.f32(c.b);
+        self.f32(c.a);
+    }
+
+    fn entity_id(&mut self, id: EntityId) {
+        self.u32(id.index);
+        self.u32(id.generation);
+    }
+
+    /// every slot, then the free indices in the order they'll be reused
+    fn ids(&mut self, ids: &IdAllocator) {
+        self.u32(ids.slots().len() as u32);
+        for slot in ids.slots() {
+            self.u32(slot.generation);
+            self.bool(slot.alive);
+        }
+
+        let free: Vec<u32> = ids.free_indices().collect();
+        self.u32(free.len() as u32);
+        for index in free {
+            self.u32(index);
+        }
+    }
+
+    fn terrain_params(&mut self, params: &TerrainParams) {
+        self.i64(params.seed as i64);
+        self.u32(params.size);
+        self.bool(params.clamped);
+        self.f32(params.roughness);
+        self.u32(params.octaves as u32);
+        self.f32(params.amplitude);
+        self.f32(params.height);
+        self.f32(params.color_spread);
+        self.u32(params.palette_len);
+        for color in params.palette() {
+            self.color(*color);
+        }
+    }
+
+    fn state(&mut self, state: &EntityState) {
+        self.vec3(state.position);
+        self.mat4(state.rotation);
+        self.color(state.color);
+    }
+
+    fn body(&mut self, body: Option<&BodyRecord>) {
+        self.bool(body.is_some());
+        if let Some(body) = body {
+            self.f32(body.mass);
+            self.f32(body.restitution);
+            self.f32(body.friction);
+        }
+    }
+
+    fn parent(&mut self, parent: Option<EntityId>) {
+        self.bool(parent.is_some());
+        if let Some(parent) = parent {
+            self.entity_id(parent);
+        }
+    }
+
+    fn entity(&mut self, record: &EntityRecord) {
+        match record {
+            EntityRecord::Cuboid(cube) => {
+                self.u8(0);
+                self.f32(cube.size);
+                self.vertex_kind(cube.vertex_kind);
+                self.topology(cube.topology);
+                self.state(&cube.state);
+            }
+            EntityRecord::Sun(sun) => {
+                self.u8(1);
+                self.f32(sun.size);
+                self.color(sun.light_color);
+                self.f32(sun.radians);
+                self.bool(sun.rotating);
+                self.vec3(sun.rotation_axis);
+                self.state(&sun.state);
+            }
+            EntityRecord::Primitive(primitive) => {
+                self.u8(2);
+                self.primitive(primitive.primitive);
+                self.vertex_kind(primitive.vertex_kind);
+                self.topology(primitive.topology);
+                self.state(&primitive.state);
+            }
+        }
+    }
+
+    fn vertex_kind(&mut self, vertex_kind: VertexKind) {
+        self.u8(match vertex_kind {
+            VertexKind::Basic => 0,
+            VertexKind::Shaded => 1,
+            VertexKind::Textured => 2,
+        });
+    }
+
+    /// a tag for the shape followed by its sizes and then how detailed it is
+    fn primitive(&mut self, primitive: PrimitiveShape) {
+        match primitive {
+            PrimitiveShape::UvSphere {
+                radius,
+                segments,
+                rings,
+            } => {
+                self.u8(0);
+                self.f32(radius);
+                self.u32(segments);
+                self.u32(rings);
+            }
+            PrimitiveShape::Icosphere {
+                radius,
+                subdivisions,
+            } => {
+                self.u8(1);
+                self.f32(radius);
+                self.u32(subdivisions);
+            }
+            PrimitiveShape::Cylinder {
+                radius,
+                height,
+                segments,
+            } => {
+                self.u8(2);
+                self.f32(radius);
+                self.f32(height);
+                self.u32(segments);
+            }
+            PrimitiveShape::Cone {
+                radius,
+                height,
+                segments,
+            } => {
+                self.u8(3);
+                self.f32(radius);
+                self.f32(height);
+                self.u32(segments);
+            }
+            PrimitiveShape::Capsule {
+                radius,
+                height,
+                segments,
+                rings,
+            } => {
+                self.u8(4);
+                self.f32(radius);
+                self.f32(height);
+                self.u32(segments);
+                self.u32(rings);
+            }
+            PrimitiveShape::Torus {
+                radius,
+                tube_radius,
+                segments,
+                sides,
+            } => {
+                self.u8(5);
+                self.f32(radius);
+                self.f32(tube_radius);
+                self.u32(segments);
+                self.u32(sides);
+            }
+            PrimitiveShape::Plane { size, subdivisions } => {
+                self.u8(6);
+                self.f32(size);
+                self.u32(subdivisions);
+            }
+        }
+    }
+
+    fn topology(&mut self, topology: Topology) {
+        let (kind, mode) = match topology {
+            Topology::PointList(mode) => (0, mode),
+            Topology::LineList(mode) => (1, mode),
+            Topology::LineStrip(mode) => (2, mode),
+            Topology::TriangleList(mode) => (3, mode),
+            Topology::TriangleStrip(mode) => (4, mode),
+        };
+        self.u8(kind);
+        self.u8(match mode {
+            PolygonMode::Fill => 0,
+            PolygonMode::Line => 1,
+            PolygonMode::Point => 2,
+        });
+    }
+}
+
+struct SaveReader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> SaveReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, cursor: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveError> {
+        let remaining = self.bytes.len() - self.cursor;
+        if len > remaining {
+            return Err(SaveError::Truncated {
+                needed: len,
+                remaining,
+            });
+        }
+
+        let out = &self.bytes[self.cursor..self.cursor + len];
+        self.cursor += len;
+        Ok(out)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveError> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, SaveError> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SaveError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, SaveError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn bool(&mut self) -> Result<bool, SaveError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, SaveError> {
+        Ok(Vec3 {
+            x: self.f32()?,
+            y: self.f32()?,
+            z: self.f32()?,
+        })
+    }
+
+    fn vec4(&mut self) -> Result<Vec4, SaveError> {
+        Ok(Vec4 {
+            x: self.f32()?,
+            y: self.f32()?,
+            z: self.f32()?,
+            w: self.f32()?,
+        })
+    }
+
+    fn mat4(&mut self) -> Result<Mat4, SaveError> {
+        Ok(Mat4::new(
+            self.vec4()?,
+            self.vec4()?,
+            self.vec4()?,
+            self.vec4()?,
+        ))
+    }
+
+    fn color(&mut self) -> Result<Color, SaveError> {
+        Ok(Color::floats_a(
+            self.f32()?,
+            self.f32()?,
+            self.f32()?,
+            self.f32()?,
+        ))
+    }
+
+    fn entity_id(&mut self) -> Result<EntityId, SaveError> {
+        Ok(EntityId::new(self.u32()?, self.u32()?))
+    }
+
+    fn ids(&mut self) -> Result<IdAllocator, SaveError> {
+        let len = self.u32()?;
+        let mut slots = vec![];
+        for _ in 0..len {
+            slots.push(Slot {
+                generation: self.u32()?,
+                alive: self.bool()?,
+            });
+        }
+
+        let len = self.u32()?;
+        let mut free = vec![];
+        for _ in 0..len {
+            free.push(self.u32()?);
+        }
+
+        Ok(IdAllocator::from_slots(slots, free))
+    }
+
+    fn terrain_params(&mut self) -> Result<TerrainParams, SaveError> {
+        let mut params = TerrainParams::new(self.i64()? as isize, self.u32()?);
+        params.clamped = self.bool()?;
+        params.roughness = self.f32()?;
+        params.octaves = self.u32()? as i32;
+        params.amplitude = self.f32()?;
+        params.height = self.f32()?;
+        params.color_spread = self.f32()?;
+
+        // a longer palette than fits can't be skipped over, everything after it would be off
+        let palette_len = self.u32()? as usize;
+        if palette_len > MAX_PALETTE_COLORS {
+            return Err(SaveError::BadTerrain {
+                params: Box::new(params),
+            });
+        }
+        let mut palette = Vec::with_capacity(palette_len);
+        for _ in 0..palette_len {
+            palette.push(self.color()?);
+        }
+        params.set_palette(&palette);
+
+        Ok(params)
+    }
+
+    fn state(&mut self) -> Result<EntityState, SaveError> {
+        Ok(EntityState {
+            position: self.vec3()?,
+            rotation: self.mat4()?,
+            color: self.color()?,
+            // nothing that gets saved moves on its own
+            velocity: Vec3::new_from_one(0),
+        })
+    }
+
+    fn body(&mut self) -> Result<Option<BodyRecord>, SaveError> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+
+        Ok(Some(BodyRecord {
+            mass: self.f32()?,
+            restitution: self.f32()?,
+            friction: self.f32()?,
+        }))
+    }
+
+    fn parent(&mut self) -> Result<Option<EntityId>, SaveError> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+
+        Ok(Some(self.entity_id()?))
+    }
+
+    fn entity(&mut self) -> Result<EntityRecord, SaveError> {
+        match self.u8()? {
+            0 => Ok(EntityRecord::Cuboid(CuboidRecord {
+                size: self.f32()?,
+                vertex_kind: self.vertex_kind()?,
+                topology: self.topology()?,
+                state: self.state()?,
+            })),
+            1 => Ok(EntityRecord::Sun(SunRecord {
+                size: self.f32()?,
+                light_color: self.color()?,
+                radians: self.f32()?,
+                rotating: self.bool()?,
+                rotation_axis: self.vec3()?,
+                state: self.state()?,
+            })),
+            2 => Ok(EntityRecord::Primitive(PrimitiveRecord {
+                primitive: self.primitive()?,
+                vertex_kind: self.vertex_kind()?,
+                topology: self.topology()?,
+                state: self.state()?,
+            })),
+            tag => Err(SaveError::UnknownTag {
+                what: "entity",
+                tag,
+            }),
+        }
+    }
+
+    fn topology(&mut self) -> Result<Topology, SaveError> {
+        let kind = self.u8()?;
+        let mode = match self.u8()? {
+            0 => PolygonMode::Fill,
+            1 => PolygonMode::Line,
+            2 => PolygonMode::Point,
+            tag => {
+                return Err(SaveError::UnknownTag {
+                    what: "polygon mode",
+                    tag,
+                })
+            }
+        };
+
+        match kind {
+            0 => Ok(Topology::PointList(mode)),
+            1 => Ok(Topology::LineList(mode)),
+            2 => Ok(Topology::LineStrip(mode)),
+            3 => Ok(Topology::TriangleList(mode)),
+            4 => Ok(Topology::TriangleStrip(mode)),
+            tag => Err(SaveError::UnknownTag {
+                what: "topology",
+                tag,
+            }),
+        }
+    }
+
+    fn vertex_kind(&mut self) -> Result<VertexKind, SaveError> {
+        match self.u8()? {
+            0 => Ok(VertexKind::Basic),
+            1 => Ok(VertexKind::Shaded),
+            2 => Ok(VertexKind::Textured),
+            tag => Err(SaveError::UnknownTag {
+                what: "vertex kind",
+                tag,
+            }),
+        }
+    }
+
+    fn primitive(&mut self) -> Result<PrimitiveShape, SaveError> {
+        let primitive = match self.u8()? {
+            0 => PrimitiveShape::UvSphere {
+                radius: self.f32()?,
+                segments: self.u32()?,
+                rings: self.u32()?,
+            },
+            1 => PrimitiveShape::Icosphere {
+                radius: self.f32()?,
+                subdivisions: self.u32()?,
+            },
+            2 => PrimitiveShape::Cylinder {
+                radius: self.f32()?,
+                height: self.f32()?,
+                segments: self.u32()?,
+            },
+            3 => PrimitiveShape::Cone {
+                radius: self.f32()?,
+                height: self.f32()?,
+                segments: self.u32()?,
+            },
+            4 => PrimitiveShape::Capsule {
+                radius: self.f32()?,
+                height: self.f32()?,
+                segments: self.u32()?,
+                rings: self.u32()?,
+            },
+            5 => PrimitiveShape::Torus {
+                radius: self.f32()?,
+                tube_radius: self.f32()?,
+                segments: self.u32()?,
+                sides: self.u32()?,
+            },
+            6 => PrimitiveShape::Plane {
+                size: self.f32()?,
+                subdivisions: self.u32()?,
+            },
+            tag => {
+                return Err(SaveError::UnknownTag {
+                    what: "primitive",
+                    tag,
+                })
+            }
+        };
+
+        Ok(primitive)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atlas::ecs::components::Look;
+    use pantheon::math::Quaternion;
+
+    fn rotation() -> Quaternion {
+        Quaternion::rotation(1.2, (0, 1, 0).into())
+    }
+
+    /// rotations are saved as matrices, so they only come back close
+    fn close(a: Quaternion, b: Quaternion) -> bool {
+        a.dot(&b).abs() > 1. - 1e-5
+    }
+
+    fn world() -> WorldSave {
+        let cube = Replicated::new(
+            Transform {
+                rotation: rotation(),
+                ..Transform::new((1, 2, 3).into())
+            },
+            Mesh::cube(5.0, Color::new(10, 20, 30)),
+        );
+        let sun = Replicated {
+            light: Some(Light {
+                color: Color::new(255, 250, 209),
+                radians: 90.0f32.to_radians(),
+                rotating: false,
+                rotation_axis: (0, 1, 0).into(),
+            }),
+            ..Replicated::new(
+                Transform::new((0, 10, 0).into()),
+                Mesh::cube(5.0, Color::new(255, 250, 209)),
+            )
+        };
+
+        let mut terrain_params = TerrainParams::new(1234, 32);
+        terrain_params.height = 7.;
+        terrain_params.scale = 1.5;
+
+        // index 0 is freed so there's a generation and a free list to keep
+        let mut ids = IdAllocator::new();
+        let gone = ids.allocate();
+        let cube_id = ids.allocate();
+        let sun_id = ids.allocate();
+        ids.free(gone);
+
+        WorldSave {
+            tick: 99,
+            id_counter: 7,
+            ids,
+            terrain_params,
+            entities: vec![
+                (cube_id, EntityRecord::from_replicated(&cube).unwrap()),
+                (sun_id, EntityRecord::from_replicated(&sun).unwrap()),
+            ],
+            bodies: BTreeMap::from([(
+                cube_id,
+                BodyRecord {
+                    mass: 2.,
+                    restitution: 0.5,
+                    friction: 0.1,
+                },
+            )]),
+            parents: BTreeMap::new(),
+        }
+    }
+
+    /// the layout before generational ids, `tick_and_counter` is `None` for v1
+    fn legacy_save(save: &WorldSave, tick_and_counter: Option<(Tick, u64)>) -> Vec<u8> {
+        let mut writer = SaveWriter::default();
+        writer.bytes.extend_from_slice(&SAVE_MAGIC);
+        match tick_and_counter {
+            Some((tick, id_counter)) => {
+                writer.u32(2);
+                writer.u32(tick);
+                writer.u64(id_counter);
+            }
+            None => writer.u32(1),
+        }
+
+        writer.u64(save.ids.slots().len() as u64);
+        writer.terrain_params(&save.terrain_params);
+        writer.u32(save.entities.len() as u32);
+        for (id, record) in save.entities.iter() {
+            writer.u64(id.index as u64);
+            writer.entity(record);
+        }
+
+        writer.bytes
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), SaveError> {
+        let mut save = world();
+        save.parents.insert(save.entities[1].0, save.entities[0].0);
+        let loaded = WorldSave::decode(&save.encode())?;
+
+        assert_eq!(loaded.tick, 99);
+        assert_eq!(loaded.id_counter, 7);
+        assert_eq!(loaded.ids.slots(), save.ids.slots());
+        assert_eq!(loaded.entities[0].0, save.entities[0].0);
+        assert_eq!(loaded.entities[1].0, EntityId::new(2, 0));
+        assert_eq!(loaded.terrain_params.seed, 1234);
+        assert_eq!(loaded.terrain_params.height, 7.);
+        assert_eq!(loaded.terrain_params.scale, 1.5);
+        assert_eq!(
+            loaded.terrain_params.palette_len,
+            save.terrain_params.palette_len
+        );
+        assert_eq!(loaded.entities.len(), 2);
+        // the sun never had a body, it shouldn't come back with one
+        assert_eq!(loaded.bodies, save.bodies);
+        assert_eq!(loaded.parents, save.parents);
+
+        let cube = loaded.entities[0].1.to_replicated();
+        assert_eq!(cube.mesh.size, 5.0);
+        assert_eq!(cube.mesh.vertex_kind, VertexKind::Shaded);
+        assert!(cube.mesh.primitive.is_none() && cube.light.is_none());
+        assert_eq!(cube.transform.position, (1, 2, 3).into());
+        assert!(close(cube.transform.rotation, rotation()));
+        assert!(matches!(loaded.entities[1].1, EntityRecord::Sun(_)));
+        let sun = loaded.entities[1].1.to_replicated();
+        assert_eq!(sun.light.unwrap().color.b, Color::new(255, 250, 209).b);
+        assert_eq!(sun.mesh.vertex_kind, VertexKind::Basic);
+
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips_primitives() -> Result<(), SaveError> {
+        let mut save = world();
+        let mut ids = vec![];
+        for name in PrimitiveShape::NAMES {
+            let primitive = Replicated::new(
+                Transform {
+                    rotation: Quaternion::rotation(0.3, (1, 0, 0).into()),
+                    ..Transform::new((4, 0, 4).into())
+                },
+                Mesh {
+                    vertex_kind: VertexKind::Basic,
+                    ..Mesh::primitive(
+                        PrimitiveShape::from_name(name, 2.).unwrap(),
+                        Color::new(0, 200, 0),
+                    )
+                },
+            );
+            let id = save.ids.allocate();
+            let record = EntityRecord::from_replicated(&primitive).unwrap();
+            save.entities.push((id, record));
+            ids.push((id, primitive));
+        }
+
+        let loaded = WorldSave::decode(&save.encode())?;
+        for (id, primitive) in ids {
+            let (_, record) = loaded
+                .entities
+                .iter()
+                .find(|(loaded, _)| *loaded == id)
+                .unwrap();
+            let loaded = record.to_replicated();
+            assert_eq!(loaded.mesh.primitive, primitive.mesh.primitive);
+            assert_eq!(loaded.mesh.vertex_kind, VertexKind::Basic);
+            assert_eq!(loaded.transform.position, primitive.transform.position);
+            assert!(close(
+                loaded.transform.rotation,
+                primitive.transform.rotation
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn players_are_not_saved() {
+        let player = Replicated {
+            look: Some(Look::default()),
+            ..Replicated::new(
+                Transform::new((0, 1, 0).into()),
+                Mesh::cube(1., Color::new(255, 0, 0)),
+            )
+        };
+        assert!(EntityRecord::from_replicated(&player).is_none());
+    }
+
+    #[test]
+    fn migrates_v1() -> Result<(), SaveError> {
+        let save = world();
+
+        let loaded = WorldSave::decode(&legacy_save(&save, None))?;
+        assert_eq!(loaded.tick, 0);
+        assert_eq!(loaded.id_counter, 3);
+        assert_eq!(loaded.terrain_params.scale, 1.);
+        let bodies: Vec<_> = loaded.bodies.keys().copied().collect();
+        assert_eq!(bodies, vec![loaded.entities[0].0]);
+        assert_eq!(loaded.entities.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn migrates_counter_ids() -> Result<(), SaveError> {
+        let save = world();
+
+        let mut loaded = WorldSave::decode(&legacy_save(&save, Some((99, 7))))?;
+        assert_eq!((loaded.tick, loaded.id_counter), (99, 7));
+        assert_eq!(loaded.entities[0].0, EntityId::new(1, 0));
+        assert!(loaded.ids.is_alive(EntityId::new(2, 0)));
+
+        // the unused index comes back a generation ahead, then new indices are handed out
+        assert_eq!(loaded.ids.allocate(), EntityId::new(0, 1));
+        assert_eq!(loaded.ids.allocate(), EntityId::new(3, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_huge_legacy_ids() {
+        // the counter right after the version
+        let mut counter = legacy_save(&world(), None);
+        counter[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            WorldSave::decode(&counter),
+            Err(SaveError::IdOutOfRange { id: u64::MAX })
+        ));
+
+        let mut far = world();
+        far.entities[0].0 = EntityId::new(u32::MAX, 0);
+        assert!(matches!(
+            WorldSave::decode(&legacy_save(&far, None)),
+            Err(SaveError::IdOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(matches!(
+            WorldSave::decode(b"nope"),
+            Err(SaveError::BadMagic)
+        ));
+
+        let mut future = world().encode();
+        future[4..8].copy_from_slice(&(SAVE_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            WorldSave::decode(&future),
+            Err(SaveError::UnsupportedVersion { .. })
+        ));
+
+        let mut dead = world();
+        dead.entities[0].0 = EntityId::new(0, 0);
+        assert!(matches!(
+            WorldSave::decode(&dead.encode()),
+            Err(SaveError::DeadEntityId { .. })
+        ));
+
+        let bad_terrain: [fn(&mut TerrainParams); 7] = [
+            |params| params.size = u32::MAX,
+            |params| params.size = 0,
+            |params| params.octaves = -1,
+            |params| params.roughness = f32::NAN,
+            |params| params.amplitude = f32::INFINITY,
+            |params| params.scale = 0.,
+            |params| params.set_palette(&[Color::new(1, 2, 3)]),
+        ];
+        for spoil in bad_terrain {
+            let mut save = world();
+            spoil(&mut save.terrain_params);
+            assert!(matches!(
+                WorldSave::decode(&save.encode()),
+                Err(SaveError::BadTerrain { .. })
+            ));
+        }
+
+        // an empty world with no colors ends in the palette length, the scale and the entity count
+        let mut long = world();
+        long.entities.clear();
+        long.terrain_params.set_palette(&[]);
+        let mut bytes = long.encode();
+        let at = bytes.len() - (4 + 4 + 4);
+        bytes[at..at + 4].copy_from_slice(&(MAX_PALETTE_COLORS as u32 + 1).to_le_bytes());
+        assert!(matches!(
+            WorldSave::decode(&bytes),
+            Err(SaveError::BadTerrain { .. })
+        ));
+
+        let mut orphan = world();
+        orphan
+            .parents
+            .insert(orphan.entities[0].0, EntityId::new(0, 0));
+        assert!(matches!(
+            WorldSave::decode(&orphan.encode()),
+            Err(SaveError::DeadEntityId { .. })
+        ));
+
+        let mut heavy = world();
+        heavy
+            .bodies
+            .values_mut()
+            .for_each(|body| body.mass = f32::NAN);
+        assert!(matches!(
+            WorldSave::decode(&heavy.encode()),
+            Err(SaveError::BadBody { .. })
+        ));
+
+        let truncated = world().encode();
+        assert!(matches!(
+            WorldSave::decode(&truncated[..truncated.len() - 1]),
+            Err(SaveError::Truncated { .. })
+        ));
+
+        // the entity count is the last thing written for an empty world
+        let mut empty = world();
+        empty.entities.clear();
+        let mut bytes = empty.encode();
+        let len = bytes.len();
+        bytes[len - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            WorldSave::decode(&bytes),
+            Err(SaveError::Truncated { .. })
+        ));
+    }
+}